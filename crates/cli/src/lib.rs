@@ -8,7 +8,7 @@ use clap::{
     builder::PossibleValuesParser, parser::ValueSource, value_parser, Arg, ArgAction, ArgMatches,
     Command,
 };
-use roc_build::link::{LinkType, LinkingStrategy};
+use roc_build::link::{supported_targets, LinkType, LinkingStrategy};
 use roc_build::program::{
     handle_error_module, handle_loading_problem, standard_load_config, BuildFileError,
     BuildOrdering, BuiltFile, CodeGenBackend, CodeGenOptions, DEFAULT_ROC_FILENAME,
@@ -32,7 +32,6 @@ use std::os::raw::{c_char, c_int};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::time::{Duration, Instant};
-use strum::IntoEnumIterator;
 #[cfg(not(target_os = "linux"))]
 use tempfile::TempDir;
 
@@ -168,8 +167,11 @@ pub fn build_app() -> Command {
         .num_args(0..)
         .allow_hyphen_values(true);
 
-    let build_target_values_parser =
-        PossibleValuesParser::new(Target::iter().map(Into::<&'static str>::into));
+    let build_target_values_parser = PossibleValuesParser::new(
+        supported_targets()
+            .into_iter()
+            .map(Into::<&'static str>::into),
+    );
 
     Command::new("roc")
         .version(concatcp!(VERSION, "\n"))
@@ -1406,12 +1408,13 @@ fn run_wasm<I: Iterator<Item = S>, S: AsRef<[u8]>>(wasm_path: &std::path::Path,
 
     let mut instance = Instance::from_bytes(&arena, &bytes, import_dispatcher, false).unwrap();
 
-    instance
-        .call_export("_start", [])
-        .unwrap()
-        .unwrap()
-        .expect_i32()
-        .unwrap();
+    let result = instance.call_export("_start", []).unwrap();
+    if let Some(code) = instance.exit_code() {
+        // Exiting skips the destructors, which would write out the last line otherwise
+        instance.import_dispatcher.wasi.flush().unwrap();
+        std::process::exit(code);
+    }
+    result.unwrap().expect_i32().unwrap();
 }
 
 #[cfg(not(feature = "run-wasm32"))]
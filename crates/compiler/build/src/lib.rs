@@ -3,5 +3,6 @@
 // See github.com/roc-lang/roc/issues/800 for discussion of the large_enum_variant check.
 #![allow(clippy::large_enum_variant)]
 pub mod link;
+pub mod link_problem;
 pub mod program;
 pub mod target;
@@ -1,3 +1,4 @@
+use crate::link_problem::{parse_linker_errors, LinkProblem};
 use crate::target::arch_str;
 use libloading::{Error, Library};
 use roc_command_utils::{cargo, clang, rustup, zig};
@@ -5,13 +6,16 @@ use roc_debug_flags;
 use roc_error_macros::internal_error;
 use roc_mono::ir::OptLevel;
 use roc_target::{Architecture, OperatingSystem, Target};
-use std::collections::HashMap;
-use std::ffi::OsString;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
 use std::fs::DirEntry;
-use std::io;
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
-use std::process::{self, Child, Command};
-use std::{env, fs};
+use std::process::{self, Child, Command, ExitStatus};
+use std::str::FromStr;
+use std::{env, fmt, fs};
+use strum::IntoEnumIterator;
 use wasi_libc_sys::{WASI_COMPILER_RT_PATH, WASI_LIBC_PATH};
 
 pub use roc_linker::LinkType;
@@ -26,22 +30,841 @@ pub enum LinkingStrategy {
     Additive,
 }
 
+/// Extra settings for `link_with_options`. The defaults match what `link` does.
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    /// Garbage-collect unused sections (`--gc-sections`, or `-dead_strip` on macOS).
+    /// `None` keeps the platform default, which is on for Linux and off everywhere else.
+    /// This only removes much if the inputs were compiled with one section per function,
+    /// e.g. `-ffunction-sections -fdata-sections`.
+    pub gc_sections: Option<bool>,
+    /// Use these C runtime objects on Linux, instead of looking for them in the usual
+    /// system library directories. Useful when cross-linking against a sysroot.
+    pub crt_paths: Option<CrtPaths>,
+    /// Link Linux executables against the C library in this directory instead of the host's,
+    /// for cross-compiling. The crt objects and default library directories are looked up
+    /// inside it, and it must contain the target's dynamic linker.
+    pub sysroot: Option<PathBuf>,
+    /// Start executables at this symbol instead of the linker's default entry point,
+    /// for hosts that define their own.
+    pub entry_symbol: Option<String>,
+    /// Also write a map of the output's sections and symbols to this file, for size analysis.
+    /// This is supported by `ld` on Linux and macOS.
+    pub map_file: Option<PathBuf>,
+    /// Relative input and output paths are resolved against this directory.
+    /// `None` means the current working directory.
+    pub base_dir: Option<PathBuf>,
+    /// When linking a Dylib, export only these symbols and hide the rest, e.g. for a plugin
+    /// with a fixed ABI. Names are as they appear in C, without macOS's leading underscore.
+    /// This uses a version script on Linux and an exported symbols list on macOS.
+    pub exported_symbols: Option<Vec<String>>,
+    /// Ask the linker to describe what it's doing, e.g. the paths it searches for libraries.
+    /// Use `link_and_wait` to capture what it prints.
+    pub verbose: bool,
+    /// Remove debug info from the output (`--strip-debug`, `-S` on macOS, or zig's `-fstrip`).
+    /// `None` keeps the platform default, which strips wasm and keeps the debug info of the host
+    /// and app everywhere else, so that native binaries work with gdb and perf out of the box.
+    pub strip_debug_info: Option<bool>,
+    /// Compress the debug info in the output, which makes it smaller but still usable.
+    /// This is supported by `ld` on Linux.
+    pub compress_debug_sections: bool,
+    /// Search these directories for libraries before the default ones (`-L`), e.g. to use a
+    /// custom sysroot's or vendored libraries. Relative paths are resolved against `base_dir`.
+    /// Directories that don't exist are skipped with a warning.
+    pub library_paths: Vec<PathBuf>,
+    /// Link the compiler-rt builtins, which implement operations like 128-bit multiplication
+    /// (`__multi3`) and float-to-int128 conversions that code gen calls instead of inlining.
+    /// `None` links them only if an input object references one of them.
+    /// This only affects `ld` on Linux, which uses the C compiler's `libgcc.a` (or `libclang_rt.builtins`
+    /// for a clang set up to use compiler-rt). zig and macOS's libSystem already provide them.
+    pub compiler_rt: Option<bool>,
+    /// Make a position-independent executable (`-pie`), or not (`-no-pie`), e.g. for loaders that
+    /// need executables at a fixed address. `None` keeps the platform default, which is PIE for `ld`
+    /// on Linux and whatever zig does elsewhere. On Linux, this also picks the matching C runtime
+    /// startup object: `Scrt1.o` for PIE and `crt1.o` otherwise.
+    /// Roc always compiles position-independent code, which links into both kinds of executable.
+    pub pie: Option<bool>,
+    /// The oldest glibc that the output has to run on, for distributing binaries to older distros.
+    /// `link_and_wait` checks the symbol versions the output needs, and fails if any is newer.
+    /// `ld` can only use the symbol versions of the C library it links against, so an executable
+    /// linked against a new glibc can't be made to run on an old one. To honor this, point `sysroot`
+    /// at a copy of the older glibc. Only x86_64 and aarch64 Linux link against glibc.
+    pub glibc_version: Option<GlibcVersion>,
+}
+
+/// A glibc version like `2.17`, as in its symbol versions like `GLIBC_2.17`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlibcVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// Only the oldest symbol versions have one, like `GLIBC_2.2.5`
+    pub patch: u32,
+}
+
+impl FromStr for GlibcVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s:?} isn't a glibc version like 2.17");
+        let mut parts = s
+            .split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| invalid()));
+        let major = parts.next().ok_or_else(invalid)??;
+        let minor = parts.next().ok_or_else(invalid)??;
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(GlibcVersion {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for GlibcVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of `link_and_wait`
+#[derive(Debug)]
+pub struct LinkOutput {
+    pub output_path: PathBuf,
+    /// The exit status of the last linker step that ran
+    pub status: ExitStatus,
+    /// Everything the linker steps printed, e.g. their search paths when `verbose` is set
+    pub stdout: String,
+    pub stderr: String,
+    /// The errors in `stderr` that we recognized, if the link failed
+    pub problems: Vec<LinkProblem>,
+}
+
+/// Files that a single link needs, like response files or version scripts.
+/// They're written to a fresh directory, which is removed along with them when this is dropped,
+/// so a failed or panicking link doesn't leave them behind.
+#[derive(Debug, Default)]
+pub struct TempArtifacts {
+    dir: Option<tempfile::TempDir>,
+}
+
+impl TempArtifacts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write a file into the directory, creating the directory the first time, and return its path
+    pub fn write(&mut self, file_name: &str, contents: &[u8]) -> io::Result<PathBuf> {
+        let path = self.path(file_name)?;
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// The path of a file in the directory, creating the directory the first time.
+    /// Nothing is written there, e.g. so that a linker can write its output to it.
+    pub fn path(&mut self, file_name: &str) -> io::Result<PathBuf> {
+        let dir = match self.dir.take() {
+            Some(dir) => dir,
+            None => tempfile::Builder::new().prefix("roc_link").tempdir()?,
+        };
+        Ok(self.dir.insert(dir).path().join(file_name))
+    }
+
+    /// Leave the files in place after this is dropped, e.g. for a linker that's still running.
+    /// Returns the directory they're in, if any were written.
+    pub fn keep(self) -> Option<PathBuf> {
+        self.dir.map(tempfile::TempDir::into_path)
+    }
+}
+
+/// The linker process from `link`, along with the files it uses.
+/// It derefs to the `Child`, and the files are removed when it's dropped,
+/// so drop it only after waiting for the linker to finish.
+#[derive(Debug)]
+pub struct LinkChild {
+    child: Child,
+    _temp_artifacts: TempArtifacts,
+}
+
+impl LinkChild {
+    fn new(child: Child, temp_artifacts: TempArtifacts) -> Self {
+        Self {
+            child,
+            _temp_artifacts: temp_artifacts,
+        }
+    }
+}
+
+impl Deref for LinkChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for LinkChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+/// Where `link_to` puts the linked binary
+pub enum LinkDest {
+    Path(PathBuf),
+    /// Stream the binary to this, e.g. stdout or a pipe. Linkers can only write to files,
+    /// so this links to a temporary file first and then copies it.
+    Writer(Box<dyn Write>),
+}
+
+/// Paths to the C runtime startup objects that get linked into Linux executables
+#[derive(Debug, Clone)]
+pub struct CrtPaths {
+    pub crti: PathBuf,
+    pub crtn: PathBuf,
+    /// `Scrt1.o`, or `crt1.o` if `LinkOptions::pie` is turned off
+    pub scrt1: PathBuf,
+}
+
+impl CrtPaths {
+    fn check_exist(&self) -> io::Result<()> {
+        for path in [&self.crti, &self.crtn, &self.scrt1] {
+            if !path.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The C runtime object {} does not exist", path.display()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the commands that do the linking, which run one after another, and the output path.
+/// Any files the commands need are written to the `TempArtifacts`.
+type LinkFn = fn(
+    Target,
+    PathBuf,
+    &[&str],
+    LinkType,
+    &LinkOptions,
+    &mut TempArtifacts,
+) -> io::Result<(Vec<Command>, PathBuf)>;
+
+/// The function that links for this target, or None if `link` doesn't support it
+fn link_fn(target: Target) -> Option<LinkFn> {
+    match target.arch_os() {
+        (Architecture::Wasm32, _) => Some(link_wasm32),
+        (
+            Architecture::X86_32 | Architecture::X86_64 | Architecture::Aarch64,
+            OperatingSystem::Linux,
+        ) => Some(link_linux),
+        (_, OperatingSystem::Mac) => Some(link_macos),
+        (_, OperatingSystem::Windows) => {
+            Some(|_, output_path, input_paths, link_type, options, _| {
+                link_windows(output_path, input_paths, link_type, options)
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The targets that `link` can link for, e.g. for the CLI to list the values `--target` accepts
+pub fn supported_targets() -> Vec<Target> {
+    Target::iter()
+        .filter(|target| link_fn(*target).is_some())
+        .collect()
+}
+
 /// input_paths can include the host as well as the app. e.g. &["host.o", "roc_app.o"]
 pub fn link(
     target: Target,
     output_path: PathBuf,
     input_paths: &[&str],
     link_type: LinkType,
-) -> io::Result<(Child, PathBuf)> {
-    match target.arch_os() {
-        (Architecture::Wasm32, _) => link_wasm32(target, output_path, input_paths, link_type),
-        (_, OperatingSystem::Linux) => link_linux(target, output_path, input_paths, link_type),
-        (_, OperatingSystem::Mac) => link_macos(target, output_path, input_paths, link_type),
-        (_, OperatingSystem::Windows) => link_windows(output_path, input_paths, link_type),
-        _ => internal_error!("TODO gracefully handle unsupported target: {:?}", target),
+) -> io::Result<(LinkChild, PathBuf)> {
+    link_with_options(
+        target,
+        output_path,
+        input_paths,
+        link_type,
+        &LinkOptions::default(),
+    )
+}
+
+pub fn link_with_options(
+    target: Target,
+    output_path: PathBuf,
+    input_paths: &[&str],
+    link_type: LinkType,
+    options: &LinkOptions,
+) -> io::Result<(LinkChild, PathBuf)> {
+    let (commands, output_path, temp_artifacts) =
+        link_commands(target, output_path, input_paths, link_type, options)?;
+
+    // Earlier steps have to finish before the last one starts
+    let mut commands = commands.into_iter();
+    let mut last = commands
+        .next_back()
+        .expect("every linker has at least one step");
+    for mut command in commands {
+        let mut child = command.spawn()?;
+        if !child.wait()?.success() {
+            return Ok((LinkChild::new(child, temp_artifacts), output_path));
+        }
+    }
+
+    // The linker is still running after we return, so its files go along with it
+    let child = last.spawn()?;
+
+    Ok((LinkChild::new(child, temp_artifacts), output_path))
+}
+
+/// Like `link_with_options`, but waits for the linker to finish and captures what it prints
+pub fn link_and_wait(
+    target: Target,
+    output_path: PathBuf,
+    input_paths: &[&str],
+    link_type: LinkType,
+    options: &LinkOptions,
+) -> io::Result<LinkOutput> {
+    // The linker's files are removed when this goes out of scope, after every step has finished
+    let (commands, output_path, _temp_artifacts) =
+        link_commands(target, output_path, input_paths, link_type, options)?;
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut status = None;
+    for mut command in commands {
+        let output = command.output()?;
+        stdout.push_str(&String::from_utf8_lossy(&output.stdout));
+        stderr.push_str(&String::from_utf8_lossy(&output.stderr));
+        status = Some(output.status);
+        if !output.status.success() {
+            break;
+        }
+    }
+
+    let status = status.expect("every linker has at least one step");
+    let problems = if status.success() {
+        Vec::new()
+    } else {
+        parse_linker_errors(&stderr)
+    };
+
+    if let (true, Some(glibc_version)) = (status.success(), options.glibc_version) {
+        check_glibc_version(&output_path, glibc_version)?;
+    }
+
+    Ok(LinkOutput {
+        output_path,
+        status,
+        stdout,
+        stderr,
+        problems,
+    })
+}
+
+/// Like `link_and_wait`, but the binary can also be streamed to a writer (see `LinkDest`).
+/// The writer only gets the binary if the link succeeded. Either way, the temporary file is removed
+/// before this returns, so for a writer the `output_path` of the result no longer exists.
+pub fn link_to(
+    target: Target,
+    dest: LinkDest,
+    input_paths: &[&str],
+    link_type: LinkType,
+    options: &LinkOptions,
+) -> io::Result<LinkOutput> {
+    match dest {
+        LinkDest::Path(output_path) => {
+            link_and_wait(target, output_path, input_paths, link_type, options)
+        }
+        LinkDest::Writer(mut writer) => {
+            // Removed when this goes out of scope, including on the early returns
+            let mut temp_output = TempArtifacts::new();
+            let output_path = temp_output.path("roc_output")?;
+
+            let output = link_and_wait(target, output_path, input_paths, link_type, options)?;
+            if output.status.success() {
+                io::copy(&mut fs::File::open(&output.output_path)?, &mut writer)?;
+                writer.flush()?;
+            }
+
+            Ok(output)
+        }
+    }
+}
+
+/// Link an app into a host that was preprocessed for surgical linking, writing the executable to `dest`.
+/// Instead of running a linker, this copies the host and patches the app's code into its placeholders,
+/// using the metadata that `roc_linker::preprocess_host` wrote next to it. That makes it much faster.
+///
+/// `host_with_placeholders` is the preprocessed host, e.g. `platform/linux-x64.rh`,
+/// and `roc_app_obj` is the app's object file.
+pub fn surgical_link(
+    target: Target,
+    host_with_placeholders: &Path,
+    roc_app_obj: &Path,
+    dest: &Path,
+) -> io::Result<()> {
+    if !roc_linker::supported(LinkType::Executable, target) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Surgical linking is not supported for {target} yet"),
+        ));
+    }
+
+    let metadata = host_with_placeholders.with_file_name(roc_linker::metadata_file_name(target));
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "The host {} has no surgical linking metadata at {}. Was it preprocessed for {target}?",
+                host_with_placeholders.display(),
+                metadata.display()
+            ),
+        ));
+    }
+
+    let roc_app_bytes = fs::read(roc_app_obj)?;
+    fs::copy(host_with_placeholders, dest)?;
+    roc_linker::link_preprocessed_host(target, host_with_placeholders, &roc_app_bytes, dest);
+
+    Ok(())
+}
+
+fn link_commands(
+    target: Target,
+    output_path: PathBuf,
+    input_paths: &[&str],
+    link_type: LinkType,
+    options: &LinkOptions,
+) -> io::Result<(Vec<Command>, PathBuf, TempArtifacts)> {
+    let base_dir = match &options.base_dir {
+        Some(dir) => dir.clone(),
+        None => env::current_dir()?,
+    };
+    let output_path = resolve_output_path(&base_dir, &output_path)?;
+    let resolved_inputs = input_paths
+        .iter()
+        .map(|path| resolve_input_path(&base_dir, path))
+        .collect::<io::Result<Vec<String>>>()?;
+    let input_paths: Vec<&str> = resolved_inputs.iter().map(String::as_str).collect();
+    let input_paths = input_paths.as_slice();
+
+    let mut options = options.clone();
+    if let Some(map_file) = &options.map_file {
+        let map_file = resolve_output_path(&base_dir, map_file)?;
+        check_writable(&map_file)?;
+        options.map_file = Some(map_file);
+    }
+    options.library_paths = resolve_library_paths(&base_dir, &options.library_paths);
+    let options = &options;
+
+    if options.gc_sections == Some(true) {
+        warn_if_sections_not_split(input_paths);
+    }
+
+    // Everything else is linked with zig
+    let uses_ld = match target.arch_os() {
+        (Architecture::Wasm32, _) | (Architecture::X86_32, OperatingSystem::Linux) => false,
+        (_, os) => matches!(os, OperatingSystem::Linux | OperatingSystem::Mac),
+    };
+    if options.map_file.is_some() && !uses_ld {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Writing a linker map file isn't supported for {target:?} yet"),
+        ));
+    }
+    if options.compress_debug_sections {
+        if options.strip_debug_info == Some(true) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Debug info can't be compressed when it's being stripped",
+            ));
+        }
+        if !uses_ld || target.operating_system() != OperatingSystem::Linux {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Compressing debug info isn't supported for {target:?} yet"),
+            ));
+        }
+    }
+    if let Some(symbols) = &options.exported_symbols {
+        if link_type != LinkType::Dylib {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Exported symbols can only be chosen when linking a Dylib",
+            ));
+        }
+        if !uses_ld {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Choosing exported symbols isn't supported for {target:?} yet"),
+            ));
+        }
+        if let Some(symbol) = symbols.iter().find(|symbol| !is_plain_symbol(symbol)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{symbol:?} can't be used as an exported symbol name"),
+            ));
+        }
+    }
+
+    if let Some(glibc_version) = options.glibc_version {
+        let links_glibc = matches!(
+            target.arch_os(),
+            (
+                Architecture::X86_64 | Architecture::Aarch64,
+                OperatingSystem::Linux
+            )
+        );
+        if !links_glibc {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("glibc {glibc_version} was requested, but {target:?} doesn't link against glibc"),
+            ));
+        }
+    }
+
+    match link_fn(target) {
+        Some(link_fn) => {
+            let mut temp_artifacts = TempArtifacts::new();
+            let (commands, output_path) = link_fn(
+                target,
+                output_path,
+                input_paths,
+                link_type,
+                options,
+                &mut temp_artifacts,
+            )?;
+            Ok((commands, output_path, temp_artifacts))
+        }
+        None => internal_error!("TODO gracefully handle unsupported target: {:?}", target),
+    }
+}
+
+/// Make a linker input path absolute, and check that it exists, so that a missing file
+/// is reported here rather than in a confusing message from the linker.
+fn resolve_input_path(base_dir: &Path, path: &str) -> io::Result<String> {
+    let resolved = base_dir.join(path);
+    if !resolved.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "The linker input {path} does not exist (looked for {})",
+                resolved.display()
+            ),
+        ));
+    }
+    resolved.into_os_string().into_string().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The linker input path {path} is not valid UTF-8"),
+        )
+    })
+}
+
+/// Make the output path absolute, and check that the directory it goes in exists
+fn resolve_output_path(base_dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let resolved = base_dir.join(path);
+    match resolved.parent() {
+        Some(dir) if !dir.is_dir() => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "The directory for the linker output {} does not exist",
+                resolved.display()
+            ),
+        )),
+        _ => Ok(resolved),
+    }
+}
+
+/// Make the library search directories absolute, leaving out the ones that don't exist.
+/// A missing directory is only a warning, since the libraries may be found somewhere else.
+fn resolve_library_paths(base_dir: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| base_dir.join(path))
+        .filter(|dir| {
+            let is_dir = dir.is_dir();
+            if !is_dir {
+                eprintln!(
+                    "Warning: the library search directory {} does not exist, so I'm leaving it out.",
+                    dir.display()
+                );
+            }
+            is_dir
+        })
+        .collect()
+}
+
+/// Fail now, rather than after linking, if the linker won't be able to write this file
+fn check_writable(path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map(|_| ())
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("I can't write to {}: {e}", path.display()),
+            )
+        })
+}
+
+/// Section garbage collection can only remove whole sections, so it does little for
+/// object files that put all their code in one `.text` section.
+fn warn_if_sections_not_split(input_paths: &[&str]) {
+    for path in input_paths {
+        match has_split_sections(Path::new(path)) {
+            Some(true) => {}
+            Some(false) => eprintln!(
+                "Warning: {path} doesn't seem to have been compiled with separate function and data sections, so garbage-collecting sections won't remove much from it."
+            ),
+            None => eprintln!(
+                "Warning: I couldn't tell whether {path} was compiled with separate function and data sections, which garbage-collecting sections needs in order to be effective."
+            ),
+        }
+    }
+}
+
+/// Functions from compiler-rt (or libgcc) that code gen calls for 128-bit integer and
+/// float conversion operations, rather than inlining them
+const COMPILER_RT_SYMBOLS: &[&str] = &[
+    "__multi3",
+    "__muloti4",
+    "__divti3",
+    "__udivti3",
+    "__modti3",
+    "__umodti3",
+    "__ashlti3",
+    "__ashrti3",
+    "__lshrti3",
+    "__fixdfti",
+    "__fixsfti",
+    "__fixunsdfti",
+    "__fixunssfti",
+    "__floattidf",
+    "__floattisf",
+    "__floatuntidf",
+    "__floatuntisf",
+];
+
+/// Whether an object file calls any of the `COMPILER_RT_SYMBOLS`.
+/// Files we can't inspect, like archives, are assumed not to.
+fn references_compiler_rt(path: &Path) -> bool {
+    use object::{Object, ObjectSymbol};
+
+    let Ok(bytes) = fs::read(path) else {
+        return false;
+    };
+    let Ok(file) = object::File::parse(bytes.as_slice()) else {
+        return false;
+    };
+
+    file.symbols()
+        .filter(|symbol| symbol.is_undefined())
+        .filter_map(|symbol| symbol.name().ok())
+        .any(|name| {
+            // macOS adds an underscore to C names
+            COMPILER_RT_SYMBOLS.contains(&name)
+                || name
+                    .strip_prefix('_')
+                    .is_some_and(|name| COMPILER_RT_SYMBOLS.contains(&name))
+        })
+}
+
+/// The symbols that an ELF executable or shared library needs from glibc, by symbol version
+fn glibc_versions_needed(data: &[u8]) -> object::Result<BTreeMap<GlibcVersion, Vec<String>>> {
+    use object::elf::{FileHeader64, SHN_UNDEF, SHT_DYNSYM};
+    use object::read::elf::{FileHeader, Sym};
+    use object::Endianness;
+
+    let header = FileHeader64::<Endianness>::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.sections(endian, data)?;
+    let symbols = sections.symbols(endian, data, SHT_DYNSYM)?;
+    let Some(versions) = sections.versions(endian, data)? else {
+        return Ok(BTreeMap::new());
+    };
+
+    let mut needed: BTreeMap<GlibcVersion, Vec<String>> = BTreeMap::new();
+    for (index, symbol) in symbols.iter().enumerate() {
+        if symbol.st_shndx(endian) != SHN_UNDEF {
+            continue;
+        }
+        let Some(version) = versions.version(versions.version_index(endian, index))? else {
+            continue;
+        };
+        let version = String::from_utf8_lossy(version.name());
+        if let Some(Ok(version)) = version.strip_prefix("GLIBC_").map(str::parse) {
+            let name = symbol.name(endian, symbols.strings())?;
+            needed
+                .entry(version)
+                .or_default()
+                .push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    Ok(needed)
+}
+
+/// Check that a linked binary only needs symbols that glibc `max_version` has
+fn check_glibc_version(binary: &Path, max_version: GlibcVersion) -> io::Result<()> {
+    let data = fs::read(binary)?;
+    let needed = glibc_versions_needed(&data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let too_new: Vec<String> = needed
+        .range(max_version..)
+        .filter(|(version, _)| **version > max_version)
+        .map(|(version, symbols)| format!("{version} for {}", symbols.join(", ")))
+        .collect();
+    if too_new.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "{} has to run on glibc {max_version}, but it needs glibc {}. Symbols always get the versions of the C library they're linked against, so link against glibc {max_version} using a sysroot.",
+                binary.display(),
+                too_new.join("; glibc ")
+            ),
+        ))
+    }
+}
+
+/// The static library with the compiler-rt builtins for Linux, from the library directories
+/// or else wherever the host's C compiler keeps its own
+fn find_compiler_rt(lib_dirs: &[PathBuf], sysroot: Option<&Path>) -> Option<PathBuf> {
+    look_for_library(lib_dirs, "libgcc.a").or_else(|| {
+        // The host's compiler's copy is no use for another sysroot
+        if sysroot.is_some() {
+            return None;
+        }
+        let output = Command::new("cc")
+            .arg("-print-libgcc-file-name")
+            .output()
+            .ok()?;
+        let path = PathBuf::from(String::from_utf8(output.stdout).ok()?.trim());
+        path.is_file().then_some(path)
+    })
+}
+
+/// Whether an object file has one section per function or data item.
+/// Returns None for files we can't inspect, like archives.
+fn has_split_sections(path: &Path) -> Option<bool> {
+    use object::{BinaryFormat, FileFlags, Object, ObjectSection};
+
+    let bytes = fs::read(path).ok()?;
+    let file = object::File::parse(bytes.as_slice()).ok()?;
+
+    match file.format() {
+        BinaryFormat::MachO => match file.flags() {
+            FileFlags::MachO { flags } => {
+                Some(flags & object::macho::MH_SUBSECTIONS_VIA_SYMBOLS != 0)
+            }
+            _ => None,
+        },
+        BinaryFormat::Elf => Some(file.sections().any(|section| {
+            section.name().map_or(false, |name| {
+                [".text.", ".data.", ".rodata.", ".bss."]
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+            })
+        })),
+        _ => None,
+    }
+}
+
+/// zig's flag for the `gc_sections` option, if it isn't left as the default
+fn zig_gc_sections_arg(options: &LinkOptions) -> Option<&'static str> {
+    match options.gc_sections {
+        Some(true) => Some("--gc-sections"),
+        Some(false) => Some("--no-gc-sections"),
+        None => None,
+    }
+}
+
+fn zig_pie_arg(options: &LinkOptions) -> Option<&'static str> {
+    match options.pie {
+        Some(true) => Some("-fPIE"),
+        Some(false) => Some("-fno-PIE"),
+        None => None,
+    }
+}
+
+/// Whether to strip debug info. Only wasm does by default.
+fn strip_debug_info(options: &LinkOptions, target: Target) -> bool {
+    options
+        .strip_debug_info
+        .unwrap_or(target.architecture() == Architecture::Wasm32)
+}
+
+/// `-L` flags for the `library_paths` option. ld and zig both search them in order,
+/// before their default directories.
+fn library_path_args(options: &LinkOptions) -> impl Iterator<Item = String> + '_ {
+    options
+        .library_paths
+        .iter()
+        .map(|dir| format!("-L{}", dir.display()))
+}
+
+/// `flag` followed by the `entry_symbol` option, if there is one.
+/// `ld` uses `-e` on both Linux and macOS, and zig (which we use on Windows) uses `--entry`.
+fn entry_symbol_args<'o>(options: &'o LinkOptions, flag: &'o str) -> Vec<&'o str> {
+    match &options.entry_symbol {
+        Some(symbol) => vec![flag, symbol.as_str()],
+        None => vec![],
     }
 }
 
+/// Symbol names that can be written into a version script or exported symbols list as-is
+fn is_plain_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.'))
+}
+
+/// The contents of the file listing the `exported_symbols` option, in the format `ld` expects
+fn exported_symbols_file_contents(os: OperatingSystem, symbols: &[String]) -> String {
+    match os {
+        OperatingSystem::Mac => symbols.iter().map(|s| format!("_{s}\n")).collect(),
+        _ => {
+            let globals: String = symbols.iter().map(|s| format!("    {s};\n")).collect();
+            format!("{{\n  global:\n{globals}  local:\n    *;\n}};\n")
+        }
+    }
+}
+
+/// `flag` followed by a file listing the `exported_symbols` option, if there is one.
+/// That's `--version-script` on Linux and `-exported_symbols_list` on macOS.
+fn exported_symbols_args(
+    options: &LinkOptions,
+    os: OperatingSystem,
+    flag: &str,
+    temp_artifacts: &mut TempArtifacts,
+) -> io::Result<Vec<OsString>> {
+    let symbols = match &options.exported_symbols {
+        Some(symbols) => symbols,
+        None => return Ok(vec![]),
+    };
+
+    let contents = exported_symbols_file_contents(os, symbols);
+    let path = temp_artifacts.write("exported_symbols", contents.as_bytes())?;
+
+    Ok(vec![flag.into(), path.into_os_string()])
+}
+
 /// Same format as the precompiled host filename, except with a file extension like ".o" or ".obj"
 pub fn legacy_host_file(target: Target, platform_main_roc: &Path) -> PathBuf {
     let lib_ext = target.static_library_file_ext();
@@ -858,7 +1681,9 @@ fn link_linux(
     output_path: PathBuf,
     input_paths: &[&str],
     link_type: LinkType,
-) -> io::Result<(Child, PathBuf)> {
+    options: &LinkOptions,
+    temp_artifacts: &mut TempArtifacts,
+) -> io::Result<(Vec<Command>, PathBuf)> {
     let architecture = format!("{}-linux-gnu", target.architecture());
 
     //    Command::new("cp")
@@ -872,52 +1697,82 @@ fn link_linux(
     //        .unwrap();
 
     if let Architecture::X86_32 = target.architecture() {
-        return Ok((
-            zig()
-                .args(["build-exe"])
-                .args(input_paths)
-                .args([
-                    "-target",
-                    "i386-linux-musl",
-                    "-lc",
-                    &format!("-femit-bin={}", output_path.to_str().unwrap()),
-                ])
-                .spawn()?,
-            output_path,
-        ));
+        let mut zig_command = zig();
+        zig_command
+            .args(["build-exe"])
+            .args(input_paths)
+            .args(library_path_args(options))
+            .args([
+                "-target",
+                "i386-linux-musl",
+                "-lc",
+                &format!("-femit-bin={}", output_path.to_str().unwrap()),
+            ])
+            .args(strip_debug_info(options, target).then_some("-fstrip"))
+            .args(zig_gc_sections_arg(options))
+            .args(zig_pie_arg(options))
+            .args(entry_symbol_args(options, "--entry"))
+            .args(options.verbose.then_some("--verbose-link"));
+        return Ok((vec![zig_command], output_path));
     }
 
-    let nix_paths_vec_string = nix_paths();
-    let nix_paths_vec: Vec<PathBuf> = nix_paths_vec_string.iter().map(PathBuf::from).collect();
+    let sysroot = options.sysroot.as_deref();
+    if let Some(sysroot) = sysroot {
+        if !sysroot.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The sysroot {} is not a directory", sysroot.display()),
+            ));
+        }
+    }
+    // Absolute paths on the target system, which are under the sysroot if we have one
+    let reroot = |path: PathBuf| match sysroot {
+        Some(sysroot) => sysroot.join(path.strip_prefix("/").unwrap_or(&path)),
+        None => path,
+    };
+
     let usr_lib_arch_path = strs_to_path(&["/usr", "lib", &architecture]);
     let lib_arch_path = strs_to_path(&["/lib", &architecture]);
 
     let mut lib_dirs: Vec<PathBuf> = vec![];
 
-    // start with nix paths, this prevents version incompatibility
-    if !nix_paths_vec.is_empty() {
-        lib_dirs.extend(nix_paths_vec)
+    // start with nix paths, this prevents version incompatibility.
+    // They're for the host though, so they don't apply when cross-compiling.
+    if sysroot.is_none() {
+        lib_dirs.extend(nix_paths().iter().map(PathBuf::from));
     }
 
-    lib_dirs.extend([
-        usr_lib_arch_path,
-        lib_arch_path,
-        strs_to_path(&["/usr", "lib64"]),
-        strs_to_path(&["/usr", "lib"]),
-    ]);
+    lib_dirs.extend(
+        [
+            usr_lib_arch_path,
+            lib_arch_path,
+            strs_to_path(&["/usr", "lib64"]),
+            strs_to_path(&["/usr", "lib"]),
+        ]
+        .map(reroot),
+    );
 
     // Look for the libraries we'll need
     let libgcc_name = "libgcc_s.so.1";
     let libgcc_path = look_for_library(&lib_dirs, libgcc_name);
-
-    let crti_name = "crti.o";
-    let crti_path = look_for_library(&lib_dirs, crti_name);
-
-    let crtn_name = "crtn.o";
-    let crtn_path = look_for_library(&lib_dirs, crtn_name);
-
-    let scrt1_name = "Scrt1.o";
-    let scrt1_path = look_for_library(&lib_dirs, scrt1_name);
+    let pie = options.pie.unwrap_or(true);
+    let crt1_name = if pie { "Scrt1.o" } else { "crt1.o" };
+
+    let (crti_path, crtn_path, scrt1_path) = match &options.crt_paths {
+        Some(crt_paths) => {
+            crt_paths.check_exist()?;
+            (
+                Some(crt_paths.crti.clone()),
+                Some(crt_paths.crtn.clone()),
+                Some(crt_paths.scrt1.clone()),
+            )
+        }
+        None => (
+            look_for_library(&lib_dirs, "crti.o"),
+            look_for_library(&lib_dirs, "crtn.o"),
+            look_for_library(&lib_dirs, crt1_name),
+        ),
+    };
 
     // Unwrap all the paths at once so we can inform the user of any missing libs
     let (libgcc_path, crti_path, crtn_path, scrt1_path) =
@@ -930,7 +1785,7 @@ fn link_linux(
                 }
                 if maybe_crti.is_none() | maybe_crtn.is_none() | maybe_scrt1.is_none() {
                     eprintln!("Couldn't find the libc development files!");
-                    eprintln!("We need the files crti.o, crtn.o, and Scrt1.o");
+                    eprintln!("We need the files crti.o, crtn.o, and {crt1_name}");
                     eprintln!();
                     eprintln!("On Ubuntu/Debian execute:");
                     eprintln!("\tsudo apt install libc-dev\n");
@@ -963,8 +1818,12 @@ fn link_linux(
         scrt1_path.to_string_lossy(),
     );
 
-    let ld_linux_path = match target.architecture() {
-        Architecture::X86_64 => {
+    let ld_linux_path = match (target.architecture(), sysroot) {
+        // When cross-compiling, the dynamic linker doesn't need to exist on the host.
+        // It's checked against the sysroot below.
+        (Architecture::X86_64, Some(_)) => strs_to_path(&["/lib64", "ld-linux-x86-64.so.2"]),
+        (Architecture::Aarch64, Some(_)) => strs_to_path(&["/lib", "ld-linux-aarch64.so.1"]),
+        (Architecture::X86_64, None) => {
             // give preference to nix_path if it's defined, this prevents bugs
             if let Some(nix_glibc_path) = nix_glibc_path_opt() {
                 build_path_or_panic([
@@ -975,13 +1834,30 @@ fn link_linux(
                 build_path_or_panic(["/lib64", "ld-linux-x86-64.so.2"])
             }
         }
-        Architecture::Aarch64 => build_path_or_panic(["/lib", "ld-linux-aarch64.so.1"]),
+        (Architecture::Aarch64, None) => build_path_or_panic(["/lib", "ld-linux-aarch64.so.1"]),
         _ => internal_error!(
             "TODO gracefully handle unsupported linux architecture: {:?}",
             target.architecture()
         ),
     };
 
+    // The dynamic linker path gets embedded in the executable, so it stays a target path,
+    // but it should at least exist in the sysroot.
+    if let Some(sysroot) = sysroot {
+        let sysroot_ld_linux_path = reroot(ld_linux_path.clone());
+        // symlink_metadata, since an absolute symlink in the sysroot would point into the host
+        if fs::symlink_metadata(&sysroot_ld_linux_path).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "The sysroot {} doesn't contain the dynamic linker {}",
+                    sysroot.display(),
+                    sysroot_ld_linux_path.display()
+                ),
+            ));
+        }
+    }
+
     let ld_linux_path_str = &ld_linux_path.to_string_lossy();
 
     let (base_args, output_path) = match link_type {
@@ -1001,6 +1877,24 @@ fn link_linux(
     };
 
     let env_path = env::var("PATH").unwrap_or_else(|_| "".to_string());
+    let gc_sections = options.gc_sections.unwrap_or(true);
+
+    let needs_compiler_rt = options.compiler_rt.unwrap_or_else(|| {
+        input_paths
+            .iter()
+            .any(|path| references_compiler_rt(Path::new(path)))
+    });
+    let compiler_rt_path = if needs_compiler_rt {
+        let path = find_compiler_rt(&lib_dirs, sysroot);
+        if path.is_none() {
+            eprintln!(
+                "Warning: I couldn't find libgcc.a or compiler-rt's builtins library, so functions like __multi3 may be undefined."
+            );
+        }
+        path
+    } else {
+        None
+    };
 
     // NOTE: order of arguments to `ld` matters here!
     // The `-l` flags should go after the `.o` arguments
@@ -1017,18 +1911,47 @@ fn link_linux(
                 .filter(|(k, _)| k.starts_with("NIX_"))
                 .collect::<HashMap<String, String>>(),
         )
+        .args(gc_sections.then_some("--gc-sections"))
+        .args(strip_debug_info(options, target).then_some("--strip-debug"))
+        .args(
+            options
+                .compress_debug_sections
+                .then_some("--compress-debug-sections=zlib"),
+        )
+        .args(options.verbose.then_some("--verbose"))
+        .args(sysroot.map(|sysroot| format!("--sysroot={}", sysroot.display())))
+        .args(library_path_args(options))
+        .args(
+            sysroot
+                .iter()
+                .flat_map(|_| lib_dirs.iter())
+                .map(|dir| format!("-L{}", dir.display())),
+        )
         .args([
-            "--gc-sections",
             "--eh-frame-hdr",
             "-A",
             arch_str(target),
-            "-pie",
+            if pie { "-pie" } else { "-no-pie" },
             &crti_path_str,
             &crtn_path_str,
         ])
         .args(base_args)
+        .args(entry_symbol_args(options, "-e"))
+        .args(exported_symbols_args(
+            options,
+            OperatingSystem::Linux,
+            "--version-script",
+            temp_artifacts,
+        )?)
+        .args(
+            options
+                .map_file
+                .iter()
+                .flat_map(|path| [OsStr::new("-Map"), path.as_os_str()]),
+        )
         .args(["-dynamic-linker", ld_linux_path_str])
         .args(input_paths)
+        .args(compiler_rt_path.iter())
         .args(extra_link_flags())
         // ld.lld requires this argument, and does not accept --arch
         // .args(&["-L/usr/lib/x86_64-linux-gnu"])
@@ -1049,9 +1972,7 @@ fn link_linux(
         ]);
     debug_print_command(&ld_command);
 
-    let ld_output = ld_command.spawn()?;
-
-    Ok((ld_output, output_path))
+    Ok((vec![ld_command], output_path))
 }
 
 fn link_macos(
@@ -1059,7 +1980,9 @@ fn link_macos(
     output_path: PathBuf,
     input_paths: &[&str],
     link_type: LinkType,
-) -> io::Result<(Child, PathBuf)> {
+    options: &LinkOptions,
+    temp_artifacts: &mut TempArtifacts,
+) -> io::Result<(Vec<Command>, PathBuf)> {
     let (link_type_args, output_path) = match link_type {
         LinkType::Executable => (vec!["-execute"], output_path),
         LinkType::Dylib => {
@@ -1077,6 +2000,8 @@ fn link_macos(
         _ => target.architecture().to_string(),
     };
 
+    let dead_strip = options.gc_sections.unwrap_or(false);
+
     let mut ld_command = Command::new("ld");
 
     ld_command
@@ -1085,12 +2010,25 @@ fn link_macos(
         // Don't allow LD_ env vars to affect this
         .env_clear()
         .args(&link_type_args)
+        // NOTE: the default macOS linker doesn't support --gc-sections,
+        // but -dead_strip does the same job. It's off unless requested.
+        .args(dead_strip.then_some("-dead_strip"))
+        .args(strip_debug_info(options, target).then_some("-S"))
+        .args(options.verbose.then_some("-v"))
+        .args(entry_symbol_args(options, "-e"))
+        .args(exported_symbols_args(
+            options,
+            OperatingSystem::Mac,
+            "-exported_symbols_list",
+            temp_artifacts,
+        )?)
+        .args(
+            options
+                .map_file
+                .iter()
+                .flat_map(|path| [OsStr::new("-map"), path.as_os_str()]),
+        )
         .args([
-            // NOTE: we don't do --gc-sections on macOS because the default
-            // macOS linker doesn't support it, but it's a performance
-            // optimization, so if we ever switch to a different linker,
-            // we'd like to re-enable it on macOS!
-            // "--gc-sections",
             "-arch",
             &arch,
             // Suppress warnings, because otherwise it prints:
@@ -1102,6 +2040,7 @@ fn link_macos(
             "-macos_version_min",
             &get_macos_version(),
         ])
+        .args(library_path_args(options))
         .args(input_paths)
         .args(extra_link_flags());
 
@@ -1149,20 +2088,15 @@ fn link_macos(
 
     debug_print_command(&ld_command);
 
-    let mut ld_child = ld_command.spawn()?;
-
     match target.architecture() {
         Architecture::Aarch64 => {
-            ld_child.wait()?;
-
             let mut codesign_cmd = Command::new("codesign");
             codesign_cmd.args(["-s", "-", output_path.to_str().unwrap()]);
             debug_print_command(&codesign_cmd);
-            let codesign_child = codesign_cmd.spawn()?;
 
-            Ok((codesign_child, output_path))
+            Ok((vec![ld_command, codesign_cmd], output_path))
         }
-        _ => Ok((ld_child, output_path)),
+        _ => Ok((vec![ld_command], output_path)),
     }
 }
 
@@ -1188,16 +2122,20 @@ fn get_macos_version() -> String {
 }
 
 fn link_wasm32(
-    _target: Target,
+    target: Target,
     output_path: PathBuf,
     input_paths: &[&str],
     _link_type: LinkType,
-) -> io::Result<(Child, PathBuf)> {
-    let child = zig()
+    options: &LinkOptions,
+    _temp_artifacts: &mut TempArtifacts,
+) -> io::Result<(Vec<Command>, PathBuf)> {
+    let mut zig_command = zig();
+    zig_command
         // .env_clear()
         // .env("PATH", &env_path)
         .args(["build-exe"])
         .args(input_paths)
+        .args(library_path_args(options))
         .args([
             // include wasi libc
             // TOOD: This now compiles fine with `-lc`. That said, the output file doesn't work.
@@ -1210,28 +2148,35 @@ fn link_wasm32(
             &format!("glue::{}", find_zig_glue_path().to_str().unwrap()),
             "--deps",
             "glue",
-            "-fstrip",
             "-O",
             "ReleaseSmall",
             "-rdynamic",
             // useful for debugging
             // "-femit-llvm-ir=/home/folkertdev/roc/roc/crates/cli/tests/benchmarks/platform/host.ll",
         ])
-        .spawn()?;
+        .args(strip_debug_info(options, target).then_some("-fstrip"))
+        .args(zig_gc_sections_arg(options))
+        .args(entry_symbol_args(options, "--entry"))
+        .args(options.verbose.then_some("--verbose-link"));
 
-    Ok((child, output_path))
+    Ok((vec![zig_command], output_path))
 }
 
 fn link_windows(
     output_path: PathBuf,
     input_paths: &[&str],
     link_type: LinkType,
-) -> io::Result<(Child, PathBuf)> {
+    options: &LinkOptions,
+) -> io::Result<(Vec<Command>, PathBuf)> {
+    let strip_debug = options.strip_debug_info.unwrap_or(false);
+
     match link_type {
         LinkType::Dylib => {
-            let child = zig()
+            let mut zig_command = zig();
+            zig_command
                 .args(["build-lib"])
                 .args(input_paths)
+                .args(library_path_args(options))
                 .args([
                     "-lc",
                     &format!("-femit-bin={}", output_path.to_str().unwrap()),
@@ -1245,14 +2190,19 @@ fn link_windows(
                     "Debug",
                     "-dynamic",
                 ])
-                .spawn()?;
+                .args(strip_debug.then_some("-fstrip"))
+                .args(zig_gc_sections_arg(options))
+                .args(entry_symbol_args(options, "--entry"))
+                .args(options.verbose.then_some("--verbose-link"));
 
-            Ok((child, output_path))
+            Ok((vec![zig_command], output_path))
         }
         LinkType::Executable => {
-            let child = zig()
+            let mut zig_command = zig();
+            zig_command
                 .args(["build-exe"])
                 .args(input_paths)
+                .args(library_path_args(options))
                 .args([
                     "-target",
                     "native",
@@ -1261,9 +2211,12 @@ fn link_windows(
                     "-lc",
                     &format!("-femit-bin={}", output_path.to_str().unwrap()),
                 ])
-                .spawn()?;
+                .args(strip_debug.then_some("-fstrip"))
+                .args(zig_gc_sections_arg(options))
+                .args(entry_symbol_args(options, "--entry"))
+                .args(options.verbose.then_some("--verbose-link"));
 
-            Ok((child, output_path))
+            Ok((vec![zig_command], output_path))
         }
         LinkType::None => todo!(),
     }
@@ -1462,3 +2415,293 @@ fn debug_print_command(_cmd: &Command) {
         print_command_str(&stringify_command(_cmd, false));
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_targets_have_a_linker() {
+        let supported = supported_targets();
+        assert!(!supported.is_empty());
+
+        for target in supported {
+            assert!(link_fn(target).is_some(), "{target:?}");
+
+            // link_linux can only find the dynamic linker for these, and falls back to zig for x86_32
+            if target.operating_system() == OperatingSystem::Linux {
+                assert!(
+                    matches!(
+                        target.architecture(),
+                        Architecture::X86_32 | Architecture::X86_64 | Architecture::Aarch64
+                    ),
+                    "{target:?}"
+                );
+            }
+        }
+    }
+
+    /// The arguments of the first linker command for an executable, with any file as the input
+    fn linker_args(target: Target, options: &LinkOptions) -> io::Result<Vec<String>> {
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let output = env::temp_dir().join("roc_link_test");
+        let (commands, _, _) =
+            link_commands(target, output, &[input], LinkType::Executable, options)?;
+        Ok(commands[0]
+            .get_args()
+            .map(|arg| arg.to_str().unwrap().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn verbose_is_passed_to_the_linker() {
+        let args = |verbose| {
+            let options = LinkOptions {
+                verbose,
+                ..Default::default()
+            };
+            linker_args(Target::LinuxX32, &options).unwrap()
+        };
+
+        assert!(args(true).contains(&"--verbose-link".to_string()));
+        assert!(!args(false).contains(&"--verbose-link".to_string()));
+    }
+
+    #[test]
+    fn debug_info_is_kept_unless_stripping_is_requested() {
+        let strips = |args: Vec<String>| {
+            args.iter().any(|arg| {
+                matches!(
+                    arg.as_str(),
+                    "-fstrip" | "-s" | "-S" | "--strip-all" | "--strip-debug"
+                )
+            })
+        };
+
+        let args = |strip_debug_info| {
+            let options = LinkOptions {
+                strip_debug_info,
+                ..Default::default()
+            };
+            linker_args(Target::LinuxX32, &options).unwrap()
+        };
+        assert!(!strips(args(None)));
+        assert!(!strips(args(Some(false))));
+        assert!(strips(args(Some(true))));
+
+        // Only wasm is stripped by default
+        let default = LinkOptions::default();
+        assert!(strip_debug_info(&default, Target::Wasm32));
+        assert!(!strip_debug_info(&default, Target::LinuxX64));
+        assert!(!strip_debug_info(&default, Target::MacArm64));
+    }
+
+    #[test]
+    fn compressing_debug_info_is_checked() {
+        let compress = |strip_debug_info| LinkOptions {
+            compress_debug_sections: true,
+            strip_debug_info,
+            ..Default::default()
+        };
+
+        let stripped = linker_args(Target::LinuxX64, &compress(Some(true))).unwrap_err();
+        assert_eq!(stripped.kind(), io::ErrorKind::InvalidInput);
+
+        let zig = linker_args(Target::LinuxX32, &compress(None)).unwrap_err();
+        assert_eq!(zig.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn library_paths_are_searched_first() {
+        let existing = env::temp_dir();
+        let missing = existing.join("roc_link_test_missing_lib_dir");
+        let options = LinkOptions {
+            library_paths: vec![existing.clone(), missing.clone()],
+            ..Default::default()
+        };
+        let args = linker_args(Target::LinuxX32, &options).unwrap();
+
+        let flag = format!("-L{}", existing.display());
+        let position = |arg: &str| args.iter().position(|a| a == arg);
+        assert!(position(&flag).unwrap() < position("-lc").unwrap());
+        assert!(!args.contains(&format!("-L{}", missing.display())));
+    }
+
+    #[test]
+    fn pie_is_only_passed_to_zig_when_set() {
+        let args = |pie| {
+            let options = LinkOptions {
+                pie,
+                ..Default::default()
+            };
+            linker_args(Target::LinuxX32, &options).unwrap()
+        };
+        let has = |args: &[String], flag: &str| args.iter().any(|arg| arg == flag);
+
+        assert!(has(&args(Some(true)), "-fPIE"));
+        assert!(has(&args(Some(false)), "-fno-PIE"));
+        let default = args(None);
+        assert!(!has(&default, "-fPIE") && !has(&default, "-fno-PIE"));
+    }
+
+    #[test]
+    fn glibc_versions() {
+        assert_eq!(
+            "2.2.5".parse(),
+            Ok(GlibcVersion {
+                major: 2,
+                minor: 2,
+                patch: 5
+            })
+        );
+        let v2_17: GlibcVersion = "2.17".parse().unwrap();
+        assert_eq!(v2_17.to_string(), "2.17");
+        assert!("2.2.5".parse::<GlibcVersion>().unwrap() < v2_17);
+        assert!("2".parse::<GlibcVersion>().is_err());
+        assert!("2.x".parse::<GlibcVersion>().is_err());
+
+        let options = LinkOptions {
+            glibc_version: Some(v2_17),
+            ..Default::default()
+        };
+        let err = linker_args(Target::LinuxX32, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+    fn glibc_versions_of_this_test_binary() {
+        let exe = env::current_exe().unwrap();
+        let needed = glibc_versions_needed(&fs::read(&exe).unwrap()).unwrap();
+        let newest = *needed.keys().next_back().unwrap();
+
+        assert!(check_glibc_version(&exe, newest).is_ok());
+        let too_old = GlibcVersion {
+            major: 2,
+            minor: 0,
+            patch: 0,
+        };
+        let message = check_glibc_version(&exe, too_old).unwrap_err().to_string();
+        assert!(
+            message.contains(&format!("glibc {newest} for ")),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn surgical_link_checks_the_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let host = dir
+            .path()
+            .join(roc_linker::preprocessed_host_filename(Target::LinuxX64));
+        let app = dir.path().join("app.o");
+        let dest = dir.path().join("app");
+        fs::write(&host, b"host").unwrap();
+        fs::write(&app, b"app").unwrap();
+
+        let unsupported = surgical_link(Target::Wasm32, &host, &app, &dest).unwrap_err();
+        assert_eq!(unsupported.kind(), io::ErrorKind::Unsupported);
+
+        let no_metadata = surgical_link(Target::LinuxX64, &host, &app, &dest).unwrap_err();
+        assert_eq!(no_metadata.kind(), io::ErrorKind::NotFound);
+        assert!(!dest.exists());
+    }
+
+    /// An ELF object file that calls these functions
+    fn object_calling(dir: &Path, name: &str, functions: &[&str]) -> PathBuf {
+        use object::write::{Object, Symbol, SymbolSection};
+        use object::{
+            Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolKind, SymbolScope,
+        };
+
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        for function in functions {
+            obj.add_symbol(Symbol {
+                name: function.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Unknown,
+                weak: false,
+                section: SymbolSection::Undefined,
+                flags: SymbolFlags::None,
+            });
+        }
+
+        let path = dir.join(name);
+        fs::write(&path, obj.write().unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn compiler_rt_references_are_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let i128_math = object_calling(dir.path(), "i128.o", &["roc_alloc", "__multi3"]);
+        let plain = object_calling(dir.path(), "plain.o", &["roc_alloc", "memcpy"]);
+
+        assert!(references_compiler_rt(&i128_math));
+        assert!(!references_compiler_rt(&plain));
+        assert!(!references_compiler_rt(&dir.path().join("missing.o")));
+    }
+
+    #[test]
+    fn exported_symbols_files() {
+        let symbols = ["roc_init".to_string(), "roc_call".to_string()];
+
+        assert_eq!(
+            exported_symbols_file_contents(OperatingSystem::Linux, &symbols),
+            "{\n  global:\n    roc_init;\n    roc_call;\n  local:\n    *;\n};\n"
+        );
+        assert_eq!(
+            exported_symbols_file_contents(OperatingSystem::Mac, &symbols),
+            "_roc_init\n_roc_call\n"
+        );
+    }
+
+    #[test]
+    fn temp_artifacts_are_removed_when_dropped() {
+        let mut temp_artifacts = TempArtifacts::new();
+        let first = temp_artifacts.write("first", b"1").unwrap();
+        let second = temp_artifacts.write("second", b"2").unwrap();
+        assert_eq!(first.parent(), second.parent());
+        assert_eq!(fs::read(&second).unwrap(), b"2");
+
+        let dir = first.parent().unwrap().to_path_buf();
+        drop(temp_artifacts);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn temp_artifact_paths_are_not_written() {
+        let mut temp_artifacts = TempArtifacts::new();
+        let path = temp_artifacts.path("roc_output").unwrap();
+        let dir = path.parent().unwrap().to_path_buf();
+        assert!(dir.is_dir());
+        assert!(!path.exists());
+
+        // e.g. the linker's output
+        fs::write(&path, b"\x7fELF").unwrap();
+        drop(temp_artifacts);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn kept_temp_artifacts_are_left_in_place() {
+        assert_eq!(TempArtifacts::new().keep(), None);
+
+        let mut temp_artifacts = TempArtifacts::new();
+        let path = temp_artifacts.write("script", b"{};").unwrap();
+        let dir = temp_artifacts.keep().unwrap();
+        assert_eq!(path.parent(), Some(dir.as_path()));
+        assert!(path.is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exported_symbol_names() {
+        assert!(is_plain_symbol("roc__mainForHost_1_exposed"));
+        assert!(!is_plain_symbol(""));
+        assert!(!is_plain_symbol("a; local: *"));
+    }
+}
@@ -0,0 +1,358 @@
+//! Turn the linker's error messages into reports that render like the compiler's own.
+//! Only the common classes of errors from GNU `ld` and `lld` (including `wasm-ld`) are recognized.
+//! The raw output is still available in `LinkOutput::stderr` for everything else.
+use roc_problem::Severity;
+use roc_reporting::report::{Annotation, Report, RocDocAllocator};
+use std::path::PathBuf;
+use ven_pretty::DocAllocator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkProblem {
+    /// `undefined reference to` (ld) or `undefined symbol:` (lld)
+    UndefinedSymbol {
+        symbol: String,
+        /// The files or functions that refer to it, as the linker wrote them
+        referenced_from: Vec<String>,
+    },
+    /// `multiple definition of` (ld) or `duplicate symbol:` (lld)
+    DuplicateSymbol {
+        symbol: String,
+        defined_in: Vec<String>,
+    },
+    /// `cannot find -l` (ld) or `unable to find library -l` (lld)
+    MissingLibrary { library: String },
+    /// Any other line that lld marked as an error
+    Other(String),
+}
+
+/// Find the problems in a linker's stderr. Repeated problems with the same symbol are merged.
+pub fn parse_linker_errors(stderr: &str) -> Vec<LinkProblem> {
+    let mut problems: Vec<LinkProblem> = Vec::new();
+    let mut lines = stderr.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        // lld puts the details on the following lines, each starting with `>>>`
+        let mut details = Vec::new();
+        while let Some(detail) = lines.peek().and_then(|l| l.strip_prefix(">>>")) {
+            details.push(detail.trim());
+            lines.next();
+        }
+
+        if let Some(symbol) = after(line, "undefined symbol: ") {
+            let referenced_from = lld_locations(&details, "referenced by ");
+            add_undefined(&mut problems, symbol, referenced_from);
+        } else if let Some(rest) = after(line, "undefined reference to `") {
+            let referenced_from = ld_location(line, "undefined reference to");
+            add_undefined(
+                &mut problems,
+                until_quote(rest),
+                referenced_from.into_iter().collect(),
+            );
+        } else if let Some(symbol) = after(line, "duplicate symbol: ") {
+            let defined_in = lld_locations(&details, "defined at ");
+            add_duplicate(&mut problems, symbol, defined_in);
+        } else if let Some(rest) = after(line, "multiple definition of `") {
+            let mut defined_in: Vec<String> = ld_location(line, "multiple definition of")
+                .into_iter()
+                .collect();
+            if let Some((_, first)) = rest.split_once("; ") {
+                defined_in.extend(ld_location(first, "first defined here"));
+            }
+            add_duplicate(&mut problems, until_quote(rest), defined_in);
+        } else if let Some(rest) =
+            after(line, "cannot find -l").or_else(|| after(line, "unable to find library -l"))
+        {
+            let library = rest.split(':').next().unwrap_or(rest).trim().to_string();
+            let problem = LinkProblem::MissingLibrary { library };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        } else if let Some(message) = after(line, "error: ") {
+            // gcc and clang always end with this after the linker's own errors
+            if !message.ends_with("returned 1 exit status") {
+                problems.push(LinkProblem::Other(message.to_string()));
+            }
+        }
+    }
+
+    problems
+}
+
+fn after<'a>(line: &'a str, pattern: &str) -> Option<&'a str> {
+    line.find(pattern)
+        .map(|i| line[i + pattern.len()..].trim_end())
+}
+
+/// ld quotes symbols like `this'
+fn until_quote(text: &str) -> &str {
+    text.split('\'').next().unwrap_or(text)
+}
+
+/// The file before a message like `main.c:(.text+0x5): undefined reference to`,
+/// without the linker's own `/usr/bin/ld: ` prefix or the section and offset
+fn ld_location(line: &str, message: &str) -> Option<String> {
+    let before = &line[..line.find(message)?];
+    let before = before.trim_end().trim_end_matches(':');
+    let file = before.split(":(").next().unwrap_or(before);
+    let file = file.rsplit(": ").next().unwrap_or(file).trim();
+    (!file.is_empty()).then(|| file.to_string())
+}
+
+/// lld's `>>> referenced by main.c` lines, skipping the `>>>   main.o:(main)` lines under them
+fn lld_locations(details: &[&str], prefix: &str) -> Vec<String> {
+    details
+        .iter()
+        .filter_map(|detail| detail.strip_prefix(prefix))
+        .map(|location| location.trim().to_string())
+        .collect()
+}
+
+fn add_undefined(problems: &mut Vec<LinkProblem>, symbol: &str, referenced_from: Vec<String>) {
+    for problem in problems.iter_mut() {
+        if let LinkProblem::UndefinedSymbol {
+            symbol: existing,
+            referenced_from: existing_refs,
+        } = problem
+        {
+            if existing == symbol {
+                merge(existing_refs, referenced_from);
+                return;
+            }
+        }
+    }
+    problems.push(LinkProblem::UndefinedSymbol {
+        symbol: symbol.to_string(),
+        referenced_from,
+    });
+}
+
+fn add_duplicate(problems: &mut Vec<LinkProblem>, symbol: &str, defined_in: Vec<String>) {
+    for problem in problems.iter_mut() {
+        if let LinkProblem::DuplicateSymbol {
+            symbol: existing,
+            defined_in: existing_defs,
+        } = problem
+        {
+            if existing == symbol {
+                merge(existing_defs, defined_in);
+                return;
+            }
+        }
+    }
+    problems.push(LinkProblem::DuplicateSymbol {
+        symbol: symbol.to_string(),
+        defined_in,
+    });
+}
+
+fn merge(existing: &mut Vec<String>, new: Vec<String>) {
+    for item in new {
+        if !existing.contains(&item) {
+            existing.push(item);
+        }
+    }
+}
+
+/// `filename` is the file being linked, shown in the report's header
+pub fn to_link_problem_report<'b>(
+    alloc: &'b RocDocAllocator<'b>,
+    problem: &LinkProblem,
+    filename: PathBuf,
+) -> Report<'b> {
+    let locations = |names: &[String]| {
+        alloc.stack(
+            names
+                .iter()
+                .map(|name| alloc.string(name.clone()).indent(4)),
+        )
+    };
+
+    let (title, doc) = match problem {
+        LinkProblem::UndefinedSymbol {
+            symbol,
+            referenced_from,
+        } => {
+            let mut lines = vec![
+                alloc.reflow("The linker couldn't find a definition for this symbol:"),
+                alloc
+                    .string(symbol.clone())
+                    .annotate(Annotation::Symbol)
+                    .indent(4),
+            ];
+            if !referenced_from.is_empty() {
+                lines.push(alloc.reflow("It's used in:"));
+                lines.push(locations(referenced_from));
+            }
+            lines.push(alloc.concat([
+                alloc.tip(),
+                alloc.reflow(
+                    "If the platform's host is supposed to provide it, check that the host defines it and that it was built for this target.",
+                ),
+            ]));
+            ("UNDEFINED SYMBOL", alloc.stack(lines))
+        }
+        LinkProblem::DuplicateSymbol { symbol, defined_in } => {
+            let mut lines = vec![
+                alloc.reflow("The linker found more than one definition of this symbol:"),
+                alloc
+                    .string(symbol.clone())
+                    .annotate(Annotation::Symbol)
+                    .indent(4),
+            ];
+            if !defined_in.is_empty() {
+                lines.push(alloc.reflow("It's defined in:"));
+                lines.push(locations(defined_in));
+            }
+            lines.push(alloc.concat([
+                alloc.tip(),
+                alloc.reflow("Maybe the same library or object file is being linked in twice?"),
+            ]));
+            ("DUPLICATE SYMBOL", alloc.stack(lines))
+        }
+        LinkProblem::MissingLibrary { library } => {
+            let doc = alloc.stack([
+                alloc.reflow("The linker couldn't find this library:"),
+                alloc
+                    .string(library.clone())
+                    .annotate(Annotation::Module)
+                    .indent(4),
+                alloc.reflow(
+                    "Is it installed? If it is, maybe it's somewhere the linker doesn't search?",
+                ),
+            ]);
+            ("MISSING LIBRARY", doc)
+        }
+        LinkProblem::Other(message) => {
+            let doc = alloc.stack([
+                alloc.reflow("The linker failed with this error:"),
+                alloc.string(message.clone()).indent(4),
+            ]);
+            ("LINKER ERROR", doc)
+        }
+    };
+
+    Report {
+        title: title.to_string(),
+        filename,
+        doc,
+        severity: Severity::Fatal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use roc_module::symbol::{Interns, ModuleIds};
+
+    #[test]
+    fn ld_errors() {
+        let stderr = indoc!(
+            r#"
+            /usr/bin/ld: app.o: in function `main':
+            app.c:(.text+0x9): undefined reference to `roc_alloc'
+            /usr/bin/ld: host.o:(.text+0x20): undefined reference to `roc_alloc'
+            /usr/bin/ld: b.o: in function `roc_fx_stdout':
+            b.c:(.text+0x0): multiple definition of `roc_fx_stdout'; a.o:a.c:(.text+0x0): first defined here
+            /usr/bin/ld: cannot find -lssl: No such file or directory
+            collect2: error: ld returned 1 exit status
+            "#
+        );
+
+        assert_eq!(
+            parse_linker_errors(stderr),
+            vec![
+                LinkProblem::UndefinedSymbol {
+                    symbol: "roc_alloc".into(),
+                    referenced_from: vec!["app.c".into(), "host.o".into()],
+                },
+                LinkProblem::DuplicateSymbol {
+                    symbol: "roc_fx_stdout".into(),
+                    defined_in: vec!["b.c".into(), "a.o:a.c".into()],
+                },
+                LinkProblem::MissingLibrary {
+                    library: "ssl".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lld_errors() {
+        let stderr = indoc!(
+            r#"
+            ld.lld: error: undefined symbol: roc_alloc
+            >>> referenced by app.c
+            >>>               app.o:(main)
+            >>> referenced by host.c
+            >>>               host.o:(roc_main)
+            ld.lld: error: duplicate symbol: roc_fx_stdout
+            >>> defined at a.c
+            >>>            a.o:(roc_fx_stdout)
+            >>> defined at b.c
+            >>>            b.o:(.text+0x0)
+            ld.lld: error: unable to find library -lssl
+            wasm-ld: error: app.o: undefined symbol: roc_dealloc
+            ld.lld: error: too many errors emitted, stopping now
+            "#
+        );
+
+        assert_eq!(
+            parse_linker_errors(stderr),
+            vec![
+                LinkProblem::UndefinedSymbol {
+                    symbol: "roc_alloc".into(),
+                    referenced_from: vec!["app.c".into(), "host.c".into()],
+                },
+                LinkProblem::DuplicateSymbol {
+                    symbol: "roc_fx_stdout".into(),
+                    defined_in: vec!["a.c".into(), "b.c".into()],
+                },
+                LinkProblem::MissingLibrary {
+                    library: "ssl".into()
+                },
+                LinkProblem::UndefinedSymbol {
+                    symbol: "roc_dealloc".into(),
+                    referenced_from: vec![],
+                },
+                LinkProblem::Other("too many errors emitted, stopping now".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_symbol_report() {
+        let src_lines: Vec<&str> = Vec::new();
+        let mut module_ids = ModuleIds::default();
+        let module_id = module_ids.get_or_insert(&"Main".into());
+        let interns = Interns::default();
+        let alloc = RocDocAllocator::new(&src_lines, module_id, &interns);
+
+        let problem = LinkProblem::UndefinedSymbol {
+            symbol: "roc_alloc".into(),
+            referenced_from: vec!["host.c".into()],
+        };
+        let mut buf = String::new();
+        to_link_problem_report(&alloc, &problem, PathBuf::from("app")).render_ci(&mut buf, &alloc);
+
+        assert_eq!(
+            buf,
+            indoc!(
+                r#"
+                ── UNDEFINED SYMBOL in app ─────────────────────────────────────────────────────
+
+                The linker couldn't find a definition for this symbol:
+
+                    `roc_alloc`
+
+                It's used in:
+
+                    host.c
+
+                Tip: If the platform's host is supposed to provide it, check that the
+                host defines it and that it was built for this target."#
+            )
+            .trim_start()
+        );
+    }
+}
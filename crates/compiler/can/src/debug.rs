@@ -1,6 +1,8 @@
 mod pretty_print;
+mod render_problems;
 
 pub use pretty_print::pretty_print_declarations;
 pub use pretty_print::pretty_print_def;
 pub use pretty_print::pretty_write_declarations;
 pub use pretty_print::Ctx as PPCtx;
+pub use render_problems::render_problems;
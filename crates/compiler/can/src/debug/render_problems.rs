@@ -0,0 +1,274 @@
+//! Plain-text rendering of canonicalization problems, underlining each one's region in the source.
+//! The full error messages live in roc_reporting; this is for tools and tests that just want
+//! to see where the problems are.
+
+use roc_problem::can::Problem;
+use roc_problem::Severity;
+use roc_region::all::{LineColumnRegion, LineInfo};
+use std::fmt::Write;
+
+/// Render each problem as a header line followed by the source lines its region covers,
+/// with the region underlined by carets, e.g.
+///
+/// ```text
+/// warning: UnusedDef
+///  --> 3:1
+///   |
+/// 3 | unused = 1
+///   | ^^^^^^
+/// ```
+///
+/// Lines and columns are 1-based, like in the compiler's own error messages.
+pub fn render_problems(problems: &[Problem], src: &str) -> String {
+    let line_info = LineInfo::new(src);
+    let lines: Vec<&str> = src.lines().collect();
+    let mut buf = String::new();
+
+    for problem in problems {
+        let severity = match problem.severity() {
+            Severity::Warning => "warning",
+            Severity::RuntimeError | Severity::Fatal => "error",
+        };
+        writeln!(buf, "{severity}: {}", problem_name(problem)).unwrap();
+
+        if let Some(region) = problem.region() {
+            render_region(&mut buf, &lines, line_info.convert_region(region));
+        }
+        buf.push('\n');
+    }
+
+    buf
+}
+
+/// The name of the Problem variant, e.g. "UnusedDef"
+fn problem_name(problem: &Problem) -> &'static str {
+    match problem {
+        Problem::UnusedDef(..) => "UnusedDef",
+        Problem::UnusedImport(..) => "UnusedImport",
+        Problem::UnusedModuleImport(..) => "UnusedModuleImport",
+        Problem::ExposedButNotDefined(..) => "ExposedButNotDefined",
+        Problem::UnknownGeneratesWith(..) => "UnknownGeneratesWith",
+        Problem::ImportNameConflict { .. } => "ImportNameConflict",
+        Problem::ExplicitBuiltinImport(..) => "ExplicitBuiltinImport",
+        Problem::ExplicitBuiltinTypeImport(..) => "ExplicitBuiltinTypeImport",
+        Problem::ImportShadowsSymbol { .. } => "ImportShadowsSymbol",
+        Problem::UnusedArgument(..) => "UnusedArgument",
+        Problem::UnusedBranchDef(..) => "UnusedBranchDef",
+        Problem::DefsOnlyUsedInRecursion(..) => "DefsOnlyUsedInRecursion",
+        Problem::PrecedenceProblem(..) => "PrecedenceProblem",
+        Problem::UnsupportedPattern(..) => "UnsupportedPattern",
+        Problem::Shadowing { .. } => "Shadowing",
+        Problem::CyclicAlias(..) => "CyclicAlias",
+        Problem::BadRecursion(..) => "BadRecursion",
+        Problem::PhantomTypeArgument { .. } => "PhantomTypeArgument",
+        Problem::UndeclaredTypeVar { .. } => "UndeclaredTypeVar",
+        Problem::DuplicateRecordFieldValue { .. } => "DuplicateRecordFieldValue",
+        Problem::DuplicateRecordFieldType { .. } => "DuplicateRecordFieldType",
+        Problem::InvalidOptionalValue { .. } => "InvalidOptionalValue",
+        Problem::DuplicateTag { .. } => "DuplicateTag",
+        Problem::RuntimeError(..) => "RuntimeError",
+        Problem::SignatureDefMismatch { .. } => "SignatureDefMismatch",
+        Problem::InvalidAliasRigid { .. } => "InvalidAliasRigid",
+        Problem::InvalidInterpolation(..) => "InvalidInterpolation",
+        Problem::InvalidHexadecimal(..) => "InvalidHexadecimal",
+        Problem::InvalidUnicodeCodePt(..) => "InvalidUnicodeCodePt",
+        Problem::NestedDatatype { .. } => "NestedDatatype",
+        Problem::InvalidExtensionType { .. } => "InvalidExtensionType",
+        Problem::AbilityHasTypeVariables { .. } => "AbilityHasTypeVariables",
+        Problem::ImplementsClauseIsNotAbility { .. } => "ImplementsClauseIsNotAbility",
+        Problem::IllegalImplementsClause { .. } => "IllegalImplementsClause",
+        Problem::DuplicateImplementsAbility { .. } => "DuplicateImplementsAbility",
+        Problem::AbilityMemberMissingImplementsClause { .. } => {
+            "AbilityMemberMissingImplementsClause"
+        }
+        Problem::AbilityMemberMultipleBoundVars { .. } => "AbilityMemberMultipleBoundVars",
+        Problem::AbilityNotOnToplevel { .. } => "AbilityNotOnToplevel",
+        Problem::AbilityUsedAsType(..) => "AbilityUsedAsType",
+        Problem::NestedSpecialization(..) => "NestedSpecialization",
+        Problem::IllegalDerivedAbility(..) => "IllegalDerivedAbility",
+        Problem::ImplementationNotFound { .. } => "ImplementationNotFound",
+        Problem::NotAnAbilityMember { .. } => "NotAnAbilityMember",
+        Problem::OptionalAbilityImpl { .. } => "OptionalAbilityImpl",
+        Problem::QualifiedAbilityImpl { .. } => "QualifiedAbilityImpl",
+        Problem::AbilityImplNotIdent { .. } => "AbilityImplNotIdent",
+        Problem::DuplicateImpl { .. } => "DuplicateImpl",
+        Problem::NotAnAbility(..) => "NotAnAbility",
+        Problem::ImplementsNonRequired { .. } => "ImplementsNonRequired",
+        Problem::DoesNotImplementAbility { .. } => "DoesNotImplementAbility",
+        Problem::NotBoundInAllPatterns { .. } => "NotBoundInAllPatterns",
+        Problem::NoIdentifiersIntroduced(..) => "NoIdentifiersIntroduced",
+        Problem::OverloadedSpecialization { .. } => "OverloadedSpecialization",
+        Problem::UnnecessaryOutputWildcard { .. } => "UnnecessaryOutputWildcard",
+        Problem::MultipleListRestPattern { .. } => "MultipleListRestPattern",
+        Problem::BadTypeArguments { .. } => "BadTypeArguments",
+        Problem::UnappliedCrash { .. } => "UnappliedCrash",
+        Problem::OverAppliedCrash { .. } => "OverAppliedCrash",
+        Problem::FileProblem { .. } => "FileProblem",
+        Problem::WildcardNotAllowed { .. } => "WildcardNotAllowed",
+        Problem::UnderscoreNotAllowed { .. } => "UnderscoreNotAllowed",
+        Problem::UseOfDeprecated { .. } => "UseOfDeprecated",
+        Problem::QualifiedLookupShadowedByLocal { .. } => "QualifiedLookupShadowedByLocal",
+    }
+}
+
+fn render_region(buf: &mut String, lines: &[&str], region: LineColumnRegion) {
+    let first = region.start.line as usize;
+    let last = region.end.line as usize;
+    let gutter = (last + 1).to_string().len();
+    let blank = " ".repeat(gutter);
+
+    let first_line = lines.get(first).copied().unwrap_or("");
+    writeln!(
+        buf,
+        "{blank}--> {}:{}",
+        region.start.line + 1,
+        char_column(first_line, region.start.column as usize) + 1
+    )
+    .unwrap();
+    writeln!(buf, "{blank} |").unwrap();
+
+    for line_index in first..=last {
+        let line = lines.get(line_index).copied().unwrap_or("");
+        writeln!(buf, "{:>gutter$} | {line}", line_index + 1).unwrap();
+
+        // Regions continue onto later lines after their indentation, so that isn't underlined
+        let start = if line_index == first {
+            region.start.column as usize
+        } else {
+            line.len() - line.trim_start().len()
+        };
+        let end = if line_index == last {
+            region.end.column as usize
+        } else {
+            line.len()
+        };
+        let (start, end) = (char_column(line, start), char_column(line, end));
+        // Always show at least one caret, so empty regions are still visible
+        let carets = "^".repeat(end.saturating_sub(start).max(1));
+        writeln!(buf, "{blank} | {}{carets}", " ".repeat(start)).unwrap();
+    }
+}
+
+/// The column of a byte offset in the line, counted in characters, since that's how it's displayed.
+/// Offsets past the end of the line, e.g. its newline, count one column per byte.
+fn char_column(line: &str, byte_offset: usize) -> usize {
+    match line.get(..byte_offset) {
+        Some(before) => before.chars().count(),
+        None if byte_offset >= line.len() => line.chars().count() + (byte_offset - line.len()),
+        // Not at a character boundary, which a region shouldn't be
+        None => line
+            .char_indices()
+            .take_while(|(index, _)| *index < byte_offset)
+            .count(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use roc_module::symbol::Symbol;
+    use roc_region::all::{Position, Region};
+
+    fn region(start: u32, end: u32) -> Region {
+        Region::new(Position::new(start), Position::new(end))
+    }
+
+    #[test]
+    fn single_line_regions() {
+        let src = "x = 1\n\nunused = 2\n";
+        let problems = [
+            Problem::UnusedDef(Symbol::LIST_MAP, region(7, 13)),
+            Problem::InvalidHexadecimal(region(4, 5)),
+        ];
+
+        assert_eq!(
+            render_problems(&problems, src),
+            indoc!(
+                "
+                warning: UnusedDef
+                 --> 3:1
+                  |
+                3 | unused = 2
+                  | ^^^^^^
+
+                error: InvalidHexadecimal
+                 --> 1:5
+                  |
+                1 | x = 1
+                  |     ^
+
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn multi_line_region() {
+        let src = "a = 1\nb = when x is\n    _ -> 2\nc = 3\n";
+        // from `when` on line 2 through `2` on line 3
+        let problems = [Problem::NoIdentifiersIntroduced(region(10, 30))];
+
+        assert_eq!(
+            render_problems(&problems, src),
+            indoc!(
+                "
+                warning: NoIdentifiersIntroduced
+                 --> 2:5
+                  |
+                2 | b = when x is
+                  |     ^^^^^^^^^
+                3 |     _ -> 2
+                  |     ^^^^^^
+
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn gutter_fits_the_last_line_number() {
+        let src = "\n".repeat(8) + "long = [\n  1,\n]\n";
+        let problems = [Problem::NoIdentifiersIntroduced(region(15, 22))];
+
+        assert_eq!(
+            render_problems(&problems, &src),
+            indoc!(
+                "
+                warning: NoIdentifiersIntroduced
+                  --> 9:8
+                   |
+                 9 | long = [
+                   |        ^
+                10 |   1,
+                   |   ^^
+                11 | ]
+                   | ^
+
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let src = "s = \"héllo\" bad\n";
+        let start = src.find("bad").unwrap() as u32;
+        let problems = [Problem::InvalidHexadecimal(region(start, start + 3))];
+
+        assert_eq!(
+            render_problems(&problems, src),
+            indoc!(
+                "
+                error: InvalidHexadecimal
+                 --> 1:13
+                  |
+                1 | s = \"héllo\" bad
+                  |             ^^^
+
+                "
+            )
+        );
+    }
+}
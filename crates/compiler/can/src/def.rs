@@ -1361,6 +1361,7 @@ fn canonicalize_type_defs<'a>(
                     derived_defs,
                 }) = alias_and_derives
                 {
+                    env.insert_home_opaque(name.value);
                     aliases.insert(name.value, opaque_def);
                     all_derived_defs.extend(derived_defs);
                 }
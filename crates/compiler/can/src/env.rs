@@ -5,10 +5,157 @@ use crate::scope::Scope;
 use bumpalo::Bump;
 use roc_collections::{MutMap, VecSet};
 use roc_module::ident::{Ident, ModuleName};
-use roc_module::symbol::{IdentIdsByModule, ModuleId, PQModuleName, PackageModuleIds, Symbol};
+use roc_module::symbol::{
+    IdentId, IdentIdsByModule, ModuleId, PQModuleName, PackageModuleIds, Symbol,
+};
 use roc_problem::can::{Problem, RuntimeError};
+use roc_problem::Severity;
 use roc_region::all::{Loc, Region};
 
+/// Qualified lookups into other modules, keyed on the module and the ident that was looked up.
+pub type QualifiedLookupCache = MutMap<(ModuleId, Ident), Symbol>;
+
+/// The idents each module has marked as deprecated, with a note on what to use instead.
+pub type DeprecatedIdentsByModule = MutMap<ModuleId, MutMap<IdentId, String>>;
+
+/// Modules a package exposes but doesn't define, keyed on the name they're exposed under,
+/// e.g. a platform that re-exports `http.Http` as `pf.Http`. The origin may itself be a re-export.
+pub type ReExportedModules<'a> = MutMap<PQModuleName<'a>, PQModuleName<'a>>;
+
+/// Everything an `Env` accumulated while canonicalizing, from `Env::finish`.
+#[derive(Debug)]
+pub struct CanonicalizedEnvOutput {
+    pub home: ModuleId,
+    pub problems: Vec<Problem>,
+    pub closures: MutMap<Symbol, References>,
+    pub qualified_value_lookups: VecSet<Symbol>,
+    pub qualified_type_lookups: VecSet<Symbol>,
+    pub top_level_symbols: VecSet<Symbol>,
+    /// Empty unless `Env::record_resolved_lookups` or `Env::seed_lookup_cache` was called
+    pub resolved_qualified_lookups: QualifiedLookupCache,
+    /// Empty unless `Env::record_qualified_references` was called
+    pub qualified_references: Vec<(Symbol, Region)>,
+}
+
+/// How much detail `Env` puts in the errors from failed lookups
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LookupErrors {
+    /// Include suggestions, like the names that are in scope, for reports that people read
+    #[default]
+    Explain,
+    /// Leave the suggestion lists empty, for tools that don't show them.
+    /// This skips collecting them, which can be slow in big modules.
+    Quiet,
+}
+
+/// Builds an `Env` from named fields, which is harder to get wrong than `Env::new`'s
+/// positional arguments. Only `opt_shorthand` is optional.
+#[derive(Debug, Default)]
+pub struct EnvBuilder<'a> {
+    arena: Option<&'a Bump>,
+    home: Option<ModuleId>,
+    module_path: Option<&'a Path>,
+    dep_idents: Option<&'a IdentIdsByModule>,
+    qualified_module_ids: Option<&'a PackageModuleIds<'a>>,
+    opt_shorthand: Option<&'a str>,
+    deprecated_idents: Option<&'a DeprecatedIdentsByModule>,
+    lookup_errors: LookupErrors,
+    re_exported_modules: Option<&'a ReExportedModules<'a>>,
+    note_shadowing_locals: bool,
+}
+
+/// `EnvBuilder::build` was called before a required field was set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingEnvField(pub &'static str);
+
+impl std::fmt::Display for MissingEnvField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Env can't be built without setting `{}`", self.0)
+    }
+}
+
+impl<'a> EnvBuilder<'a> {
+    pub fn arena(mut self, arena: &'a Bump) -> Self {
+        self.arena = Some(arena);
+        self
+    }
+
+    pub fn home(mut self, home: ModuleId) -> Self {
+        self.home = Some(home);
+        self
+    }
+
+    pub fn module_path(mut self, module_path: &'a Path) -> Self {
+        self.module_path = Some(module_path);
+        self
+    }
+
+    pub fn dep_idents(mut self, dep_idents: &'a IdentIdsByModule) -> Self {
+        self.dep_idents = Some(dep_idents);
+        self
+    }
+
+    pub fn qualified_module_ids(mut self, qualified_module_ids: &'a PackageModuleIds<'a>) -> Self {
+        self.qualified_module_ids = Some(qualified_module_ids);
+        self
+    }
+
+    /// The shorthand a package module is referred to by, e.g. `pf`
+    pub fn opt_shorthand(mut self, opt_shorthand: Option<&'a str>) -> Self {
+        self.opt_shorthand = opt_shorthand;
+        self
+    }
+
+    pub fn deprecated_idents(mut self, deprecated_idents: &'a DeprecatedIdentsByModule) -> Self {
+        self.deprecated_idents = Some(deprecated_idents);
+        self
+    }
+
+    pub fn lookup_errors(mut self, lookup_errors: LookupErrors) -> Self {
+        self.lookup_errors = lookup_errors;
+        self
+    }
+
+    pub fn re_exported_modules(mut self, re_exported_modules: &'a ReExportedModules<'a>) -> Self {
+        self.re_exported_modules = Some(re_exported_modules);
+        self
+    }
+
+    pub fn note_shadowing_locals(mut self, note_shadowing_locals: bool) -> Self {
+        self.note_shadowing_locals = note_shadowing_locals;
+        self
+    }
+
+    pub fn build(self) -> Result<Env<'a>, MissingEnvField> {
+        Ok(Env {
+            arena: self.arena.ok_or(MissingEnvField("arena"))?,
+            home: self.home.ok_or(MissingEnvField("home"))?,
+            module_path: self.module_path.ok_or(MissingEnvField("module_path"))?,
+            dep_idents: self.dep_idents.ok_or(MissingEnvField("dep_idents"))?,
+            qualified_module_ids: self
+                .qualified_module_ids
+                .ok_or(MissingEnvField("qualified_module_ids"))?,
+            problems: Vec::new(),
+            closures: MutMap::default(),
+            qualified_value_lookups: VecSet::default(),
+            qualified_type_lookups: VecSet::default(),
+            tailcallable_symbol: None,
+            top_level_symbols: VecSet::default(),
+            home_opaques: VecSet::default(),
+            opt_shorthand: self.opt_shorthand,
+            resolved_qualified_lookups: MutMap::default(),
+            record_resolved_lookups: false,
+            cached_qualified_lookups: MutMap::default(),
+            invalidated_modules: VecSet::default(),
+            qualified_references: None,
+            deprecated_idents: self.deprecated_idents,
+            lookup_errors: self.lookup_errors,
+            re_exported_modules: self.re_exported_modules,
+            note_shadowing_locals: self.note_shadowing_locals,
+        })
+    }
+}
+
 /// The canonicalization environment for a particular module.
 pub struct Env<'a> {
     /// The module's path. Opaques and unqualified references to identifiers
@@ -36,11 +183,47 @@ pub struct Env<'a> {
     /// Symbols of types which were referenced by qualified lookups.
     pub qualified_type_lookups: VecSet<Symbol>,
 
+    /// Symbols defined at the top level of the module. These are only ever inserted,
+    /// so iteration follows the order the defs were canonicalized in, which is source order.
     pub top_level_symbols: VecSet<Symbol>,
 
+    /// Opaque types defined in this module, in the order they were canonicalized.
+    home_opaques: VecSet<Symbol>,
+
     pub arena: &'a Bump,
 
     pub opt_shorthand: Option<&'a str>,
+
+    /// Qualified lookups into other modules that were resolved during this canonicalization.
+    /// These can be used to seed the next canonicalization of this module.
+    /// They're only recorded when asked for (see `record_resolved_lookups`).
+    pub resolved_qualified_lookups: QualifiedLookupCache,
+
+    record_resolved_lookups: bool,
+
+    /// Qualified lookups from a previous canonicalization of this module.
+    cached_qualified_lookups: QualifiedLookupCache,
+
+    /// Modules whose cached lookups can't be trusted anymore, e.g. because their exposed idents changed.
+    invalidated_modules: VecSet<ModuleId>,
+
+    /// Every qualified reference and where it occurred, in the order they were canonicalized.
+    /// This is only recorded when asked for, e.g. by an editor that wants to find all references.
+    qualified_references: Option<Vec<(Symbol, Region)>>,
+
+    /// Idents other modules have deprecated. Qualified lookups of these still resolve,
+    /// but report a `Problem::UseOfDeprecated` warning.
+    deprecated_idents: Option<&'a DeprecatedIdentsByModule>,
+
+    /// Whether to put suggestions in lookup errors
+    lookup_errors: LookupErrors,
+
+    /// Qualified lookups follow these to find where an imported module is really defined
+    re_exported_modules: Option<&'a ReExportedModules<'a>>,
+
+    /// Whether to warn about qualified lookups of values like `List.map` when a local `map`
+    /// is in scope. This is a lint, so it's off by default.
+    note_shadowing_locals: bool,
 }
 
 impl<'a> Env<'a> {
@@ -52,22 +235,70 @@ impl<'a> Env<'a> {
         qualified_module_ids: &'a PackageModuleIds<'a>,
         opt_shorthand: Option<&'a str>,
     ) -> Env<'a> {
-        Env {
-            arena,
-            home,
-            module_path,
-            dep_idents,
-            qualified_module_ids,
-            problems: Vec::new(),
-            closures: MutMap::default(),
-            qualified_value_lookups: VecSet::default(),
-            qualified_type_lookups: VecSet::default(),
-            tailcallable_symbol: None,
-            top_level_symbols: VecSet::default(),
-            opt_shorthand,
+        Self::builder()
+            .arena(arena)
+            .home(home)
+            .module_path(module_path)
+            .dep_idents(dep_idents)
+            .qualified_module_ids(qualified_module_ids)
+            .opt_shorthand(opt_shorthand)
+            .build()
+            .expect("every required field was set")
+    }
+
+    pub fn builder() -> EnvBuilder<'a> {
+        EnvBuilder::default()
+    }
+
+    /// Finish canonicalizing, and take everything this Env accumulated along the way.
+    /// Prefer this to reading the individual fields afterwards.
+    pub fn finish(self) -> CanonicalizedEnvOutput {
+        CanonicalizedEnvOutput {
+            home: self.home,
+            problems: self.problems,
+            closures: self.closures,
+            qualified_value_lookups: self.qualified_value_lookups,
+            qualified_type_lookups: self.qualified_type_lookups,
+            top_level_symbols: self.top_level_symbols,
+            resolved_qualified_lookups: self.resolved_qualified_lookups,
+            qualified_references: self.qualified_references.unwrap_or_default(),
         }
     }
 
+    /// Record the region of every qualified lookup from now on, not just which symbols were
+    /// looked up. Batch compiles don't need this, so it's off by default.
+    pub fn record_qualified_references(&mut self) {
+        self.qualified_references.get_or_insert_with(Vec::new);
+    }
+
+    /// Record the qualified lookups that resolve from now on, so that they can seed the next
+    /// canonicalization of this module. Batch compiles don't need this, so it's off by default.
+    pub fn record_resolved_lookups(&mut self) {
+        self.record_resolved_lookups = true;
+    }
+
+    /// The qualified references recorded so far (see `record_qualified_references`)
+    pub fn qualified_references(&self) -> &[(Symbol, Region)] {
+        self.qualified_references.as_deref().unwrap_or_default()
+    }
+
+    /// Reuse the qualified lookups resolved by a previous canonicalization of this module
+    /// (see `resolved_qualified_lookups`), so that they don't need to consult `dep_idents` again.
+    ///
+    /// Lookups into any of the `invalidated_modules` are always resolved from scratch,
+    /// so every module whose exposed idents may have changed since then must be in that set.
+    ///
+    /// This also records this canonicalization's lookups, to seed the one after it.
+    pub fn seed_lookup_cache(
+        &mut self,
+        previous_lookups: QualifiedLookupCache,
+        invalidated_modules: VecSet<ModuleId>,
+    ) {
+        self.record_resolved_lookups();
+        self.cached_qualified_lookups = previous_lookups;
+        self.invalidated_modules = invalidated_modules;
+    }
+
     pub fn qualified_lookup(
         &mut self,
         scope: &Scope,
@@ -82,21 +313,46 @@ impl<'a> Env<'a> {
 
         let module_name = ModuleName::from(module_name_str);
 
-        match scope.modules.get_id(&module_name) {
-            Some(module_id) => self.qualified_lookup_help(scope, module_id, ident, region),
-            None => Err(RuntimeError::ModuleNotImported {
-                module_name: module_name.clone(),
-                imported_modules: scope
-                    .modules
-                    .available_names()
-                    .map(|string| string.as_ref().into())
-                    .collect(),
-                region,
-                module_exists: self
+        self.qualified_lookup_with_module_name(scope, &module_name, ident, region)
+    }
+
+    /// Like `qualified_lookup`, for callers that already have the `ModuleName`,
+    /// e.g. because they're resolving many references to the same module.
+    pub fn qualified_lookup_with_module_name(
+        &mut self,
+        scope: &Scope,
+        module_name: &ModuleName,
+        ident: &str,
+        region: Region,
+    ) -> Result<Symbol, RuntimeError> {
+        match scope.modules.get_id(module_name) {
+            Some(module_id) => {
+                let module_id = self.follow_re_exports(module_id, region)?;
+                self.qualified_lookup_help(scope, module_id, ident, region)
+            }
+            None => {
+                // It might be in a package, e.g. `pf.Stdout`, even though it's referred to unqualified
+                let existing = self
                     .qualified_module_ids
-                    .get_id(&PQModuleName::Unqualified(module_name))
-                    .is_some(),
-            }),
+                    .available_modules()
+                    .find(|name| name.as_inner() == module_name);
+
+                Err(RuntimeError::ModuleNotImported {
+                    module_name: module_name.clone(),
+                    imported_modules: self.suggestions(|| {
+                        scope
+                            .modules
+                            .available_names()
+                            .map(|string| string.as_ref().into())
+                            .collect()
+                    }),
+                    region,
+                    module_exists: existing.is_some(),
+                    package_shorthand: existing
+                        .and_then(|name| name.package_shorthand())
+                        .map(Into::into),
+                })
+            }
         }
     }
 
@@ -110,10 +366,67 @@ impl<'a> Env<'a> {
         if !scope.modules.has_id(module_id) {
             Err(self.module_exists_but_not_imported(scope, module_id, region))
         } else {
+            let module_id = self.follow_re_exports(module_id, region)?;
             self.qualified_lookup_help(scope, module_id, ident, region)
         }
     }
 
+    /// Follow the re-exports of a module, e.g. from `pf.Http` to `http.Http`,
+    /// to the module that actually defines it
+    fn follow_re_exports(
+        &self,
+        module_id: ModuleId,
+        region: Region,
+    ) -> Result<ModuleId, RuntimeError> {
+        let (Some(re_exports), Some(module_name)) = (
+            self.re_exported_modules,
+            self.qualified_module_ids.get_name(module_id),
+        ) else {
+            return Ok(module_id);
+        };
+
+        let mut visited = vec![module_name];
+        let mut current = module_name;
+
+        while let Some(origin) = re_exports.get(current) {
+            let is_cycle = visited.contains(&origin);
+            visited.push(origin);
+
+            if is_cycle {
+                return Err(RuntimeError::ReExportCycle {
+                    cycle: visited.into_iter().map(pq_name_str).collect(),
+                    region,
+                });
+            }
+
+            current = origin;
+        }
+
+        if visited.len() == 1 {
+            return Ok(module_id);
+        }
+
+        self.qualified_module_ids.get_id(current).ok_or_else(|| {
+            RuntimeError::ReExportOriginNotFound {
+                module_name: pq_name_str(module_name),
+                origin: pq_name_str(current),
+                region,
+            }
+        })
+    }
+
+    fn record_qualified_lookup(&mut self, symbol: Symbol, is_type_name: bool, region: Region) {
+        if is_type_name {
+            self.qualified_type_lookups.insert(symbol);
+        } else {
+            self.qualified_value_lookups.insert(symbol);
+        }
+
+        if let Some(references) = &mut self.qualified_references {
+            references.push((symbol, region));
+        }
+    }
+
     /// Returns Err if the symbol resolved, but it was not exposed by the given module
     fn qualified_lookup_help(
         &mut self,
@@ -131,11 +444,7 @@ impl<'a> Env<'a> {
                 Some(ident_id) => {
                     let symbol = Symbol::new(module_id, ident_id);
 
-                    if is_type_name {
-                        self.qualified_type_lookups.insert(symbol);
-                    } else {
-                        self.qualified_value_lookups.insert(symbol);
-                    }
+                    self.record_qualified_lookup(symbol, is_type_name, region);
 
                     Ok(symbol)
                 }
@@ -145,12 +454,14 @@ impl<'a> Env<'a> {
                             value: Ident::from(ident),
                             region,
                         },
-                        suggestion_options: scope
-                            .locals
-                            .ident_ids
-                            .ident_strs()
-                            .map(|(_, string)| string.into())
-                            .collect(),
+                        suggestion_options: self.suggestions(|| {
+                            scope
+                                .locals
+                                .ident_ids
+                                .ident_strs()
+                                .map(|(_, string)| string.into())
+                                .collect()
+                        }),
                         underscored_suggestion_region: None,
                     };
                     Err(error)
@@ -158,14 +469,21 @@ impl<'a> Env<'a> {
             }
         } else {
             match self.dep_idents.get(&module_id) {
-                Some(exposed_ids) => match exposed_ids.get_id(ident) {
-                    Some(ident_id) => {
-                        let symbol = Symbol::new(module_id, ident_id);
-
-                        if is_type_name {
-                            self.qualified_type_lookups.insert(symbol);
-                        } else {
-                            self.qualified_value_lookups.insert(symbol);
+                Some(exposed_ids) => match self.cached_lookup(module_id, ident).or_else(|| {
+                    exposed_ids
+                        .get_id(ident)
+                        .map(|ident_id| Symbol::new(module_id, ident_id))
+                }) {
+                    Some(symbol) => {
+                        self.record_qualified_lookup(symbol, is_type_name, region);
+                        self.check_deprecated(symbol, region);
+                        if !is_type_name {
+                            self.check_shadowing_local(scope, symbol, ident, region);
+                        }
+
+                        if self.record_resolved_lookups {
+                            self.resolved_qualified_lookups
+                                .insert((module_id, Ident::from(ident)), symbol);
                         }
 
                         Ok(symbol)
@@ -179,7 +497,7 @@ impl<'a> Env<'a> {
                             .clone(),
                         ident: Ident::from(ident),
                         region,
-                        exposed_values: exposed_ids.exposed_values(),
+                        exposed_values: self.suggestions(|| exposed_ids.exposed_values()),
                     }),
                 },
                 _ => Err(self.module_exists_but_not_imported(scope, module_id, region)),
@@ -187,30 +505,923 @@ impl<'a> Env<'a> {
         }
     }
 
+    fn check_deprecated(&mut self, symbol: Symbol, region: Region) {
+        let note = self
+            .deprecated_idents
+            .and_then(|by_module| by_module.get(&symbol.module_id()))
+            .and_then(|deprecated| deprecated.get(&symbol.ident_id()));
+
+        if let Some(note) = note {
+            self.problem(Problem::UseOfDeprecated {
+                symbol,
+                note: note.clone(),
+                region,
+            });
+        }
+    }
+
+    fn check_shadowing_local(
+        &mut self,
+        scope: &Scope,
+        symbol: Symbol,
+        ident: &str,
+        region: Region,
+    ) {
+        if !self.note_shadowing_locals {
+            return;
+        }
+
+        if let Some((local, local_region)) = scope.lookup_local_str(ident) {
+            self.problem(Problem::QualifiedLookupShadowedByLocal {
+                symbol,
+                region,
+                local,
+                local_region,
+            });
+        }
+    }
+
+    fn cached_lookup(&self, module_id: ModuleId, ident: &str) -> Option<Symbol> {
+        if self.cached_qualified_lookups.is_empty() || self.invalidated_modules.contains(&module_id)
+        {
+            None
+        } else {
+            self.cached_qualified_lookups
+                .get(&(module_id, Ident::from(ident)))
+                .copied()
+        }
+    }
+
+    /// The suggestions for a lookup error, or none if `LookupErrors::Quiet` was asked for
+    fn suggestions<T: Default>(&self, build: impl FnOnce() -> T) -> T {
+        match self.lookup_errors {
+            LookupErrors::Explain => build(),
+            LookupErrors::Quiet => T::default(),
+        }
+    }
+
     fn module_exists_but_not_imported(
         &self,
         scope: &Scope,
         module_id: ModuleId,
         region: Region,
     ) -> RuntimeError {
+        let module_name = self
+            .qualified_module_ids
+            .get_name(module_id)
+            .expect("Module ID known, but not in the module IDs somehow");
+
         RuntimeError::ModuleNotImported {
-            module_name: self
-                .qualified_module_ids
-                .get_name(module_id)
-                .expect("Module ID known, but not in the module IDs somehow")
-                .as_inner()
-                .clone(),
-            imported_modules: scope
-                .modules
-                .available_names()
-                .map(|string| string.as_ref().into())
-                .collect(),
+            module_name: module_name.as_inner().clone(),
+            imported_modules: self.suggestions(|| {
+                scope
+                    .modules
+                    .available_names()
+                    .map(|string| string.as_ref().into())
+                    .collect()
+            }),
             region,
             module_exists: true,
+            package_shorthand: module_name.package_shorthand().map(Into::into),
         }
     }
 
+    /// The top-level symbols sorted by symbol, i.e. by the order their idents were added.
+    /// Use this where output must not depend on how the defs happened to be visited.
+    pub fn sorted_top_level_symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.top_level_symbols.iter().copied().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// The other modules this one refers to with qualified lookups like `Foo.bar`,
+    /// in the order they were first looked up. Values come before types.
+    pub fn module_dependencies(&self) -> Vec<ModuleId> {
+        let mut modules = Vec::new();
+        let lookups = self
+            .qualified_value_lookups
+            .iter()
+            .chain(self.qualified_type_lookups.iter());
+        for symbol in lookups {
+            let module_id = symbol.module_id();
+            if module_id != self.home && !modules.contains(&module_id) {
+                modules.push(module_id);
+            }
+        }
+        modules
+    }
+
+    pub(crate) fn insert_home_opaque(&mut self, symbol: Symbol) {
+        debug_assert_eq!(symbol.module_id(), self.home);
+        self.home_opaques.insert(symbol);
+    }
+
+    /// Whether this symbol is an opaque type defined in the home module.
+    /// Only opaques like this can be wrapped and unwrapped with `@` here.
+    pub fn is_home_opaque(&self, symbol: Symbol) -> bool {
+        self.home_opaques.contains(&symbol)
+    }
+
+    pub fn home_opaques(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.home_opaques.iter().copied()
+    }
+
     pub fn problem(&mut self, problem: Problem) {
         self.problems.push(problem)
     }
+
+    /// Whether any problem so far is worse than a warning, so the module shouldn't be run as is
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity() != Severity::Warning)
+    }
+
+    /// The problems so far that are only warnings, like unused imports
+    pub fn warnings(&self) -> impl Iterator<Item = &Problem> {
+        self.problems
+            .iter()
+            .filter(|problem| problem.severity() == Severity::Warning)
+    }
+}
+
+/// A module name the way it's written in source, e.g. `pf.Http`
+fn pq_name_str(name: &PQModuleName) -> Box<str> {
+    match name.package_shorthand() {
+        Some(shorthand) => format!("{shorthand}.{}", name.as_inner()).into(),
+        None => name.as_inner().as_str().into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::abilities::PendingAbilitiesStore;
+    use roc_module::symbol::{IdentIds, ModuleIds, PQModuleName};
+    use roc_region::all::Position;
+
+    use pretty_assertions::assert_eq;
+
+    struct Modules<'a> {
+        home: ModuleId,
+        foo: ModuleId,
+        qualified_module_ids: PackageModuleIds<'a>,
+    }
+
+    fn modules<'a>() -> Modules<'a> {
+        let _register_module_debug_names = ModuleIds::default();
+        let mut qualified_module_ids = PackageModuleIds::default();
+        let home = qualified_module_ids.get_or_insert(&PQModuleName::Unqualified("Test".into()));
+        let foo = qualified_module_ids.get_or_insert(&PQModuleName::Unqualified("Foo".into()));
+
+        Modules {
+            home,
+            foo,
+            qualified_module_ids,
+        }
+    }
+
+    fn foo_exposing(foo: ModuleId, idents: &[&str]) -> IdentIdsByModule {
+        let mut foo_ident_ids = IdentIds::default();
+        for ident in idents {
+            foo_ident_ids.add_str(ident);
+        }
+
+        let mut dep_idents = IdentIdsByModule::default();
+        dep_idents.insert(foo, foo_ident_ids);
+        dep_idents
+    }
+
+    fn scope_importing_foo(modules: &Modules) -> Scope {
+        let mut scope = Scope::new(
+            modules.home,
+            "Test".into(),
+            IdentIds::default(),
+            PendingAbilitiesStore::default(),
+        );
+        scope
+            .modules
+            .insert("Foo".into(), modules.foo, Region::zero())
+            .unwrap();
+        scope
+    }
+
+    #[test]
+    fn resolved_lookups_are_recorded() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar", "baz"]);
+        let scope = scope_importing_foo(&modules);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .unwrap();
+        assert!(env.resolved_qualified_lookups.is_empty());
+
+        env.record_resolved_lookups();
+        let symbol = env
+            .qualified_lookup(&scope, "Foo", "baz", Region::zero())
+            .unwrap();
+
+        let mut expected = QualifiedLookupCache::default();
+        expected.insert((modules.foo, "baz".into()), symbol);
+        assert_eq!(env.resolved_qualified_lookups, expected);
+    }
+
+    #[test]
+    fn builder_requires_fields() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &[]);
+
+        let builder = || {
+            Env::builder()
+                .arena(&arena)
+                .home(modules.home)
+                .module_path(Path::new("Test.roc"))
+                .dep_idents(&dep_idents)
+        };
+        assert_eq!(
+            builder().build().err(),
+            Some(MissingEnvField("qualified_module_ids"))
+        );
+        assert_eq!(
+            Env::builder().home(modules.home).build().err(),
+            Some(MissingEnvField("arena"))
+        );
+
+        let env = builder()
+            .qualified_module_ids(&modules.qualified_module_ids)
+            .opt_shorthand(Some("pf"))
+            .build()
+            .unwrap();
+        assert_eq!(env.home, modules.home);
+        assert_eq!(env.opt_shorthand, Some("pf"));
+    }
+
+    #[test]
+    fn qualified_references_are_recorded_when_asked() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar", "baz"]);
+        let scope = scope_importing_foo(&modules);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.qualified_lookup(
+            &scope,
+            "Foo",
+            "bar",
+            Region::new(Position::new(0), Position::new(7)),
+        )
+        .unwrap();
+        assert!(env.qualified_references().is_empty());
+
+        env.record_qualified_references();
+        let first = Region::new(Position::new(10), Position::new(17));
+        let second = Region::new(Position::new(20), Position::new(27));
+        let bar = env.qualified_lookup(&scope, "Foo", "bar", first).unwrap();
+        let baz = env.qualified_lookup(&scope, "Foo", "baz", second).unwrap();
+        // A failed lookup isn't a reference to anything
+        assert!(env
+            .qualified_lookup(&scope, "Foo", "nope", Region::zero())
+            .is_err());
+
+        assert_eq!(env.qualified_references(), [(bar, first), (baz, second)]);
+        assert_eq!(
+            env.finish().qualified_references,
+            [(bar, first), (baz, second)]
+        );
+    }
+
+    #[test]
+    fn lookup_with_module_name() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar"]);
+        let scope = scope_importing_foo(&modules);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        let foo = ModuleName::from("Foo");
+        let by_name = env
+            .qualified_lookup_with_module_name(&scope, &foo, "bar", Region::zero())
+            .unwrap();
+        let by_str = env
+            .qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .unwrap();
+        assert_eq!(by_name, by_str);
+
+        let bar = ModuleName::from("Bar");
+        assert!(matches!(
+            env.qualified_lookup_with_module_name(&scope, &bar, "bar", Region::zero()),
+            Err(RuntimeError::ModuleNotImported {
+                module_exists: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn deprecated_lookups_resolve_with_a_warning() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["old", "new"]);
+        let scope = scope_importing_foo(&modules);
+
+        let old_id = dep_idents.get(&modules.foo).unwrap().get_id("old").unwrap();
+        let mut deprecated_idents = DeprecatedIdentsByModule::default();
+        deprecated_idents.insert(
+            modules.foo,
+            MutMap::from_iter([(old_id, "Use `Foo.new` instead.".to_string())]),
+        );
+
+        let mut env = Env::builder()
+            .arena(&arena)
+            .home(modules.home)
+            .module_path(Path::new("Test.roc"))
+            .dep_idents(&dep_idents)
+            .qualified_module_ids(&modules.qualified_module_ids)
+            .deprecated_idents(&deprecated_idents)
+            .build()
+            .unwrap();
+
+        let region = Region::new(Position::new(3), Position::new(10));
+        let old = env.qualified_lookup(&scope, "Foo", "old", region).unwrap();
+        env.qualified_lookup(&scope, "Foo", "new", Region::zero())
+            .unwrap();
+
+        assert_eq!(old, Symbol::new(modules.foo, old_id));
+        assert_eq!(
+            env.problems,
+            [Problem::UseOfDeprecated {
+                symbol: old,
+                note: "Use `Foo.new` instead.".to_string(),
+                region,
+            }]
+        );
+        assert!(!env.has_errors());
+    }
+
+    #[test]
+    fn re_exported_modules_resolve_to_their_origin() {
+        let arena = Bump::new();
+        let mut modules = modules();
+        let mut module_id = |package, name: &str| {
+            modules
+                .qualified_module_ids
+                .get_or_insert(&PQModuleName::Qualified(package, name.into()))
+        };
+        let pf_http = module_id("pf", "Http");
+        let http = module_id("http", "Http");
+        let pf_loop = module_id("pf", "Loop");
+        module_id("other", "Loop");
+        let pf_gone = module_id("pf", "Gone");
+        let dep_idents = foo_exposing(http, &["get"]);
+
+        let mut scope = scope_importing_foo(&modules);
+        for (name, id) in [("Http", pf_http), ("Loop", pf_loop), ("Gone", pf_gone)] {
+            scope
+                .modules
+                .insert(name.into(), id, Region::zero())
+                .unwrap();
+        }
+
+        let mut re_exported_modules = ReExportedModules::default();
+        for (from, to) in [
+            (("pf", "Http"), ("http", "Http")),
+            (("pf", "Loop"), ("other", "Loop")),
+            (("other", "Loop"), ("pf", "Loop")),
+            (("pf", "Gone"), ("gone", "Gone")),
+        ] {
+            re_exported_modules.insert(
+                PQModuleName::Qualified(from.0, from.1.into()),
+                PQModuleName::Qualified(to.0, to.1.into()),
+            );
+        }
+
+        let mut env = Env::builder()
+            .arena(&arena)
+            .home(modules.home)
+            .module_path(Path::new("Test.roc"))
+            .dep_idents(&dep_idents)
+            .qualified_module_ids(&modules.qualified_module_ids)
+            .re_exported_modules(&re_exported_modules)
+            .build()
+            .unwrap();
+
+        let get_id = dep_idents.get(&http).unwrap().get_id("get").unwrap();
+        assert_eq!(
+            env.qualified_lookup(&scope, "Http", "get", Region::zero()),
+            Ok(Symbol::new(http, get_id))
+        );
+        assert_eq!(
+            env.qualified_lookup_with_module_id(&scope, pf_http, "get", Region::zero()),
+            Ok(Symbol::new(http, get_id))
+        );
+
+        assert_eq!(
+            env.qualified_lookup(&scope, "Loop", "get", Region::zero()),
+            Err(RuntimeError::ReExportCycle {
+                cycle: vec!["pf.Loop".into(), "other.Loop".into(), "pf.Loop".into()],
+                region: Region::zero(),
+            })
+        );
+
+        assert_eq!(
+            env.qualified_lookup(&scope, "Gone", "get", Region::zero()),
+            Err(RuntimeError::ReExportOriginNotFound {
+                module_name: "pf.Gone".into(),
+                origin: "gone.Gone".into(),
+                region: Region::zero(),
+            })
+        );
+
+        // Re-exported modules still have to be imported
+        let scope = scope_importing_foo(&modules);
+        assert!(matches!(
+            env.qualified_lookup(&scope, "Http", "get", Region::zero()),
+            Err(RuntimeError::ModuleNotImported { .. })
+        ));
+    }
+
+    #[test]
+    fn qualified_lookups_shadowed_by_a_local_are_noted() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar", "baz"]);
+        let mut scope = scope_importing_foo(&modules);
+
+        let local_region = Region::new(Position::new(0), Position::new(3));
+        let local = scope.introduce_str("bar", local_region).unwrap();
+
+        let env = |note_shadowing_locals| {
+            Env::builder()
+                .arena(&arena)
+                .home(modules.home)
+                .module_path(Path::new("Test.roc"))
+                .dep_idents(&dep_idents)
+                .qualified_module_ids(&modules.qualified_module_ids)
+                .note_shadowing_locals(note_shadowing_locals)
+                .build()
+                .unwrap()
+        };
+
+        let region = Region::new(Position::new(10), Position::new(17));
+        let mut noted = env(true);
+        let bar = noted
+            .qualified_lookup(&scope, "Foo", "bar", region)
+            .unwrap();
+        noted
+            .qualified_lookup(&scope, "Foo", "baz", Region::zero())
+            .unwrap();
+
+        assert_eq!(
+            noted.problems,
+            [Problem::QualifiedLookupShadowedByLocal {
+                symbol: bar,
+                region,
+                local,
+                local_region,
+            }]
+        );
+        assert!(!noted.has_errors());
+
+        let mut quiet = env(false);
+        quiet
+            .qualified_lookup(&scope, "Foo", "bar", region)
+            .unwrap();
+        assert_eq!(quiet.problems, []);
+    }
+
+    #[test]
+    fn module_in_a_package_exists() {
+        let arena = Bump::new();
+        let mut modules = modules();
+        modules
+            .qualified_module_ids
+            .get_or_insert(&PQModuleName::Qualified("pf", "Stdout".into()));
+        let dep_idents = IdentIdsByModule::default();
+        let scope = scope_importing_foo(&modules);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        assert!(matches!(
+            env.qualified_lookup(&scope, "Stdout", "line", Region::zero()),
+            Err(RuntimeError::ModuleNotImported {
+                module_exists: true,
+                package_shorthand: Some(shorthand),
+                ..
+            }) if &*shorthand == "pf"
+        ));
+        assert!(matches!(
+            env.qualified_lookup(&scope, "Stdin", "line", Region::zero()),
+            Err(RuntimeError::ModuleNotImported {
+                module_exists: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn module_dependencies_are_distinct() {
+        let arena = Bump::new();
+        let mut modules = modules();
+        let bar = modules
+            .qualified_module_ids
+            .get_or_insert(&PQModuleName::Unqualified("Bar".into()));
+        let mut dep_idents = foo_exposing(modules.foo, &["one", "two"]);
+        let mut bar_ident_ids = IdentIds::default();
+        bar_ident_ids.add_str("Thing");
+        dep_idents.insert(bar, bar_ident_ids);
+        let mut scope = scope_importing_foo(&modules);
+        scope
+            .modules
+            .insert("Bar".into(), bar, Region::zero())
+            .unwrap();
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        assert_eq!(env.module_dependencies(), vec![]);
+
+        env.qualified_lookup(&scope, "Bar", "Thing", Region::zero())
+            .unwrap();
+        env.qualified_lookup(&scope, "Foo", "one", Region::zero())
+            .unwrap();
+        env.qualified_lookup(&scope, "Foo", "two", Region::zero())
+            .unwrap();
+        assert_eq!(env.qualified_type_lookups.len(), 1);
+        assert_eq!(env.module_dependencies(), vec![modules.foo, bar]);
+    }
+
+    #[test]
+    fn finish_bundles_the_output() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar"]);
+        let scope = scope_importing_foo(&modules);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.record_resolved_lookups();
+        let bar = env
+            .qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .unwrap();
+        env.problem(Problem::ExplicitBuiltinImport(modules.foo, Region::zero()));
+
+        let output = env.finish();
+        assert_eq!(output.home, modules.home);
+        assert_eq!(output.problems.len(), 1);
+        assert!(output.qualified_value_lookups.contains(&bar));
+        assert!(output.qualified_type_lookups.is_empty());
+        assert_eq!(output.resolved_qualified_lookups.len(), 1);
+    }
+
+    #[test]
+    fn warnings_are_not_errors() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &[]);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.problem(Problem::UnusedModuleImport(modules.foo, Region::zero()));
+        assert!(!env.has_errors());
+        assert_eq!(env.warnings().count(), 1);
+
+        env.problem(Problem::ExposedButNotDefined(Symbol::LIST_MAP));
+        assert!(env.has_errors());
+        assert_eq!(env.warnings().count(), 1);
+    }
+
+    #[test]
+    fn unchanged_lookup_uses_cache() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar", "baz"]);
+        let scope = scope_importing_foo(&modules);
+
+        // Pretend the previous run resolved `Foo.bar` to something dep_idents wouldn't,
+        // so we can tell whether the cached symbol was used.
+        let cached_symbol = Symbol::new(
+            modules.foo,
+            dep_idents.get(&modules.foo).unwrap().get_id("baz").unwrap(),
+        );
+        let mut previous = QualifiedLookupCache::default();
+        previous.insert((modules.foo, "bar".into()), cached_symbol);
+
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.seed_lookup_cache(previous, VecSet::default());
+
+        let symbol = env
+            .qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .unwrap();
+        assert_eq!(symbol, cached_symbol);
+        assert!(env.qualified_value_lookups.contains(&cached_symbol));
+    }
+
+    #[test]
+    fn renamed_export_misses_cache() {
+        let arena = Bump::new();
+        let modules = modules();
+        let scope = scope_importing_foo(&modules);
+
+        // First run: Foo exposes `bar`
+        let old_dep_idents = foo_exposing(modules.foo, &["bar"]);
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &old_dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        env.record_resolved_lookups();
+        env.qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .unwrap();
+        let previous = env.resolved_qualified_lookups;
+
+        // Second run: Foo renamed `bar` to `baz`, so it's invalidated
+        let new_dep_idents = foo_exposing(modules.foo, &["baz"]);
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &new_dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+        let mut invalidated = VecSet::default();
+        invalidated.insert(modules.foo);
+        env.seed_lookup_cache(previous, invalidated);
+
+        assert!(matches!(
+            env.qualified_lookup(&scope, "Foo", "bar", Region::zero()),
+            Err(RuntimeError::ValueNotExposed { .. })
+        ));
+        assert!(env
+            .qualified_lookup(&scope, "Foo", "baz", Region::zero())
+            .is_ok());
+    }
+
+    #[test]
+    fn home_opaques_are_recorded() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = IdentIdsByModule::default();
+        let mut scope = scope_importing_foo(&modules);
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+
+        let src = "Age := U32\n\nName : Str\n\nage = \\n -> @Age n\n";
+        let defs = roc_parse::test_helpers::parse_defs_with(&arena, src).unwrap();
+        crate::def::canonicalize_defs(
+            &mut env,
+            Default::default(),
+            &mut roc_types::subs::VarStore::default(),
+            &mut scope,
+            arena.alloc(defs),
+            roc_parse::pattern::PatternType::TopLevelDef,
+        );
+
+        // `Test.Age` is a lookup of the home module's own opaque
+        let age = env
+            .qualified_lookup(&scope, "Test", "Age", Region::zero())
+            .unwrap();
+        let name = env
+            .qualified_lookup(&scope, "Test", "Name", Region::zero())
+            .unwrap();
+
+        assert!(env.is_home_opaque(age));
+        assert!(!env.is_home_opaque(name));
+        assert_eq!(env.home_opaques().collect::<Vec<_>>(), [age]);
+    }
+
+    /// The top-level names of `src` in the order they were canonicalized, then sorted.
+    /// The module exposes `exposed`, in that order, so their idents are added before any defs.
+    fn top_level_names(src: &str, exposed: &[&str]) -> (Vec<String>, Vec<String>) {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = IdentIdsByModule::default();
+        let mut ident_ids = IdentIds::default();
+        for ident in exposed {
+            ident_ids.add_str(ident);
+        }
+        let mut scope = Scope::new(
+            modules.home,
+            "Test".into(),
+            ident_ids,
+            PendingAbilitiesStore::default(),
+        );
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+
+        let defs = roc_parse::test_helpers::parse_defs_with(&arena, src).unwrap();
+        crate::def::canonicalize_defs(
+            &mut env,
+            Default::default(),
+            &mut roc_types::subs::VarStore::default(),
+            &mut scope,
+            arena.alloc(defs),
+            roc_parse::pattern::PatternType::TopLevelDef,
+        );
+
+        let name = |symbol: &Symbol| {
+            let ident_ids = &scope.locals.ident_ids;
+            ident_ids.get_name(symbol.ident_id()).unwrap().to_string()
+        };
+        (
+            env.top_level_symbols.iter().map(name).collect(),
+            env.sorted_top_level_symbols().iter().map(name).collect(),
+        )
+    }
+
+    #[test]
+    fn top_level_symbols_order_is_stable() {
+        let src = "zed = 1\n\nalpha = \\x -> x\n\nmid = zed\n";
+
+        // Exposed in a different order than they're defined, so sorting changes the order
+        let exposed = ["mid", "alpha", "zed"];
+
+        let first = top_level_names(src, &exposed);
+        let second = top_level_names(src, &exposed);
+
+        assert_eq!(first, second);
+        assert_eq!(first.0, ["zed", "alpha", "mid"]);
+        assert_eq!(first.1, ["mid", "alpha", "zed"]);
+    }
+
+    /// The suggestions for a missing `Test.missing` and a missing `Bar.x`, in the home module
+    /// of a scope that defines `idents` in that order
+    fn suggestions(idents: &[&str]) -> (Vec<Box<str>>, Vec<Box<str>>) {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &[]);
+        let mut ident_ids = IdentIds::default();
+        for ident in idents {
+            ident_ids.add_str(ident);
+        }
+        let scope = Scope::new(
+            modules.home,
+            "Test".into(),
+            ident_ids,
+            PendingAbilitiesStore::default(),
+        );
+        let mut env = Env::new(
+            &arena,
+            modules.home,
+            Path::new("Test.roc"),
+            &dep_idents,
+            &modules.qualified_module_ids,
+            None,
+        );
+
+        let suggestion_options =
+            match env.qualified_lookup(&scope, "Test", "missing", Region::zero()) {
+                Err(RuntimeError::LookupNotInScope {
+                    suggestion_options, ..
+                }) => suggestion_options.into_iter().collect(),
+                other => panic!("{other:?}"),
+            };
+        let imported_modules = match env.qualified_lookup(&scope, "Bar", "x", Region::zero()) {
+            Err(RuntimeError::ModuleNotImported {
+                imported_modules, ..
+            }) => imported_modules.into_iter().collect(),
+            other => panic!("{other:?}"),
+        };
+        (suggestion_options, imported_modules)
+    }
+
+    #[test]
+    fn quiet_lookup_errors_have_no_suggestions() {
+        let arena = Bump::new();
+        let modules = modules();
+        let dep_idents = foo_exposing(modules.foo, &["bar"]);
+        let scope = scope_importing_foo(&modules);
+
+        let env = |lookup_errors| {
+            Env::builder()
+                .arena(&arena)
+                .home(modules.home)
+                .module_path(Path::new("Test.roc"))
+                .dep_idents(&dep_idents)
+                .qualified_module_ids(&modules.qualified_module_ids)
+                .lookup_errors(lookup_errors)
+                .build()
+                .unwrap()
+        };
+        let not_exposed =
+            |env: &mut Env| match env.qualified_lookup(&scope, "Foo", "nope", Region::zero()) {
+                Err(RuntimeError::ValueNotExposed {
+                    ident,
+                    exposed_values,
+                    ..
+                }) => (ident, exposed_values),
+                other => panic!("{other:?}"),
+            };
+        let not_imported =
+            |env: &mut Env| match env.qualified_lookup(&scope, "Bar", "x", Region::zero()) {
+                Err(RuntimeError::ModuleNotImported {
+                    imported_modules, ..
+                }) => imported_modules.len(),
+                other => panic!("{other:?}"),
+            };
+
+        let mut explained = env(LookupErrors::Explain);
+        assert_eq!(
+            not_exposed(&mut explained),
+            ("nope".into(), vec!["bar".into()])
+        );
+        assert!(not_imported(&mut explained) > 0);
+
+        let mut quiet = env(LookupErrors::Quiet);
+        assert_eq!(not_exposed(&mut quiet), ("nope".into(), vec![]));
+        assert_eq!(not_imported(&mut quiet), 0);
+
+        // Lookups that succeed aren't affected
+        assert!(quiet
+            .qualified_lookup(&scope, "Foo", "bar", Region::zero())
+            .is_ok());
+    }
+
+    #[test]
+    fn suggestions_order_is_stable() {
+        let idents = [
+            "zed", "alpha", "mid", "beta", "omega", "kappa", "delta", "gamma",
+        ];
+        let mut reversed = idents;
+        reversed.reverse();
+
+        let first = suggestions(&idents);
+        assert_eq!(first, suggestions(&idents));
+        assert_eq!(first, suggestions(&reversed));
+
+        let mut sorted = idents.map(Box::<str>::from).to_vec();
+        sorted.sort();
+        assert_eq!(first.0, sorted);
+        assert!(!first.1.is_empty());
+        assert!(first.1.windows(2).all(|pair| pair[0] < pair[1]));
+    }
 }
@@ -717,8 +717,10 @@ pub fn canonicalize_module_defs<'a>(
     // Incorporate any remaining output.calls entries into references.
     referenced_values.extend(output.references.calls().copied());
 
+    let env_output = env.finish();
+
     // Gather up all the symbols that were referenced from other modules.
-    referenced_values.extend(env.qualified_value_lookups.iter().copied());
+    referenced_values.extend(env_output.qualified_value_lookups.iter().copied());
 
     let mut fix_closures_no_capture_symbols = VecSet::default();
     let mut fix_closures_closure_captures = VecMap::default();
@@ -815,7 +817,7 @@ pub fn canonicalize_module_defs<'a>(
         declarations,
         referenced_values,
         exposed_imports: can_exposed_imports,
-        problems: env.problems,
+        problems: env_output.problems,
         symbols_from_requires,
         pending_derives,
         loc_expects: collected.expects,
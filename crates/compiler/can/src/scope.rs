@@ -215,6 +215,14 @@ impl Scope {
         None
     }
 
+    /// The local of this name that's in scope, if any. Exposed imports aren't locals.
+    pub fn lookup_local_str(&self, ident: &str) -> Option<(Symbol, Region)> {
+        match self.locals.contains_ident(ident) {
+            ContainsIdent::InScope(symbol, region) => Some((symbol, region)),
+            ContainsIdent::NotInScope(_) | ContainsIdent::NotPresent => None,
+        }
+    }
+
     /// Is an identifier in scope, either in the locals or imports
     fn scope_contains_ident(&self, ident: &str) -> ContainsIdent {
         // exposed imports are likely to be small
@@ -80,14 +80,14 @@ pub fn can_expr_with(arena: &Bump, home: ModuleId, expr_str: &str) -> CanExprOut
     );
 
     let dep_idents = IdentIds::exposed_builtins(0);
-    let mut env = Env::new(
-        arena,
-        home,
-        Path::new("Test.roc"),
-        &dep_idents,
-        &qualified_module_ids,
-        None,
-    );
+    let mut env = Env::builder()
+        .arena(arena)
+        .home(home)
+        .module_path(Path::new("Test.roc"))
+        .dep_idents(&dep_idents)
+        .qualified_module_ids(&qualified_module_ids)
+        .build()
+        .unwrap();
     let (loc_expr, output) = canonicalize_expr(
         &mut env,
         &mut var_store,
@@ -103,6 +103,7 @@ pub fn can_expr_with(arena: &Bump, home: ModuleId, expr_str: &str) -> CanExprOut
         module_ids: env.qualified_module_ids.clone().into_module_ids(),
         all_ident_ids,
     };
+    let env = env.finish();
 
     CanExprOut {
         loc_expr,
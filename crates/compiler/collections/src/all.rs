@@ -30,6 +30,11 @@ pub type SendMap<K, V> = im::hashmap::HashMap<K, V, BuildHasher>;
 
 pub type SendSet<K> = im::hashset::HashSet<K, BuildHasher>;
 
+// For collections whose order users can see, like the suggestions in an error message.
+// A hash set's iteration order depends on its capacity and insertion history,
+// so the same program could get its suggestions in a different order. This one is always sorted.
+pub type SortedSet<K> = std::collections::BTreeSet<K>;
+
 pub type BumpMap<K, V> = hashbrown::HashMap<K, V, BuildHasher>;
 pub type BumpSet<K> = hashbrown::HashSet<K, BuildHasher>;
 
@@ -11,7 +11,7 @@ pub mod soa;
 mod vec_map;
 mod vec_set;
 
-pub use all::{default_hasher, BumpMap, ImEntry, ImMap, ImSet, MutMap, MutSet, SendMap};
+pub use all::{default_hasher, BumpMap, ImEntry, ImMap, ImSet, MutMap, MutSet, SendMap, SortedSet};
 pub use reference_matrix::{ReferenceMatrix, Sccs, TopologicalSort};
 pub use small_string_interner::SmallStringInterner;
 pub use small_vec::SmallVec;
@@ -218,6 +218,7 @@ pub fn can_expr_with<'a>(
         module_ids: env.qualified_module_ids.clone().into_module_ids(),
         all_ident_ids,
     };
+    let env = env.finish();
 
     Ok(CanExprOut {
         loc_expr,
@@ -658,10 +658,10 @@ mod test_reporting {
 
     Did you mean one of these?
 
-        Str
         Frac
         Num
-        U8
+        Str
+        Bool
     "###
     );
 
@@ -813,8 +813,8 @@ mod test_reporting {
 
                 Did you mean one of these?
 
-                    List
                     Box
+                    List
                     Str
                     isDisabled
                 "
@@ -1809,10 +1809,10 @@ mod test_reporting {
 
     Did you mean one of these?
 
-        Box
         Bool
-        U8
-        F64
+        Box
+        Dec
+        Eq
     "
     );
 
@@ -2213,10 +2213,10 @@ mod test_reporting {
 
     Did you mean one of these?
 
-        U8
         Box
         Eq
-        f
+        I8
+        U8
     "
     );
 
@@ -3782,18 +3782,17 @@ mod test_reporting {
 
     Only variables can be updated with record update syntax.
 
-    ── MODULE NOT IMPORTED in /code/proj/Main.roc ──────────────────────────────────
+    ── MODULE NOT FOUND in /code/proj/Main.roc ─────────────────────────────────────
 
-    The `Test` module is not imported:
+    I can't find a `Test` module in this project or any of its packages:
 
     10│      y = { Test.example & age: 3 }
                    ^^^^^^^^^^^^
 
-    Is there an import missing? Perhaps there is a typo. Did you mean one
-    of these?
+    Perhaps there is a typo. Did you mean one of these imported modules?
 
-        Set
         List
+        Set
         Dict
         Hash
 
@@ -3816,18 +3815,17 @@ mod test_reporting {
             "
         ),
         @r"
-    ── MODULE NOT IMPORTED in /code/proj/Main.roc ──────────────────────────────────
+    ── MODULE NOT FOUND in /code/proj/Main.roc ─────────────────────────────────────
 
-    The `Foo` module is not imported:
+    I can't find a `Foo` module in this project or any of its packages:
 
     4│      Foo.test
             ^^^^^^^^
 
-    Is there an import missing? Perhaps there is a typo. Did you mean one
-    of these?
+    Perhaps there is a typo. Did you mean one of these imported modules?
 
-        Box
         Bool
+        Box
         Num
         Set
     "
@@ -6041,9 +6039,9 @@ All branches in an `if` must have the same type!
     Did you mean one of these?
 
         Str
-        U8
-        F64
         Box
+        Dec
+        Eq
     "###
     );
 
@@ -8133,19 +8131,18 @@ In roc, functions are always written as a lambda, like{}
             "#
         ),
         @r"
-    ── MODULE NOT IMPORTED in /code/proj/Main.roc ──────────────────────────────────
+    ── MODULE NOT FOUND in /code/proj/Main.roc ─────────────────────────────────────
 
-    The `Task` module is not imported:
+    I can't find a `Task` module in this project or any of its packages:
 
     4│      alt : Task.Task {} []
                   ^^^^^^^^^^^^^^^
 
-    Is there an import missing? Perhaps there is a typo. Did you mean one
-    of these?
+    Perhaps there is a typo. Did you mean one of these imported modules?
 
         Hash
         List
-        Num
+        Bool
         Box
     "
     );
@@ -8514,8 +8511,8 @@ In roc, functions are always written as a lambda, like{}
     Did you mean one of these?
 
         Type
-        Unsigned8
         Unsigned16
+        Unsigned32
         Unsigned64
 
     ── UNRECOGNIZED NAME in /code/proj/Main.roc ────────────────────────────────────
@@ -8528,8 +8525,8 @@ In roc, functions are always written as a lambda, like{}
     Did you mean one of these?
 
         Type
-        Unsigned8
         Unsigned16
+        Unsigned32
         Unsigned64
     "
     );
@@ -10576,10 +10573,10 @@ In roc, functions are always written as a lambda, like{}
 
                 If not, did you mean one of these?
 
-                    Box
                     Bool
-                    U8
-                    F64
+                    Box
+                    Dec
+                    Eq
                 "
             ),
         )
@@ -1613,9 +1613,9 @@ fn issue_2863_module_type_does_not_exist() {
                         Did you mean one of these?
 
                             Decoding
-                            Result
-                            Dict
                             DecodeError
+                            DecodeResult
+                            Decoder
                         "
                       )
                 )
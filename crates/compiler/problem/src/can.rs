@@ -1,7 +1,7 @@
 use std::io;
 use std::path::PathBuf;
 
-use roc_collections::all::MutSet;
+use roc_collections::all::SortedSet;
 use roc_module::called_via::BinOp;
 use roc_module::ident::{Ident, Lowercase, ModuleName, TagName};
 use roc_module::symbol::{ModuleId, ScopeModuleSource, Symbol};
@@ -236,6 +236,20 @@ pub enum Problem {
         one_occurrence: Region,
         kind: AliasKind,
     },
+    /// A module marked this ident as deprecated. The lookup still resolves to it.
+    UseOfDeprecated {
+        symbol: Symbol,
+        note: String,
+        region: Region,
+    },
+    /// A qualified lookup like `List.map` where a local `map` is in scope,
+    /// so writing just `map` there would mean something else.
+    QualifiedLookupShadowedByLocal {
+        symbol: Symbol,
+        region: Region,
+        local: Symbol,
+        local_region: Region,
+    },
 }
 
 impl Problem {
@@ -308,6 +322,8 @@ impl Problem {
             Problem::OverAppliedCrash { .. } => RuntimeError,
             Problem::DefsOnlyUsedInRecursion(_, _) => Warning,
             Problem::FileProblem { .. } => Fatal,
+            Problem::UseOfDeprecated { .. } => Warning,
+            Problem::QualifiedLookupShadowedByLocal { .. } => Warning,
         }
     }
 
@@ -398,6 +414,8 @@ impl Problem {
             | Problem::RuntimeError(RuntimeError::OpaqueAppliedToMultipleArgs(region))
             | Problem::RuntimeError(RuntimeError::ValueNotExposed { region, .. })
             | Problem::RuntimeError(RuntimeError::ModuleNotImported { region, .. })
+            | Problem::RuntimeError(RuntimeError::ReExportCycle { region, .. })
+            | Problem::RuntimeError(RuntimeError::ReExportOriginNotFound { region, .. })
             | Problem::RuntimeError(RuntimeError::InvalidPrecedence(_, region))
             | Problem::RuntimeError(RuntimeError::MalformedIdentifier(_, _, region))
             | Problem::RuntimeError(RuntimeError::MalformedTypeName(_, region))
@@ -464,6 +482,8 @@ impl Problem {
             | Problem::UnnecessaryOutputWildcard { region }
             | Problem::OverAppliedCrash { region }
             | Problem::UnappliedCrash { region }
+            | Problem::UseOfDeprecated { region, .. }
+            | Problem::QualifiedLookupShadowedByLocal { region, .. }
             | Problem::DefsOnlyUsedInRecursion(_, region) => Some(*region),
             Problem::RuntimeError(RuntimeError::CircularDef(cycle_entries))
             | Problem::BadRecursion(cycle_entries) => {
@@ -568,14 +588,14 @@ pub enum RuntimeError {
     LookupNotInScope {
         loc_name: Loc<Ident>,
         /// All of the names in scope (for the error message)
-        suggestion_options: MutSet<Box<str>>,
+        suggestion_options: SortedSet<Box<str>>,
         /// If the unfound variable is `name` and there's an ignored variable called `_name`,
         /// this is the region where `_name` is defined (for the error message)
         underscored_suggestion_region: Option<Region>,
     },
     OpaqueNotDefined {
         usage: Loc<Ident>,
-        opaques_in_scope: MutSet<Box<str>>,
+        opaques_in_scope: SortedSet<Box<str>>,
         opt_defined_alias: Option<Region>,
     },
     OpaqueOutsideScope {
@@ -607,7 +627,7 @@ pub enum RuntimeError {
         /// The name of the module that was referenced
         module_name: ModuleName,
         /// A list of modules which *have* been imported
-        imported_modules: MutSet<Box<str>>,
+        imported_modules: SortedSet<Box<str>>,
         /// Where the problem occurred
         region: Region,
         /// Whether or not the module exists at all
@@ -622,6 +642,23 @@ pub enum RuntimeError {
         ///
         /// If unsure, this should be set to `false`
         module_exists: bool,
+        /// The shorthand of the package the module exists in, e.g. `pf` for `pf.Stdout`,
+        /// so that the suggested import names the package too
+        package_shorthand: Option<Box<str>>,
+    },
+    /// Following the re-exports of a module led back to a module that was already on the way.
+    /// The cycle starts and ends with the same name, e.g. `["pf.Http", "http.Http", "pf.Http"]`.
+    ReExportCycle {
+        cycle: Vec<Box<str>>,
+        region: Region,
+    },
+    /// A package re-exports a module from another package, but that module doesn't exist
+    ReExportOriginNotFound {
+        /// The name it was looked up as, e.g. `pf.Http`
+        module_name: Box<str>,
+        /// Where it's re-exported from, e.g. `http.Http`
+        origin: Box<str>,
+        region: Region,
     },
     ReadIngestedFileError {
         filename: PathBuf,
@@ -696,6 +733,8 @@ impl RuntimeError {
             | RuntimeError::OpaqueAppliedToMultipleArgs(region)
             | RuntimeError::ValueNotExposed { region, .. }
             | RuntimeError::ModuleNotImported { region, .. }
+            | RuntimeError::ReExportCycle { region, .. }
+            | RuntimeError::ReExportOriginNotFound { region, .. }
             | RuntimeError::InvalidPrecedence(_, region)
             | RuntimeError::MalformedIdentifier(_, _, region)
             | RuntimeError::MalformedTypeName(_, region)
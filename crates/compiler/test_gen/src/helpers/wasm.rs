@@ -9,7 +9,7 @@ use roc_packaging::cache::RocCacheDir;
 use roc_reporting::report::DEFAULT_PALETTE_HTML;
 use roc_solve::FunctionKind;
 use roc_std::RocStr;
-use roc_wasm_interp::{wasi, ImportDispatcher, Instance, WasiDispatcher};
+use roc_wasm_interp::{wasi, ImportDispatcher, Instance, TrapReason, WasiDispatcher};
 use roc_wasm_module::{Export, ExportType, Value, WasmModule};
 use std::marker::PhantomData;
 use std::path::PathBuf;
@@ -218,6 +218,14 @@ impl<'a> ImportDispatcher for TestDispatcher<'a> {
             );
         }
     }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.wasi.exit_code()
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        self.wasi.take_trap()
+    }
 }
 
 pub(crate) fn run_wasm_test_bytes<T>(
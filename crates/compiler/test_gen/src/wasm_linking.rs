@@ -17,7 +17,7 @@ use roc_mono::ir::{
     Call, CallType, Expr, Literal, Proc, ProcLayout, SelfRecursive, Stmt, UpdateModeId,
 };
 use roc_mono::layout::{LambdaName, Layout, Niche, STLayoutInterner};
-use roc_wasm_interp::{wasi, ImportDispatcher, Instance, WasiDispatcher};
+use roc_wasm_interp::{wasi, ImportDispatcher, Instance, TrapReason, WasiDispatcher};
 use roc_wasm_module::{Value, WasmModule};
 
 const LINKING_TEST_HOST_WASM: &str = "build/wasm_linking_test_host.wasm";
@@ -209,6 +209,14 @@ impl ImportDispatcher for TestDispatcher<'_> {
             );
         }
     }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.wasi.exit_code()
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        self.wasi.take_trap()
+    }
 }
 
 fn execute_wasm_module<'a>(arena: &'a Bump, orig_module: WasmModule<'a>) -> Result<i32, String> {
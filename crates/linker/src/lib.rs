@@ -50,7 +50,9 @@ pub fn preprocessed_host_filename(target: Target) -> String {
     format!("{target}.{PRECOMPILED_HOST_EXT}")
 }
 
-fn metadata_file_name(target: Target) -> String {
+/// The file that `preprocess_host` writes next to the preprocessed host, describing where its
+/// placeholders for the app's symbols are
+pub fn metadata_file_name(target: Target) -> String {
     format!("metadata_{}.rm", target)
 }
 
@@ -1,7 +1,7 @@
 use bumpalo::Bump;
 use regex::Regex;
 use roc_wasm_interp::{
-    wasi, DefaultImportDispatcher, ImportDispatcher, Instance, Value, WasiDispatcher,
+    wasi, DefaultImportDispatcher, ImportDispatcher, Instance, TrapReason, Value, WasiDispatcher,
 };
 
 const COMPILER_BYTES: &[u8] =
@@ -121,6 +121,14 @@ impl<'a> ImportDispatcher for CompilerDispatcher<'a> {
             unknown()
         }
     }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.wasi.exit_code()
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        self.wasi.take_trap()
+    }
 }
 
 fn run(src: &'static str) -> String {
@@ -1,4 +1,4 @@
-use roc_collections::all::MutSet;
+use roc_collections::all::SortedSet;
 use roc_module::ident::{Ident, Lowercase, ModuleName};
 use roc_module::symbol::{ScopeModuleSource, DERIVABLE_ABILITIES};
 use roc_problem::can::PrecedenceProblem::BothNonAssociative;
@@ -20,6 +20,8 @@ const NAMING_PROBLEM: &str = "NAMING PROBLEM";
 const UNRECOGNIZED_NAME: &str = "UNRECOGNIZED NAME";
 const UNUSED_DEF: &str = "UNUSED DEFINITION";
 const UNUSED_IMPORT: &str = "UNUSED IMPORT";
+const DEPRECATED: &str = "DEPRECATED";
+const SHADOWED_BY_LOCAL: &str = "SHADOWED BY LOCAL";
 const IMPORT_NAME_CONFLICT: &str = "IMPORT NAME CONFLICT";
 const EXPLICIT_BUILTIN_IMPORT: &str = "EXPLICIT BUILTIN IMPORT";
 const UNUSED_ALIAS_PARAM: &str = "UNUSED TYPE ALIAS PARAMETER";
@@ -36,6 +38,8 @@ pub const CIRCULAR_DEF: &str = "CIRCULAR DEFINITION";
 const DUPLICATE_NAME: &str = "DUPLICATE NAME";
 const VALUE_NOT_EXPOSED: &str = "NOT EXPOSED";
 const MODULE_NOT_IMPORTED: &str = "MODULE NOT IMPORTED";
+const MODULE_NOT_FOUND: &str = "MODULE NOT FOUND";
+const RE_EXPORT_CYCLE: &str = "RE-EXPORT CYCLE";
 const INGESTED_FILE_ERROR: &str = "INGESTED FILE ERROR";
 const NESTED_DATATYPE: &str = "NESTED DATATYPE";
 const CONFLICTING_NUMBER_SUFFIX: &str = "CONFLICTING NUMBER SUFFIX";
@@ -1306,6 +1310,60 @@ pub fn can_problem<'b>(
             ]);
             title = "OVERAPPLIED CRASH".to_string();
         }
+        Problem::UseOfDeprecated {
+            symbol,
+            note,
+            region,
+        } => {
+            let mut stack = vec![
+                alloc.concat([
+                    alloc.symbol_qualified(symbol),
+                    alloc.reflow(" is deprecated:"),
+                ]),
+                alloc.region(lines.convert_region(region), severity),
+            ];
+            if !note.is_empty() {
+                stack.push(alloc.string(note));
+            }
+            stack.push(alloc.concat([
+                alloc.reflow("It still works for now, but a future version of "),
+                alloc.module(symbol.module_id()),
+                alloc.reflow(" may remove it."),
+            ]));
+            doc = alloc.stack(stack);
+
+            title = DEPRECATED.to_string();
+        }
+        Problem::QualifiedLookupShadowedByLocal {
+            symbol,
+            region,
+            local,
+            local_region,
+        } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This refers to "),
+                    alloc.symbol_qualified(symbol),
+                    alloc.reflow(":"),
+                ]),
+                alloc.region(lines.convert_region(region), severity),
+                alloc.concat([
+                    alloc.reflow("But there's also a local named "),
+                    alloc.symbol_unqualified(local),
+                    alloc.reflow(" in scope here:"),
+                ]),
+                alloc.region(lines.convert_region(local_region), severity),
+                alloc.concat([
+                    alloc.reflow("So writing just "),
+                    alloc.symbol_unqualified(local),
+                    alloc.reflow(" instead would refer to the local, not to "),
+                    alloc.symbol_qualified(symbol),
+                    alloc.reflow("."),
+                ]),
+            ]);
+
+            title = SHADOWED_BY_LOCAL.to_string();
+        }
         Problem::FileProblem { filename, error } => {
             let report = to_file_problem_report(alloc, filename, error);
             doc = report.doc;
@@ -1956,18 +2014,68 @@ fn pretty_runtime_error<'b>(
             imported_modules,
             region,
             module_exists,
+            package_shorthand,
         } => {
+            let import_name = module_exists.then(|| match package_shorthand {
+                Some(shorthand) => format!("{shorthand}.{module_name}"),
+                None => module_name.to_string(),
+            });
+
             doc = module_not_found(
                 alloc,
                 lines,
                 region,
                 &module_name,
                 imported_modules,
-                module_exists,
+                import_name,
                 severity,
             );
 
-            title = MODULE_NOT_IMPORTED;
+            title = if module_exists {
+                MODULE_NOT_IMPORTED
+            } else {
+                MODULE_NOT_FOUND
+            };
+        }
+        RuntimeError::ReExportCycle { cycle, region } => {
+            doc = alloc.stack([
+                alloc.reflow("I can't find the module this refers to, because its re-exports go around in a circle:"),
+                alloc.region(lines.convert_region(region), severity),
+                alloc
+                    .intersperse(
+                        cycle.iter().map(|name| alloc.string(name.to_string())),
+                        alloc.text(" → "),
+                    )
+                    .indent(4),
+                alloc.reflow("One of these packages needs to define the module itself, instead of re-exporting it."),
+            ]);
+
+            title = RE_EXPORT_CYCLE;
+        }
+        RuntimeError::ReExportOriginNotFound {
+            module_name,
+            origin,
+            region,
+        } => {
+            doc = alloc.stack([
+                alloc.concat([
+                    alloc.reflow("This refers to "),
+                    alloc.string(module_name.to_string()),
+                    alloc.reflow(", which is re-exported from "),
+                    alloc.string(origin.to_string()),
+                    alloc.reflow(":"),
+                ]),
+                alloc.region(lines.convert_region(region), severity),
+                alloc.concat([
+                    alloc.reflow("But I can't find "),
+                    alloc.string(origin.to_string()),
+                    alloc.reflow(". Is that package missing from the "),
+                    alloc.keyword("packages"),
+                    alloc.reflow(" of the module that re-exports it?"),
+                ]),
+            ]);
+
+            title = MODULE_NOT_FOUND;
         }
         RuntimeError::ReadIngestedFileError {
             filename,
@@ -2503,7 +2611,7 @@ fn not_found<'b>(
     lines: &LineInfo,
     region: roc_region::all::Region,
     name: &Ident,
-    options: MutSet<Box<str>>,
+    options: SortedSet<Box<str>>,
     underscored_suggestion_region: Option<Region>,
     severity: Severity,
 ) -> RocDocBuilder<'b> {
@@ -2563,44 +2671,53 @@ fn module_not_found<'b>(
     lines: &LineInfo,
     region: roc_region::all::Region,
     name: &ModuleName,
-    options: MutSet<Box<str>>,
-    module_exists: bool,
+    options: SortedSet<Box<str>>,
+    import_name: Option<String>,
     severity: Severity,
 ) -> RocDocBuilder<'b> {
-    // If the module exists, suggest that the user import it
-    let details = if module_exists {
-        // TODO:  Maybe give an example of how to do that
-        alloc.reflow("Did you mean to import it?")
-    } else {
-        // If the module might not exist, suggest that it's a typo
-        let mut suggestions =
-            suggest::sort(name.as_str(), options.iter().map(|v| v.as_ref()).collect());
-        suggestions.truncate(4);
-
-        if suggestions.is_empty() {
-            // We don't have any recommended spelling corrections
+    // If the module exists, suggest that the user import it by the name it has in its package
+    if let Some(import_name) = import_name {
+        return alloc.stack([
             alloc.concat([
-                alloc.reflow("Is there an "),
-                alloc.keyword("import"),
-                alloc.reflow(" or "),
-                alloc.keyword("exposing"),
-                alloc.reflow(" missing up-top"),
-            ])
-        } else {
-            alloc.stack([
-                alloc.reflow("Is there an import missing? Perhaps there is a typo. Did you mean one of these?"),
-                alloc
-                    .vcat(suggestions.into_iter().map(|v| alloc.string(v.to_string())))
-                    .indent(4),
-            ])
-        }
+                alloc.reflow("The `"),
+                alloc.string(name.to_string()),
+                alloc.reflow("` module is not imported:"),
+            ]),
+            alloc.region(lines.convert_region(region), severity),
+            alloc.concat([
+                alloc.reflow("It exists, so you can use it by adding "),
+                alloc.string(format!("`import {import_name}`")),
+                alloc.reflow(" at the top of this file, or above the definition that uses it."),
+            ]),
+        ]);
+    }
+
+    // Otherwise, suggest that it's a typo
+    let mut suggestions =
+        suggest::sort(name.as_str(), options.iter().map(|v| v.as_ref()).collect());
+    suggestions.truncate(4);
+
+    let details = if suggestions.is_empty() {
+        // We don't have any recommended spelling corrections
+        alloc.concat([
+            alloc.reflow("Is there a typo in the module name, or a package missing from the "),
+            alloc.keyword("packages"),
+            alloc.reflow(" in the header?"),
+        ])
+    } else {
+        alloc.stack([
+            alloc.reflow("Perhaps there is a typo. Did you mean one of these imported modules?"),
+            alloc
+                .vcat(suggestions.into_iter().map(|v| alloc.string(v.to_string())))
+                .indent(4),
+        ])
     };
 
     alloc.stack([
         alloc.concat([
-            alloc.reflow("The `"),
+            alloc.reflow("I can't find a `"),
             alloc.string(name.to_string()),
-            alloc.reflow("` module is not imported:"),
+            alloc.reflow("` module in this project or any of its packages:"),
         ]),
         alloc.region(lines.convert_region(region), severity),
         details,
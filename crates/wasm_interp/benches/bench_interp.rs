@@ -0,0 +1,89 @@
+use bumpalo::Bump;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use roc_wasm_interp::{DefaultImportDispatcher, Instance, Value};
+use roc_wasm_module::{
+    opcodes::OpCode, Export, ExportType, SerialBuffer, Serialize, Signature, ValueType, WasmModule,
+};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: i32 = 100_000;
+
+/// A module exporting `count_down(n)`, which loops until `n` reaches zero
+fn count_down_module(arena: &Bump) -> WasmModule<'_> {
+    let mut module = WasmModule::new(arena);
+
+    module.add_function_signature(Signature {
+        param_types: bumpalo::vec![in arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.export.append(Export {
+        name: "count_down",
+        ty: ExportType::Func,
+        index: 0,
+    });
+
+    let mut body = bumpalo::vec![in arena; 0]; // no locals
+    body.push(OpCode::LOOP as u8);
+    body.push(ValueType::VOID);
+    body.push(OpCode::GETLOCAL as u8);
+    body.encode_u32(0);
+    body.push(OpCode::I32CONST as u8);
+    body.encode_i32(1);
+    body.push(OpCode::I32SUB as u8);
+    body.push(OpCode::TEELOCAL as u8);
+    body.encode_u32(0);
+    body.push(OpCode::BRIF as u8);
+    body.encode_u32(0);
+    body.push(OpCode::END as u8);
+    body.push(OpCode::GETLOCAL as u8);
+    body.encode_u32(0);
+    body.push(OpCode::END as u8);
+
+    module.code.function_count = 1;
+    module.code.function_offsets.push(0);
+    body.serialize(&mut module.code.bytes);
+
+    module
+}
+
+/// The same loop with and without per-instruction instrumentation.
+/// A deadline is enough to take the instrumented path, since it has to be checked as the program runs.
+pub fn interp_benchmark(c: &mut Criterion) {
+    let arena = Bump::new();
+    let module = count_down_module(&arena);
+
+    c.bench_function("count down, uninstrumented", |b| {
+        let mut inst =
+            Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false)
+                .unwrap();
+        b.iter(|| {
+            let result = inst.call_export("count_down", [Value::I32(black_box(ITERATIONS))]);
+            black_box(result.unwrap());
+        })
+    });
+
+    c.bench_function("count down, with a deadline", |b| {
+        let mut inst =
+            Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false)
+                .unwrap()
+                .with_deadline(Instant::now() + Duration::from_secs(24 * 60 * 60));
+        b.iter(|| {
+            let result = inst.call_export("count_down", [Value::I32(black_box(ITERATIONS))]);
+            black_box(result.unwrap());
+        })
+    });
+
+    c.bench_function("count down, with an opcode profile", |b| {
+        let mut inst =
+            Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false)
+                .unwrap();
+        inst.enable_opcode_profile();
+        b.iter(|| {
+            let result = inst.call_export("count_down", [Value::I32(black_box(ITERATIONS))]);
+            black_box(result.unwrap());
+        })
+    });
+}
+
+criterion_group!(benches, interp_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// One active function call, from `Instance::backtrace`.
+///
+/// It's displayed as `module::function (func[12] @0x3a)`, using names from the module's
+/// Name section. Without a function name, it's just `func[12] @0x3a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo<'a> {
+    /// Index of the function, counting imports
+    pub fn_index: u32,
+    /// The module name from the Name section, if it has one
+    pub module_name: Option<&'a str>,
+    /// The function name from the Name section, if it has one
+    pub function_name: Option<&'a str>,
+    /// Address in the Code section that the frame is executing.
+    /// For callers, this is the call instruction rather than the return address.
+    pub address: usize,
+}
+
+impl fmt::Display for FrameInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            fn_index, address, ..
+        } = self;
+        match (self.module_name, self.function_name) {
+            (Some(module_name), Some(function_name)) => write!(
+                f,
+                "{module_name}::{function_name} (func[{fn_index}] @{address:#x})"
+            ),
+            (None, Some(function_name)) => {
+                write!(f, "{function_name} (func[{fn_index}] @{address:#x})")
+            }
+            (_, None) => write!(f, "func[{fn_index}] @{address:#x}"),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::wasi::{self, WasiDispatcher};
+use crate::{ImportDispatcher, TrapReason, Value};
+
+/// A host function that the Wasm module can import
+pub type ImportFn<'a> = Box<dyn FnMut(&[Value], &mut [u8]) -> Option<Value> + 'a>;
+
+/// An ImportDispatcher built from a list of closures, so that hosts can register
+/// imports inline instead of writing a new struct. WASI calls go to `wasi`,
+/// unless a closure was registered for them. Imported globals can be registered too.
+///
+/// ```
+/// # use roc_wasm_interp::{ClosureImportDispatcher, Value};
+/// let dispatcher = ClosureImportDispatcher::default()
+///     .with("env", "double", |args, _memory| {
+///         Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+///     });
+/// ```
+#[derive(Default)]
+pub struct ClosureImportDispatcher<'a> {
+    pub wasi: WasiDispatcher<'a>,
+    functions: Vec<(String, String, ImportFn<'a>)>,
+    globals: Vec<(String, String, Value)>,
+}
+
+impl<'a> ClosureImportDispatcher<'a> {
+    pub fn new(args: &'a [&'a [u8]]) -> Self {
+        ClosureImportDispatcher {
+            wasi: WasiDispatcher::new(args),
+            functions: Vec::new(),
+            globals: Vec::new(),
+        }
+    }
+
+    /// Register a closure to handle calls to `module_name.function_name`
+    pub fn with<F>(mut self, module_name: &str, function_name: &str, function: F) -> Self
+    where
+        F: FnMut(&[Value], &mut [u8]) -> Option<Value> + 'a,
+    {
+        self.functions.push((
+            module_name.to_string(),
+            function_name.to_string(),
+            Box::new(function),
+        ));
+        self
+    }
+
+    /// Provide `module_name.global_name` to modules that import it, starting with this value
+    pub fn with_global(mut self, module_name: &str, global_name: &str, value: Value) -> Self {
+        self.globals
+            .push((module_name.to_string(), global_name.to_string(), value));
+        self
+    }
+}
+
+impl<'a> ImportDispatcher for ClosureImportDispatcher<'a> {
+    fn dispatch(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        arguments: &[Value],
+        memory: &mut [u8],
+    ) -> Option<Value> {
+        let registered = self
+            .functions
+            .iter_mut()
+            .find(|(m, f, _)| m == module_name && f == function_name);
+
+        match registered {
+            Some((_, _, function)) => function(arguments, memory),
+            None if module_name == wasi::MODULE_NAME => {
+                self.wasi.dispatch(function_name, arguments, memory)
+            }
+            // check_import has already trapped
+            None => None,
+        }
+    }
+
+    fn can_handle(&self, module_name: &str, function_name: &str) -> bool {
+        let is_registered = self
+            .functions
+            .iter()
+            .any(|(m, f, _)| m == module_name && f == function_name);
+
+        is_registered || module_name == wasi::MODULE_NAME
+    }
+
+    fn check_import(&mut self, module_name: &str, function_name: &str) -> Result<(), TrapReason> {
+        if self.can_handle(module_name, function_name) {
+            Ok(())
+        } else {
+            Err(TrapReason::UnknownImport {
+                module_name: module_name.to_string(),
+                function_name: function_name.to_string(),
+            })
+        }
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.wasi.exit_code()
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        self.wasi.take_trap()
+    }
+
+    fn import_global(&self, module_name: &str, global_name: &str) -> Option<Value> {
+        self.globals
+            .iter()
+            .find(|(m, g, _)| m == module_name && g == global_name)
+            .map(|(_, _, value)| *value)
+    }
+}
@@ -0,0 +1,51 @@
+use roc_wasm_module::{ExportType, Value, ValueType};
+use std::fmt;
+
+use crate::format::{format_result, ValueHint};
+use crate::typed::signature_string;
+
+/// One of the module's exports, from `Instance::exports`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportInfo<'a> {
+    pub name: &'a str,
+    pub ty: ExportType,
+    /// Index in the function, table, memory or global index space, depending on `ty`.
+    /// Function indices count imported functions first.
+    pub index: u32,
+    /// The function's type, if it's a function
+    pub signature: Option<ExportSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportSignature {
+    pub param_types: Vec<ValueType>,
+    pub ret_type: Option<ValueType>,
+}
+
+impl ExportSignature {
+    /// Show what a call to this function returned, with its type. See `format_value`.
+    pub fn format_result(&self, result: Option<Value>, hint: Option<ValueHint>) -> String {
+        format_result(self.ret_type, result, hint)
+    }
+}
+
+/// Written like `func main (I32, I32) -> I32` or `memory memory`, e.g. for listing the exports in a CLI
+impl fmt::Display for ExportInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.ty {
+            ExportType::Func => "func",
+            ExportType::Table => "table",
+            ExportType::Mem => "memory",
+            ExportType::Global => "global",
+        };
+        write!(f, "{kind} {}", self.name)?;
+        if let Some(ExportSignature {
+            param_types,
+            ret_type,
+        }) = &self.signature
+        {
+            write!(f, " {}", signature_string(param_types, *ret_type))?;
+        }
+        Ok(())
+    }
+}
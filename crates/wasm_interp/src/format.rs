@@ -0,0 +1,68 @@
+use roc_wasm_module::{Value, ValueType};
+
+/// How to read a value, beyond its Wasm type. A Roc `Bool`, `U8` and `I32` are all `I32` in Wasm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueHint {
+    Bool,
+    /// A Unicode scalar value
+    Char,
+    Unsigned,
+    /// The bits in hexadecimal. For floats, that's their encoding.
+    Hex,
+}
+
+/// Show a value with its Wasm type, like `42 : I32`.
+/// With a hint, the interpretation comes first, like `Bool.true (1 : I32)`.
+/// Hints that don't make sense for the value, like `Char` for an invalid code point, are ignored.
+/// If the value doesn't have the `expected` type from the signature, that's shown too.
+pub fn format_value(value: Value, expected: ValueType, hint: Option<ValueHint>) -> String {
+    let (ty, number) = match value {
+        Value::I32(x) => (ValueType::I32, x.to_string()),
+        Value::I64(x) => (ValueType::I64, x.to_string()),
+        Value::F32(x) => (ValueType::F32, format!("{x:?}")),
+        Value::F64(x) => (ValueType::F64, format!("{x:?}")),
+    };
+    let typed = format!("{number} : {ty:?}");
+    let formatted = match hint.and_then(|hint| interpret(value, hint)) {
+        Some(interpretation) => format!("{interpretation} ({typed})"),
+        None => typed,
+    };
+    if ty == expected {
+        formatted
+    } else {
+        format!("{formatted} (expected {expected:?})")
+    }
+}
+
+/// Show the result of calling a function that returns `ret_type`, as `Instance::call_export` returns it.
+/// Functions that don't return anything show as `{}`, like Roc's empty record.
+pub fn format_result(
+    ret_type: Option<ValueType>,
+    result: Option<Value>,
+    hint: Option<ValueHint>,
+) -> String {
+    match (ret_type, result) {
+        (Some(ty), Some(value)) => format_value(value, ty, hint),
+        (None, None) => "{}".to_string(),
+        (Some(ty), None) => format!("nothing (expected {ty:?})"),
+        (None, Some(value)) => {
+            let ty = ValueType::from(value);
+            format!("{} (expected nothing)", format_value(value, ty, hint))
+        }
+    }
+}
+
+fn interpret(value: Value, hint: ValueHint) -> Option<String> {
+    match (hint, value) {
+        (ValueHint::Bool, Value::I32(0)) => Some("Bool.false".to_string()),
+        (ValueHint::Bool, Value::I32(1)) => Some("Bool.true".to_string()),
+        (ValueHint::Char, Value::I32(x)) => char::from_u32(x as u32).map(|c| format!("{c:?}")),
+        (ValueHint::Unsigned, Value::I32(x)) => Some((x as u32).to_string()),
+        (ValueHint::Unsigned, Value::I64(x)) => Some((x as u64).to_string()),
+        (ValueHint::Hex, Value::I32(x)) => Some(format!("{x:#x}")),
+        (ValueHint::Hex, Value::I64(x)) => Some(format!("{x:#x}")),
+        (ValueHint::Hex, Value::F32(x)) => Some(format!("{:#x}", x.to_bits())),
+        (ValueHint::Hex, Value::F64(x)) => Some(format!("{:#x}", x.to_bits())),
+        _ => None,
+    }
+}
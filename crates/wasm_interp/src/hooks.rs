@@ -0,0 +1,33 @@
+use std::fmt::{self, Debug};
+
+/// Callback for `memory.grow`, receiving the number of pages before and after growing.
+pub type MemoryGrowHook<'a> = Box<dyn FnMut(u32, u32) + 'a>;
+
+/// Callback for a load or store that is about to trap because it's out of bounds,
+/// receiving the address, the size of the access in bytes, and whether it's a write.
+pub type MemoryFaultHook<'a> = Box<dyn FnMut(u64, u32, bool) + 'a>;
+
+/// Callback for entering or leaving a Wasm function, receiving the function's index.
+pub type CallHook<'a> = Box<dyn FnMut(u32) + 'a>;
+
+/// Optional callbacks into the embedder's code, for profiling and debugging.
+/// They're all `None` by default, so an Instance without hooks doesn't do any extra work.
+#[derive(Default)]
+pub(crate) struct Hooks<'a> {
+    pub memory_grow: Option<MemoryGrowHook<'a>>,
+    pub memory_fault: Option<MemoryFaultHook<'a>>,
+    pub call: Option<CallHook<'a>>,
+    pub ret: Option<CallHook<'a>>,
+}
+
+impl Debug for Hooks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The closures themselves can't be printed, so just show which ones are registered
+        f.debug_struct("Hooks")
+            .field("memory_grow", &self.memory_grow.is_some())
+            .field("memory_fault", &self.memory_fault.is_some())
+            .field("call", &self.call.is_some())
+            .field("ret", &self.ret.is_some())
+            .finish()
+    }
+}
@@ -1,16 +1,36 @@
-use bumpalo::{collections::Vec, Bump};
+use bumpalo::{
+    collections::{CollectionAllocErr, Vec},
+    Bump,
+};
 use std::fmt::{self, Write};
+use std::fs;
+use std::io;
 use std::iter::{self, once, Iterator};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Instant;
 
-use roc_wasm_module::opcodes::{MemoryInstruction, OpCode};
+use roc_wasm_module::opcodes::{
+    AtomicInstruction, AtomicRmwOp, AtomicWidth, MemoryInstruction, OpCode, LOOKUP_TABLE,
+};
 use roc_wasm_module::parse::{Parse, SkipBytes};
-use roc_wasm_module::sections::{ImportDesc, MemorySection, SignatureParamsIter};
-use roc_wasm_module::{ExportType, WasmModule};
+use roc_wasm_module::sections::{Import, ImportDesc, Limits, MemorySection, SignatureParamsIter};
+use roc_wasm_module::{ExportType, GlobalType, WasmModule};
 use roc_wasm_module::{Value, ValueType};
 
+use crate::backtrace::FrameInfo;
+use crate::exports::{ExportInfo, ExportSignature};
 use crate::frame::Frame;
+use crate::hooks::{CallHook, Hooks, MemoryFaultHook, MemoryGrowHook};
+use crate::mem_stats::MemStats;
+use crate::opcode_profile::OpcodeProfile;
+use crate::report::{RunOutcome, RunReport};
+use crate::snapshot::Snapshot;
+use crate::trace::{ExecutionTrace, TraceEvent, TraceMode};
+use crate::typed::{signature_string, WasmArgs, WasmResult};
+use crate::unsupported::unsupported;
 use crate::value_store::ValueStore;
-use crate::{Error, ImportDispatcher};
+use crate::{verify_with_limits, Error, ImportDispatcher, ModuleLimits, TrapReason};
 
 #[derive(Debug)]
 pub enum Action {
@@ -30,6 +50,19 @@ enum BlockType {
 struct Block {
     ty: BlockType,
     vstack: usize,
+    /// The value the block leaves on the stack when it ends or is branched out of
+    result: Option<ValueType>,
+}
+
+/// A wall-clock limit on running the program, checked every `check_interval` instructions
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    at: Instant,
+    check_interval: u64,
+}
+
+impl Deadline {
+    const DEFAULT_CHECK_INTERVAL: u64 = 10_000;
 }
 
 #[derive(Debug, Clone)]
@@ -44,10 +77,14 @@ pub struct Instance<'a, I: ImportDispatcher> {
     pub(crate) module: &'a WasmModule<'a>,
     /// Contents of the WebAssembly instance's memory
     pub memory: Vec<'a, u8>,
+    /// Whether the module defines a memory at all. Pure computations sometimes don't.
+    has_memory: bool,
+    /// Whether the memory uses 64-bit addresses (memory64 proposal)
+    is_memory64: bool,
     /// The current call frame
     pub(crate) current_frame: Frame,
     /// Previous call frames
-    previous_frames: Vec<'a, Frame>,
+    pub(crate) previous_frames: Vec<'a, Frame>,
     /// The WebAssembly stack machine's stack of values
     pub(crate) value_store: ValueStore<'a>,
     /// Values of any global variables
@@ -58,7 +95,10 @@ pub struct Instance<'a, I: ImportDispatcher> {
     blocks: Vec<'a, Block>,
     /// Cache for branching instructions, split into buckets for each function.
     branch_cache: Vec<'a, Vec<'a, BranchCacheEntry>>,
-    /// Number of imports in the module
+    /// The module's imported functions, which come first in the function index space.
+    /// Other kinds of imports don't get function indices.
+    imported_functions: Vec<'a, &'a Import<'a>>,
+    /// Number of imported functions in the module
     import_count: usize,
     /// Import dispatcher from user code
     pub import_dispatcher: I,
@@ -66,6 +106,24 @@ pub struct Instance<'a, I: ImportDispatcher> {
     import_arguments: Vec<'a, Value>,
     /// temporary storage for output using the --debug option
     debug_string: Option<String>,
+    /// The largest size the memory has reached, in pages
+    memory_high_water_mark: u32,
+    /// Number of instructions executed so far, across all calls
+    instruction_count: u64,
+    /// When to stop the program, if there's a time limit
+    deadline: Option<Deadline>,
+    /// Optional callbacks into the embedder's code
+    hooks: Hooks<'a>,
+    /// Time spent on each opcode, if profiling is enabled
+    opcode_profile: Option<OpcodeProfile>,
+    /// Memory access counts, if they're enabled
+    mem_stats: Option<MemStats>,
+    /// The state from before the last `eval`, if it trapped
+    failed_eval: Option<Snapshot>,
+    /// The trace being recorded or replayed, if any
+    trace: Option<TraceMode>,
+    /// How the last call stopped early, if the program exited or the embedder trapped it
+    stopped: Option<RunOutcome>,
 }
 
 impl<'a, I: ImportDispatcher> Instance<'a, I> {
@@ -84,6 +142,8 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         Instance {
             module: arena.alloc(WasmModule::new(arena)),
             memory: Vec::from_iter_in(iter::repeat(0).take(mem_bytes as usize), arena),
+            has_memory: true,
+            is_memory64: false,
             current_frame: Frame::new(),
             previous_frames: Vec::new_in(arena),
             value_store: ValueStore::new(arena),
@@ -91,10 +151,20 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             program_counter,
             blocks: Vec::new_in(arena),
             branch_cache: bumpalo::vec![in arena; bumpalo::vec![in arena]],
+            imported_functions: Vec::new_in(arena),
             import_count: 0,
             import_dispatcher,
             import_arguments: Vec::new_in(arena),
             debug_string: Some(String::new()),
+            memory_high_water_mark: memory_pages,
+            instruction_count: 0,
+            deadline: None,
+            hooks: Hooks::default(),
+            opcode_profile: None,
+            mem_stats: None,
+            failed_eval: None,
+            trace: None,
+            stopped: None,
         }
     }
 
@@ -115,25 +185,127 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         import_dispatcher: I,
         is_debug_mode: bool,
     ) -> Result<Self, std::string::String> {
-        let mem_bytes = module.memory.min_bytes().map_err(|e| {
-            format!(
-                "Error parsing Memory section at offset {:#x}:\n{}",
-                e.offset, e.message
-            )
-        })?;
-        let mut memory = Vec::from_iter_in(iter::repeat(0).take(mem_bytes as usize), arena);
-        module.data.load_into(&mut memory)?;
+        let limits = ModuleLimits::default();
+        Self::instantiate(
+            arena,
+            module,
+            None,
+            Some(&limits),
+            import_dispatcher,
+            is_debug_mode,
+        )
+    }
+
+    /// Create an Instance, rejecting the module if it's bigger than `limits` allow.
+    /// Use this for modules that can't be trusted, where the default limits are too generous.
+    pub fn for_module_with_limits(
+        arena: &'a Bump,
+        module: &'a WasmModule<'a>,
+        limits: &ModuleLimits,
+        import_dispatcher: I,
+        is_debug_mode: bool,
+    ) -> Result<Self, std::string::String> {
+        Self::instantiate(
+            arena,
+            module,
+            None,
+            Some(limits),
+            import_dispatcher,
+            is_debug_mode,
+        )
+    }
+
+    /// Create an Instance of a module that imports its memory, using `memory` from the host.
+    /// The module's data segments are written into it, so the host can fill in anything else
+    /// beforehand. Afterwards, `Instance::memory` is the same memory, and `into_memory` gives
+    /// it back to the host.
+    ///
+    /// `memory` must be a whole number of pages, within the limits that the module imports it with.
+    pub fn for_module_with_memory(
+        arena: &'a Bump,
+        module: &'a WasmModule<'a>,
+        memory: Vec<'a, u8>,
+        import_dispatcher: I,
+        is_debug_mode: bool,
+    ) -> Result<Self, std::string::String> {
+        Self::instantiate(
+            arena,
+            module,
+            Some(memory),
+            Some(&ModuleLimits::default()),
+            import_dispatcher,
+            is_debug_mode,
+        )
+    }
 
-        let globals = module.global.initial_values(arena);
+    /// `limits` is None for a module that was already verified, e.g. by `SharedModuleBytes::new`
+    pub(crate) fn instantiate(
+        arena: &'a Bump,
+        module: &'a WasmModule<'a>,
+        host_memory: Option<Vec<'a, u8>>,
+        limits: Option<&ModuleLimits>,
+        import_dispatcher: I,
+        is_debug_mode: bool,
+    ) -> Result<Self, std::string::String> {
+        if let Some(limits) = limits {
+            verify_with_limits(module, limits).map_err(|e| format!("Invalid Wasm module: {e}"))?;
+        }
 
-        // We don't handle non-function import types (memories, tables, and globals),
-        // and it's nice for lookups to assume they're all functions, so let's assert that.
-        let all_imports_are_functions = module.import.imports.iter().all(|imp| imp.is_function());
+        // We don't handle imported tables, so let's check for them up front
+        let unsupported_import = module
+            .import
+            .imports
+            .iter()
+            .find(|imp| matches!(imp.description, ImportDesc::Table { .. }));
         assert!(
-            all_imports_are_functions,
-            "This Wasm interpreter doesn't support non-function imports"
+            unsupported_import.is_none(),
+            "This Wasm interpreter doesn't support importing tables, but the module imports {:?}",
+            unsupported_import.map(|imp| (imp.module, imp.name))
         );
 
+        let imported_memory = imported_memory_limits(module);
+        let has_memory = module.memory.count > 0 || imported_memory.is_some();
+        let mut memory = match (imported_memory, host_memory) {
+            (Some(limits), Some(memory)) => {
+                check_host_memory(limits, memory.len())?;
+                memory
+            }
+            (Some(_), None) => {
+                return Err(
+                    "This module imports its memory, so the host needs to provide it, using Instance::for_module_with_memory".into(),
+                );
+            }
+            (None, Some(_)) => {
+                return Err(
+                    "The host provided a memory, but this module doesn't import one".into(),
+                );
+            }
+            (None, None) => {
+                let mem_bytes = if has_memory {
+                    module.memory.min_bytes().map_err(|e| {
+                        format!(
+                            "Error parsing Memory section at offset {:#x}:\n{}",
+                            e.offset, e.message
+                        )
+                    })?
+                } else {
+                    0
+                };
+                Vec::from_iter_in(iter::repeat(0).take(mem_bytes as usize), arena)
+            }
+        };
+        let mem_bytes = memory.len() as u64;
+        module.data.load_into(&mut memory)?;
+
+        // Imported globals come first in the index space
+        let mut globals = Vec::new_in(arena);
+        for import in module.import.imports.iter() {
+            if let ImportDesc::Global { ty } = &import.description {
+                globals.push(imported_global_value(&import_dispatcher, import, ty)?);
+            }
+        }
+        globals.extend(module.global.initial_values(arena));
+
         let value_store = ValueStore::new(arena);
 
         let debug_string = if is_debug_mode {
@@ -142,7 +314,11 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             None
         };
 
-        let import_count = module.import.imports.len();
+        let imported_functions = Vec::from_iter_in(
+            module.import.imports.iter().filter(|imp| imp.is_function()),
+            arena,
+        );
+        let import_count = imported_functions.len();
         let branch_cache = {
             let num_functions = import_count + module.code.function_count as usize;
             let empty_caches_iter = iter::repeat(Vec::new_in(arena)).take(num_functions);
@@ -152,6 +328,8 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         Ok(Instance {
             module,
             memory,
+            has_memory,
+            is_memory64: module.memory.is_memory64(),
             current_frame: Frame::new(),
             previous_frames: Vec::new_in(arena),
             value_store,
@@ -159,19 +337,341 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             program_counter: usize::MAX,
             blocks: Vec::new_in(arena),
             branch_cache,
+            imported_functions,
             import_count,
             import_dispatcher,
             import_arguments: Vec::new_in(arena),
             debug_string,
+            memory_high_water_mark: (mem_bytes / MemorySection::PAGE_SIZE as u64) as u32,
+            instruction_count: 0,
+            deadline: None,
+            hooks: Hooks::default(),
+            opcode_profile: None,
+            mem_stats: None,
+            failed_eval: None,
+            trace: None,
+            stopped: None,
+        })
+    }
+
+    /// Create an independent Instance of the same module, with fresh memory, globals and stacks,
+    /// as if it had just been instantiated. The parsed module is shared rather than re-parsed
+    /// or re-verified. Hooks and settings like the deadline are not copied.
+    ///
+    /// The module lives in a `Bump` arena, which can't be shared between threads, so this is for
+    /// running several Instances on one thread. For multiple threads, use `SharedModuleBytes`.
+    pub fn fork<J: ImportDispatcher>(
+        &self,
+        arena: &'a Bump,
+        import_dispatcher: J,
+    ) -> Result<Instance<'a, J>, std::string::String> {
+        Instance::instantiate(
+            arena,
+            self.module,
+            None,
+            None,
+            import_dispatcher,
+            self.debug_string.is_some(),
+        )
+    }
+
+    /// Trap with `TrapReason::Timeout` if the program is still running at `deadline`.
+    /// Unlike counting instructions, this also covers time spent in slow imports.
+    /// The time is only checked every few thousand instructions, so the program can overrun a bit;
+    /// see `with_deadline_check_interval`.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        let check_interval = self
+            .deadline
+            .map_or(Deadline::DEFAULT_CHECK_INTERVAL, |d| d.check_interval);
+        self.deadline = Some(Deadline {
+            at: deadline,
+            check_interval,
+        });
+        self
+    }
+
+    /// Check the deadline every `instructions` instructions instead of the default 10,000.
+    /// Checking more often stops closer to the deadline, but reading the clock slows the program down.
+    /// Has no effect without `with_deadline`.
+    pub fn with_deadline_check_interval(mut self, instructions: u64) -> Self {
+        assert!(
+            instructions > 0,
+            "The deadline check interval must be at least 1"
+        );
+        if let Some(deadline) = self.deadline.as_mut() {
+            deadline.check_interval = instructions;
+        }
+        self
+    }
+
+    /// Register a callback to be notified every time `memory.grow` succeeds.
+    /// It receives the number of pages before and after growing.
+    pub fn set_memory_grow_hook(&mut self, hook: MemoryGrowHook<'a>) {
+        self.hooks.memory_grow = Some(hook);
+    }
+
+    /// Register a callback to be notified of a load or store that is out of bounds,
+    /// just before it traps. It receives the address, the access size in bytes,
+    /// and whether the access is a write.
+    pub fn set_memory_fault_hook(&mut self, hook: MemoryFaultHook<'a>) {
+        self.hooks.memory_fault = Some(hook);
+    }
+
+    /// Register a callback to be notified every time a Wasm function's frame is pushed onto the
+    /// call stack, including the exported function the call starts from. It receives the function index.
+    /// Calls to imports don't get a frame, so they aren't reported.
+    pub fn set_call_hook(&mut self, hook: CallHook<'a>) {
+        self.hooks.call = Some(hook);
+    }
+
+    /// Register a callback to be notified every time a Wasm function returns and its frame is popped.
+    /// It receives the function index. Each call gets exactly one return, and a tail call returns from
+    /// the caller before entering the callee. Frames that are abandoned by a trap don't get one.
+    pub fn set_return_hook(&mut self, hook: CallHook<'a>) {
+        self.hooks.ret = Some(hook);
+    }
+
+    fn notify_call(&mut self, fn_index: usize) {
+        if let Some(hook) = self.hooks.call.as_mut() {
+            hook(fn_index as u32);
+        }
+    }
+
+    fn notify_return(&mut self, fn_index: usize) {
+        if let Some(hook) = self.hooks.ret.as_mut() {
+            hook(fn_index as u32);
+        }
+    }
+
+    /// Start recording which functions are called and what the imports return, for the rest of this
+    /// Instance's calls. Any trace that was being recorded or replayed is discarded.
+    pub fn record_trace(&mut self) {
+        self.trace = Some(TraceMode::Record(ExecutionTrace::default()));
+    }
+
+    /// The trace recorded since `record_trace`
+    pub fn saved_trace(&self) -> Option<&ExecutionTrace> {
+        match &self.trace {
+            Some(TraceMode::Record(trace)) => Some(trace),
+            _ => None,
+        }
+    }
+
+    /// Check that the following calls follow the same path as when `trace` was recorded, e.g. to find
+    /// nondeterminism or a change in behaviour between interpreter versions. The first call or import
+    /// result that's different traps with `TrapReason::TraceDivergence`. The imports are still called,
+    /// so the host needs to behave the same way it did during recording.
+    pub fn replay(&mut self, trace: ExecutionTrace) {
+        self.trace = Some(TraceMode::Replay {
+            expected: trace,
+            position: 0,
+        });
+    }
+
+    /// Stop replaying, and check that the program got all the way through the trace
+    pub fn finish_replay(&mut self) -> Result<(), std::string::String> {
+        match self.trace.take() {
+            Some(TraceMode::Replay { expected, position }) if position < expected.events.len() => {
+                Err(format!(
+                    "The program stopped at event {position} of the replayed trace, which has {} events. The next one was {}.",
+                    expected.events.len(),
+                    expected.events[position]
+                ))
+            }
+            Some(TraceMode::Replay { .. }) => Ok(()),
+            other => {
+                self.trace = other;
+                Err("There is no trace being replayed".into())
+            }
+        }
+    }
+
+    fn trace_event(&mut self, event: TraceEvent) -> Result<(), Error> {
+        match self.trace.as_mut() {
+            Some(trace) => trace.event(event).map_err(Error::Trap),
+            None => Ok(()),
+        }
+    }
+
+    /// Start recording how much time the interpreter spends on each opcode
+    pub fn enable_opcode_profile(&mut self) {
+        self.opcode_profile
+            .get_or_insert_with(OpcodeProfile::default);
+    }
+
+    pub fn opcode_profile(&self) -> Option<&OpcodeProfile> {
+        self.opcode_profile.as_ref()
+    }
+
+    /// Start counting memory reads, writes and grows
+    pub fn enable_mem_stats(&mut self) {
+        self.mem_stats.get_or_insert_with(MemStats::default);
+    }
+
+    /// The memory access counts since `enable_mem_stats`, along with the high-water mark
+    pub fn mem_stats(&self) -> Option<MemStats> {
+        self.mem_stats.map(|stats| MemStats {
+            high_water_mark: self.memory_high_water_mark,
+            ..stats
+        })
+    }
+
+    /// The imports of this module that `dispatcher` says it can't handle, as (module, function) pairs.
+    /// Check this before running to find missing imports up front, rather than when they're called.
+    pub fn unresolved_imports<D: ImportDispatcher>(
+        &self,
+        dispatcher: &D,
+    ) -> std::vec::Vec<(String, String)> {
+        self.imported_functions
+            .iter()
+            .filter(|import| !dispatcher.can_handle(import.module, import.name))
+            .map(|import| (import.module.to_string(), import.name.to_string()))
+            .collect()
+    }
+
+    /// Everything the module exports, in the order of its export section
+    pub fn exports(&self) -> impl Iterator<Item = ExportInfo<'a>> + '_ {
+        self.module.export.exports.iter().map(|export| {
+            let signature = (export.ty == ExportType::Func).then(|| {
+                let signature_index = self.signature_index(export.index as usize);
+                let (param_types, ret_type) = self.module.types.look_up(signature_index);
+                ExportSignature {
+                    param_types: param_types.collect(),
+                    ret_type,
+                }
+            });
+            ExportInfo {
+                name: export.name,
+                ty: export.ty,
+                index: export.index,
+                signature,
+            }
         })
     }
 
+    /// The index in the type section of a function's signature. It counts imported functions first.
+    fn signature_index(&self, fn_index: usize) -> u32 {
+        if fn_index < self.import_count {
+            match self.imported_functions[fn_index].description {
+                ImportDesc::Func { signature_index } => signature_index,
+                _ => unreachable!(),
+            }
+        } else {
+            self.module.function.signatures[fn_index - self.import_count]
+        }
+    }
+
+    /// The current value of an imported global. The program's writes to mutable globals show up here.
+    pub fn imported_global(&self, module_name: &str, global_name: &str) -> Option<Value> {
+        self.module
+            .import
+            .imports
+            .iter()
+            .filter(|import| matches!(import.description, ImportDesc::Global { .. }))
+            .position(|import| import.module == module_name && import.name == global_name)
+            .map(|index| self.globals[index])
+    }
+
+    /// The code the program exited with, if it called an exit function like WASI `proc_exit`.
+    /// Exiting only stops the Instance, never the host process.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.import_dispatcher.exit_code()
+    }
+
+    /// The linear memory, e.g. for reading what a call left in it.
+    /// This borrows the Instance, so it can only be used between calls. Nothing that runs during
+    /// a call can get at the Instance: imports are only given the memory, and hooks get neither.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The linear memory, e.g. for writing a call's input into it beforehand.
+    /// Like `memory`, this can only be used between calls, and the next call sees what was written.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// The contents of one of the module's Custom sections (see `WasmModule::custom_section`)
+    pub fn custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        self.module.custom_section(name)
+    }
+
+    /// The largest size the memory has reached so far, in pages
+    pub fn memory_high_water_mark(&self) -> u32 {
+        self.memory_high_water_mark
+    }
+
+    /// The number of instructions executed so far, across all calls
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// The number of values on the value stack, including the locals of every frame
+    #[cfg(any(test, feature = "inspect"))]
+    pub fn value_stack_depth(&self) -> usize {
+        self.value_store.depth()
+    }
+
+    /// The values on the value stack, from the bottom up. Locals are stored on the same stack,
+    /// just below the values pushed by their function's instructions.
+    #[cfg(any(test, feature = "inspect"))]
+    pub fn value_stack(&self) -> &[Value] {
+        self.value_store.iter().as_slice()
+    }
+
+    /// Give the memory back to the host, e.g. one passed to `for_module_with_memory`
+    pub fn into_memory(self) -> Vec<'a, u8> {
+        self.memory
+    }
+
+    /// Write the whole linear memory to a file, for post-mortem debugging
+    pub fn dump_memory<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, &self.memory)
+    }
+
+    /// Write `len` bytes of linear memory, starting at address `start`, to a file
+    pub fn dump_memory_range<P: AsRef<Path>>(
+        &self,
+        path: P,
+        start: u32,
+        len: u32,
+    ) -> io::Result<()> {
+        let start = start as usize;
+        let end = start + len as usize;
+        if end > self.memory.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Memory range {start:#x}..{end:#x} is out of bounds. The memory size is {:#x}.",
+                    self.memory.len()
+                ),
+            ));
+        }
+        fs::write(path, &self.memory[start..end])
+    }
+
     pub fn call_export<A>(&mut self, fn_name: &str, arg_values: A) -> Result<Option<Value>, String>
     where
         A: IntoIterator<Item = Value>,
     {
         let (fn_index, param_type_iter, ret_type) =
             self.call_export_help_before_arg_load(self.module, fn_name)?;
+        let n_args = self.push_export_args(fn_name, arg_values, param_type_iter)?;
+
+        self.call_export_help_after_arg_load(self.module, fn_index, n_args, ret_type)
+    }
+
+    /// Push the arguments for a call to an export, checking them against its signature.
+    /// Returns how many it expects.
+    fn push_export_args<A>(
+        &mut self,
+        fn_name: &str,
+        arg_values: A,
+        param_type_iter: SignatureParamsIter,
+    ) -> Result<usize, String>
+    where
+        A: IntoIterator<Item = Value>,
+    {
         let n_args = param_type_iter.len();
 
         for (i, (value, expected_type)) in arg_values.into_iter().zip(param_type_iter).enumerate() {
@@ -184,7 +684,156 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             self.value_store.push(value);
         }
 
-        self.call_export_help_after_arg_load(self.module, fn_index, n_args, ret_type)
+        Ok(n_args)
+    }
+
+    /// Call an exported function that returns exactly one value, and return that value.
+    /// It's an error if the function's signature has no result, or if it exits without returning one.
+    /// (Functions with more than one result can't be declared, since our `Signature` only has one `ret_type`.)
+    pub fn call_one<A>(&mut self, fn_name: &str, arg_values: A) -> Result<Value, String>
+    where
+        A: IntoIterator<Item = Value>,
+    {
+        let (fn_index, param_type_iter, ret_type) =
+            self.call_export_help_before_arg_load(self.module, fn_name)?;
+        if ret_type.is_none() {
+            return Err(format!(
+                "Expected {fn_name} to return one value, but its signature has no result"
+            ));
+        }
+        let n_args = self.push_export_args(fn_name, arg_values, param_type_iter)?;
+
+        self.call_export_help_after_arg_load(self.module, fn_index, n_args, ret_type)?
+            .ok_or_else(|| format!("{fn_name} exited before returning a value"))
+    }
+
+    /// Call an exported function with a tuple of Rust arguments, and convert its return value,
+    /// e.g. `let sum: i64 = inst.call_typed("add", (1i64, 2i64))?;`
+    /// It's an error if the types don't match the function's signature exactly.
+    pub fn call_typed<A, R>(&mut self, fn_name: &str, args: A) -> Result<R, String>
+    where
+        A: WasmArgs,
+        R: WasmResult,
+    {
+        let (fn_index, param_type_iter, ret_type) =
+            self.call_export_help_before_arg_load(self.module, fn_name)?;
+        let param_types: std::vec::Vec<ValueType> = param_type_iter.collect();
+
+        let expected_params = A::param_types();
+        let expected_ret = R::result_type();
+        if param_types != expected_params || ret_type != expected_ret {
+            return Err(format!(
+                "Type mismatch calling {fn_name}. Its signature is {} but it was called as {}",
+                signature_string(&param_types, ret_type),
+                signature_string(&expected_params, expected_ret),
+            ));
+        }
+
+        for value in args.into_values() {
+            self.value_store.push(value);
+        }
+        let return_value = self.call_export_help_after_arg_load(
+            self.module,
+            fn_index,
+            param_types.len(),
+            ret_type,
+        )?;
+
+        R::from_result(return_value)
+            .ok_or_else(|| format!("{fn_name} exited before returning a value"))
+    }
+
+    /// Call an exported function like `call_export`, and tell apart the ways the run can end:
+    /// returning, exiting, or being stopped by a trap.
+    pub fn run<A>(&mut self, fn_name: &str, arg_values: A) -> RunOutcome
+    where
+        A: IntoIterator<Item = Value>,
+    {
+        self.stopped = None;
+        let result = self.call_export(fn_name, arg_values);
+        match (self.stopped.take(), result) {
+            (Some(outcome), _) => outcome,
+            (None, Ok(return_value)) => RunOutcome::Completed(return_value.into_iter().collect()),
+            (None, Err(message)) => RunOutcome::Failed(message),
+        }
+    }
+
+    /// Call an exported function like `call_export`, and summarize the run in one struct
+    /// instead of returning a `Result`
+    pub fn run_with_report<A>(&mut self, fn_name: &str, arg_values: A) -> RunReport
+    where
+        A: IntoIterator<Item = Value>,
+    {
+        let instructions_before = self.instruction_count;
+        let depth_before = self.value_store.depth();
+        let result = self.call_export(fn_name, arg_values);
+
+        let (stack, trap) = match result {
+            Ok(return_value) => (return_value.into_iter().collect(), None),
+            Err(message) => {
+                let stack = self
+                    .value_store
+                    .iter()
+                    .skip(depth_before)
+                    .copied()
+                    .collect();
+                (stack, Some(message))
+            }
+        };
+        // The run is over, so don't leave anything behind for the next one
+        self.value_store.truncate(depth_before);
+
+        RunReport {
+            stack,
+            memory_high_water_mark: self.memory_high_water_mark,
+            instruction_count: self.instruction_count - instructions_before,
+            exit_code: self.exit_code(),
+            trap,
+        }
+    }
+
+    /// Copy the memory and globals, so that `restore` can undo what the program does next
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.to_vec(),
+            globals: self.globals.to_vec(),
+        }
+    }
+
+    /// Put the memory and globals back the way they were when the snapshot was taken
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory.clear();
+        self.memory.extend_from_slice(&snapshot.memory);
+        self.globals.clear();
+        self.globals.extend_from_slice(&snapshot.globals);
+    }
+
+    /// Call an exported function, for a REPL that evaluates one expression after another.
+    /// Memory and globals are kept between calls, so each evaluation sees the definitions
+    /// made by the ones before it. If the call traps, `rollback` undoes what it changed.
+    pub fn eval<A>(&mut self, fn_name: &str, arg_values: A) -> Result<Option<Value>, String>
+    where
+        A: IntoIterator<Item = Value>,
+    {
+        let snapshot = self.snapshot();
+        let depth_before = self.value_store.depth();
+        let result = self.call_export(fn_name, arg_values);
+        // Don't leave this call's locals, or a trapped call's values, under the next evaluation's
+        self.value_store.truncate(depth_before);
+        self.failed_eval = result.is_err().then_some(snapshot);
+        result
+    }
+
+    /// Undo the changes made by the last `eval`, if it trapped.
+    /// Returns false if there was nothing to roll back.
+    pub fn rollback(&mut self) -> bool {
+        match self.failed_eval.take() {
+            Some(snapshot) => {
+                self.restore(&snapshot);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn call_export_from_cli(
@@ -203,6 +852,17 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         //      The WASI `_start` function itself takes no arguments (its Wasm type signature is `() -> nil`).
         //      The program uses WASI syscalls to copy strings into Wasm memory and process them.
         //      But that happens *elsewhere*! Here, `arg_strings` is ignored because `_start` takes no arguments.
+        //
+        // WASI "reactor" modules don't have a `_start`. Instead they export `_initialize`, which the host must
+        // call once before any other export. So if we're calling some other entry point, do that first.
+        if fn_name != "_start"
+            && fn_name != "_initialize"
+            && Self::find_exported_function(module, "_initialize").is_some()
+        {
+            let (init_index, _, init_ret_type) =
+                self.call_export_help_before_arg_load(module, "_initialize")?;
+            self.call_export_help_after_arg_load(module, init_index, 0, init_ret_type)?;
+        }
 
         // Implement the "basic numbers" CLI
         // Check if the called Wasm function takes numeric arguments, and if so, try to parse them from the CLI.
@@ -234,16 +894,8 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         fn_name: &str,
     ) -> Result<(usize, SignatureParamsIter<'m>, Option<ValueType>), String> {
         let fn_index = {
-            let mut export_iter = module.export.exports.iter();
-            export_iter
-                // First look up the name in exports
-                .find_map(|ex| {
-                    if ex.ty == ExportType::Func && ex.name == fn_name {
-                        Some(ex.index)
-                    } else {
-                        None
-                    }
-                })
+            // First look up the name in exports
+            Self::find_exported_function(module, fn_name)
                 .or_else(|| {
                     // Then look it up in the debug info!
                     // This is non-spec behaviour that Wasm3 seems to implement,
@@ -260,7 +912,23 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                     )
                 })
                 .ok_or_else(|| {
-                    format!("I couldn't find a function '{fn_name}' in this WebAssembly module")
+                    let mut message = format!(
+                        "I couldn't find a function '{fn_name}' in this WebAssembly module."
+                    );
+                    let mut exported_fns = self
+                        .exports()
+                        .filter(|ex| ex.ty == ExportType::Func)
+                        .map(|ex| ex.name)
+                        .peekable();
+                    if exported_fns.peek().is_none() {
+                        message.push_str(" It doesn't export any functions.");
+                    } else {
+                        message.push_str(" These are the functions it exports:");
+                        for name in exported_fns {
+                            write!(message, "\n    {name}").unwrap();
+                        }
+                    }
+                    message
                 })? as usize
         };
 
@@ -289,6 +957,16 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         Ok((fn_index, param_type_iter, return_type))
     }
 
+    fn find_exported_function(module: &WasmModule<'a>, fn_name: &str) -> Option<u32> {
+        module.export.exports.iter().find_map(|ex| {
+            if ex.ty == ExportType::Func && ex.name == fn_name {
+                Some(ex.index)
+            } else {
+                None
+            }
+        })
+    }
+
     fn call_export_help_after_arg_load(
         &mut self,
         module: &WasmModule<'a>,
@@ -301,6 +979,7 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         self.blocks.push(Block {
             ty: BlockType::Locals(fn_index),
             vstack: self.value_store.depth(),
+            result: None,
         });
         self.current_frame = Frame::enter(
             fn_index,
@@ -315,21 +994,38 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         self.blocks.push(Block {
             ty: BlockType::FunctionBody(fn_index),
             vstack: self.value_store.depth(),
+            result: return_type,
         });
+        self.notify_call(fn_index);
+        if let Err(e) = self.trace_event(TraceEvent::Call(fn_index as u32)) {
+            if let Error::Trap(reason) = &e {
+                self.stopped = Some(RunOutcome::Trapped(reason.clone()));
+            }
+            return Err(e.to_string_at(self.program_counter + module.code.section_offset as usize));
+        }
 
-        loop {
-            match self.execute_next_instruction(module) {
-                Ok(Action::Continue) => {}
-                Ok(Action::Break) => {
-                    break;
-                }
-                Err(e) => {
-                    let file_offset = self.program_counter + module.code.section_offset as usize;
-                    let mut message = e.to_string_at(file_offset);
-                    self.debug_stack_trace(&mut message).unwrap();
-                    return Err(message);
+        let result = if self.is_instrumented() {
+            self.execute_until_return::<true>(module)
+        } else {
+            self.execute_until_return::<false>(module)
+        };
+        match result {
+            Ok(()) => {}
+            Err(Error::Exit(code)) => {
+                // Discard whatever the program left on the stack. The caller can get the code from `exit_code`.
+                self.value_store.truncate(0);
+                self.stopped = Some(RunOutcome::Exited(code));
+                return Ok(None);
+            }
+            Err(e) => {
+                if let Error::Trap(reason) = &e {
+                    self.stopped = Some(RunOutcome::Trapped(reason.clone()));
                 }
-            };
+                let file_offset = self.program_counter + module.code.section_offset as usize;
+                let mut message = e.to_string_at(file_offset);
+                self.debug_stack_trace(&mut message).unwrap();
+                return Err(message);
+            }
         }
 
         let return_value = if !self.value_store.is_empty() {
@@ -341,6 +1037,24 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         Ok(return_value)
     }
 
+    fn fetch_block_type(&mut self, module: &WasmModule<'a>) -> Result<Option<ValueType>, Error> {
+        let byte = module.code.bytes[self.program_counter];
+        let result = match byte {
+            ValueType::VOID => None,
+            0x7c..=0x7f => Some(ValueType::from(byte)),
+            // Anything else is a type index, for a block with params or several results
+            _ => return Err(Error::MultiValueBlock),
+        };
+        self.program_counter += 1;
+        if let Some(debug_string) = self.debug_string.as_mut() {
+            match result {
+                Some(ty) => write!(debug_string, "{ty:?} ").unwrap(),
+                None => write!(debug_string, "void ").unwrap(),
+            }
+        }
+        Ok(result)
+    }
+
     fn fetch_immediate_u32(&mut self, module: &WasmModule<'a>) -> u32 {
         let x = u32::parse((), &module.code.bytes, &mut self.program_counter).unwrap();
         if let Some(debug_string) = self.debug_string.as_mut() {
@@ -349,15 +1063,96 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         x
     }
 
+    /// Fetch the offset from a memarg immediate. It's 64 bits wide in a 64-bit memory.
+    fn fetch_memarg_offset(&mut self, module: &WasmModule<'a>) -> u64 {
+        if self.is_memory64 {
+            let x = u64::parse((), &module.code.bytes, &mut self.program_counter).unwrap();
+            self.write_debug(x);
+            x
+        } else {
+            self.fetch_immediate_u32(module) as u64
+        }
+    }
+
+    /// Pop a memory address or size, which is an i64 in a 64-bit memory and an i32 otherwise
+    fn pop_address(&mut self) -> Result<u64, Error> {
+        if self.is_memory64 {
+            self.value_store.pop_u64()
+        } else {
+            self.value_store.pop_u32().map(u64::from)
+        }
+    }
+
+    fn push_address(&mut self, value: u64) {
+        if self.is_memory64 {
+            self.value_store.push(Value::I64(value as i64));
+        } else {
+            self.value_store.push(Value::I32(value as u32 as i32));
+        }
+    }
+
+    /// Add the base address and offset without wrapping, and check the access is inside the memory
+    fn effective_address(
+        &mut self,
+        base_addr: u64,
+        offset: u64,
+        size: u32,
+        is_write: bool,
+    ) -> Result<u64, Error> {
+        let memory_size = self.memory.len() as u64;
+        match base_addr.checked_add(offset) {
+            Some(addr) if addr.saturating_add(size as u64) <= memory_size => Ok(addr),
+            addr => Err(self.memory_fault(addr.unwrap_or(u64::MAX), size, is_write)),
+        }
+    }
+
+    /// The range of `size` bytes at `addr`, for bulk memory instructions like `memory.copy`.
+    /// Like `effective_address`, this lets the memory fault hook see it if it's out of bounds.
+    fn memory_range(
+        &mut self,
+        addr: u64,
+        size: u64,
+        is_write: bool,
+    ) -> Result<Range<usize>, Error> {
+        let Ok(size) = u32::try_from(size) else {
+            return Err(self.memory_fault(addr, u32::MAX, is_write));
+        };
+        let start = self.effective_address(addr, 0, size, is_write)? as usize;
+        Ok(start..start + size as usize)
+    }
+
+    /// Grow the memory to `new_len` bytes, for `memory.grow`. The spec says the new pages are
+    /// all zeros, and `resize` writes every new byte, so they are even when the Vec reuses
+    /// capacity that still has stale data in it, e.g. after `restore` shrank the memory.
+    ///
+    /// Fails if the host can't allocate it. A memory64 module without a declared maximum
+    /// can ask for terabytes, and that should make `memory.grow` return -1, not abort.
+    fn grow_memory_zeroed(&mut self, new_len: usize) -> Result<(), CollectionAllocErr> {
+        let additional = new_len.saturating_sub(self.memory.len());
+        self.memory.try_reserve_exact(additional)?;
+        self.memory.resize(new_len, 0);
+        Ok(())
+    }
+
+    /// The error for an out-of-bounds access, after letting the memory fault hook see it
+    fn memory_fault(&mut self, addr: u64, size: u32, is_write: bool) -> Error {
+        if let Some(hook) = self.hooks.memory_fault.as_mut() {
+            hook(addr, size, is_write);
+        }
+        Error::MemoryAccessOutOfBounds(addr, self.memory.len() as u64)
+    }
+
     fn do_return(&mut self) -> Action {
         // self.debug_values_and_blocks("start do_return");
 
         let Frame {
+            fn_index,
             return_addr,
             body_block_index,
             return_type,
             ..
         } = self.current_frame;
+        self.notify_return(fn_index);
 
         // Throw away all locals and values except the return value
         let locals_block_index = body_block_index - 1;
@@ -387,60 +1182,251 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         }
     }
 
-    fn get_load_address(&mut self, module: &WasmModule<'a>) -> Result<u32, Error> {
+    /// Count a memory access, if memory statistics are enabled
+    fn record_access(&mut self, is_write: bool) {
+        if let Some(stats) = self.mem_stats.as_mut() {
+            if is_write {
+                stats.writes += 1;
+            } else {
+                stats.reads += 1;
+            }
+        }
+    }
+
+    fn get_load_address(&mut self, module: &WasmModule<'a>, size: u32) -> Result<u64, Error> {
         // Alignment is not used in the execution steps from the spec! Maybe it's just an optimization hint?
         // https://webassembly.github.io/spec/core/exec/instructions.html#memory-instructions
         // Also note: in the text format we can specify the useless `align=` but not the useful `offset=`!
         let _alignment = self.fetch_immediate_u32(module);
-        let offset = self.fetch_immediate_u32(module);
-        let base_addr = self.value_store.pop_u32()?;
-        let addr = base_addr + offset;
-        let memory_size = self.memory.len() as u32;
-        if addr >= memory_size {
-            Err(Error::MemoryAccessOutOfBounds(addr, memory_size))
-        } else {
-            Ok(addr)
-        }
+        let offset = self.fetch_memarg_offset(module);
+        let base_addr = self.pop_address()?;
+        let addr = self.effective_address(base_addr, offset, size, false)?;
+        self.record_access(false);
+        Ok(addr)
     }
 
-    fn get_store_addr_value(&mut self, module: &WasmModule<'a>) -> Result<(usize, Value), Error> {
+    fn get_store_addr_value(
+        &mut self,
+        module: &WasmModule<'a>,
+        size: u32,
+    ) -> Result<(usize, Value), Error> {
         // Alignment is not used in the execution steps from the spec! Maybe it's just an optimization hint?
         // https://webassembly.github.io/spec/core/exec/instructions.html#memory-instructions
         // Also note: in the text format we can specify the useless `align=` but not the useful `offset=`!
         let _alignment = self.fetch_immediate_u32(module);
-        let offset = self.fetch_immediate_u32(module);
+        let offset = self.fetch_memarg_offset(module);
         let value = self.value_store.pop();
-        let base_addr = self.value_store.pop_u32()?;
-        let addr = base_addr + offset;
-        let memory_size = self.memory.len() as u32;
-        if addr >= memory_size {
-            Err(Error::MemoryAccessOutOfBounds(addr, memory_size))
+        let base_addr = self.pop_address()?;
+        let addr = self.effective_address(base_addr, offset, size, true)?;
+        self.record_access(true);
+        Ok((addr as usize, value))
+    }
+
+    /// Fetch the memarg of an atomic instruction and pop its address.
+    /// Unlike ordinary loads and stores, atomics trap if the address isn't naturally aligned.
+    fn get_atomic_address(
+        &mut self,
+        module: &WasmModule<'a>,
+        size: u32,
+        is_write: bool,
+    ) -> Result<usize, Error> {
+        let _alignment = self.fetch_immediate_u32(module);
+        let offset = self.fetch_memarg_offset(module);
+        let base_addr = self.pop_address()?;
+        let memory_size = self.memory.len() as u64;
+        let addr = match base_addr.checked_add(offset) {
+            Some(addr) => addr,
+            None => return Err(self.memory_fault(u64::MAX, size, is_write)),
+        };
+        if addr % size as u64 != 0 {
+            Err(Error::UnalignedAtomic(addr, size))
+        } else if addr + size as u64 > memory_size {
+            Err(self.memory_fault(addr, size, is_write))
         } else {
-            Ok((addr as usize, value))
+            Ok(addr as usize)
         }
     }
 
+    fn read_atomic(&mut self, addr: usize, width: AtomicWidth) -> u64 {
+        self.record_access(false);
+        let mut bytes = [0; 8];
+        let size = width.bytes() as usize;
+        bytes[..size].copy_from_slice(&self.memory[addr..][..size]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn write_atomic(&mut self, addr: usize, width: AtomicWidth, value: u64) {
+        self.record_access(true);
+        let size = width.bytes() as usize;
+        self.memory[addr..][..size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    fn pop_atomic_operand(&mut self, width: AtomicWidth) -> Result<u64, Error> {
+        if width.is_64_bit() {
+            self.value_store.pop_u64()
+        } else {
+            self.value_store.pop_u32().map(u64::from)
+        }
+    }
+
+    fn push_atomic_result(&mut self, width: AtomicWidth, value: u64) {
+        if width.is_64_bit() {
+            self.value_store.push(Value::I64(value as i64));
+        } else {
+            self.value_store.push(Value::I32(value as u32 as i32));
+        }
+    }
+
+    /// Instructions from the threads proposal. The interpreter only ever runs one thread,
+    /// so every access is trivially atomic, and there's nobody to wait for or notify.
+    fn execute_atomic(&mut self, module: &WasmModule<'a>) -> Result<(), Error> {
+        let sub_opcode = self.fetch_immediate_u32(module);
+        let instruction = match AtomicInstruction::try_from(sub_opcode) {
+            Ok(instruction) => instruction,
+            Err(other) => return Err(unsupported(OpCode::ATOMIC as u8, Some(other))),
+        };
+        match instruction {
+            AtomicInstruction::Fence => {
+                // skip the reserved zero byte
+                self.program_counter += 1;
+            }
+            AtomicInstruction::Notify => {
+                let _count = self.value_store.pop_u32()?;
+                self.get_atomic_address(module, 4, false)?;
+                // number of waiters that were woken up
+                self.value_store.push(Value::I32(0));
+            }
+            AtomicInstruction::Wait32 | AtomicInstruction::Wait64 => {
+                let width = if instruction == AtomicInstruction::Wait32 {
+                    AtomicWidth::I32
+                } else {
+                    AtomicWidth::I64
+                };
+                let timeout = self.value_store.pop_i64()?;
+                let expected = self.pop_atomic_operand(width)?;
+                let addr = self.get_atomic_address(module, width.bytes(), false)?;
+                if self.read_atomic(addr, width) != expected {
+                    // "not-equal"
+                    self.value_store.push(Value::I32(1));
+                } else if timeout >= 0 {
+                    // "timed-out", since no other thread could ever change the value
+                    self.value_store.push(Value::I32(2));
+                } else {
+                    return Err(Error::AtomicWaitForever);
+                }
+            }
+            AtomicInstruction::Load(width) => {
+                let addr = self.get_atomic_address(module, width.bytes(), false)?;
+                let value = self.read_atomic(addr, width);
+                self.push_atomic_result(width, value);
+            }
+            AtomicInstruction::Store(width) => {
+                let value = self.pop_atomic_operand(width)?;
+                let addr = self.get_atomic_address(module, width.bytes(), true)?;
+                self.write_atomic(addr, width, value);
+            }
+            AtomicInstruction::Rmw(AtomicRmwOp::Cmpxchg, width) => {
+                let replacement = self.pop_atomic_operand(width)?;
+                let expected = self.pop_atomic_operand(width)?;
+                let addr = self.get_atomic_address(module, width.bytes(), true)?;
+                let old = self.read_atomic(addr, width);
+                // the expected value is wrapped to the access size before comparing
+                let mask = u64::MAX >> (64 - 8 * width.bytes());
+                if old == expected & mask {
+                    self.write_atomic(addr, width, replacement);
+                }
+                self.push_atomic_result(width, old);
+            }
+            AtomicInstruction::Rmw(op, width) => {
+                let operand = self.pop_atomic_operand(width)?;
+                let addr = self.get_atomic_address(module, width.bytes(), true)?;
+                let old = self.read_atomic(addr, width);
+                let new = match op {
+                    AtomicRmwOp::Add => old.wrapping_add(operand),
+                    AtomicRmwOp::Sub => old.wrapping_sub(operand),
+                    AtomicRmwOp::And => old & operand,
+                    AtomicRmwOp::Or => old | operand,
+                    AtomicRmwOp::Xor => old ^ operand,
+                    AtomicRmwOp::Xchg => operand,
+                    AtomicRmwOp::Cmpxchg => unreachable!(),
+                };
+                self.write_atomic(addr, width, new);
+                self.push_atomic_result(width, old);
+            }
+        }
+        Ok(())
+    }
+
     fn write_debug<T: fmt::Debug>(&mut self, value: T) {
         if let Some(debug_string) = self.debug_string.as_mut() {
             std::write!(debug_string, "{value:?} ").unwrap();
         }
     }
 
-    fn do_break(&mut self, relative_blocks_outward: u32, module: &WasmModule<'a>) {
+    fn do_break(
+        &mut self,
+        relative_blocks_outward: u32,
+        module: &WasmModule<'a>,
+    ) -> Result<(), Error> {
         let block_index = self.blocks.len() - 1 - relative_blocks_outward as usize;
-        let Block { ty, vstack } = self.blocks[block_index];
+        let Block { ty, vstack, result } = self.blocks[block_index];
         match ty {
             BlockType::Loop(start_addr) => {
+                // Branching to a loop goes back to the start, where it takes no parameters in Wasm MVP
                 self.blocks.truncate(block_index + 1);
                 self.value_store.truncate(vstack);
                 self.program_counter = start_addr;
             }
             BlockType::FunctionBody(_) | BlockType::Normal => {
+                // Branching forward carries the block's result out with it
+                let carried = match result {
+                    Some(expected) if self.value_store.depth() > vstack => {
+                        let value = self.value_store.pop();
+                        let actual = ValueType::from(value);
+                        if actual != expected {
+                            return Err(Error::Type(expected, actual));
+                        }
+                        Some(value)
+                    }
+                    Some(expected) => {
+                        return Err(Error::BlockResult {
+                            expected: Some(expected),
+                            found: vec![],
+                        })
+                    }
+                    None => None,
+                };
                 self.break_forward(relative_blocks_outward, module);
                 self.value_store.truncate(vstack);
+                if let Some(value) = carried {
+                    self.value_store.push(value);
+                }
             }
             BlockType::Locals(_) => unreachable!(),
         }
+        Ok(())
+    }
+
+    /// Check that the innermost block is leaving exactly its declared result on the stack
+    fn check_block_result(&self) -> Result<(), Error> {
+        let Block { vstack, result, .. } = *self.blocks.last().unwrap();
+        let depth = self.value_store.depth();
+        let is_ok = match result {
+            None => depth == vstack,
+            Some(ty) => {
+                depth == vstack + 1 && ValueType::from(*self.value_store.get(vstack).unwrap()) == ty
+            }
+        };
+        if is_ok {
+            Ok(())
+        } else {
+            Err(Error::BlockResult {
+                expected: result,
+                found: (vstack..depth)
+                    .map(|i| ValueType::from(*self.value_store.get(i).unwrap()))
+                    .collect(),
+            })
+        }
     }
 
     // Break to an outer block, going forward in the program
@@ -483,17 +1469,36 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         self.blocks.truncate(target_block_depth);
     }
 
+    /// `return_call` and `return_call_indirect`: the callee replaces the current frame instead of
+    /// going on top of it, so tail recursion runs in constant stack space.
+    fn do_tail_call(
+        &mut self,
+        expected_signature: Option<u32>,
+        fn_index: usize,
+        module: &WasmModule<'a>,
+    ) -> Result<Action, Error> {
+        if fn_index < self.import_count {
+            // Imports don't get a frame, so just call it and return whatever it returned
+            self.do_call(expected_signature, fn_index, module, false)?;
+            Ok(self.do_return())
+        } else {
+            self.do_call(expected_signature, fn_index, module, true)?;
+            Ok(Action::Continue)
+        }
+    }
+
     fn do_call(
         &mut self,
         expected_signature: Option<u32>,
         fn_index: usize,
         module: &WasmModule<'a>,
+        is_tail_call: bool,
     ) -> Result<(), Error> {
         // self.debug_values_and_blocks(&format!("start do_call {}", fn_index));
 
         let (signature_index, opt_import) = if fn_index < self.import_count {
             // Imported non-Wasm function
-            let import = &module.import.imports[fn_index];
+            let import = self.imported_functions[fn_index];
             let sig = match import.description {
                 ImportDesc::Func { signature_index } => signature_index,
                 _ => unreachable!(),
@@ -519,32 +1524,71 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         }
 
         if let Some(import) = opt_import {
+            // Check the arguments against the import's signature, so that a miscompiled module
+            // can't make the dispatcher panic on a value of the wrong type
+            let args_start = self
+                .value_store
+                .depth()
+                .checked_sub(n_args)
+                .ok_or(Error::StackEmpty)?;
             self.import_arguments.clear();
-            self.import_arguments
-                .extend(std::iter::repeat(Value::I64(0)).take(n_args));
-            for (i, expected) in arg_type_iter.enumerate().rev() {
-                let arg = self.value_store.pop();
+            for (index, expected) in arg_type_iter.enumerate() {
+                let arg = *self.value_store.get(args_start + index).unwrap();
                 let actual = ValueType::from(arg);
                 if actual != expected {
-                    return Err(Error::Type(expected, actual));
+                    return Err(Error::Trap(TrapReason::ImportArgumentMismatch {
+                        module_name: import.module.to_string(),
+                        function_name: import.name.to_string(),
+                        index,
+                        expected,
+                        actual,
+                    }));
                 }
-                self.import_arguments[i] = arg;
+                self.import_arguments.push(arg);
             }
+            self.value_store.truncate(args_start);
 
+            self.import_dispatcher
+                .check_import(import.module, import.name)
+                .map_err(Error::Trap)?;
             let optional_return_val = self.import_dispatcher.dispatch(
                 import.module,
                 import.name,
                 &self.import_arguments,
                 &mut self.memory,
             );
+            self.trace_event(TraceEvent::Import {
+                fn_index: fn_index as u32,
+                result: optional_return_val,
+            })?;
             if let Some(return_val) = optional_return_val {
                 self.value_store.push(return_val);
             }
             if let Some(debug_string) = self.debug_string.as_mut() {
                 write!(debug_string, " {}.{}", import.module, import.name).unwrap();
             }
+            if let Some(reason) = self.import_dispatcher.take_trap() {
+                return Err(Error::Trap(reason));
+            }
+            if let Some(code) = self.import_dispatcher.exit_code() {
+                return Err(Error::Exit(code));
+            }
         } else {
-            let return_addr = self.program_counter;
+            let return_addr = if is_tail_call {
+                // Discard the current frame's locals and blocks, keeping only the args for the callee
+                let locals_block_index = self.current_frame.body_block_index - 1;
+                let locals_start = self.blocks[locals_block_index].vstack;
+                let args_start = self.value_store.depth() - n_args;
+                for i in 0..n_args {
+                    let arg = *self.value_store.get(args_start + i).unwrap();
+                    self.value_store.set(locals_start + i, arg);
+                }
+                self.value_store.truncate(locals_start + n_args);
+                self.blocks.truncate(locals_block_index);
+                self.current_frame.return_addr
+            } else {
+                self.program_counter
+            };
             // set PC to start of function bytes
             let internal_fn_index = fn_index - self.import_count;
             self.program_counter = module.code.function_offsets[internal_fn_index] as usize;
@@ -554,6 +1598,7 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             self.blocks.push(Block {
                 ty: BlockType::Locals(fn_index),
                 vstack: self.value_store.depth() - n_args,
+                result: None,
             });
             let body_block_index = self.blocks.len();
 
@@ -568,11 +1613,18 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                 &mut self.program_counter,
             );
             std::mem::swap(&mut swap_frame, &mut self.current_frame);
-            self.previous_frames.push(swap_frame);
+            if is_tail_call {
+                self.notify_return(swap_frame.fn_index);
+            } else {
+                self.previous_frames.push(swap_frame);
+            }
+            self.notify_call(fn_index);
+            self.trace_event(TraceEvent::Call(fn_index as u32))?;
 
             self.blocks.push(Block {
                 ty: BlockType::FunctionBody(fn_index),
                 vstack: self.value_store.depth(),
+                result: ret_type,
             });
         }
         // self.debug_values_and_blocks("end do_call");
@@ -580,6 +1632,32 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         Ok(())
     }
 
+    /// Read the immediates of an indirect call and look up the function it refers to
+    fn fetch_indirect_call_target(
+        &mut self,
+        module: &WasmModule<'a>,
+        file_offset: u32,
+    ) -> Result<(u32, usize), Error> {
+        let expected_signature = self.fetch_immediate_u32(module);
+        let table_index = self.fetch_immediate_u32(module);
+        let element_index = self.value_store.pop_u32()?;
+
+        // So far, all compilers seem to be emitting MVP-compatible code. (Rust, Zig, Roc...)
+        assert_eq!(
+            table_index, 0,
+            "Table index {table_index} not supported at file offset {file_offset:#x}. This interpreter only supports Wasm MVP."
+        );
+
+        // Dereference the function pointer (look up the element index in the function table)
+        let fn_index = module.element.lookup(element_index).unwrap_or_else(|| {
+            panic!(
+                "Indirect function call failed. There is no function with element index {element_index}"
+            )
+        });
+
+        Ok((expected_signature, fn_index as usize))
+    }
+
     fn debug_call(&mut self, n_args: usize, return_type: Option<ValueType>) {
         if let Some(debug_string) = self.debug_string.as_mut() {
             write!(debug_string, "         args=[").unwrap();
@@ -600,49 +1678,108 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         }
     }
 
+    /// Loads, stores, and all the other instructions that need a memory to exist
+    fn uses_memory(op_code: OpCode) -> bool {
+        matches!(op_code as u8, 0x28..=0x40) || matches!(op_code, OpCode::MEMORY | OpCode::ATOMIC)
+    }
+
+    /// Whether anything needs checking on every instruction: a deadline, the opcode profile,
+    /// or debug output. Hooks and traces only run on calls and memory accesses, so they don't count.
+    fn is_instrumented(&self) -> bool {
+        self.deadline.is_some() || self.opcode_profile.is_some() || self.debug_string.is_some()
+    }
+
+    /// Run until the function that the host called returns.
+    /// This is chosen once per call, so that the uninstrumented copy of the loop has no
+    /// per-instruction checks at all (see `is_instrumented`).
+    fn execute_until_return<const INSTRUMENTED: bool>(
+        &mut self,
+        module: &WasmModule<'a>,
+    ) -> Result<(), Error> {
+        loop {
+            if let Action::Break = self.execute_instruction::<INSTRUMENTED>(module)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute a single instruction, for tests that step through a function
+    #[cfg(test)]
     pub(crate) fn execute_next_instruction(
         &mut self,
         module: &WasmModule<'a>,
+    ) -> Result<Action, Error> {
+        if self.is_instrumented() {
+            self.execute_instruction::<true>(module)
+        } else {
+            self.execute_instruction::<false>(module)
+        }
+    }
+
+    fn execute_instruction<const INSTRUMENTED: bool>(
+        &mut self,
+        module: &WasmModule<'a>,
     ) -> Result<Action, Error> {
         use OpCode::*;
 
         let file_offset = self.program_counter as u32 + module.code.section_offset;
-        let op_code = OpCode::from(module.code.bytes[self.program_counter]);
+        let byte = module.code.bytes[self.program_counter];
+        let op_code = LOOKUP_TABLE[byte as usize].ok_or_else(|| unsupported(byte, None))?;
         self.program_counter += 1;
-
-        if let Some(debug_string) = self.debug_string.as_mut() {
-            debug_string.clear();
-            self.write_debug(op_code);
+        self.instruction_count += 1;
+        let mut profile_start = None;
+        if INSTRUMENTED {
+            if let Some(deadline) = self.deadline {
+                if self.instruction_count % deadline.check_interval == 0
+                    && Instant::now() >= deadline.at
+                {
+                    return Err(Error::Trap(TrapReason::Timeout));
+                }
+            }
+            profile_start = self.opcode_profile.is_some().then(Instant::now);
+            if let Some(debug_string) = self.debug_string.as_mut() {
+                debug_string.clear();
+                self.write_debug(op_code);
+            }
         }
+        #[cfg(feature = "opcode-coverage")]
+        crate::opcode_coverage::record(op_code);
 
         let mut action = Action::Continue;
         let mut implicit_return = false;
 
+        if !self.has_memory && Self::uses_memory(op_code) {
+            return Err(Error::NoMemory);
+        }
+
         match op_code {
             UNREACHABLE => {
                 return Err(Error::UnreachableOp);
             }
             NOP => {}
             BLOCK => {
-                self.fetch_immediate_u32(module); // blocktype (ignored)
+                let result = self.fetch_block_type(module)?;
                 self.blocks.push(Block {
                     ty: BlockType::Normal,
                     vstack: self.value_store.depth(),
+                    result,
                 });
             }
             LOOP => {
-                self.fetch_immediate_u32(module); // blocktype (ignored)
+                let result = self.fetch_block_type(module)?;
                 self.blocks.push(Block {
                     ty: BlockType::Loop(self.program_counter),
                     vstack: self.value_store.depth(),
+                    result,
                 });
             }
             IF => {
-                self.fetch_immediate_u32(module); // blocktype (ignored)
+                let result = self.fetch_block_type(module)?;
                 let condition = self.value_store.pop_i32()?;
                 self.blocks.push(Block {
                     ty: BlockType::Normal,
                     vstack: self.value_store.depth(),
+                    result,
                 });
                 if condition == 0 {
                     let addr = self.program_counter as u32;
@@ -691,7 +1828,8 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                 // We only reach this point when we finish executing the "then" block of an IF statement
                 // (For a false condition, we would have skipped past the ELSE when we saw the IF)
                 // We don't want to execute the ELSE block, so we skip it, just like `br 0` would.
-                self.do_break(0, module);
+                self.check_block_result()?;
+                self.do_break(0, module)?;
             }
             END => {
                 if self.blocks.len() == (self.current_frame.body_block_index + 1) {
@@ -699,18 +1837,19 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                     action = self.do_return();
                     implicit_return = true;
                 } else {
+                    self.check_block_result()?;
                     self.blocks.pop().unwrap();
                 }
             }
             BR => {
                 let relative_blocks_outward = self.fetch_immediate_u32(module);
-                self.do_break(relative_blocks_outward, module);
+                self.do_break(relative_blocks_outward, module)?;
             }
             BRIF => {
                 let relative_blocks_outward = self.fetch_immediate_u32(module);
                 let condition = self.value_store.pop_i32()?;
                 if condition != 0 {
-                    self.do_break(relative_blocks_outward, module);
+                    self.do_break(relative_blocks_outward, module)?;
                 }
             }
             BRTABLE => {
@@ -725,34 +1864,28 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                 }
                 let fallback = self.fetch_immediate_u32(module);
                 let relative_blocks_outward = selected.unwrap_or(fallback);
-                self.do_break(relative_blocks_outward, module);
+                self.do_break(relative_blocks_outward, module)?;
             }
             RETURN => {
                 action = self.do_return();
             }
             CALL => {
                 let fn_index = self.fetch_immediate_u32(module) as usize;
-                self.do_call(None, fn_index, module)?;
+                self.do_call(None, fn_index, module, false)?;
             }
             CALLINDIRECT => {
-                let expected_signature = self.fetch_immediate_u32(module);
-                let table_index = self.fetch_immediate_u32(module);
-                let element_index = self.value_store.pop_u32()?;
-
-                // So far, all compilers seem to be emitting MVP-compatible code. (Rust, Zig, Roc...)
-                assert_eq!(
-                    table_index, 0,
-                    "Table index {table_index} not supported at file offset {file_offset:#x}. This interpreter only supports Wasm MVP."
-                );
-
-                // Dereference the function pointer (look up the element index in the function table)
-                let fn_index = module.element.lookup(element_index).unwrap_or_else(|| {
-                    panic!(
-                        "Indirect function call failed. There is no function with element index {element_index}"
-                    )
-                });
-
-                self.do_call(Some(expected_signature), fn_index as usize, module)?;
+                let (expected_signature, fn_index) =
+                    self.fetch_indirect_call_target(module, file_offset)?;
+                self.do_call(Some(expected_signature), fn_index, module, false)?;
+            }
+            RETURNCALL => {
+                let fn_index = self.fetch_immediate_u32(module) as usize;
+                action = self.do_tail_call(None, fn_index, module)?;
+            }
+            RETURNCALLINDIRECT => {
+                let (expected_signature, fn_index) =
+                    self.fetch_indirect_call_target(module, file_offset)?;
+                action = self.do_tail_call(Some(expected_signature), fn_index, module)?;
             }
             DROP => {
                 self.value_store.pop();
@@ -795,149 +1928,149 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                 self.globals[index as usize] = self.value_store.pop();
             }
             I32LOAD => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 4)? as usize;
                 let mut bytes = [0; 4];
                 bytes.copy_from_slice(&self.memory[addr..][..4]);
                 let value = i32::from_le_bytes(bytes);
                 self.value_store.push(Value::I32(value));
             }
             I64LOAD => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 8)? as usize;
                 let mut bytes = [0; 8];
                 bytes.copy_from_slice(&self.memory[addr..][..8]);
                 let value = i64::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value));
             }
             F32LOAD => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 4)? as usize;
                 let mut bytes = [0; 4];
                 bytes.copy_from_slice(&self.memory[addr..][..4]);
                 let value = f32::from_le_bytes(bytes);
                 self.value_store.push(Value::F32(value));
             }
             F64LOAD => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 8)? as usize;
                 let mut bytes = [0; 8];
                 bytes.copy_from_slice(&self.memory[addr..][..8]);
                 let value = f64::from_le_bytes(bytes);
                 self.value_store.push(Value::F64(value));
             }
             I32LOAD8S => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 1)? as usize;
                 let mut bytes = [0; 1];
                 bytes.copy_from_slice(&self.memory[addr..][..1]);
                 let value = i8::from_le_bytes(bytes);
                 self.value_store.push(Value::I32(value as i32));
             }
             I32LOAD8U => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 1)? as usize;
                 let value = self.memory[addr];
                 self.value_store.push(Value::I32(value as i32));
             }
             I32LOAD16S => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 2)? as usize;
                 let mut bytes = [0; 2];
                 bytes.copy_from_slice(&self.memory[addr..][..2]);
                 let value = i16::from_le_bytes(bytes);
                 self.value_store.push(Value::I32(value as i32));
             }
             I32LOAD16U => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 2)? as usize;
                 let mut bytes = [0; 2];
                 bytes.copy_from_slice(&self.memory[addr..][..2]);
                 let value = u16::from_le_bytes(bytes);
                 self.value_store.push(Value::I32(value as i32));
             }
             I64LOAD8S => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 1)? as usize;
                 let mut bytes = [0; 1];
                 bytes.copy_from_slice(&self.memory[addr..][..1]);
                 let value = i8::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value as i64));
             }
             I64LOAD8U => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 1)? as usize;
                 let value = self.memory[addr];
                 self.value_store.push(Value::I64(value as i64));
             }
             I64LOAD16S => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 2)? as usize;
                 let mut bytes = [0; 2];
                 bytes.copy_from_slice(&self.memory[addr..][..2]);
                 let value = i16::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value as i64));
             }
             I64LOAD16U => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 2)? as usize;
                 let mut bytes = [0; 2];
                 bytes.copy_from_slice(&self.memory[addr..][..2]);
                 let value = u16::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value as i64));
             }
             I64LOAD32S => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 4)? as usize;
                 let mut bytes = [0; 4];
                 bytes.copy_from_slice(&self.memory[addr..][..4]);
                 let value = i32::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value as i64));
             }
             I64LOAD32U => {
-                let addr = self.get_load_address(module)? as usize;
+                let addr = self.get_load_address(module, 4)? as usize;
                 let mut bytes = [0; 4];
                 bytes.copy_from_slice(&self.memory[addr..][..4]);
                 let value = u32::from_le_bytes(bytes);
                 self.value_store.push(Value::I64(value as i64));
             }
             I32STORE => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 4)?;
                 let unwrapped = value.expect_i32().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..4];
                 target.copy_from_slice(&unwrapped.to_le_bytes());
             }
             I64STORE => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 8)?;
                 let unwrapped = value.expect_i64().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..8];
                 target.copy_from_slice(&unwrapped.to_le_bytes());
             }
             F32STORE => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 4)?;
                 let unwrapped = value.expect_f32().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..4];
                 target.copy_from_slice(&unwrapped.to_le_bytes());
             }
             F64STORE => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 8)?;
                 let unwrapped = value.expect_f64().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..8];
                 target.copy_from_slice(&unwrapped.to_le_bytes());
             }
             I32STORE8 => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 1)?;
                 let unwrapped = value.expect_i32().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..1];
                 target.copy_from_slice(&unwrapped.to_le_bytes()[..1]);
             }
             I32STORE16 => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 2)?;
                 let unwrapped = value.expect_i32().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..2];
                 target.copy_from_slice(&unwrapped.to_le_bytes()[..2]);
             }
             I64STORE8 => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 1)?;
                 let unwrapped = value.expect_i64().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..1];
                 target.copy_from_slice(&unwrapped.to_le_bytes()[..1]);
             }
             I64STORE16 => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 2)?;
                 let unwrapped = value.expect_i64().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..2];
                 target.copy_from_slice(&unwrapped.to_le_bytes()[..2]);
             }
             I64STORE32 => {
-                let (addr, value) = self.get_store_addr_value(module)?;
+                let (addr, value) = self.get_store_addr_value(module, 4)?;
                 let unwrapped = value.expect_i64().map_err(Error::from)?;
                 let target = &mut self.memory[addr..][..4];
                 target.copy_from_slice(&unwrapped.to_le_bytes()[..4]);
@@ -945,62 +2078,92 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             CURRENTMEMORY => {
                 let memory_index = self.fetch_immediate_u32(module);
                 assert_eq!(memory_index, 0);
-                let size = self.memory.len() as i32 / MemorySection::PAGE_SIZE as i32;
-                self.value_store.push(Value::I32(size));
+                let size = self.memory.len() as u64 / MemorySection::PAGE_SIZE as u64;
+                self.push_address(size);
             }
             GROWMEMORY => {
                 let memory_index = self.fetch_immediate_u32(module);
                 assert_eq!(memory_index, 0);
-                let old_bytes = self.memory.len() as u32;
-                let old_pages = old_bytes / MemorySection::PAGE_SIZE;
-                let grow_pages = self.value_store.pop_u32()?;
-                let grow_bytes = grow_pages * MemorySection::PAGE_SIZE;
-                let new_bytes = old_bytes + grow_bytes;
-
-                let success = match module.memory.max_bytes().unwrap() {
-                    Some(max_bytes) => new_bytes <= max_bytes,
-                    None => true,
+                let page_size = MemorySection::PAGE_SIZE as u64;
+                let old_pages = self.memory.len() as u64 / page_size;
+                let grow_pages = self.pop_address()?;
+
+                // Without a declared maximum, a 32-bit memory can still only be addressed up to 4GiB.
+                // Page counts are 32 bits wide in either case.
+                let max_bytes = match imported_memory_limits(module) {
+                    Some(Limits::MinMax(_, max)) => Some(*max as u64 * page_size),
+                    Some(Limits::Min(_)) => None,
+                    None => module.memory.max_bytes().unwrap(),
                 };
-                if success {
-                    self.memory
-                        .extend(iter::repeat(0).take(grow_bytes as usize));
-                    self.value_store.push(Value::I32(old_pages as i32));
-                } else {
-                    self.value_store.push(Value::I32(-1));
+                let max_pages = match max_bytes {
+                    Some(max_bytes) => max_bytes / page_size,
+                    None if self.is_memory64 => u32::MAX as u64,
+                    None => (1 << 32) / page_size,
+                };
+                let grown = match old_pages.checked_add(grow_pages) {
+                    Some(new_pages) if new_pages <= max_pages => self
+                        .grow_memory_zeroed((new_pages * page_size) as usize)
+                        .ok()
+                        .map(|()| new_pages),
+                    _ => None,
+                };
+                match grown {
+                    Some(new_pages) => {
+                        if let Some(stats) = self.mem_stats.as_mut() {
+                            stats.grows += 1;
+                        }
+                        let (old_pages, new_pages) = (old_pages as u32, new_pages as u32);
+                        self.memory_high_water_mark = self.memory_high_water_mark.max(new_pages);
+                        if let Some(hook) = self.hooks.memory_grow.as_mut() {
+                            hook(old_pages, new_pages);
+                        }
+                        self.push_address(old_pages as u64);
+                    }
+                    None => self.push_address(u64::MAX),
                 }
             }
             MEMORY => {
                 // the first argument determines exactly which memory operation we have
                 match MemoryInstruction::try_from(module.code.bytes[self.program_counter]) {
                     Ok(op) => match op {
-                        MemoryInstruction::MemoryInit => todo!("WASM instruction: memory.init"),
-                        MemoryInstruction::DataDrop => todo!("WASM instruction: data.drop"),
+                        MemoryInstruction::MemoryInit | MemoryInstruction::DataDrop => {
+                            return Err(unsupported(MEMORY as u8, Some(op as u32)));
+                        }
                         MemoryInstruction::MemoryCopy => {
-                            let size = self.value_store.pop_u32()? as usize;
-                            let source = self.value_store.pop_u32()? as usize;
-                            let destination = self.value_store.pop_u32()? as usize;
+                            let size = self.pop_address()?;
+                            let source = self.pop_address()?;
+                            let destination = self.pop_address()?;
 
                             // skip the op byte and an extra two zero bytes.
                             // in future versions of WebAssembly this byte may be used to index additional memories
                             self.program_counter += 1 + 2;
 
-                            self.memory.copy_within(source..source + size, destination)
+                            let source = self.memory_range(source, size, false)?;
+                            let destination = self.memory_range(destination, size, true)?;
+                            self.memory.copy_within(source, destination.start);
+                            self.record_access(false);
+                            self.record_access(true);
                         }
                         MemoryInstruction::MemoryFill => {
-                            let size = self.value_store.pop_u32()? as usize;
+                            let size = self.pop_address()?;
                             let byte_value = self.value_store.pop_u32()? as u8;
-                            let destination = self.value_store.pop_u32()? as usize;
+                            let destination = self.pop_address()?;
 
                             // skip the op byte and an extra zero byte.
                             // in future versions of WebAssembly this byte may be used to index additional memories
                             self.program_counter += 1 + 1;
 
-                            self.memory[destination..][..size].fill(byte_value);
+                            let destination = self.memory_range(destination, size, true)?;
+                            self.memory[destination].fill(byte_value);
+                            self.record_access(true);
                         }
                     },
-                    Err(other) => unreachable!("invalid memory instruction {other:?}"),
+                    Err(other) => return Err(unsupported(MEMORY as u8, Some(other as u32))),
                 };
             }
+            ATOMIC => {
+                self.execute_atomic(module)?;
+            }
             I32CONST => {
                 let value = i32::parse((), &module.code.bytes, &mut self.program_counter).unwrap();
                 self.write_debug(value);
@@ -1491,13 +2654,41 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             F32MIN => {
                 let arg2 = self.value_store.pop_f32()?;
                 let arg1 = self.value_store.pop_f32()?;
-                let result = if arg1 < arg2 { arg1 } else { arg2 };
+                // https://webassembly.github.io/spec/core/exec/numerics.html#op-fmin
+                let result = if arg1.is_nan() || arg2.is_nan() {
+                    f32::NAN
+                } else if arg1 == arg2 {
+                    // -0.0 == +0.0, so pick by sign
+                    if arg1.is_sign_negative() {
+                        arg1
+                    } else {
+                        arg2
+                    }
+                } else if arg1 < arg2 {
+                    arg1
+                } else {
+                    arg2
+                };
                 self.value_store.push(Value::F32(result));
             }
             F32MAX => {
                 let arg2 = self.value_store.pop_f32()?;
                 let arg1 = self.value_store.pop_f32()?;
-                let result = if arg1 > arg2 { arg1 } else { arg2 };
+                // https://webassembly.github.io/spec/core/exec/numerics.html#op-fmax
+                let result = if arg1.is_nan() || arg2.is_nan() {
+                    f32::NAN
+                } else if arg1 == arg2 {
+                    // -0.0 == +0.0, so pick by sign
+                    if arg1.is_sign_positive() {
+                        arg1
+                    } else {
+                        arg2
+                    }
+                } else if arg1 > arg2 {
+                    arg1
+                } else {
+                    arg2
+                };
                 self.value_store.push(Value::F32(result));
             }
             F32COPYSIGN => {
@@ -1578,13 +2769,41 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             F64MIN => {
                 let arg2 = self.value_store.pop_f64()?;
                 let arg1 = self.value_store.pop_f64()?;
-                let result = if arg1 < arg2 { arg1 } else { arg2 };
+                // https://webassembly.github.io/spec/core/exec/numerics.html#op-fmin
+                let result = if arg1.is_nan() || arg2.is_nan() {
+                    f64::NAN
+                } else if arg1 == arg2 {
+                    // -0.0 == +0.0, so pick by sign
+                    if arg1.is_sign_negative() {
+                        arg1
+                    } else {
+                        arg2
+                    }
+                } else if arg1 < arg2 {
+                    arg1
+                } else {
+                    arg2
+                };
                 self.value_store.push(Value::F64(result));
             }
             F64MAX => {
                 let arg2 = self.value_store.pop_f64()?;
                 let arg1 = self.value_store.pop_f64()?;
-                let result = if arg1 > arg2 { arg1 } else { arg2 };
+                // https://webassembly.github.io/spec/core/exec/numerics.html#op-fmax
+                let result = if arg1.is_nan() || arg2.is_nan() {
+                    f64::NAN
+                } else if arg1 == arg2 {
+                    // -0.0 == +0.0, so pick by sign
+                    if arg1.is_sign_positive() {
+                        arg1
+                    } else {
+                        arg2
+                    }
+                } else if arg1 > arg2 {
+                    arg1
+                } else {
+                    arg2
+                };
                 self.value_store.push(Value::F64(result));
             }
             F64COPYSIGN => {
@@ -1750,8 +2969,19 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
             }
         }
 
+        if !INSTRUMENTED {
+            return Ok(action);
+        }
+
+        if let (Some(profile), Some(start)) = (self.opcode_profile.as_mut(), profile_start) {
+            profile.record(op_code, start.elapsed());
+        }
+
         if let Some(debug_string) = &self.debug_string {
-            if matches!(op_code, CALL | CALLINDIRECT) {
+            if matches!(
+                op_code,
+                CALL | CALLINDIRECT | RETURNCALL | RETURNCALLINDIRECT
+            ) {
                 eprintln!("\n{file_offset:06x} {debug_string}");
             } else {
                 // For calls, we print special debug stuff in do_call
@@ -1783,7 +3013,7 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
 
         let mut print_blocks = |i| {
             block_str.clear();
-            while let Some((b, Block { vstack, ty })) = block {
+            while let Some((b, Block { vstack, ty, .. })) = block {
                 if *vstack > i {
                     break;
                 }
@@ -1804,10 +3034,45 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         eprintln!();
     }
 
+    /// The function calls that are currently active, innermost first.
+    /// After a trap, these are the calls that were active when it happened.
+    pub fn backtrace(&self) -> std::vec::Vec<FrameInfo<'a>> {
+        let frames = self.previous_frames.iter().chain(once(&self.current_frame));
+        let mut backtrace: std::vec::Vec<_> = frames
+            .zip(self.debug_frame_addrs())
+            .map(|(frame, address)| self.frame_info(frame, address))
+            .collect();
+        backtrace.reverse();
+        backtrace
+    }
+
+    fn frame_info(&self, frame: &Frame, address: usize) -> FrameInfo<'a> {
+        let fn_index = frame.fn_index as u32;
+        FrameInfo {
+            fn_index,
+            module_name: self.module.names.module_name,
+            function_name: self.module.names.function_name(fn_index),
+            address,
+        }
+    }
+
+    /// Find the code address to display for each frame, outermost first.
+    /// For previous frames, show the address of the CALL instruction.
+    /// For the current frame, show the program counter value.
+    fn debug_frame_addrs(&self) -> impl Iterator<Item = usize> + '_ {
+        let frames = self.previous_frames.iter().chain(once(&self.current_frame));
+        let next_frames = frames.skip(1);
+        // for each previous_frame, find return address of the *next* frame
+        let return_addrs = next_frames.map(|f| f.return_addr);
+        // roll back to the CALL instruction before that return address, it's more meaningful.
+        let call_addrs = return_addrs.map(|ra| self.debug_return_addr_to_call_addr(ra));
+        // For the current frame, show the program_counter
+        call_addrs.chain(once(self.program_counter))
+    }
+
     /// Dump a stack trace when an error occurs
     /// --------------
-    /// func[123]
-    ///   address  0x12345
+    /// module::function (func[123] @0x12345)
     ///   args     0: I64(234), 1: F64(7.15)
     ///   locals   2: I32(412), 3: F64(3.14)
     ///   stack    [I64(111), F64(3.14)]
@@ -1818,19 +3083,7 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
 
         let frames = self.previous_frames.iter().chain(once(&self.current_frame));
         let next_frames = frames.clone().skip(1);
-
-        // Find the code address to display for each frame
-        // For previous frames, show the address of the CALL instruction
-        // For the current frame, show the program counter value
-        let mut execution_addrs = {
-            // for each previous_frame, find return address of the *next* frame
-            let return_addrs = next_frames.clone().map(|f| f.return_addr);
-            // roll back to the CALL instruction before that return address, it's more meaningful.
-            let call_addrs = return_addrs.map(|ra| self.debug_return_addr_to_call_addr(ra));
-            // For the current frame, show the program_counter
-            call_addrs.chain(once(self.program_counter))
-        };
-
+        let mut execution_addrs = self.debug_frame_addrs();
         let mut frame_ends = next_frames.map(|f| f.locals_start);
 
         for frame in frames {
@@ -1841,30 +3094,16 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
                 ..
             } = frame;
 
-            let arg_count = {
-                let signature_index = if *fn_index < self.import_count {
-                    match self.module.import.imports[*fn_index].description {
-                        ImportDesc::Func { signature_index } => signature_index,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    self.module.function.signatures[fn_index - self.import_count]
-                };
-                self.module.types.look_up(signature_index).0.len()
-            };
-
-            let fn_name = self
+            let arg_count = self
                 .module
-                .names
-                .function_names
-                .iter()
-                .find(|(idx, _)| *idx == *fn_index as u32)
-                .map(|(_, name)| *name)
-                .unwrap_or("");
+                .types
+                .look_up(self.signature_index(*fn_index))
+                .0
+                .len();
 
-            // Function and address match wasm-objdump formatting, for easy copy & find
-            writeln!(buffer, "func[{fn_index}]  {fn_name}")?;
-            writeln!(buffer, "  address  {:06x}", execution_addrs.next().unwrap())?;
+            // The function index matches wasm-objdump formatting, for easy copy & find
+            let info = self.frame_info(frame, execution_addrs.next().unwrap());
+            writeln!(buffer, "{info}")?;
 
             write!(buffer, "  args     ")?;
             for local_index in 0..*locals_count {
@@ -1913,3 +3152,60 @@ impl<'a, I: ImportDispatcher> Instance<'a, I> {
         call_addr
     }
 }
+
+/// The limits of the module's memory, if it imports it rather than defining it
+fn imported_memory_limits<'m>(module: &'m WasmModule) -> Option<&'m Limits> {
+    module
+        .import
+        .imports
+        .iter()
+        .find_map(|imp| match &imp.description {
+            ImportDesc::Mem { limits } => Some(limits),
+            _ => None,
+        })
+}
+
+/// The host's value for an imported global, checked against the type the module imports it with
+fn imported_global_value<I: ImportDispatcher>(
+    import_dispatcher: &I,
+    import: &Import,
+    ty: &GlobalType,
+) -> Result<Value, std::string::String> {
+    let value = import_dispatcher
+        .import_global(import.module, import.name)
+        .ok_or_else(|| {
+            format!(
+                "The module imports the global {}.{}, but the host doesn't provide it",
+                import.module, import.name
+            )
+        })?;
+    if ValueType::from(value) == ty.value_type {
+        Ok(value)
+    } else {
+        Err(format!(
+            "The module imports the global {}.{} as {:?}, but the host provides {value:?}",
+            import.module, import.name, ty.value_type
+        ))
+    }
+}
+
+/// Check that a host's memory is one that the module could have imported
+fn check_host_memory(limits: &Limits, len: usize) -> Result<(), std::string::String> {
+    let page_size = MemorySection::PAGE_SIZE as usize;
+    let (min, max) = match *limits {
+        Limits::Min(min) => (min, None),
+        Limits::MinMax(min, max) => (min, Some(max)),
+    };
+    let pages = len / page_size;
+    if len % page_size != 0 {
+        Err(format!(
+            "The host's memory is {len:#x} bytes, which isn't a whole number of {page_size:#x}-byte pages"
+        ))
+    } else if pages < min as usize || max.is_some_and(|max| pages > max as usize) {
+        Err(format!(
+            "The host's memory is {pages} pages, but the module imports it with limits {limits:?}"
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -1,18 +1,59 @@
+mod backtrace;
+mod closure_dispatcher;
+mod exports;
+mod format;
 mod frame;
+mod hooks;
 mod instance;
+mod mem_stats;
+#[cfg(feature = "opcode-coverage")]
+pub mod opcode_coverage;
+mod opcode_profile;
+mod pure_dispatcher;
+mod report;
+mod roc_values;
+mod shared;
+mod snapshot;
+mod spectest_dispatcher;
 #[cfg(test)]
 mod tests;
-
+mod trace;
+mod typed;
+mod unsupported;
+mod validate;
 mod value_store;
 pub mod wasi;
 
 // Main external interface
+pub use backtrace::FrameInfo;
+pub use closure_dispatcher::{ClosureImportDispatcher, ImportFn};
+pub use exports::{ExportInfo, ExportSignature};
+pub use format::{format_result, format_value, ValueHint};
+pub use hooks::{CallHook, MemoryFaultHook, MemoryGrowHook};
 pub use instance::Instance;
-pub use wasi::{WasiDispatcher, WasiFile};
+pub use mem_stats::MemStats;
+pub use opcode_profile::OpcodeProfile;
+pub use pure_dispatcher::PureImportDispatcher;
+pub use report::{RunOutcome, RunReport};
+pub use roc_values::{read_roc_list, read_roc_str};
+pub use shared::SharedModuleBytes;
+pub use snapshot::{diff_memory, MemoryChange, Snapshot};
+pub use spectest_dispatcher::{SpectestDispatcher, SPECTEST_MODULE_NAME};
+pub use trace::{ExecutionTrace, TraceEvent};
+pub use typed::{WasmArgs, WasmResult, WasmType};
+pub use validate::{verify, verify_with_limits, ModuleLimit, ModuleLimits, ValidationError};
+pub use wasi::{PathMapper, WasiDispatcher, WasiFile, WasiFileSystem, WriteBuffering};
 
 pub use roc_wasm_module::Value;
 use roc_wasm_module::ValueType;
+use std::fmt;
 
+/// Handles the calls a module makes to its imports.
+///
+/// A dispatcher that wraps a `WasiDispatcher`, or any other dispatcher, MUST forward
+/// `exit_code` and `take_trap` to it. They default to `None`, so a wrapper that doesn't
+/// forward them carries on running the program after it calls `proc_exit`, instead of stopping.
+/// `DefaultImportDispatcher` shows how.
 pub trait ImportDispatcher {
     /// Dispatch a call from WebAssembly to your own code, based on module and function name.
     fn dispatch(
@@ -22,26 +63,112 @@ pub trait ImportDispatcher {
         arguments: &[Value],
         memory: &mut [u8],
     ) -> Option<Value>;
+
+    /// Whether `dispatch` has an implementation for this import, for `Instance::unresolved_imports`.
+    /// The default claims to handle everything, so that existing dispatchers aren't reported.
+    fn can_handle(&self, _module_name: &str, _function_name: &str) -> bool {
+        true
+    }
+
+    /// Called before each import is dispatched. Returning an error stops execution with a trap.
+    fn check_import(&mut self, _module_name: &str, _function_name: &str) -> Result<(), TrapReason> {
+        Ok(())
+    }
+
+    /// The exit code, once the program has asked to exit (e.g. with WASI `proc_exit`).
+    /// The Instance checks this after each import call, and stops running the program.
+    ///
+    /// Wrappers must forward this to the dispatcher they wrap, e.g. `self.wasi.exit_code()`.
+    fn exit_code(&self) -> Option<i32> {
+        None
+    }
+
+    /// Called after each import is dispatched. Returning a reason stops execution with a trap.
+    ///
+    /// Wrappers must forward this to the dispatcher they wrap, e.g. `self.wasi.take_trap()`.
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        None
+    }
+
+    /// The initial value of a global that the module imports, when it's instantiated.
+    /// The default doesn't provide any, so modules that import globals fail to instantiate.
+    fn import_global(&self, _module_name: &str, _global_name: &str) -> Option<Value> {
+        None
+    }
 }
 
 impl Default for DefaultImportDispatcher<'_> {
     fn default() -> Self {
-        DefaultImportDispatcher {
-            wasi: WasiDispatcher::new(&[]),
-        }
+        DefaultImportDispatcher::new(&[])
     }
 }
 
+/// Dispatches WASI calls to `wasi`, and calls to other modules to the dispatchers registered
+/// for them with `DefaultImportDispatcher::builder()`.
 pub struct DefaultImportDispatcher<'a> {
     pub wasi: WasiDispatcher<'a>,
+    modules: Vec<(String, Box<dyn ImportDispatcher + 'a>)>,
 }
 
 impl<'a> DefaultImportDispatcher<'a> {
     pub fn new(args: &'a [&'a [u8]]) -> Self {
         DefaultImportDispatcher {
             wasi: WasiDispatcher::new(args),
+            modules: Vec::new(),
         }
     }
+
+    /// Build a dispatcher for WASI plus other named modules, e.g. the ones a platform provides
+    ///
+    /// ```
+    /// # use roc_wasm_interp::{ClosureImportDispatcher, DefaultImportDispatcher, WasiDispatcher};
+    /// let platform = ClosureImportDispatcher::default().with("platform", "now", |_, _| None);
+    /// let dispatcher = DefaultImportDispatcher::builder()
+    ///     .wasi(WasiDispatcher::new(&[b"app.wasm"]))
+    ///     .module("platform", platform)
+    ///     .build();
+    /// ```
+    pub fn builder() -> DefaultImportDispatcherBuilder<'a> {
+        DefaultImportDispatcherBuilder {
+            dispatcher: DefaultImportDispatcher::default(),
+        }
+    }
+
+    fn module_dispatcher(&mut self, module_name: &str) -> Option<&mut (dyn ImportDispatcher + 'a)> {
+        self.modules
+            .iter_mut()
+            .find(|(name, _)| name == module_name)
+            .map(|(_, dispatcher)| dispatcher.as_mut())
+    }
+}
+
+/// Builder for a `DefaultImportDispatcher` that handles more modules than just WASI
+pub struct DefaultImportDispatcherBuilder<'a> {
+    dispatcher: DefaultImportDispatcher<'a>,
+}
+
+impl<'a> DefaultImportDispatcherBuilder<'a> {
+    /// Use this WASI dispatcher, instead of one with no arguments
+    pub fn wasi(mut self, wasi: WasiDispatcher<'a>) -> Self {
+        self.dispatcher.wasi = wasi;
+        self
+    }
+
+    /// Send all calls to imports from `module_name` to `dispatcher`
+    pub fn module<D: ImportDispatcher + 'a>(mut self, module_name: &str, dispatcher: D) -> Self {
+        assert!(
+            module_name != wasi::MODULE_NAME,
+            "{module_name} is already handled by the WASI dispatcher"
+        );
+        self.dispatcher
+            .modules
+            .push((module_name.to_string(), Box::new(dispatcher)));
+        self
+    }
+
+    pub fn build(self) -> DefaultImportDispatcher<'a> {
+        self.dispatcher
+    }
 }
 
 impl<'a> ImportDispatcher for DefaultImportDispatcher<'a> {
@@ -54,8 +181,133 @@ impl<'a> ImportDispatcher for DefaultImportDispatcher<'a> {
     ) -> Option<Value> {
         if module_name == wasi::MODULE_NAME {
             self.wasi.dispatch(function_name, arguments, memory)
+        } else if let Some(dispatcher) = self.module_dispatcher(module_name) {
+            dispatcher.dispatch(module_name, function_name, arguments, memory)
+        } else {
+            // check_import has already trapped
+            None
+        }
+    }
+
+    fn can_handle(&self, module_name: &str, function_name: &str) -> bool {
+        if module_name == wasi::MODULE_NAME {
+            true
+        } else {
+            self.modules
+                .iter()
+                .find(|(name, _)| name == module_name)
+                .is_some_and(|(_, dispatcher)| dispatcher.can_handle(module_name, function_name))
+        }
+    }
+
+    fn check_import(&mut self, module_name: &str, function_name: &str) -> Result<(), TrapReason> {
+        if module_name == wasi::MODULE_NAME {
+            Ok(())
+        } else if let Some(dispatcher) = self.module_dispatcher(module_name) {
+            dispatcher.check_import(module_name, function_name)
         } else {
-            panic!("DefaultImportDispatcher does not implement {module_name}.{function_name}");
+            Err(TrapReason::UnknownImport {
+                module_name: module_name.to_string(),
+                function_name: function_name.to_string(),
+            })
+        }
+    }
+
+    fn import_global(&self, module_name: &str, global_name: &str) -> Option<Value> {
+        self.modules
+            .iter()
+            .find(|(name, _)| name == module_name)
+            .and_then(|(_, dispatcher)| dispatcher.import_global(module_name, global_name))
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        let mut module_exit_codes = self.modules.iter().map(|(_, d)| d.exit_code());
+        self.wasi
+            .exit_code()
+            .or_else(|| module_exit_codes.find_map(|code| code))
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        let mut module_traps = self.modules.iter_mut().map(|(_, d)| d.take_trap());
+        self.wasi
+            .take_trap()
+            .or_else(|| module_traps.find_map(|trap| trap))
+    }
+}
+
+/// Reasons the embedder can ask for execution to stop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapReason {
+    /// An effectful import was called while evaluating in a pure context (see `PureImportDispatcher`)
+    EffectInPureContext {
+        module_name: String,
+        function_name: String,
+    },
+    /// The program called an import that the host doesn't provide
+    UnknownImport {
+        module_name: String,
+        function_name: String,
+    },
+    /// The program wrote more to an in-memory file than `WasiDispatcher::output_limit` allows
+    OutputLimitExceeded { fd: usize, limit: usize },
+    /// The program was still running at the deadline from `Instance::with_deadline`
+    Timeout,
+    /// The program did something different from the trace passed to `Instance::replay`
+    TraceDivergence {
+        position: usize,
+        expected: String,
+        actual: String,
+    },
+    /// The program called an import with an argument that doesn't match the import's signature
+    ImportArgumentMismatch {
+        module_name: String,
+        function_name: String,
+        index: usize,
+        expected: ValueType,
+        actual: ValueType,
+    },
+}
+
+impl fmt::Display for TrapReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EffectInPureContext {
+                module_name,
+                function_name,
+            } => write!(
+                f,
+                "The program called {module_name}.{function_name}, which has effects, but it's being evaluated in a pure context."
+            ),
+            Self::UnknownImport {
+                module_name,
+                function_name,
+            } => write!(
+                f,
+                "The program called {module_name}.{function_name}, but the host doesn't provide that import."
+            ),
+            Self::OutputLimitExceeded { fd, limit } => write!(
+                f,
+                "The program wrote more than {limit} bytes to file descriptor {fd}, which is the limit for captured output."
+            ),
+            Self::Timeout => write!(f, "The program ran for longer than its time limit."),
+            Self::TraceDivergence {
+                position,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The program diverged from the replayed trace at event {position}: it expected {expected}, but the program did {actual}."
+            ),
+            Self::ImportArgumentMismatch {
+                module_name,
+                function_name,
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The program called {module_name}.{function_name} with {actual:?} for argument {index}, but the import's signature says it's {expected:?}."
+            ),
         }
     }
 }
@@ -66,8 +318,29 @@ impl<'a> ImportDispatcher for DefaultImportDispatcher<'a> {
 pub(crate) enum Error {
     Type(ValueType, ValueType),
     StackEmpty,
-    MemoryAccessOutOfBounds(u32, u32),
+    MemoryAccessOutOfBounds(u64, u64),
+    /// A memory instruction ran in a module that doesn't define a memory
+    NoMemory,
     UnreachableOp,
+    UnalignedAtomic(u64, u32),
+    AtomicWaitForever,
+    Trap(TrapReason),
+    /// A `block`, `loop` or `if` ended without leaving exactly its declared result on the stack
+    BlockResult {
+        expected: Option<ValueType>,
+        found: Vec<ValueType>,
+    },
+    /// A block type refers to a signature, which is only allowed since the multi-value proposal
+    MultiValueBlock,
+    /// An instruction from a Wasm proposal that this interpreter doesn't implement yet
+    UnsupportedFeature {
+        feature: &'static str,
+        opcode: u8,
+        sub_opcode: Option<u32>,
+    },
+    /// Not really an error: the program asked to exit, and we unwind the same way.
+    /// This doesn't get a stack trace.
+    Exit(i32),
 }
 
 impl Error {
@@ -89,9 +362,57 @@ impl Error {
                     file_offset, addr, memory_size-1
                 )
             }
+            Error::NoMemory => {
+                format!(
+                    "ERROR: A Wasm instruction at file offset {file_offset:#x} accesses memory, but this module has no memory defined.\n"
+                )
+            }
             Error::UnreachableOp => {
                 format!("WebAssembly `unreachable` instruction at file offset {file_offset:#x}.\n")
             }
+            Error::UnalignedAtomic(addr, size) => {
+                format!(
+                    "ERROR: An atomic Wasm instruction at file offset {file_offset:#x} tried to access memory at {addr:#x}, which is not aligned to {size} bytes.\n"
+                )
+            }
+            Error::Trap(reason) => {
+                format!("ERROR: Trap at file offset {file_offset:#x}. {reason}\n")
+            }
+            Error::AtomicWaitForever => {
+                format!(
+                    "ERROR: A Wasm instruction at file offset {file_offset:#x} waits with no timeout, but the interpreter is single-threaded so nothing could ever wake it.\n"
+                )
+            }
+            Error::BlockResult { expected, found } => {
+                let expected = match expected {
+                    Some(ty) => format!("[{ty:?}]"),
+                    None => "[]".to_string(),
+                };
+                format!(
+                    "ERROR: A block at file offset {file_offset:#x} should leave {expected} on the stack, but it left {found:?}.\n"
+                )
+            }
+            Error::MultiValueBlock => {
+                format!(
+                    "ERROR: The block at file offset {file_offset:#x} has params or multiple results. This interpreter only supports Wasm MVP.\n"
+                )
+            }
+            Error::UnsupportedFeature {
+                feature,
+                opcode,
+                sub_opcode,
+            } => {
+                let instruction = match sub_opcode {
+                    Some(sub_opcode) => format!("{opcode:#04x} {sub_opcode}"),
+                    None => format!("{opcode:#04x}"),
+                };
+                format!(
+                    "ERROR: The instruction at file offset {file_offset:#x} (opcode {instruction}) is part of the {feature} feature, which this interpreter doesn't support yet. Try disabling it in code gen.\n"
+                )
+            }
+            Error::Exit(code) => {
+                format!("The program exited with code {code} at file offset {file_offset:#x}.\n")
+            }
         }
     }
 }
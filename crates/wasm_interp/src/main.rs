@@ -6,12 +6,14 @@ use std::io;
 use std::iter::once;
 use std::process;
 
-use roc_wasm_interp::{DefaultImportDispatcher, Instance};
+use roc_wasm_interp::{wasi, DefaultImportDispatcher, Instance};
 use roc_wasm_module::WasmModule;
 
 pub const FLAG_FUNCTION: &str = "function";
 pub const FLAG_DEBUG: &str = "debug";
 pub const FLAG_HEX: &str = "hex";
+pub const FLAG_ARGS_FILES: &str = "args-files";
+pub const FLAG_PROFILE_OPCODES: &str = "profile-opcodes";
 pub const WASM_FILE: &str = "WASM_FILE";
 pub const ARGS_FOR_APP: &str = "ARGS_FOR_APP";
 
@@ -22,7 +24,7 @@ fn main() -> io::Result<()> {
 
     let flag_function = Arg::new(FLAG_FUNCTION)
         .long(FLAG_FUNCTION)
-        .help("Call a specific function exported from the WebAssembly module")
+        .help("Call a specific function exported from the WebAssembly module.\nFor WASI reactors, `_initialize` is called first.")
         .default_value("_start")
         .required(false);
 
@@ -38,6 +40,18 @@ fn main() -> io::Result<()> {
         .action(ArgAction::SetTrue)
         .required(false);
 
+    let flag_args_files = Arg::new(FLAG_ARGS_FILES)
+        .long(FLAG_ARGS_FILES)
+        .help("Expand any `@file` argument for the app into the newline-separated arguments listed in that file.")
+        .action(ArgAction::SetTrue)
+        .required(false);
+
+    let flag_profile_opcodes = Arg::new(FLAG_PROFILE_OPCODES)
+        .long(FLAG_PROFILE_OPCODES)
+        .help("After running, print a histogram of the time the interpreter spent on each opcode.")
+        .action(ArgAction::SetTrue)
+        .required(false);
+
     let wasm_file_to_run = Arg::new(WASM_FILE)
         .help("The .wasm file to run")
         .required(true);
@@ -51,6 +65,8 @@ fn main() -> io::Result<()> {
         .arg(flag_function)
         .arg(flag_debug)
         .arg(flag_hex)
+        .arg(flag_args_files)
+        .arg(flag_profile_opcodes)
         .arg(wasm_file_to_run)
         .arg(args_for_app);
 
@@ -60,13 +76,23 @@ fn main() -> io::Result<()> {
     let start_fn_name = matches.get_one::<String>(FLAG_FUNCTION).unwrap();
     let is_debug_mode = matches.get_flag(FLAG_DEBUG);
     let is_hex_format = matches.get_flag(FLAG_HEX);
+    let is_args_files = matches.get_flag(FLAG_ARGS_FILES);
+    let is_profile_opcodes = matches.get_flag(FLAG_PROFILE_OPCODES);
     let start_arg_strings = matches.get_many::<String>(ARGS_FOR_APP).unwrap_or_default();
     let wasm_path = matches.get_one::<String>(WASM_FILE).unwrap();
     // WASI expects the .wasm file to be argv[0]
     let wasi_argv_iter = once(wasm_path)
         .chain(start_arg_strings)
         .map(|s| s.as_bytes());
-    let wasi_argv = Vec::from_iter_in(wasi_argv_iter, &arena);
+    let wasi_argv = Vec::from_iter_in(wasi_argv_iter, &arena).into_bump_slice();
+    let wasi_argv = if is_args_files {
+        wasi::expand_args_files(&arena, wasi_argv).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            process::exit(1);
+        })
+    } else {
+        wasi_argv
+    };
 
     // Load the WebAssembly binary file
 
@@ -87,16 +113,30 @@ fn main() -> io::Result<()> {
 
     // Create an execution instance
 
-    let dispatcher = DefaultImportDispatcher::new(&wasi_argv);
+    let dispatcher = DefaultImportDispatcher::new(wasi_argv);
     let mut inst =
         Instance::for_module(&arena, &module, dispatcher, is_debug_mode).unwrap_or_else(|e| {
             eprintln!("{e}");
             process::exit(2);
         });
 
+    if is_profile_opcodes {
+        inst.enable_opcode_profile();
+    }
+
     // Run
 
-    let result = inst.call_export_from_cli(&module, start_fn_name, &wasi_argv);
+    let result = inst.call_export_from_cli(&module, start_fn_name, wasi_argv);
+    // Some of the exit paths below skip destructors, so write out any partial line now
+    inst.import_dispatcher.wasi.flush()?;
+
+    if let Some(profile) = inst.opcode_profile() {
+        eprintln!("{profile}");
+    }
+
+    if let Some(code) = inst.exit_code() {
+        process::exit(code);
+    }
 
     // Print out return value, if any
 
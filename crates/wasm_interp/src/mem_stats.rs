@@ -0,0 +1,12 @@
+/// How much a program used its memory, from `Instance::mem_stats`.
+/// Each load or store instruction counts once, whatever its size.
+/// Atomic read-modify-writes and `memory.copy` count as both a read and a write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemStats {
+    pub reads: u64,
+    pub writes: u64,
+    /// Number of times `memory.grow` succeeded
+    pub grows: u64,
+    /// See `Instance::memory_high_water_mark`
+    pub high_water_mark: u32,
+}
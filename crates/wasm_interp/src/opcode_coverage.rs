@@ -0,0 +1,32 @@
+use roc_wasm_module::opcodes::{OpCode, LOOKUP_TABLE};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Which opcodes have been executed by any Instance in this process, one bit per opcode.
+/// It's global rather than per-Instance so that it can add up a whole test suite.
+static EXECUTED: [AtomicU64; 4] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: AtomicU64 = AtomicU64::new(0);
+    [NONE; 4]
+};
+
+pub(crate) fn record(op_code: OpCode) {
+    let i = op_code as usize;
+    EXECUTED[i / 64].fetch_or(1 << (i % 64), Ordering::Relaxed);
+}
+
+fn was_executed(op_code: OpCode) -> bool {
+    let i = op_code as usize;
+    EXECUTED[i / 64].load(Ordering::Relaxed) & (1 << (i % 64)) != 0
+}
+
+fn all_opcodes() -> impl Iterator<Item = OpCode> {
+    LOOKUP_TABLE.iter().filter_map(|op| *op)
+}
+
+pub fn executed_opcodes() -> Vec<OpCode> {
+    all_opcodes().filter(|op| was_executed(*op)).collect()
+}
+
+pub fn unexecuted_opcodes() -> Vec<OpCode> {
+    all_opcodes().filter(|op| !was_executed(*op)).collect()
+}
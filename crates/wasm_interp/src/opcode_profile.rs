@@ -0,0 +1,70 @@
+use roc_wasm_module::opcodes::OpCode;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// How many times each opcode was executed and how long the interpreter spent on it.
+/// This is about the interpreter's own performance, to see which opcodes are worth optimizing.
+#[derive(Debug, Clone)]
+pub struct OpcodeProfile {
+    counts: [u64; 256],
+    nanos: [u64; 256],
+}
+
+impl Default for OpcodeProfile {
+    fn default() -> Self {
+        OpcodeProfile {
+            counts: [0; 256],
+            nanos: [0; 256],
+        }
+    }
+}
+
+impl OpcodeProfile {
+    pub(crate) fn record(&mut self, op_code: OpCode, elapsed: Duration) {
+        let i = op_code as usize;
+        self.counts[i] += 1;
+        self.nanos[i] += elapsed.as_nanos() as u64;
+    }
+
+    pub fn count(&self, op_code: OpCode) -> u64 {
+        self.counts[op_code as usize]
+    }
+
+    pub fn total_time(&self, op_code: OpCode) -> Duration {
+        Duration::from_nanos(self.nanos[op_code as usize])
+    }
+}
+
+impl Display for OpcodeProfile {
+    /// A histogram of the opcodes that were executed, with the slowest first
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BAR_WIDTH: u64 = 40;
+
+        let mut executed: Vec<u8> = (0..=255u8)
+            .filter(|op| self.counts[*op as usize] > 0)
+            .collect();
+        executed.sort_by_key(|op| std::cmp::Reverse(self.nanos[*op as usize]));
+
+        let total_nanos: u64 = self.nanos.iter().sum();
+        writeln!(
+            f,
+            "{:<16} {:>12} {:>12} {:>8}",
+            "opcode", "count", "total ns", "avg ns"
+        )?;
+        for op in executed {
+            let count = self.counts[op as usize];
+            let nanos = self.nanos[op as usize];
+            let bar_len = (nanos * BAR_WIDTH).checked_div(total_nanos).unwrap_or(0);
+            writeln!(
+                f,
+                "{:<16} {:>12} {:>12} {:>8} {}",
+                format!("{:?}", OpCode::from(op)),
+                count,
+                nanos,
+                nanos / count,
+                "#".repeat(bar_len as usize)
+            )?;
+        }
+        Ok(())
+    }
+}
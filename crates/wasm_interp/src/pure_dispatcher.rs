@@ -0,0 +1,91 @@
+use crate::wasi;
+use crate::{ImportDispatcher, TrapReason, Value};
+
+/// Wraps another ImportDispatcher so that effectful imports trap instead of running.
+/// This is for evaluating pure code, for example constant-folding at compile time,
+/// where performing an effect would be a bug.
+///
+/// By default every WASI function is treated as effectful. Anything else is forwarded to `inner`.
+///
+/// After a trap, the reason is available from `trap()`.
+pub struct PureImportDispatcher<I: ImportDispatcher> {
+    pub inner: I,
+    /// (module name, function name) pairs, where a function name of "*" matches the whole module
+    effectful: Vec<(String, String)>,
+    trap: Option<TrapReason>,
+}
+
+impl<I: ImportDispatcher> PureImportDispatcher<I> {
+    pub fn new(inner: I) -> Self {
+        Self::with_effectful_imports(inner, [(wasi::MODULE_NAME, "*")])
+    }
+
+    /// Treat only the listed imports as effectful, instead of all of WASI.
+    /// A function name of "*" matches every function in that module.
+    pub fn with_effectful_imports<'s, E>(inner: I, effectful: E) -> Self
+    where
+        E: IntoIterator<Item = (&'s str, &'s str)>,
+    {
+        PureImportDispatcher {
+            inner,
+            effectful: effectful
+                .into_iter()
+                .map(|(m, f)| (m.to_string(), f.to_string()))
+                .collect(),
+            trap: None,
+        }
+    }
+
+    pub fn is_effectful(&self, module_name: &str, function_name: &str) -> bool {
+        self.effectful
+            .iter()
+            .any(|(m, f)| m == module_name && (f == "*" || f == function_name))
+    }
+
+    /// The reason execution was stopped, if an effectful import was called
+    pub fn trap(&self) -> Option<&TrapReason> {
+        self.trap.as_ref()
+    }
+}
+
+impl<I: ImportDispatcher> ImportDispatcher for PureImportDispatcher<I> {
+    fn dispatch(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        arguments: &[Value],
+        memory: &mut [u8],
+    ) -> Option<Value> {
+        self.inner
+            .dispatch(module_name, function_name, arguments, memory)
+    }
+
+    fn can_handle(&self, module_name: &str, function_name: &str) -> bool {
+        self.inner.can_handle(module_name, function_name)
+    }
+
+    fn check_import(&mut self, module_name: &str, function_name: &str) -> Result<(), TrapReason> {
+        if self.is_effectful(module_name, function_name) {
+            let reason = TrapReason::EffectInPureContext {
+                module_name: module_name.to_string(),
+                function_name: function_name.to_string(),
+            };
+            self.trap = Some(reason.clone());
+            Err(reason)
+        } else {
+            self.inner.check_import(module_name, function_name)
+        }
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.inner.exit_code()
+    }
+
+    fn take_trap(&mut self) -> Option<TrapReason> {
+        self.inner.take_trap()
+    }
+
+    fn import_global(&self, module_name: &str, global_name: &str) -> Option<Value> {
+        self.inner.import_global(module_name, global_name)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::TrapReason;
+use roc_wasm_module::Value;
+
+/// How a call into an Instance ended, from `Instance::run`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    /// The function returned. This holds its return value, if it has one.
+    Completed(Vec<Value>),
+    /// The program called an exit function like WASI `proc_exit`, with this code
+    Exited(i32),
+    /// The embedder stopped the program, e.g. because it went over its time limit
+    Trapped(TrapReason),
+    /// The program hit a WebAssembly trap like `unreachable`, or the call couldn't start.
+    /// This is the error message, including the stack trace.
+    Failed(String),
+}
+
+/// A summary of one call into an Instance, from `Instance::run_with_report`.
+/// It's a single artifact per run, e.g. for comparing runs in CI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    /// Values left on the value stack when the run finished, from the bottom up.
+    /// After a successful call this is just the return value, if there is one.
+    /// After a trap, it also includes the locals of every frame that was still active.
+    pub stack: Vec<Value>,
+    /// See `Instance::memory_high_water_mark`
+    pub memory_high_water_mark: u32,
+    /// Number of instructions executed during this run
+    pub instruction_count: u64,
+    /// The code the program passed to an exit function like WASI `proc_exit`, if it called one
+    pub exit_code: Option<i32>,
+    /// The error message, if the run trapped or failed to start
+    pub trap: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+mod serialize {
+    use super::RunReport;
+    use roc_wasm_module::Value;
+    use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+    /// Values are written as externally tagged variants like `{"I32": 5}`,
+    /// so that 32 and 64-bit numbers can be told apart
+    struct SerialValue(Value);
+
+    impl Serialize for SerialValue {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0 {
+                Value::I32(x) => serializer.serialize_newtype_variant("Value", 0, "I32", &x),
+                Value::I64(x) => serializer.serialize_newtype_variant("Value", 1, "I64", &x),
+                Value::F32(x) => serializer.serialize_newtype_variant("Value", 2, "F32", &x),
+                Value::F64(x) => serializer.serialize_newtype_variant("Value", 3, "F64", &x),
+            }
+        }
+    }
+
+    struct SerialStack<'a>(&'a [Value]);
+
+    impl Serialize for SerialStack<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter().map(|value| SerialValue(*value)))
+        }
+    }
+
+    impl Serialize for RunReport {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut report = serializer.serialize_struct("RunReport", 5)?;
+            report.serialize_field("stack", &SerialStack(&self.stack))?;
+            report.serialize_field("memory_high_water_mark", &self.memory_high_water_mark)?;
+            report.serialize_field("instruction_count", &self.instruction_count)?;
+            report.serialize_field("exit_code", &self.exit_code)?;
+            report.serialize_field("trap", &self.trap)?;
+            report.end()
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Decode Roc values from the memory of a Roc app compiled to wasm32, e.g. the result of `main`.
+//! `Str` and `List` are both three words: a pointer to the elements, the length, and the capacity.
+
+/// Size of a Roc `Str` or `List` on wasm32
+const WRAPPER_SIZE: usize = 12;
+
+/// The highest bit of a `Str`'s length is set for seamless slices, which point into another string
+const SEAMLESS_SLICE_BIT: u32 = 1 << 31;
+
+/// Read the Roc `Str` at `ptr`.
+/// Strings of up to 11 bytes are stored inline, in the 12 bytes of the `Str` itself.
+/// Their last byte is the length, with the highest bit set to mark them as small.
+pub fn read_roc_str(memory: &[u8], ptr: u32) -> Result<&str, String> {
+    let wrapper = read_bytes(memory, ptr, WRAPPER_SIZE)?;
+    let last_byte = wrapper[WRAPPER_SIZE - 1];
+
+    let bytes = if last_byte >= 0x80 {
+        let small_length = (last_byte & 0x7f) as usize;
+        if small_length >= WRAPPER_SIZE {
+            return Err(format!(
+                "The small string at {ptr:#x} has length {small_length}, which doesn't fit in it"
+            ));
+        }
+        &wrapper[..small_length]
+    } else {
+        let elements = read_u32(wrapper, 0);
+        let length = read_u32(wrapper, 4) & !SEAMLESS_SLICE_BIT;
+        read_bytes(memory, elements, length as usize)?
+    };
+
+    std::str::from_utf8(bytes).map_err(|e| format!("The string at {ptr:#x} isn't valid UTF-8: {e}"))
+}
+
+/// Read the Roc `List` at `ptr`, returning the bytes of each element.
+/// `elem_size` is the size of the element type on wasm32, including any padding for alignment.
+pub fn read_roc_list(memory: &[u8], ptr: u32, elem_size: u32) -> Result<Vec<&[u8]>, String> {
+    let wrapper = read_bytes(memory, ptr, WRAPPER_SIZE)?;
+    let elements = read_u32(wrapper, 0);
+    let length = read_u32(wrapper, 4) as usize;
+
+    let size = length
+        .checked_mul(elem_size as usize)
+        .ok_or_else(|| format!("The list at {ptr:#x} has an impossible length {length}"))?;
+    let bytes = read_bytes(memory, elements, size)?;
+    if elem_size == 0 {
+        return Ok(vec![&[][..]; length]);
+    }
+    Ok(bytes.chunks_exact(elem_size as usize).collect())
+}
+
+fn read_bytes(memory: &[u8], addr: u32, len: usize) -> Result<&[u8], String> {
+    let start = addr as usize;
+    start
+        .checked_add(len)
+        .and_then(|end| memory.get(start..end))
+        .ok_or_else(|| {
+            format!(
+                "Reading {len} bytes at {addr:#x} goes past the end of memory, which is {:#x} bytes",
+                memory.len()
+            )
+        })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..][..4].try_into().unwrap())
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use bumpalo::Bump;
+use roc_wasm_module::WasmModule;
+
+use crate::{verify_with_limits, ImportDispatcher, Instance, ModuleLimits};
+
+/// The bytes of a verified module, which can be shared between threads, e.g. to serve requests
+/// in parallel. Each thread creates its own Instances from them with `instantiate`, in its own
+/// arena, and they all start from the module's initial state rather than from each other's.
+///
+/// Only the bytes are shared, and they're only verified once, in `new`. The parsed module isn't
+/// shared: a `WasmModule` lives in a `Bump` arena, which can't be shared between threads,
+/// so every `instantiate` parses the bytes again. On a single thread, `Instance::fork` avoids that.
+#[derive(Debug, Clone)]
+pub struct SharedModuleBytes {
+    bytes: Arc<[u8]>,
+}
+
+impl SharedModuleBytes {
+    /// Verify a module with the default `ModuleLimits`
+    pub fn new(bytes: impl Into<Arc<[u8]>>) -> Result<Self, String> {
+        Self::with_limits(bytes, &ModuleLimits::default())
+    }
+
+    /// Verify a module, rejecting it if it's bigger than `limits` allow
+    pub fn with_limits(bytes: impl Into<Arc<[u8]>>, limits: &ModuleLimits) -> Result<Self, String> {
+        let bytes = bytes.into();
+        let arena = Bump::new();
+        let module = parse(&arena, &bytes)?;
+        verify_with_limits(&module, limits).map_err(|e| format!("Invalid Wasm module: {e}"))?;
+        Ok(SharedModuleBytes { bytes })
+    }
+
+    /// Parse the bytes into `arena`, and create an Instance with its own memory, globals and stacks
+    pub fn instantiate<'a, I: ImportDispatcher>(
+        &self,
+        arena: &'a Bump,
+        import_dispatcher: I,
+        is_debug_mode: bool,
+    ) -> Result<Instance<'a, I>, String> {
+        let module = arena.alloc(parse(arena, &self.bytes)?);
+        Instance::instantiate(arena, module, None, None, import_dispatcher, is_debug_mode)
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+fn parse<'a>(arena: &'a Bump, bytes: &[u8]) -> Result<WasmModule<'a>, String> {
+    WasmModule::preload(arena, bytes, false).map_err(|e| format!("{e:?}"))
+}
@@ -0,0 +1,83 @@
+use roc_wasm_module::Value;
+use std::fmt;
+
+/// A copy of the state that a call can change, from `Instance::snapshot`.
+/// Restoring it undoes the changes the program made since then.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub(crate) memory: Vec<u8>,
+    pub(crate) globals: Vec<Value>,
+}
+
+impl Snapshot {
+    /// The bytes that are different in `memory` now, e.g. `Instance::memory` after a call.
+    /// Tests can use this to check that a program changed only the bytes it should have.
+    pub fn memory_diff(&self, memory: &[u8]) -> Vec<MemoryChange> {
+        diff_memory(&self.memory, memory)
+    }
+}
+
+/// A run of consecutive bytes that changed, from `diff_memory`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+    /// The address of the first changed byte
+    pub start: usize,
+    pub before: Vec<u8>,
+    /// The new values of the bytes. If the memory grew or shrank, this has a different
+    /// length from `before`, and covers the part that only one of the memories has.
+    pub after: Vec<u8>,
+}
+
+/// Written like `0x10..0x14: 00 00 00 00 -> 04 03 02 01`
+impl fmt::Display for MemoryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let end = self.start + self.before.len().max(self.after.len());
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "{:#x}..{end:#x}: {} -> {}",
+            self.start,
+            hex(&self.before),
+            hex(&self.after)
+        )
+    }
+}
+
+/// Find the runs of bytes that differ between two memories, in address order
+pub fn diff_memory(before: &[u8], after: &[u8]) -> Vec<MemoryChange> {
+    let common_len = before.len().min(after.len());
+    let mut changes = Vec::new();
+    let mut addr = 0;
+
+    while addr < common_len {
+        if before[addr] == after[addr] {
+            addr += 1;
+            continue;
+        }
+        let start = addr;
+        while addr < common_len && before[addr] != after[addr] {
+            addr += 1;
+        }
+        changes.push(MemoryChange {
+            start,
+            before: before[start..addr].to_vec(),
+            after: after[start..addr].to_vec(),
+        });
+    }
+
+    if before.len() != after.len() {
+        changes.push(MemoryChange {
+            start: common_len,
+            before: before[common_len..].to_vec(),
+            after: after[common_len..].to_vec(),
+        });
+    }
+
+    changes
+}
@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::{ImportDispatcher, TrapReason, Value};
+
+/// The module name that the official Wasm spec tests import from
+pub const SPECTEST_MODULE_NAME: &str = "spectest";
+
+/// Provides the `spectest` module that the official Wasm spec tests import, so that a
+/// conformance runner can instantiate them. Register it with `DefaultImportDispatcher::builder()`.
+///
+/// Everything the print functions receive is written to `output`, one value per line,
+/// in the same `value : type` format as the reference interpreter.
+///
+/// The spec tests also import `global_i32` and friends, a memory, and a table.
+/// For the memory, pass one of `MEMORY_PAGES` to `Instance::for_module_with_memory`.
+/// The Instance doesn't support imported tables yet.
+///
+/// ```
+/// # use roc_wasm_interp::{DefaultImportDispatcher, SpectestDispatcher, SPECTEST_MODULE_NAME};
+/// let dispatcher = DefaultImportDispatcher::builder()
+///     .module(SPECTEST_MODULE_NAME, SpectestDispatcher::default())
+///     .build();
+/// ```
+#[derive(Debug, Default)]
+pub struct SpectestDispatcher {
+    output: Rc<RefCell<String>>,
+}
+
+impl SpectestDispatcher {
+    /// The limits of the `spectest.memory` import, in pages
+    pub const MEMORY_PAGES: (u32, u32) = (1, 2);
+
+    /// The limits of the `spectest.table` import, in elements
+    pub const TABLE_SIZE: (u32, u32) = (10, 20);
+
+    /// What the print functions have printed so far. This handle can still be read after
+    /// the dispatcher has been moved into a `DefaultImportDispatcher`.
+    pub fn output(&self) -> Rc<RefCell<String>> {
+        self.output.clone()
+    }
+
+    /// The value of one of the `spectest` globals, like `global_i32`
+    pub fn global(name: &str) -> Option<Value> {
+        match name {
+            "global_i32" => Some(Value::I32(666)),
+            "global_i64" => Some(Value::I64(666)),
+            "global_f32" => Some(Value::F32(666.6)),
+            "global_f64" => Some(Value::F64(666.6)),
+            _ => None,
+        }
+    }
+
+    fn is_print_function(function_name: &str) -> bool {
+        matches!(
+            function_name,
+            "print"
+                | "print_i32"
+                | "print_i64"
+                | "print_f32"
+                | "print_f64"
+                | "print_i32_f32"
+                | "print_f64_f64"
+        )
+    }
+}
+
+impl ImportDispatcher for SpectestDispatcher {
+    fn dispatch(
+        &mut self,
+        _module_name: &str,
+        _function_name: &str,
+        arguments: &[Value],
+        _memory: &mut [u8],
+    ) -> Option<Value> {
+        // The reference interpreter prints each argument on its own line
+        let mut output = self.output.borrow_mut();
+        for arg in arguments {
+            match arg {
+                Value::I32(x) => writeln!(output, "{x} : i32"),
+                Value::I64(x) => writeln!(output, "{x} : i64"),
+                Value::F32(x) => writeln!(output, "{x} : f32"),
+                Value::F64(x) => writeln!(output, "{x} : f64"),
+            }
+            .unwrap();
+        }
+        if arguments.is_empty() {
+            output.push('\n');
+        }
+        None
+    }
+
+    fn can_handle(&self, _module_name: &str, function_name: &str) -> bool {
+        Self::is_print_function(function_name)
+    }
+
+    fn check_import(&mut self, module_name: &str, function_name: &str) -> Result<(), TrapReason> {
+        if Self::is_print_function(function_name) {
+            Ok(())
+        } else {
+            Err(TrapReason::UnknownImport {
+                module_name: module_name.to_string(),
+                function_name: function_name.to_string(),
+            })
+        }
+    }
+
+    fn import_global(&self, _module_name: &str, global_name: &str) -> Option<Value> {
+        Self::global(global_name)
+    }
+}
@@ -1,5 +1,6 @@
 #![cfg(test)]
 
+mod test_atomic;
 mod test_basics;
 mod test_convert;
 mod test_f32;
@@ -7,6 +8,10 @@ mod test_f64;
 mod test_i32;
 mod test_i64;
 mod test_mem;
+mod test_pure;
+mod test_spectest;
+mod test_validate;
+mod test_wasi;
 
 use crate::{DefaultImportDispatcher, Instance};
 use bumpalo::{collections::Vec, Bump};
@@ -51,6 +56,16 @@ pub fn const_value(buf: &mut Vec<'_, u8>, value: Value) {
 }
 
 pub fn test_op_example<A>(op: OpCode, args: A, expected: Value)
+where
+    A: IntoIterator<Item = Value>,
+{
+    let return_val = run_op_example(op, args, ValueType::from(expected));
+    assert_eq!(return_val, expected);
+}
+
+/// Run a single instruction on constant arguments and return its result,
+/// for results that can't be checked with `==`, like NaN or signed zeros
+pub fn run_op_example<A>(op: OpCode, args: A, ret_type: ValueType) -> Value
 where
     A: IntoIterator<Item = Value>,
 {
@@ -74,7 +89,7 @@ where
         module.code.function_offsets.push(0);
         module.add_function_signature(Signature {
             param_types: Vec::new_in(&arena),
-            ret_type: Some(ValueType::from(expected)),
+            ret_type: Some(ret_type),
         });
         module.export.append(Export {
             name: "test",
@@ -95,9 +110,7 @@ where
     let mut inst =
         Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), true).unwrap();
 
-    let return_val = inst.call_export("test", []).unwrap().unwrap();
-
-    assert_eq!(return_val, expected);
+    inst.call_export("test", []).unwrap().unwrap()
 }
 
 pub fn create_exported_function_no_locals<'a, F>(
@@ -156,3 +169,24 @@ pub fn create_exported_function_with_locals<'a, F>(
     module.code.function_count += 1;
     module.code.function_offsets.push(offset as u32);
 }
+
+/// Print the opcodes that none of the other tests executed. Run it with:
+///
+///     cargo test -p roc_wasm_interp --features opcode-coverage -- --include-ignored --test-threads=1 --nocapture
+///
+/// With one thread, tests run in name order, so this one runs after all the others.
+#[cfg(feature = "opcode-coverage")]
+#[test]
+#[ignore]
+fn zz_opcode_coverage_report() {
+    let unexecuted = crate::opcode_coverage::unexecuted_opcodes();
+    let executed = crate::opcode_coverage::executed_opcodes();
+    println!(
+        "\n{} of {} opcodes were executed. These were not:",
+        executed.len(),
+        executed.len() + unexecuted.len()
+    );
+    for op in unexecuted {
+        println!("    {op:?}");
+    }
+}
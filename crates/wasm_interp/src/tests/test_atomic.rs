@@ -0,0 +1,205 @@
+use super::create_exported_function_no_locals;
+use crate::{DefaultImportDispatcher, Instance};
+use bumpalo::{collections::Vec, Bump};
+use roc_wasm_module::{
+    opcodes::OpCode,
+    sections::{DataMode, DataSegment, MemorySection},
+    ConstExpr, SerialBuffer, Signature, Value, ValueType, WasmModule,
+};
+
+// Sub-opcodes following the 0xFE prefix
+const NOTIFY: u32 = 0x00;
+const WAIT32: u32 = 0x01;
+const FENCE: u32 = 0x03;
+const I32_LOAD: u32 = 0x10;
+const I64_LOAD: u32 = 0x11;
+const I32_LOAD8_U: u32 = 0x12;
+const I32_STORE: u32 = 0x17;
+const I32_RMW_ADD: u32 = 0x1e;
+const I32_RMW8_SUB_U: u32 = 0x27;
+const I64_RMW_XCHG: u32 = 0x42;
+const I32_RMW_CMPXCHG: u32 = 0x48;
+
+const DATA_ADDR: u32 = 0x10;
+
+fn atomic(buf: &mut Vec<'_, u8>, sub_opcode: u32, offset: u32) {
+    buf.append_u8(OpCode::ATOMIC as u8);
+    buf.encode_u32(sub_opcode);
+    buf.encode_u32(0); // align
+    buf.encode_u32(offset);
+}
+
+fn i32_const(buf: &mut Vec<'_, u8>, value: i32) {
+    buf.append_u8(OpCode::I32CONST as u8);
+    buf.encode_i32(value);
+}
+
+fn run_atomic_test<F>(ret_type: Option<ValueType>, write_body: F) -> Result<Option<Value>, String>
+where
+    F: FnOnce(&mut Vec<'_, u8>),
+{
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+    module.data.append_segment(DataSegment {
+        mode: DataMode::Active {
+            offset: ConstExpr::I32(DATA_ADDR as i32),
+        },
+        init: bumpalo::vec![in &arena; 0xff, 0x01, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00],
+    });
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type,
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        write_body(buf);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    inst.call_export("test", [])
+}
+
+#[test]
+fn test_atomic_loads() {
+    let load = |sub_opcode, ty| {
+        run_atomic_test(Some(ty), |buf| {
+            i32_const(buf, DATA_ADDR as i32);
+            atomic(buf, sub_opcode, 0);
+        })
+    };
+    assert_eq!(load(I32_LOAD, ValueType::I32), Ok(Some(Value::I32(0x1ff))));
+    assert_eq!(
+        load(I32_LOAD8_U, ValueType::I32),
+        Ok(Some(Value::I32(0xff)))
+    );
+    assert_eq!(
+        load(I64_LOAD, ValueType::I64),
+        Ok(Some(Value::I64(0x0000_002a_0000_01ff)))
+    );
+}
+
+#[test]
+fn test_atomic_store_then_load() {
+    let result = run_atomic_test(Some(ValueType::I32), |buf| {
+        i32_const(buf, 0);
+        i32_const(buf, 12345);
+        atomic(buf, I32_STORE, 0x20);
+        i32_const(buf, 0x20);
+        atomic(buf, I32_LOAD, 0);
+    });
+    assert_eq!(result, Ok(Some(Value::I32(12345))));
+}
+
+#[test]
+fn test_atomic_rmw() {
+    // Read-modify-write returns the old value
+    let result = run_atomic_test(Some(ValueType::I32), |buf| {
+        i32_const(buf, DATA_ADDR as i32);
+        i32_const(buf, 1);
+        atomic(buf, I32_RMW_ADD, 0);
+        buf.append_u8(OpCode::DROP as u8);
+        i32_const(buf, DATA_ADDR as i32);
+        atomic(buf, I32_LOAD, 0);
+    });
+    assert_eq!(result, Ok(Some(Value::I32(0x200))));
+
+    // Narrow accesses wrap around within their own width
+    let result = run_atomic_test(Some(ValueType::I32), |buf| {
+        i32_const(buf, DATA_ADDR as i32 + 2);
+        i32_const(buf, 1);
+        atomic(buf, I32_RMW8_SUB_U, 0);
+        buf.append_u8(OpCode::DROP as u8);
+        i32_const(buf, DATA_ADDR as i32);
+        atomic(buf, I32_LOAD, 0);
+    });
+    assert_eq!(result, Ok(Some(Value::I32(0x00ff_01ff))));
+
+    let result = run_atomic_test(Some(ValueType::I64), |buf| {
+        i32_const(buf, DATA_ADDR as i32);
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(-1);
+        atomic(buf, I64_RMW_XCHG, 0);
+    });
+    assert_eq!(result, Ok(Some(Value::I64(0x0000_002a_0000_01ff))));
+}
+
+#[test]
+fn test_atomic_cmpxchg() {
+    let cmpxchg = |expected| {
+        run_atomic_test(Some(ValueType::I32), |buf| {
+            i32_const(buf, DATA_ADDR as i32 + 4);
+            i32_const(buf, expected);
+            i32_const(buf, 7);
+            atomic(buf, I32_RMW_CMPXCHG, 0);
+            buf.append_u8(OpCode::DROP as u8);
+            i32_const(buf, DATA_ADDR as i32 + 4);
+            atomic(buf, I32_LOAD, 0);
+        })
+    };
+    assert_eq!(cmpxchg(42), Ok(Some(Value::I32(7))));
+    assert_eq!(cmpxchg(41), Ok(Some(Value::I32(42))));
+}
+
+#[test]
+fn test_atomic_fence_notify_wait() {
+    let result = run_atomic_test(Some(ValueType::I32), |buf| {
+        buf.append_u8(OpCode::ATOMIC as u8);
+        buf.encode_u32(FENCE);
+        buf.append_u8(0);
+        i32_const(buf, DATA_ADDR as i32);
+        i32_const(buf, 1);
+        atomic(buf, NOTIFY, 0);
+    });
+    assert_eq!(result, Ok(Some(Value::I32(0))));
+
+    let wait = |expected, timeout| {
+        run_atomic_test(Some(ValueType::I32), |buf| {
+            i32_const(buf, DATA_ADDR as i32 + 4);
+            i32_const(buf, expected);
+            buf.append_u8(OpCode::I64CONST as u8);
+            buf.encode_i64(timeout);
+            atomic(buf, WAIT32, 0);
+        })
+    };
+    assert_eq!(wait(0, -1), Ok(Some(Value::I32(1))));
+    assert_eq!(wait(42, 1000), Ok(Some(Value::I32(2))));
+    assert!(wait(42, -1).unwrap_err().contains("no timeout"));
+}
+
+#[test]
+fn test_atomic_traps() {
+    let unaligned = run_atomic_test(Some(ValueType::I32), |buf| {
+        i32_const(buf, DATA_ADDR as i32 + 1);
+        atomic(buf, I32_LOAD, 0);
+    });
+    assert!(unaligned.unwrap_err().contains("not aligned"));
+
+    // The whole access must be in bounds, not just the first byte
+    let out_of_bounds = run_atomic_test(Some(ValueType::I64), |buf| {
+        i32_const(buf, MemorySection::PAGE_SIZE as i32 - 4);
+        atomic(buf, I64_LOAD, 0);
+    });
+    assert!(out_of_bounds.is_err());
+}
+
+#[test]
+fn test_atomic_skipped_by_branch() {
+    // Branching forward over atomic instructions has to skip their immediates correctly
+    let result = run_atomic_test(Some(ValueType::I32), |buf| {
+        buf.append_u8(OpCode::BLOCK as u8);
+        buf.append_u8(ValueType::VOID);
+        buf.append_u8(OpCode::BR as u8);
+        buf.encode_u32(0);
+        buf.append_u8(OpCode::ATOMIC as u8);
+        buf.encode_u32(FENCE);
+        buf.append_u8(0);
+        atomic(buf, I32_RMW_CMPXCHG, 0x12345);
+        buf.append_u8(OpCode::END as u8);
+        i32_const(buf, 99);
+    });
+    assert_eq!(result, Ok(Some(Value::I32(99))));
+}
@@ -5,13 +5,23 @@ use crate::tests::{
     const_value, create_exported_function_no_locals, create_exported_function_with_locals,
     default_state,
 };
-use crate::{DefaultImportDispatcher, ImportDispatcher, Instance};
+use crate::wasi::{self, WasiDispatcher};
+use crate::{
+    format_result, format_value, ClosureImportDispatcher, DefaultImportDispatcher, ExportInfo,
+    ExportSignature, FrameInfo, ImportDispatcher, Instance, RunOutcome, SharedModuleBytes,
+    TraceEvent, TrapReason, ValueHint,
+};
 use bumpalo::{collections::Vec, Bump};
-use roc_wasm_module::sections::{Import, ImportDesc};
+use roc_wasm_module::sections::{
+    update_section_size, write_custom_section_header, Global, GlobalType, Import, ImportDesc,
+    MemorySection,
+};
 use roc_wasm_module::{
-    opcodes::OpCode, sections::ElementSegment, Export, ExportType, SerialBuffer, Serialize,
-    Signature, Value, ValueType, WasmModule,
+    opcodes::OpCode, sections::ElementSegment, ConstExpr, Export, ExportType, SerialBuffer,
+    Serialize, Signature, Value, ValueType, WasmModule,
 };
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_loop() {
@@ -379,6 +389,149 @@ fn test_br_if_help(condition: i32, expected: i32) {
     assert_eq!(result, Value::I32(expected))
 }
 
+#[test]
+fn test_block_result() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        // block (result i32)
+        buf.push(OpCode::BLOCK as u8);
+        buf.push(ValueType::I32 as u8);
+
+        //   i32.const 111
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(111);
+
+        //   i32.const 222
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(222);
+
+        //   br 0
+        buf.push(OpCode::BR as u8);
+        buf.encode_u32(0);
+
+        // end
+        buf.push(OpCode::END as u8);
+
+        // end function
+        buf.push(OpCode::END as u8);
+    });
+
+    let is_debug_mode = false;
+    let mut inst = Instance::for_module(
+        &arena,
+        &module,
+        DefaultImportDispatcher::default(),
+        is_debug_mode,
+    )
+    .unwrap();
+
+    // The branch carries the top value out of the block, and drops the one underneath
+    let result = inst.call_export("test", []).unwrap();
+    assert_eq!(result, Some(Value::I32(222)));
+}
+
+#[test]
+fn test_if_else_result_mismatch() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        // local.get 0
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.encode_u32(0);
+
+        // if (result i32)
+        buf.push(OpCode::IF as u8);
+        buf.push(ValueType::I32 as u8);
+
+        //   i32.const 111
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(111);
+
+        // else
+        buf.push(OpCode::ELSE as u8);
+
+        //   i64.const 222
+        buf.push(OpCode::I64CONST as u8);
+        buf.encode_i64(222);
+
+        // end
+        buf.push(OpCode::END as u8);
+
+        // end function
+        buf.push(OpCode::END as u8);
+    });
+
+    let is_debug_mode = false;
+    let mut inst = Instance::for_module(
+        &arena,
+        &module,
+        DefaultImportDispatcher::default(),
+        is_debug_mode,
+    )
+    .unwrap();
+
+    let then_result = inst.call_export("test", [Value::I32(1)]).unwrap();
+    assert_eq!(then_result, Some(Value::I32(111)));
+
+    let else_result = inst.call_export("test", [Value::I32(0)]).unwrap_err();
+    assert!(
+        else_result.contains("should leave [I32] on the stack, but it left [I64]"),
+        "{else_result}"
+    );
+}
+
+#[test]
+fn test_block_leaves_extra_value() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        // block <void>
+        buf.push(OpCode::BLOCK as u8);
+        buf.push(ValueType::VOID);
+
+        //   i32.const 111
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(111);
+
+        // end
+        buf.push(OpCode::END as u8);
+
+        // end function
+        buf.push(OpCode::END as u8);
+    });
+
+    let is_debug_mode = false;
+    let mut inst = Instance::for_module(
+        &arena,
+        &module,
+        DefaultImportDispatcher::default(),
+        is_debug_mode,
+    )
+    .unwrap();
+
+    let message = inst.call_export("test", []).unwrap_err();
+    assert!(
+        message.contains("should leave [] on the stack, but it left [I32]"),
+        "{message}"
+    );
+}
+
 #[test]
 fn test_br_table() {
     test_br_table_help(0, 333);
@@ -573,6 +726,219 @@ fn test_call_import() {
     assert_eq!(return_val, Value::I32(234));
 }
 
+#[test]
+fn test_call_closure_imports() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let start_fn_name = "test";
+
+    let mut log_calls = 0;
+    let import_dispatcher = ClosureImportDispatcher::default()
+        .with("env", "double", |args, _memory| {
+            Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+        })
+        .with("env", "log", |_args, _memory| {
+            log_calls += 1;
+            None
+        });
+
+    // Function 0 is `double`, function 1 is `log`
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.types.insert(Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "double",
+        description: ImportDesc::Func { signature_index: 0 },
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "log",
+        description: ImportDesc::Func { signature_index: 1 },
+    });
+
+    // Function 2, which calls both imports
+    module.code.function_count = 1;
+    let func0_offset = module.code.bytes.len() as u32;
+    module.code.function_offsets.push(func0_offset);
+    module.add_function_signature(Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    });
+    module.export.append(Export {
+        name: start_fn_name,
+        ty: ExportType::Func,
+        index: 2,
+    });
+    [
+        0, // no locals
+        OpCode::CALL as u8,
+        1, // log
+        OpCode::I32CONST as u8,
+        21, // argument to double
+        OpCode::CALL as u8,
+        0, // double
+        OpCode::CALL as u8,
+        1, // log
+        OpCode::END as u8,
+    ]
+    .serialize(&mut module.code.bytes);
+
+    let mut inst = Instance::for_module(&arena, &module, import_dispatcher, false).unwrap();
+    let return_val = inst.call_export(start_fn_name, []).unwrap().unwrap();
+    drop(inst);
+
+    assert_eq!(return_val, Value::I32(42));
+    assert_eq!(log_calls, 2);
+}
+
+#[test]
+fn test_import_argument_mismatch() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let mut double_calls = 0;
+    let import_dispatcher = ClosureImportDispatcher::default().with("env", "double", |args, _| {
+        double_calls += 1;
+        Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+    });
+
+    // `double` takes an I32, but the export passes it an I64
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "double",
+        description: ImportDesc::Func { signature_index: 0 },
+    });
+    create_exported_function_no_locals(
+        &mut module,
+        "test",
+        Signature {
+            param_types: Vec::new_in(&arena),
+            ret_type: Some(ValueType::I32),
+        },
+        |buf| {
+            buf.push(OpCode::I64CONST as u8);
+            buf.encode_i64(21);
+            buf.push(OpCode::CALL as u8);
+            buf.encode_u32(0);
+            buf.push(OpCode::END as u8);
+        },
+    );
+
+    let mut inst = Instance::for_module(&arena, &module, import_dispatcher, false).unwrap();
+    assert_eq!(
+        inst.run("test", []),
+        RunOutcome::Trapped(TrapReason::ImportArgumentMismatch {
+            module_name: "env".into(),
+            function_name: "double".into(),
+            index: 0,
+            expected: ValueType::I32,
+            actual: ValueType::I64,
+        })
+    );
+    drop(inst);
+
+    // The dispatcher never sees the bad argument
+    assert_eq!(double_calls, 0);
+}
+
+#[test]
+fn test_default_dispatcher_builder() {
+    let args: &[&[u8]] = &[b"app.wasm", b"hello"];
+    let platform = ClosureImportDispatcher::default().with("platform", "double", |args, _| {
+        Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+    });
+    let mut dispatcher = DefaultImportDispatcher::builder()
+        .wasi(WasiDispatcher::new(args))
+        .module("platform", platform)
+        .build();
+    let mut memory = [0; 8];
+
+    // Calls to the platform module go to its dispatcher
+    assert_eq!(dispatcher.check_import("platform", "double"), Ok(()));
+    let result = dispatcher.dispatch("platform", "double", &[Value::I32(21)], &mut memory);
+    assert_eq!(result, Some(Value::I32(42)));
+    assert!(dispatcher.check_import("platform", "triple").is_err());
+
+    // WASI calls go to the WASI dispatcher
+    assert_eq!(
+        dispatcher.check_import(wasi::MODULE_NAME, "args_sizes_get"),
+        Ok(())
+    );
+    let sizes_args = [Value::I32(0), Value::I32(4)];
+    let result = dispatcher.dispatch(
+        wasi::MODULE_NAME,
+        "args_sizes_get",
+        &sizes_args,
+        &mut memory,
+    );
+    assert_eq!(result, Some(Value::I32(0)));
+    assert_eq!(memory[0], 2); // argc
+
+    assert_eq!(
+        dispatcher.check_import("env", "double"),
+        Err(TrapReason::UnknownImport {
+            module_name: "env".into(),
+            function_name: "double".into(),
+        })
+    );
+}
+
+#[test]
+fn test_unresolved_imports() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.types.insert(Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    });
+    let imports = [
+        ("platform", "double"),
+        ("platform", "triple"),
+        (wasi::MODULE_NAME, "fd_write"),
+        ("env", "missing"),
+    ];
+    for (module_name, name) in imports {
+        module.import.imports.push(Import {
+            module: module_name,
+            name,
+            description: ImportDesc::Func { signature_index: 0 },
+        });
+    }
+
+    let platform = ClosureImportDispatcher::default().with("platform", "double", |_, _| None);
+    let dispatcher = DefaultImportDispatcher::builder()
+        .module("platform", platform)
+        .build();
+    let inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+
+    assert_eq!(
+        inst.unresolved_imports(&inst.import_dispatcher),
+        [
+            ("platform".to_string(), "triple".to_string()),
+            ("env".to_string(), "missing".to_string()),
+        ]
+    );
+
+    // Dispatchers that don't say what they handle are assumed to handle everything
+    struct Anything;
+    impl ImportDispatcher for Anything {
+        fn dispatch(&mut self, _: &str, _: &str, _: &[Value], _: &mut [u8]) -> Option<Value> {
+            None
+        }
+    }
+    assert!(inst.unresolved_imports(&Anything).is_empty());
+}
+
 #[test]
 fn test_call_return_no_args() {
     let arena = Bump::new();
@@ -663,6 +1029,7 @@ fn test_call_return_with_args() {
     });
 
     // Function 1: add two numbers
+    module.code.function_count += 1;
     let func1_offset = module.code.bytes.len() as u32;
     module.code.function_offsets.push(func1_offset);
     module.add_function_signature(Signature {
@@ -941,6 +1308,71 @@ fn test_global() {
     assert_eq!(state.value_store.pop(), Value::I32(222));
 }
 
+#[test]
+fn test_imported_global() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.import.imports.push(Import {
+        module: "env",
+        name: "stack_base",
+        description: ImportDesc::Global {
+            ty: GlobalType {
+                value_type: ValueType::I32,
+                is_mutable: true,
+            },
+        },
+    });
+    // Module-defined globals come after the imported ones
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: false,
+        },
+        init: ConstExpr::I32(7),
+    });
+
+    let signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "bump", signature, |buf| {
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(16);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(1);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let dispatcher =
+        ClosureImportDispatcher::default().with_global("env", "stack_base", Value::I32(1000));
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    assert_eq!(inst.call_export("bump", []), Ok(Some(Value::I32(1023))));
+    assert_eq!(
+        inst.imported_global("env", "stack_base"),
+        Some(Value::I32(1016))
+    );
+    assert_eq!(inst.imported_global("env", "missing"), None);
+
+    let missing = Instance::for_module(&arena, &module, ClosureImportDispatcher::default(), false);
+    let err = missing.err().unwrap();
+    assert!(err.contains("env.stack_base"), "{err}");
+
+    let wrong_type =
+        ClosureImportDispatcher::default().with_global("env", "stack_base", Value::I64(1000));
+    let err = Instance::for_module(&arena, &module, wrong_type, false)
+        .err()
+        .unwrap();
+    assert!(err.contains("as I32"), "{err}");
+}
+
 #[test]
 fn test_i32const() {
     let arena = Bump::new();
@@ -992,3 +1424,1092 @@ fn test_f64const() {
     state.execute_next_instruction(&module).unwrap();
     assert_eq!(state.value_store.pop(), Value::F64(12345.67890))
 }
+
+#[test]
+fn test_call_export_missing_lists_exports() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    for name in ["foo", "bar"] {
+        let signature = Signature {
+            param_types: bumpalo::vec![in &arena],
+            ret_type: None,
+        };
+        create_exported_function_no_locals(&mut module, name, signature, |buf| {
+            buf.push(OpCode::END as u8);
+        });
+    }
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    let message = inst.call_export("_start", []).unwrap_err();
+
+    assert_eq!(
+        message,
+        "I couldn't find a function '_start' in this WebAssembly module. These are the functions it exports:\n    foo\n    bar"
+    );
+}
+
+#[test]
+fn test_exports() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    // The import comes first in the function index space
+    let signature_index = module.types.insert(Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "double",
+        description: ImportDesc::Func { signature_index },
+    });
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I64, ValueType::F64],
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "run", signature, |buf| {
+        buf.push(OpCode::END as u8);
+    });
+    module.export.exports.push(Export {
+        name: "memory",
+        ty: ExportType::Mem,
+        index: 0,
+    });
+
+    let inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    let exports: std::vec::Vec<ExportInfo> = inst.exports().collect();
+
+    assert_eq!(
+        exports,
+        [
+            ExportInfo {
+                name: "run",
+                ty: ExportType::Func,
+                index: 1,
+                signature: Some(ExportSignature {
+                    param_types: vec![ValueType::I64, ValueType::F64],
+                    ret_type: None,
+                }),
+            },
+            ExportInfo {
+                name: "memory",
+                ty: ExportType::Mem,
+                index: 0,
+                signature: None,
+            },
+        ]
+    );
+    assert_eq!(exports[0].to_string(), "func run (I64, F64)");
+    assert_eq!(exports[1].to_string(), "memory memory");
+}
+
+#[test]
+fn test_format_value() {
+    use ValueHint::*;
+    let i32_result = ExportSignature {
+        param_types: vec![],
+        ret_type: Some(ValueType::I32),
+    };
+
+    assert_eq!(
+        format_value(Value::I32(-1), ValueType::I32, None),
+        "-1 : I32"
+    );
+    assert_eq!(
+        format_value(Value::I64(1 << 40), ValueType::I64, None),
+        "1099511627776 : I64"
+    );
+    assert_eq!(
+        format_value(Value::F32(1.0), ValueType::F32, None),
+        "1.0 : F32"
+    );
+    assert_eq!(
+        format_value(Value::F64(-0.25), ValueType::F64, None),
+        "-0.25 : F64"
+    );
+    assert_eq!(
+        format_value(Value::F64(f64::NAN), ValueType::F64, None),
+        "NaN : F64"
+    );
+
+    assert_eq!(
+        i32_result.format_result(Some(Value::I32(1)), Some(Bool)),
+        "Bool.true (1 : I32)"
+    );
+    assert_eq!(
+        i32_result.format_result(Some(Value::I32(2)), Some(Bool)),
+        "2 : I32"
+    );
+    assert_eq!(
+        i32_result.format_result(Some(Value::I32(97)), Some(Char)),
+        "'a' (97 : I32)"
+    );
+    assert_eq!(
+        i32_result.format_result(Some(Value::I32(-1)), Some(Unsigned)),
+        "4294967295 (-1 : I32)"
+    );
+    assert_eq!(
+        format_value(Value::I64(-1), ValueType::I64, Some(Unsigned)),
+        "18446744073709551615 (-1 : I64)"
+    );
+    assert_eq!(
+        format_value(Value::F32(1.0), ValueType::F32, Some(Hex)),
+        "0x3f800000 (1.0 : F32)"
+    );
+
+    // Results that don't match the signature
+    assert_eq!(
+        i32_result.format_result(Some(Value::I64(5)), None),
+        "5 : I64 (expected I32)"
+    );
+    assert_eq!(
+        i32_result.format_result(None, None),
+        "nothing (expected I32)"
+    );
+    assert_eq!(format_result(None, None, None), "{}");
+    assert_eq!(
+        format_result(None, Some(Value::F64(1.5)), None),
+        "1.5 : F64 (expected nothing)"
+    );
+}
+
+#[test]
+fn test_call_export_from_cli_reactor() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: true,
+        },
+        init: ConstExpr::I32(0),
+    });
+
+    // A WASI reactor expects `_initialize` to be called before any other export
+    let init_signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "_initialize", init_signature, |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(100);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let entry_signature = Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "entry", entry_signature, |buf| {
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let argv: &[&[u8]] = arena.alloc([b"reactor.wasm".as_slice(), b"23".as_slice()]);
+    let dispatcher = DefaultImportDispatcher::new(argv);
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    let result = inst.call_export_from_cli(&module, "entry", argv).unwrap();
+
+    assert_eq!(result, Some(Value::I32(123)));
+}
+
+#[test]
+fn test_cli_initialize_runs_once() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: true,
+        },
+        init: ConstExpr::I32(0),
+    });
+
+    // Count the calls, and return the count so that the test can see it
+    let init_signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "_initialize", init_signature, |buf| {
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let argv: &[&[u8]] = arena.alloc([b"reactor.wasm".as_slice()]);
+    let dispatcher = DefaultImportDispatcher::new(argv);
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    let result = inst.call_export_from_cli(&module, "_initialize", argv);
+
+    assert_eq!(result, Ok(Some(Value::I32(1))));
+}
+
+#[test]
+fn test_fork() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: true,
+        },
+        init: ConstExpr::I32(0),
+    });
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "increment", signature, |buf| {
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.call_export("increment", []), Ok(Some(Value::I32(1))));
+
+    // The fork starts from the module's initial state, not the original's, and they don't share it
+    let mut fork = inst
+        .fork(&arena, DefaultImportDispatcher::default())
+        .unwrap();
+    assert_eq!(fork.call_export("increment", []), Ok(Some(Value::I32(1))));
+    assert_eq!(fork.call_export("increment", []), Ok(Some(Value::I32(2))));
+    assert_eq!(inst.call_export("increment", []), Ok(Some(Value::I32(2))));
+}
+
+#[test]
+fn test_shared_module_on_several_threads() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: true,
+        },
+        init: ConstExpr::I32(0),
+    });
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "increment", signature, |buf| {
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut bytes = Vec::new_in(&arena);
+    module.serialize(&mut bytes);
+    let shared = SharedModuleBytes::new(&bytes[..]).unwrap();
+
+    // Every thread starts from the module's initial state, whatever the others have done
+    let results: std::vec::Vec<_> = std::thread::scope(|scope| {
+        let threads: std::vec::Vec<_> = (0..4)
+            .map(|calls| {
+                let shared = shared.clone();
+                scope.spawn(move || {
+                    let arena = Bump::new();
+                    let mut inst = shared
+                        .instantiate(&arena, DefaultImportDispatcher::default(), false)
+                        .unwrap();
+                    for _ in 0..calls {
+                        inst.call_export("increment", []).unwrap();
+                    }
+                    inst.call_export("increment", []).unwrap()
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+
+    assert_eq!(
+        results,
+        (1..=4)
+            .map(|n| Some(Value::I32(n)))
+            .collect::<std::vec::Vec<_>>()
+    );
+
+    assert!(SharedModuleBytes::new(&b"not wasm"[..]).is_err());
+}
+
+#[test]
+fn test_opcode_profile() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(2);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert!(inst.opcode_profile().is_none());
+
+    inst.enable_opcode_profile();
+    inst.call_export("test", []).unwrap();
+
+    let profile = inst.opcode_profile().unwrap();
+    assert_eq!(profile.count(OpCode::I32CONST), 2);
+    assert_eq!(profile.count(OpCode::I32ADD), 1);
+    assert_eq!(profile.count(OpCode::I32SUB), 0);
+
+    let report = profile.to_string();
+    assert!(report.contains("I32CONST"));
+    assert!(!report.contains("I32SUB"));
+}
+
+#[test]
+fn test_value_stack_accessors() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let buf = &mut module.code.bytes;
+    buf.push(OpCode::I32CONST as u8);
+    buf.encode_i32(1);
+    buf.push(OpCode::I64CONST as u8);
+    buf.encode_i64(2);
+    buf.push(OpCode::I32CONST as u8);
+    buf.encode_i32(3);
+    buf.push(OpCode::DROP as u8);
+
+    let mut state = default_state(&arena);
+    state.program_counter = 0;
+    assert_eq!(state.value_stack_depth(), 0);
+
+    for _ in 0..3 {
+        state.execute_next_instruction(&module).unwrap();
+    }
+    assert_eq!(state.value_stack_depth(), 3);
+    assert_eq!(
+        state.value_stack(),
+        [Value::I32(1), Value::I64(2), Value::I32(3)]
+    );
+
+    state.execute_next_instruction(&module).unwrap();
+    assert_eq!(state.value_stack(), [Value::I32(1), Value::I64(2)]);
+}
+
+#[test]
+fn test_run_with_report() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let signature = || Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "add_one", signature(), |buf| {
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "trap", signature(), |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(5);
+        buf.push(OpCode::UNREACHABLE as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let module = arena.alloc(module);
+    let mut inst =
+        Instance::for_module(&arena, module, DefaultImportDispatcher::default(), false).unwrap();
+
+    let report = inst.run_with_report("add_one", [Value::I32(41)]);
+    assert_eq!(report.stack, [Value::I32(42)]);
+    assert_eq!(report.instruction_count, 4);
+    assert_eq!(report.memory_high_water_mark, 0);
+    assert_eq!(report.exit_code, None);
+    assert_eq!(report.trap, None);
+
+    let report = inst.run_with_report("trap", [Value::I32(0)]);
+    assert_eq!(report.stack, [Value::I32(0), Value::I32(5)]);
+    assert_eq!(report.instruction_count, 2);
+    assert!(report.trap.unwrap().contains("unreachable"));
+    assert_eq!(inst.instruction_count(), 6);
+}
+
+#[test]
+fn test_backtrace() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let signature = || Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "inner", signature(), |buf| {
+        buf.push(OpCode::UNREACHABLE as u8);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "outer", signature(), |buf| {
+        buf.push(OpCode::NOP as u8);
+        buf.push(OpCode::CALL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+    // The outer function has no name, to check the fallback
+    module.names.module_name = Some("app");
+    module.names.append_function(0, "inner");
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    let message = inst.call_export("outer", []).unwrap_err();
+
+    let backtrace = inst.backtrace();
+    let [inner, outer] = backtrace.as_slice() else {
+        panic!("{backtrace:?}");
+    };
+    assert_eq!(
+        *inner,
+        FrameInfo {
+            fn_index: 0,
+            module_name: Some("app"),
+            function_name: Some("inner"),
+            address: inner.address,
+        }
+    );
+    assert_eq!(module.code.bytes[outer.address], OpCode::CALL as u8);
+    assert_eq!(
+        inner.to_string(),
+        format!("app::inner (func[0] @{:#x})", inner.address)
+    );
+    assert_eq!(outer.to_string(), format!("func[1] @{:#x}", outer.address));
+    assert!(message.contains(&inner.to_string()), "{message}");
+    assert!(message.contains(&outer.to_string()), "{message}");
+
+    let named_without_module = FrameInfo {
+        module_name: None,
+        ..*inner
+    };
+    assert_eq!(
+        named_without_module.to_string(),
+        format!("inner (func[0] @{:#x})", inner.address)
+    );
+}
+
+#[test]
+fn test_eval_keeps_state_and_rolls_back() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+    module.global.append(Global {
+        ty: GlobalType {
+            value_type: ValueType::I32,
+            is_mutable: true,
+        },
+        init: ConstExpr::I32(0),
+    });
+
+    // Add the argument to the global, store it in memory, and return it
+    let signature = || Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    };
+    let write_add = |buf: &mut Vec<'_, u8>| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32ADD as u8);
+        buf.push(OpCode::SETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::I32STORE as u8);
+        buf.encode_u32(2);
+        buf.encode_u32(0);
+    };
+    create_exported_function_no_locals(&mut module, "add", signature(), |buf| {
+        write_add(buf);
+        buf.push(OpCode::GETGLOBAL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "add_then_trap", signature(), |buf| {
+        write_add(buf);
+        buf.push(OpCode::UNREACHABLE as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+
+    // Each evaluation sees the state left by the last one
+    assert_eq!(inst.eval("add", [Value::I32(5)]), Ok(Some(Value::I32(5))));
+    assert_eq!(inst.eval("add", [Value::I32(3)]), Ok(Some(Value::I32(8))));
+    assert!(!inst.rollback());
+
+    assert!(inst.eval("add_then_trap", [Value::I32(100)]).is_err());
+    assert_eq!(inst.globals[0], Value::I32(108));
+    assert_eq!(inst.value_stack_depth(), 0);
+
+    assert!(inst.rollback());
+    assert_eq!(inst.globals[0], Value::I32(8));
+    assert_eq!(inst.memory[0], 8);
+    assert!(!inst.rollback());
+
+    assert_eq!(inst.eval("add", [Value::I32(1)]), Ok(Some(Value::I32(9))));
+}
+
+#[test]
+fn test_call_typed() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    create_exported_function_no_locals(
+        &mut module,
+        "add",
+        Signature {
+            param_types: bumpalo::vec![in &arena; ValueType::I64, ValueType::I64],
+            ret_type: Some(ValueType::I64),
+        },
+        |buf| {
+            buf.push(OpCode::GETLOCAL as u8);
+            buf.encode_u32(0);
+            buf.push(OpCode::GETLOCAL as u8);
+            buf.encode_u32(1);
+            buf.push(OpCode::I64ADD as u8);
+            buf.push(OpCode::END as u8);
+        },
+    );
+    create_exported_function_no_locals(
+        &mut module,
+        "half",
+        Signature {
+            param_types: bumpalo::vec![in &arena; ValueType::F64],
+            ret_type: Some(ValueType::F64),
+        },
+        |buf| {
+            buf.push(OpCode::GETLOCAL as u8);
+            buf.encode_u32(0);
+            buf.push(OpCode::F64CONST as u8);
+            buf.encode_f64(2.0);
+            buf.push(OpCode::F64DIV as u8);
+            buf.push(OpCode::END as u8);
+        },
+    );
+    create_exported_function_no_locals(
+        &mut module,
+        "nothing",
+        Signature {
+            param_types: Vec::new_in(&arena),
+            ret_type: None,
+        },
+        |buf| {
+            buf.push(OpCode::END as u8);
+        },
+    );
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+
+    assert_eq!(inst.call_typed::<_, i64>("add", (40i64, 2i64)), Ok(42));
+    assert_eq!(inst.call_typed::<_, u64>("add", (u64::MAX, 1u64)), Ok(0));
+    assert_eq!(inst.call_typed::<_, f64>("half", (5.0f64,)), Ok(2.5));
+    assert_eq!(inst.call_typed::<_, ()>("nothing", ()), Ok(()));
+
+    // The generic types have to match the signature exactly
+    assert_eq!(
+        inst.call_typed::<_, i64>("add", (1i32, 2i32)),
+        Err("Type mismatch calling add. Its signature is (I64, I64) -> I64 but it was called as (I32, I32) -> I64".to_string())
+    );
+    assert_eq!(
+        inst.call_typed::<_, i64>("add", (1i64,)),
+        Err("Type mismatch calling add. Its signature is (I64, I64) -> I64 but it was called as (I64) -> I64".to_string())
+    );
+    assert_eq!(
+        inst.call_typed::<_, ()>("half", (1.0f64,)),
+        Err(
+            "Type mismatch calling half. Its signature is (F64) -> F64 but it was called as (F64)"
+                .to_string()
+        )
+    );
+    assert!(inst
+        .call_typed::<_, ()>("missing", ())
+        .unwrap_err()
+        .contains("I couldn't find a function 'missing'"));
+}
+
+#[test]
+fn test_call_one() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    create_exported_function_no_locals(
+        &mut module,
+        "double",
+        Signature {
+            param_types: bumpalo::vec![in &arena; ValueType::I32],
+            ret_type: Some(ValueType::I32),
+        },
+        |buf| {
+            buf.push(OpCode::GETLOCAL as u8);
+            buf.encode_u32(0);
+            buf.push(OpCode::GETLOCAL as u8);
+            buf.encode_u32(0);
+            buf.push(OpCode::I32ADD as u8);
+            buf.push(OpCode::END as u8);
+        },
+    );
+    create_exported_function_no_locals(
+        &mut module,
+        "nothing",
+        Signature {
+            param_types: Vec::new_in(&arena),
+            ret_type: None,
+        },
+        |buf| {
+            buf.push(OpCode::END as u8);
+        },
+    );
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+
+    assert_eq!(
+        inst.call_one("double", [Value::I32(21)]),
+        Ok(Value::I32(42))
+    );
+
+    // A function with no result is an error, and isn't run at all
+    let count_before = inst.instruction_count();
+    assert_eq!(
+        inst.call_one("nothing", []),
+        Err("Expected nothing to return one value, but its signature has no result".to_string())
+    );
+    assert_eq!(inst.instruction_count(), count_before);
+
+    // Argument errors are the same as for call_export
+    assert_eq!(
+        inst.call_one("double", [Value::I64(21)]),
+        Err("Type mismatch on argument 0 of double. Expected I32 but got I64(21)".to_string())
+    );
+}
+
+#[test]
+fn test_module_without_memory() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    assert_eq!(module.memory.count, 0);
+
+    let signature = || Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "answer", signature(), |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(6);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(7);
+        buf.push(OpCode::I32MUL as u8);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "load", signature(), |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(0);
+        buf.push(OpCode::I32LOAD as u8);
+        buf.encode_u32(2);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "size", signature(), |buf| {
+        buf.push(OpCode::CURRENTMEMORY as u8);
+        buf.push(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert!(inst.memory.is_empty());
+
+    assert_eq!(inst.call_export("answer", []), Ok(Some(Value::I32(42))));
+    for name in ["load", "size"] {
+        let message = inst.call_export(name, []).unwrap_err();
+        assert!(
+            message.contains("this module has no memory defined"),
+            "{message}"
+        );
+    }
+}
+
+#[test]
+fn test_unsupported_feature() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let cases: [(&str, &[u8], &str); 6] = [
+        (
+            "simd",
+            &[0xfd, 0x0c],
+            "(opcode 0xfd) is part of the SIMD feature",
+        ),
+        (
+            "throw",
+            &[0x08, 0x00],
+            "(opcode 0x08) is part of the exception handling feature",
+        ),
+        (
+            "ref_null",
+            &[0xd0, 0x70],
+            "(opcode 0xd0) is part of the reference types feature",
+        ),
+        (
+            "trunc_sat",
+            &[0xfc, 0x00],
+            "(opcode 0xfc 0) is part of the non-trapping float-to-int conversions feature",
+        ),
+        (
+            "memory_init",
+            &[0xfc, 0x08, 0x00, 0x00],
+            "(opcode 0xfc 8) is part of the bulk memory feature",
+        ),
+        (
+            "atomic",
+            &[0xfe, 0x7f],
+            "(opcode 0xfe 127) is part of the threads (atomics) feature",
+        ),
+    ];
+    for (name, instruction, _) in cases.iter() {
+        let signature = Signature {
+            param_types: Vec::new_in(&arena),
+            ret_type: None,
+        };
+        create_exported_function_no_locals(&mut module, name, signature, |buf| {
+            buf.extend_from_slice(instruction);
+            buf.push(OpCode::END as u8);
+        });
+    }
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    for (name, _, expected) in cases.iter() {
+        let message = inst.call_export(name, []).unwrap_err();
+        assert!(message.contains(expected), "{message}");
+    }
+}
+
+#[test]
+fn test_return_call() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    // count(n, acc) = if n == 0 { acc } else { count(n - 1, acc + 1) }
+    let count_signature = Signature {
+        param_types: Vec::from_iter_in([ValueType::I64, ValueType::I64], &arena),
+        ret_type: Some(ValueType::I64),
+    };
+    create_exported_function_no_locals(&mut module, "count", count_signature, |buf| {
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.push(0);
+        buf.push(OpCode::I64EQZ as u8);
+        buf.push(OpCode::IF as u8);
+        buf.push(ValueType::I64 as u8);
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.push(1);
+        buf.push(OpCode::ELSE as u8);
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.push(0);
+        buf.push(OpCode::I64CONST as u8);
+        buf.push(1);
+        buf.push(OpCode::I64SUB as u8);
+        buf.push(OpCode::GETLOCAL as u8);
+        buf.push(1);
+        buf.push(OpCode::I64CONST as u8);
+        buf.push(1);
+        buf.push(OpCode::I64ADD as u8);
+        buf.push(OpCode::RETURNCALL as u8);
+        buf.push(0); // count
+        buf.push(OpCode::END as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    // The caller's frame should be untouched by the tail calls in the callee
+    let caller_signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I64),
+    };
+    create_exported_function_no_locals(&mut module, "caller", caller_signature, |buf| {
+        buf.push(OpCode::I64CONST as u8);
+        buf.encode_i64(1000);
+        buf.push(OpCode::I64CONST as u8);
+        buf.push(0);
+        buf.push(OpCode::CALL as u8);
+        buf.push(0); // count
+        buf.push(OpCode::I64CONST as u8);
+        buf.push(1);
+        buf.push(OpCode::I64ADD as u8);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+
+    let result = inst.call_export("count", [Value::I64(100_000), Value::I64(0)]);
+    assert_eq!(result, Ok(Some(Value::I64(100_000))));
+    // The entry function never made a regular call, so no frame was ever pushed
+    assert_eq!(inst.previous_frames.capacity(), 0);
+
+    assert_eq!(inst.call_export("caller", []), Ok(Some(Value::I64(1001))));
+}
+
+#[test]
+fn test_deadline() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let signature = || Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "forever", signature(), |buf| {
+        buf.push(OpCode::LOOP as u8);
+        buf.push(ValueType::VOID);
+        buf.push(OpCode::BR as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "quick", signature(), |buf| {
+        buf.push(OpCode::END as u8);
+    });
+
+    let start = Instant::now();
+    let mut inst = Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false)
+        .unwrap()
+        .with_deadline(start + Duration::from_millis(20))
+        .with_deadline_check_interval(100);
+    let err = inst.call_export("forever", []).unwrap_err();
+    assert!(err.contains(&TrapReason::Timeout.to_string()), "{err}");
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // The clock isn't read until the interval is up, so short calls don't pay for it
+    let mut inst = Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false)
+        .unwrap()
+        .with_deadline(start);
+    assert_eq!(inst.call_export("quick", []), Ok(None));
+}
+
+#[test]
+fn test_call_and_return_hooks() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let signature = || Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    };
+    // main calls f twice, and f tail-calls g
+    create_exported_function_no_locals(&mut module, "main", signature(), |buf| {
+        for _ in 0..2 {
+            buf.push(OpCode::CALL as u8);
+            buf.encode_u32(1);
+        }
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "f", signature(), |buf| {
+        buf.push(OpCode::RETURNCALL as u8);
+        buf.encode_u32(2);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "g", signature(), |buf| {
+        buf.push(OpCode::END as u8);
+    });
+
+    let events = RefCell::new(std::vec::Vec::new());
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    inst.set_call_hook(Box::new(|f| events.borrow_mut().push(("call", f))));
+    inst.set_return_hook(Box::new(|f| events.borrow_mut().push(("return", f))));
+    assert_eq!(inst.call_export("main", []), Ok(None));
+    drop(inst);
+
+    let events = events.into_inner();
+    assert_eq!(
+        events,
+        [
+            ("call", 0),
+            ("call", 1),
+            ("return", 1),
+            ("call", 2),
+            ("return", 2),
+            ("call", 1),
+            ("return", 1),
+            ("call", 2),
+            ("return", 2),
+            ("return", 0),
+        ]
+    );
+
+    // Every return matches the most recent call that hasn't returned yet
+    let mut stack = std::vec::Vec::new();
+    for (event, f) in events {
+        match event {
+            "call" => stack.push(f),
+            _ => assert_eq!(stack.pop(), Some(f)),
+        }
+    }
+    assert!(stack.is_empty());
+}
+
+#[test]
+fn test_record_and_replay_trace() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let random_signature = module.types.insert(Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "random",
+        description: ImportDesc::Func {
+            signature_index: random_signature,
+        },
+    });
+    let main_signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "main", main_signature, |buf| {
+        buf.push(OpCode::CALL as u8);
+        buf.encode_u32(2); // helper
+        buf.push(OpCode::CALL as u8);
+        buf.encode_u32(0); // random
+        buf.push(OpCode::END as u8);
+    });
+    let helper_signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "helper", helper_signature, |buf| {
+        buf.push(OpCode::END as u8);
+    });
+
+    let random = Cell::new(4);
+    let dispatcher = ClosureImportDispatcher::default()
+        .with("env", "random", |_, _| Some(Value::I32(random.get())));
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+
+    inst.record_trace();
+    assert_eq!(inst.call_export("main", []), Ok(Some(Value::I32(4))));
+    let trace = inst.saved_trace().unwrap().clone();
+    assert_eq!(
+        trace.events,
+        [
+            TraceEvent::Call(1),
+            TraceEvent::Call(2),
+            TraceEvent::Import {
+                fn_index: 0,
+                result: Some(Value::I32(4)),
+            },
+        ]
+    );
+
+    inst.replay(trace.clone());
+    assert_eq!(inst.call_export("main", []), Ok(Some(Value::I32(4))));
+    assert_eq!(inst.finish_replay(), Ok(()));
+
+    // The import returns something different this time
+    random.set(5);
+    inst.replay(trace.clone());
+    let err = inst.call_export("main", []).unwrap_err();
+    let divergence = TrapReason::TraceDivergence {
+        position: 2,
+        expected: "import 0 -> I32(4)".into(),
+        actual: "import 0 -> I32(5)".into(),
+    };
+    assert!(err.contains(&divergence.to_string()), "{err}");
+    assert!(inst.finish_replay().is_err());
+
+    // A run that stops early doesn't get through the whole trace
+    random.set(4);
+    let mut longer = trace;
+    longer.events.push(TraceEvent::Call(2));
+    inst.replay(longer);
+    assert_eq!(inst.call_export("main", []), Ok(Some(Value::I32(4))));
+    assert!(inst.finish_replay().is_err());
+    // and now there's nothing left to finish
+    assert!(inst.finish_replay().is_err());
+}
+
+#[test]
+fn test_custom_section() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    create_exported_function_no_locals(
+        &mut module,
+        "nothing",
+        Signature {
+            param_types: Vec::new_in(&arena),
+            ret_type: None,
+        },
+        |buf| {
+            buf.push(OpCode::END as u8);
+        },
+    );
+
+    let mut bytes = Vec::new_in(&arena);
+    module.serialize(&mut bytes);
+    for (name, contents) in [("producers", &b"roc"[..]), ("roc_app", &[1, 2, 3])] {
+        let header = write_custom_section_header(&mut bytes, name);
+        bytes.extend_from_slice(contents);
+        update_section_size(&mut bytes, header);
+    }
+
+    let inst =
+        Instance::from_bytes(&arena, &bytes, DefaultImportDispatcher::default(), false).unwrap();
+
+    assert_eq!(inst.custom_section("roc_app"), Some(&[1, 2, 3][..]));
+    assert_eq!(inst.custom_section("producers"), Some(&b"roc"[..]));
+    assert_eq!(inst.custom_section("missing"), None);
+}
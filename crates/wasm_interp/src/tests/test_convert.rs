@@ -10,6 +10,14 @@ fn test_i32wrapi64() {
         [Value::I64(0x123456789)],
         Value::I32(0x23456789),
     );
+    // Bit 31 of the low half becomes the sign bit
+    test_op_example(
+        I32WRAPI64,
+        [Value::I64(0x7_8000_0001)],
+        Value::I32(0x8000_0001_u32 as i32),
+    );
+    test_op_example(I32WRAPI64, [Value::I64(-1)], Value::I32(-1));
+    test_op_example(I32WRAPI64, [Value::I64(i64::MIN)], Value::I32(0));
 }
 
 #[test]
@@ -83,11 +91,27 @@ fn test_i32truncuf64_oob() {
 #[test]
 fn test_i64extendsi32() {
     test_op_example(I64EXTENDSI32, [Value::I32(-1)], Value::I64(-1));
+    test_op_example(
+        I64EXTENDSI32,
+        [Value::I32(i32::MIN)],
+        Value::I64(-0x8000_0000),
+    );
+    test_op_example(
+        I64EXTENDSI32,
+        [Value::I32(i32::MAX)],
+        Value::I64(0x7fff_ffff),
+    );
 }
 
 #[test]
 fn test_i64extendui32() {
     test_op_example(I64EXTENDUI32, [Value::I32(-1)], Value::I64(0xffff_ffff));
+    test_op_example(
+        I64EXTENDUI32,
+        [Value::I32(i32::MIN)],
+        Value::I64(0x8000_0000),
+    );
+    test_op_example(I64EXTENDUI32, [Value::I32(0x1234)], Value::I64(0x1234));
 }
 
 #[test]
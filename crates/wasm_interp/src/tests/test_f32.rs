@@ -1,7 +1,7 @@
 #![cfg(test)]
 
-use super::test_op_example;
-use roc_wasm_module::{opcodes::OpCode, opcodes::OpCode::*, Value};
+use super::{run_op_example, test_op_example};
+use roc_wasm_module::{opcodes::OpCode, opcodes::OpCode::*, Value, ValueType};
 
 fn test_f32_comparison(op: OpCode, arg1: f32, arg2: f32, expected: bool) {
     test_op_example(
@@ -19,6 +19,13 @@ fn test_f32_binop(op: OpCode, arg1: f32, arg2: f32, expected: f32) {
     )
 }
 
+fn run_f32_binop(op: OpCode, arg1: f32, arg2: f32) -> f32 {
+    match run_op_example(op, [Value::F32(arg1), Value::F32(arg2)], ValueType::F32) {
+        Value::F32(x) => x,
+        other => panic!("expected an F32 but got {other:?}"),
+    }
+}
+
 fn test_f32_unop(op: OpCode, arg: f32, expected: f32) {
     test_op_example(op, [Value::F32(arg)], Value::F32(expected))
 }
@@ -69,6 +76,24 @@ fn test_f32ge() {
     test_f32_comparison(op, -1.1, 1.1, false);
 }
 
+#[test]
+fn test_f32_comparisons_nan() {
+    // NaN is unordered, so every comparison is false except `ne`
+    let nan = f32::NAN;
+    for (arg1, arg2) in [(nan, 1.0), (1.0, nan), (nan, nan)] {
+        test_f32_comparison(F32EQ, arg1, arg2, false);
+        test_f32_comparison(F32NE, arg1, arg2, true);
+        test_f32_comparison(F32LT, arg1, arg2, false);
+        test_f32_comparison(F32GT, arg1, arg2, false);
+        test_f32_comparison(F32LE, arg1, arg2, false);
+        test_f32_comparison(F32GE, arg1, arg2, false);
+    }
+
+    // Zero is equal to negative zero
+    test_f32_comparison(F32EQ, 0.0, -0.0, true);
+    test_f32_comparison(F32LT, -0.0, 0.0, false);
+}
+
 #[test]
 fn test_f32abs() {
     let op = F32ABS;
@@ -168,6 +193,22 @@ fn test_f32min() {
     test_f32_binop(op, -1.1, -2.2, -2.2);
 }
 
+#[test]
+fn test_f32min_signed_zero() {
+    let op = F32MIN;
+    assert!(run_f32_binop(op, -0.0, 0.0).is_sign_negative());
+    assert!(run_f32_binop(op, 0.0, -0.0).is_sign_negative());
+}
+
+#[test]
+fn test_f32min_nan() {
+    let op = F32MIN;
+    let nan = f32::NAN;
+    assert!(run_f32_binop(op, nan, 1.0).is_nan());
+    assert!(run_f32_binop(op, 1.0, nan).is_nan());
+    assert!(run_f32_binop(op, nan, nan).is_nan());
+}
+
 #[test]
 fn test_f32max() {
     let op = F32MAX;
@@ -175,6 +216,22 @@ fn test_f32max() {
     test_f32_binop(op, -1.1, -2.2, -1.1);
 }
 
+#[test]
+fn test_f32max_signed_zero() {
+    let op = F32MAX;
+    assert!(run_f32_binop(op, -0.0, 0.0).is_sign_positive());
+    assert!(run_f32_binop(op, 0.0, -0.0).is_sign_positive());
+}
+
+#[test]
+fn test_f32max_nan() {
+    let op = F32MAX;
+    let nan = f32::NAN;
+    assert!(run_f32_binop(op, nan, 1.0).is_nan());
+    assert!(run_f32_binop(op, 1.0, nan).is_nan());
+    assert!(run_f32_binop(op, nan, nan).is_nan());
+}
+
 #[test]
 fn test_f32copysign() {
     let op = F32COPYSIGN;
@@ -1,7 +1,7 @@
 #![cfg(test)]
 
-use super::test_op_example;
-use roc_wasm_module::{opcodes::OpCode, opcodes::OpCode::*, Value};
+use super::{run_op_example, test_op_example};
+use roc_wasm_module::{opcodes::OpCode, opcodes::OpCode::*, Value, ValueType};
 
 fn test_f64_comparison(op: OpCode, arg1: f64, arg2: f64, expected: bool) {
     test_op_example(
@@ -19,6 +19,13 @@ fn test_f64_binop(op: OpCode, arg1: f64, arg2: f64, expected: f64) {
     )
 }
 
+fn run_f64_binop(op: OpCode, arg1: f64, arg2: f64) -> f64 {
+    match run_op_example(op, [Value::F64(arg1), Value::F64(arg2)], ValueType::F64) {
+        Value::F64(x) => x,
+        other => panic!("expected an F64 but got {other:?}"),
+    }
+}
+
 fn test_f64_unop(op: OpCode, arg: f64, expected: f64) {
     test_op_example(op, [Value::F64(arg)], Value::F64(expected))
 }
@@ -69,6 +76,24 @@ fn test_f64ge() {
     test_f64_comparison(op, -1.1, 1.1, false);
 }
 
+#[test]
+fn test_f64_comparisons_nan() {
+    // NaN is unordered, so every comparison is false except `ne`
+    let nan = f64::NAN;
+    for (arg1, arg2) in [(nan, 1.0), (1.0, nan), (nan, nan)] {
+        test_f64_comparison(F64EQ, arg1, arg2, false);
+        test_f64_comparison(F64NE, arg1, arg2, true);
+        test_f64_comparison(F64LT, arg1, arg2, false);
+        test_f64_comparison(F64GT, arg1, arg2, false);
+        test_f64_comparison(F64LE, arg1, arg2, false);
+        test_f64_comparison(F64GE, arg1, arg2, false);
+    }
+
+    // Zero is equal to negative zero
+    test_f64_comparison(F64EQ, 0.0, -0.0, true);
+    test_f64_comparison(F64LT, -0.0, 0.0, false);
+}
+
 #[test]
 fn test_f64abs() {
     let op = F64ABS;
@@ -168,6 +193,22 @@ fn test_f64min() {
     test_f64_binop(op, -1.1, -2.2, -2.2);
 }
 
+#[test]
+fn test_f64min_signed_zero() {
+    let op = F64MIN;
+    assert!(run_f64_binop(op, -0.0, 0.0).is_sign_negative());
+    assert!(run_f64_binop(op, 0.0, -0.0).is_sign_negative());
+}
+
+#[test]
+fn test_f64min_nan() {
+    let op = F64MIN;
+    let nan = f64::NAN;
+    assert!(run_f64_binop(op, nan, 1.0).is_nan());
+    assert!(run_f64_binop(op, 1.0, nan).is_nan());
+    assert!(run_f64_binop(op, nan, nan).is_nan());
+}
+
 #[test]
 fn test_f64max() {
     let op = F64MAX;
@@ -175,6 +216,22 @@ fn test_f64max() {
     test_f64_binop(op, -1.1, -2.2, -1.1);
 }
 
+#[test]
+fn test_f64max_signed_zero() {
+    let op = F64MAX;
+    assert!(run_f64_binop(op, -0.0, 0.0).is_sign_positive());
+    assert!(run_f64_binop(op, 0.0, -0.0).is_sign_positive());
+}
+
+#[test]
+fn test_f64max_nan() {
+    let op = F64MAX;
+    let nan = f64::NAN;
+    assert!(run_f64_binop(op, nan, 1.0).is_nan());
+    assert!(run_f64_binop(op, 1.0, nan).is_nan());
+    assert!(run_f64_binop(op, nan, nan).is_nan());
+}
+
 #[test]
 fn test_f64copysign() {
     let op = F64COPYSIGN;
@@ -131,6 +131,28 @@ fn test_i32geu() {
     test_u32_binop(op, u32::MAX, u32::MIN, 1);
 }
 
+#[test]
+fn test_i32_comparisons_signed_vs_unsigned() {
+    // 0xFFFFFFFF is -1 when signed, but the largest value when unsigned
+    let all_ones = -1;
+    test_i32_binop(I32LTS, all_ones, 1, 1);
+    test_i32_binop(I32LTU, all_ones, 1, 0);
+    test_i32_binop(I32GTS, all_ones, 1, 0);
+    test_i32_binop(I32GTU, all_ones, 1, 1);
+    test_i32_binop(I32LES, all_ones, 1, 1);
+    test_i32_binop(I32LEU, all_ones, 1, 0);
+    test_i32_binop(I32GES, all_ones, 1, 0);
+    test_i32_binop(I32GEU, all_ones, 1, 1);
+
+    // Same for the sign bit on its own
+    test_i32_binop(I32LTS, i32::MIN, 0, 1);
+    test_i32_binop(I32LTU, i32::MIN, 0, 0);
+
+    test_i32_binop(I32EQ, all_ones, all_ones, 1);
+    test_i32_binop(I32NE, all_ones, all_ones, 0);
+    test_i32_unop(I32EQZ, all_ones, 0);
+}
+
 #[test]
 fn test_i32clz() {
     let op = I32CLZ;
@@ -147,6 +147,28 @@ fn test_i64geu() {
     test_u64_comparison(op, u64::MAX, u64::MIN, true);
 }
 
+#[test]
+fn test_i64_comparisons_signed_vs_unsigned() {
+    // All ones is -1 when signed, but the largest value when unsigned
+    let all_ones = -1;
+    test_i64_comparison(I64LTS, all_ones, 1, true);
+    test_i64_comparison(I64LTU, all_ones, 1, false);
+    test_i64_comparison(I64GTS, all_ones, 1, false);
+    test_i64_comparison(I64GTU, all_ones, 1, true);
+    test_i64_comparison(I64LES, all_ones, 1, true);
+    test_i64_comparison(I64LEU, all_ones, 1, false);
+    test_i64_comparison(I64GES, all_ones, 1, false);
+    test_i64_comparison(I64GEU, all_ones, 1, true);
+
+    // Same for the sign bit on its own
+    test_i64_comparison(I64LTS, i64::MIN, 0, true);
+    test_i64_comparison(I64LTU, i64::MIN, 0, false);
+
+    // Only the low 32 bits differ from 0xFFFFFFFF, which would be equal if truncated
+    test_u64_comparison(I64EQ, 0xFFFF_FFFF, u64::MAX, false);
+    test_u64_comparison(I64NE, 0xFFFF_FFFF, u64::MAX, true);
+}
+
 #[test]
 fn test_i64clz() {
     let op = I64CLZ;
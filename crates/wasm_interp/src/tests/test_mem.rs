@@ -1,11 +1,15 @@
 use super::create_exported_function_no_locals;
-use crate::{DefaultImportDispatcher, Instance};
+use crate::{
+    read_roc_list, read_roc_str, ClosureImportDispatcher, DefaultImportDispatcher, Instance,
+    MemStats, MemoryChange,
+};
 use bumpalo::{collections::Vec, Bump};
 use roc_wasm_module::{
     opcodes::OpCode,
-    sections::{DataMode, DataSegment, MemorySection},
+    sections::{DataMode, DataSegment, Import, ImportDesc, Limits, MemorySection},
     ConstExpr, SerialBuffer, Signature, Value, ValueType, WasmModule,
 };
+use std::cell::RefCell;
 
 #[test]
 fn test_currentmemory() {
@@ -49,6 +53,71 @@ fn test_growmemory() {
     assert_eq!(state.memory.len(), 5 * MemorySection::PAGE_SIZE as usize);
 }
 
+#[test]
+fn test_growmemory_hook() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let existing_pages = 3;
+    let pc = 0;
+    module.memory = MemorySection::new(&arena, existing_pages * MemorySection::PAGE_SIZE);
+    for grow_pages in [2, 0, 1] {
+        module.code.bytes.push(OpCode::I32CONST as u8);
+        module.code.bytes.encode_i32(grow_pages);
+        module.code.bytes.push(OpCode::GROWMEMORY as u8);
+        module.code.bytes.encode_i32(0);
+    }
+
+    let events = RefCell::new(std::vec::Vec::new());
+    let mut state = Instance::new(
+        &arena,
+        existing_pages,
+        pc,
+        [],
+        DefaultImportDispatcher::default(),
+    );
+    assert_eq!(state.memory_high_water_mark(), existing_pages);
+
+    state.set_memory_grow_hook(Box::new(|prev, new| events.borrow_mut().push((prev, new))));
+    for _ in 0..6 {
+        state.execute_next_instruction(&module).unwrap();
+    }
+
+    assert_eq!(state.memory_high_water_mark(), 6);
+    drop(state);
+    assert_eq!(events.into_inner(), [(3, 5), (5, 5), (5, 6)]);
+}
+
+#[test]
+fn test_growmemory_after_restore_is_zeroed() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    let page_size = MemorySection::PAGE_SIZE as usize;
+    let pc = 0;
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+    module.code.bytes.push(OpCode::I32CONST as u8);
+    module.code.bytes.encode_i32(1);
+    module.code.bytes.push(OpCode::GROWMEMORY as u8);
+    module.code.bytes.encode_i32(0);
+
+    let mut state = Instance::new(&arena, 1, pc, [], DefaultImportDispatcher::default());
+    let snapshot = state.snapshot();
+    state.execute_next_instruction(&module).unwrap();
+    state.execute_next_instruction(&module).unwrap();
+    state.memory[page_size..].fill(0xff);
+
+    // Shrinking the memory keeps its capacity, along with the stale bytes in it
+    state.restore(&snapshot);
+    assert_eq!(state.memory.len(), page_size);
+    state.program_counter = pc;
+    state.execute_next_instruction(&module).unwrap();
+    state.execute_next_instruction(&module).unwrap();
+
+    assert_eq!(state.memory.len(), 2 * page_size);
+    assert!(state.memory[page_size..].iter().all(|byte| *byte == 0));
+}
+
 #[test]
 fn test_memory_fill() {
     let arena = Bump::new();
@@ -165,6 +234,48 @@ fn test_load(load_op: OpCode, ty: ValueType, data: &[u8], addr: u32, offset: u32
     inst.call_export(start_fn_name, []).unwrap().unwrap()
 }
 
+#[test]
+fn test_memory_mut_before_a_call() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I32),
+    };
+    // Load the I32 at address 16, and store 1 more than it at address 20
+    create_exported_function_no_locals(&mut module, "increment", signature, |buf| {
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_u32(20);
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_u32(16);
+        buf.append_u8(OpCode::I32LOAD as u8);
+        buf.encode_u32(2); // align
+        buf.encode_u32(0); // offset
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_u32(1);
+        buf.append_u8(OpCode::I32ADD as u8);
+        buf.append_u8(OpCode::I32STORE as u8);
+        buf.encode_u32(2); // align
+        buf.encode_u32(0); // offset
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_u32(16);
+        buf.append_u8(OpCode::I32LOAD as u8);
+        buf.encode_u32(2); // align
+        buf.encode_u32(0); // offset
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.memory().len(), MemorySection::PAGE_SIZE as usize);
+
+    inst.memory_mut()[16..20].copy_from_slice(&41i32.to_le_bytes());
+    assert_eq!(inst.call_export("increment", []), Ok(Some(Value::I32(41))));
+    assert_eq!(inst.memory()[20..24], 42i32.to_le_bytes());
+}
+
 #[test]
 fn test_i32load() {
     let bytes = "abcdefgh".as_bytes();
@@ -199,6 +310,17 @@ fn test_f32load() {
         test_load(OpCode::F32LOAD, ValueType::F32, &bytes, 0x11, 0),
         Value::F32(value)
     );
+    // little-endian
+    assert_eq!(
+        test_load(
+            OpCode::F32LOAD,
+            ValueType::F32,
+            &[0, 0, 0x80, 0x3f],
+            0x11,
+            0
+        ),
+        Value::F32(1.0)
+    );
 }
 
 #[test]
@@ -209,106 +331,111 @@ fn test_f64load() {
         test_load(OpCode::F64LOAD, ValueType::F64, &bytes, 0x11, 0),
         Value::F64(value)
     );
+    // little-endian
+    assert_eq!(
+        test_load(
+            OpCode::F64LOAD,
+            ValueType::F64,
+            &[0, 0, 0, 0, 0, 0, 0xf0, 0x3f],
+            0x11,
+            0
+        ),
+        Value::F64(1.0)
+    );
 }
 
+/// The top bit of the first 1, 2 and 4 bytes is set, so sign extension fills the rest with ones
+const NEGATIVE_BYTES: [u8; 4] = [0x81, 0x82, 0x83, 0x84];
+/// The top bit of the first 1, 2 and 4 bytes is clear
+const POSITIVE_BYTES: [u8; 4] = [0x01, 0x02, 0x03, 0x74];
+
 #[test]
 fn test_i32load8s() {
-    let value: i8 = -42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD8S, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I32LOAD8S;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I32, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I32(0xffffff81_u32 as i32));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I32(0x01));
 }
 
 #[test]
 fn test_i32load8u() {
-    let value: u8 = 42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD8U, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I32LOAD8U;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I32, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I32(0x81));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I32(0x01));
 }
 
 #[test]
 fn test_i32load16s() {
-    let value: i16 = -42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD16S, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I32LOAD16S;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I32, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I32(0xffff8281_u32 as i32));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I32(0x0201));
 }
 
 #[test]
 fn test_i32load16u() {
-    let value: u16 = 42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD16U, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I32LOAD16U;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I32, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I32(0x8281));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I32(0x0201));
 }
 
 #[test]
 fn test_i64load8s() {
-    let value: i8 = -42;
-    let bytes = value.to_le_bytes();
+    let op = OpCode::I64LOAD8S;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
     assert_eq!(
-        test_load(OpCode::I64LOAD8S, ValueType::I64, &bytes, 0x11, 0),
-        Value::I64(value as i64)
+        load(&NEGATIVE_BYTES),
+        Value::I64(0xffffffff_ffffff81_u64 as i64)
     );
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x01));
 }
 
 #[test]
 fn test_i64load8u() {
-    let value: u8 = 42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD8U, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I64LOAD8U;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I64(0x81));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x01));
 }
 
 #[test]
 fn test_i64load16s() {
-    let value: i16 = -42;
-    let bytes = value.to_le_bytes();
+    let op = OpCode::I64LOAD16S;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
     assert_eq!(
-        test_load(OpCode::I64LOAD8S, ValueType::I64, &bytes, 0x11, 0),
-        Value::I64(value as i64)
+        load(&NEGATIVE_BYTES),
+        Value::I64(0xffffffff_ffff8281_u64 as i64)
     );
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x0201));
 }
 
 #[test]
 fn test_i64load16u() {
-    let value: u16 = 42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD8U, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I64LOAD16U;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I64(0x8281));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x0201));
 }
 
 #[test]
 fn test_i64load32s() {
-    let value: i32 = -42;
-    let bytes = value.to_le_bytes();
+    let op = OpCode::I64LOAD32S;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
     assert_eq!(
-        test_load(OpCode::I64LOAD8S, ValueType::I64, &bytes, 0x11, 0),
-        Value::I64(value as i64)
+        load(&NEGATIVE_BYTES),
+        Value::I64(0xffffffff_84838281_u64 as i64)
     );
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x74030201));
 }
 
 #[test]
 fn test_i64load32u() {
-    let value: u32 = 42;
-    let bytes = value.to_le_bytes();
-    assert_eq!(
-        test_load(OpCode::I32LOAD8U, ValueType::I32, &bytes, 0x11, 0),
-        Value::I32(value as i32)
-    );
+    let op = OpCode::I64LOAD32U;
+    let load = |bytes: &[u8]| test_load(op, ValueType::I64, bytes, 0x11, 0);
+    assert_eq!(load(&NEGATIVE_BYTES), Value::I64(0x84838281));
+    assert_eq!(load(&POSITIVE_BYTES), Value::I64(0x74030201));
 }
 
 fn test_store<'a>(
@@ -516,3 +643,490 @@ fn test_i64store32() {
         &[0xf0, 0xde, 0xbc, 0x9a, 0x00, 0x00, 0x00, 0x00]
     );
 }
+
+#[test]
+fn test_dump_memory() {
+    let arena = Bump::new();
+    let mut state = Instance::new(&arena, 1, 0, [], DefaultImportDispatcher::default());
+    state.memory[0x100..0x104].copy_from_slice(&[1, 2, 3, 4]);
+
+    let dir = std::env::temp_dir();
+    let full_path = dir.join(format!("roc_wasm_interp_dump_{}", std::process::id()));
+    let range_path = dir.join(format!("roc_wasm_interp_dump_range_{}", std::process::id()));
+
+    state.dump_memory(&full_path).unwrap();
+    state.dump_memory_range(&range_path, 0xff, 6).unwrap();
+    let out_of_bounds = state.dump_memory_range(&range_path, MemorySection::PAGE_SIZE - 2, 4);
+
+    let full = std::fs::read(&full_path).unwrap();
+    let range = std::fs::read(&range_path).unwrap();
+    std::fs::remove_file(full_path).unwrap();
+    std::fs::remove_file(range_path).unwrap();
+
+    assert_eq!(full.len(), MemorySection::PAGE_SIZE as usize);
+    assert_eq!(&full[0x100..0x104], &[1, 2, 3, 4]);
+    assert_eq!(range, [0, 1, 2, 3, 4, 0]);
+    assert_eq!(
+        out_of_bounds.unwrap_err().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_memory64() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    // memory64 limits: min 1 page, max 2 pages
+    module.memory = MemorySection {
+        count: 1,
+        bytes: bumpalo::vec![in &arena; 0x05, 1, 2],
+    };
+    assert!(module.memory.is_memory64());
+
+    let i64_signature = || Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: Some(ValueType::I64),
+    };
+
+    let load = |buf: &mut Vec<'_, u8>, base: i64, offset: u64| {
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(base);
+        buf.append_u8(OpCode::I64LOAD as u8);
+        buf.encode_u32(3); // align
+        buf.encode_u64(offset);
+    };
+
+    create_exported_function_no_locals(&mut module, "in_bounds", i64_signature(), |buf| {
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(8);
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(0x1234);
+        buf.append_u8(OpCode::I64STORE as u8);
+        buf.encode_u32(3); // align
+        buf.encode_u64(8);
+        load(buf, 16, 0);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    // These would both wrap around to address 16 if they were truncated to 32 bits
+    create_exported_function_no_locals(&mut module, "big_base", i64_signature(), |buf| {
+        load(buf, 0x1_0000_0010, 0);
+        buf.append_u8(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "big_offset", i64_signature(), |buf| {
+        load(buf, 0, 0x1_0000_0010);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    create_exported_function_no_locals(&mut module, "grow", i64_signature(), |buf| {
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(1);
+        buf.append_u8(OpCode::GROWMEMORY as u8);
+        buf.append_u8(0);
+        buf.append_u8(OpCode::DROP as u8);
+        // growing past the maximum fails
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(1);
+        buf.append_u8(OpCode::GROWMEMORY as u8);
+        buf.append_u8(0);
+        buf.append_u8(OpCode::DROP as u8);
+        buf.append_u8(OpCode::CURRENTMEMORY as u8);
+        buf.append_u8(0);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    let module = arena.alloc(module);
+    let mut inst =
+        Instance::for_module(&arena, module, DefaultImportDispatcher::default(), false).unwrap();
+
+    assert_eq!(
+        inst.call_export("in_bounds", []).unwrap(),
+        Some(Value::I64(0x1234))
+    );
+    let big_base = inst.call_export("big_base", []).unwrap_err();
+    assert!(big_base.contains("0x100000010"), "{big_base}");
+    let big_offset = inst.call_export("big_offset", []).unwrap_err();
+    assert!(big_offset.contains("0x100000010"), "{big_offset}");
+
+    let mut inst =
+        Instance::for_module(&arena, module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.call_export("grow", []).unwrap(), Some(Value::I64(2)));
+    assert_eq!(inst.memory_high_water_mark(), 2);
+}
+
+#[test]
+fn test_memory64_grow_too_big_for_the_host() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+
+    // memory64 limits: min 1 page, no max
+    module.memory = MemorySection {
+        count: 1,
+        bytes: bumpalo::vec![in &arena; 0x04, 1],
+    };
+
+    // Page counts are 32 bits, so this is allowed, but it's 256TiB
+    create_exported_function_no_locals(
+        &mut module,
+        "grow",
+        Signature {
+            param_types: bumpalo::vec![in &arena],
+            ret_type: Some(ValueType::I64),
+        },
+        |buf| {
+            buf.append_u8(OpCode::I64CONST as u8);
+            buf.encode_i64(u32::MAX as i64 - 1);
+            buf.append_u8(OpCode::GROWMEMORY as u8);
+            buf.append_u8(0);
+            buf.append_u8(OpCode::END as u8);
+        },
+    );
+
+    let module = arena.alloc(module);
+    let mut inst =
+        Instance::for_module(&arena, module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.call_export("grow", []).unwrap(), Some(Value::I64(-1)));
+    assert_eq!(inst.memory.len(), MemorySection::PAGE_SIZE as usize);
+}
+
+#[test]
+fn test_memory_fault_hook() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let void_signature = || Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: None,
+    };
+
+    // The last 4 bytes of memory are in bounds, but an 8-byte store there isn't
+    create_exported_function_no_locals(&mut module, "store", void_signature(), |buf| {
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(MemorySection::PAGE_SIZE as i32 - 4);
+        buf.append_u8(OpCode::I64CONST as u8);
+        buf.encode_i64(0);
+        buf.append_u8(OpCode::I64STORE as u8);
+        buf.encode_u32(3); // align
+        buf.encode_u32(0); // offset
+        buf.append_u8(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "load", void_signature(), |buf| {
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(0x100);
+        buf.append_u8(OpCode::I32LOAD16U as u8);
+        buf.encode_u32(1); // align
+        buf.encode_u32(MemorySection::PAGE_SIZE); // offset
+        buf.append_u8(OpCode::DROP as u8);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    let faults = RefCell::new(std::vec::Vec::new());
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    inst.set_memory_fault_hook(Box::new(|addr, size, is_write| {
+        faults.borrow_mut().push((addr, size, is_write))
+    }));
+
+    assert!(inst.call_export("store", []).is_err());
+    assert!(inst.call_export("load", []).is_err());
+    drop(inst);
+
+    let page = MemorySection::PAGE_SIZE as u64;
+    assert_eq!(
+        faults.into_inner(),
+        [(page - 4, 8, true), (page + 0x100, 2, false)]
+    );
+}
+
+#[test]
+fn test_bulk_memory_out_of_bounds() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let void_signature = || Signature {
+        param_types: bumpalo::vec![in &arena],
+        ret_type: None,
+    };
+    let page = MemorySection::PAGE_SIZE;
+
+    // Only the first 2 of the 4 source bytes are in bounds
+    create_exported_function_no_locals(&mut module, "copy", void_signature(), |buf| {
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(0); // destination
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(page as i32 - 2); // source
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(4); // size
+        buf.extend([OpCode::MEMORY as u8, 10, 0x0, 0x0]);
+        buf.append_u8(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "fill", void_signature(), |buf| {
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(page as i32 - 2); // destination
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(0xAA); // value
+        buf.append_u8(OpCode::I32CONST as u8);
+        buf.encode_i32(4); // size
+        buf.extend([OpCode::MEMORY as u8, 11, 0x0]);
+        buf.append_u8(OpCode::END as u8);
+    });
+
+    let faults = RefCell::new(std::vec::Vec::new());
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    inst.set_memory_fault_hook(Box::new(|addr, size, is_write| {
+        faults.borrow_mut().push((addr, size, is_write))
+    }));
+
+    // These trap instead of panicking, and don't write any of the bytes that were in bounds
+    assert!(inst.call_export("copy", []).is_err());
+    assert!(inst.call_export("fill", []).is_err());
+    assert_eq!(inst.memory()[page as usize - 2..], [0, 0]);
+    drop(inst);
+
+    let page = page as u64;
+    assert_eq!(
+        faults.into_inner(),
+        [(page - 2, 4, false), (page - 2, 4, true)]
+    );
+}
+
+#[test]
+fn test_mem_stats() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        // store, then load twice
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(5);
+        buf.push(OpCode::I32STORE as u8);
+        buf.encode_u32(2);
+        buf.encode_u32(0);
+        for _ in 0..2 {
+            buf.push(OpCode::I32CONST as u8);
+            buf.encode_i32(0);
+            buf.push(OpCode::I32LOAD as u8);
+            buf.encode_u32(2);
+            buf.encode_u32(0);
+            buf.push(OpCode::DROP as u8);
+        }
+
+        // grow by one page
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::GROWMEMORY as u8);
+        buf.push(0);
+        buf.push(OpCode::DROP as u8);
+
+        // memory.copy reads and writes
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(8);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(0);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(4);
+        buf.push(OpCode::MEMORY as u8);
+        buf.extend_from_slice(&[10, 0, 0]);
+
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.mem_stats(), None);
+
+    inst.enable_mem_stats();
+    inst.call_export("test", []).unwrap();
+
+    assert_eq!(
+        inst.mem_stats(),
+        Some(MemStats {
+            reads: 3,
+            writes: 2,
+            grows: 1,
+            high_water_mark: 2,
+        })
+    );
+}
+
+#[test]
+fn test_imported_memory() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let page_size = MemorySection::PAGE_SIZE as usize;
+
+    // The memory import comes first, so it mustn't take up a function index
+    module.import.imports.push(Import {
+        module: "env",
+        name: "memory",
+        description: ImportDesc::Mem {
+            limits: Limits::MinMax(1, 2),
+        },
+    });
+    let signature_index = module.types.insert(Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "double",
+        description: ImportDesc::Func { signature_index },
+    });
+
+    let signature = || Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "read", signature(), |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(16);
+        buf.push(OpCode::I32LOAD as u8);
+        buf.encode_u32(2);
+        buf.encode_u32(0);
+        buf.push(OpCode::CALL as u8);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+    create_exported_function_no_locals(&mut module, "grow", signature(), |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(1);
+        buf.push(OpCode::GROWMEMORY as u8);
+        buf.push(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let dispatcher = || {
+        let env = ClosureImportDispatcher::default().with("env", "double", |args, _| {
+            Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+        });
+        DefaultImportDispatcher::builder()
+            .module("env", env)
+            .build()
+    };
+
+    let mut memory = bumpalo::vec![in &arena; 0; page_size];
+    memory[16..20].copy_from_slice(&21i32.to_le_bytes());
+    let mut inst =
+        Instance::for_module_with_memory(&arena, &module, memory, dispatcher(), false).unwrap();
+    assert_eq!(inst.call_export("read", []), Ok(Some(Value::I32(42))));
+
+    // The host's memory was imported with a maximum of 2 pages
+    assert_eq!(inst.call_export("grow", []), Ok(Some(Value::I32(1))));
+    assert_eq!(inst.call_export("grow", []), Ok(Some(Value::I32(-1))));
+    let memory = inst.into_memory();
+    assert_eq!(memory.len(), 2 * page_size);
+    assert_eq!(memory[16..20], 21i32.to_le_bytes());
+
+    assert!(Instance::for_module(&arena, &module, dispatcher(), false).is_err());
+    let too_big = bumpalo::vec![in &arena; 0; 3 * page_size];
+    assert!(
+        Instance::for_module_with_memory(&arena, &module, too_big, dispatcher(), false).is_err()
+    );
+}
+
+#[test]
+fn test_memory_diff() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    module.memory = MemorySection::new(&arena, MemorySection::PAGE_SIZE);
+
+    let signature = Signature {
+        param_types: Vec::new_in(&arena),
+        ret_type: None,
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        // Overwriting a byte with the same value isn't a change
+        for (addr, value) in [(8, 0x0102_0304), (20, 0x7f), (21, 0)] {
+            buf.push(OpCode::I32CONST as u8);
+            buf.encode_i32(addr);
+            buf.push(OpCode::I32CONST as u8);
+            buf.encode_i32(value);
+            buf.push(OpCode::I32STORE8 as u8);
+            buf.encode_u32(0);
+            buf.encode_u32(0);
+        }
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(9);
+        buf.push(OpCode::I32CONST as u8);
+        buf.encode_i32(0x0102);
+        buf.push(OpCode::I32STORE16 as u8);
+        buf.encode_u32(1);
+        buf.encode_u32(0);
+        buf.push(OpCode::END as u8);
+    });
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    let before = inst.snapshot();
+    inst.call_export("test", []).unwrap();
+    let changes = before.memory_diff(&inst.memory);
+
+    assert_eq!(
+        changes,
+        [
+            MemoryChange {
+                start: 8,
+                before: vec![0, 0, 0],
+                after: vec![0x04, 0x02, 0x01],
+            },
+            MemoryChange {
+                start: 20,
+                before: vec![0],
+                after: vec![0x7f],
+            },
+        ]
+    );
+    assert_eq!(changes[0].to_string(), "0x8..0xb: 00 00 00 -> 04 02 01");
+    assert!(before.memory_diff(&before.memory).is_empty());
+}
+
+#[test]
+fn test_read_roc_str_and_list() {
+    let mut memory = std::vec![0u8; 64];
+    let write_wrapper = |memory: &mut [u8], addr: usize, words: [u32; 3]| {
+        for (i, word) in words.iter().enumerate() {
+            memory[addr + 4 * i..][..4].copy_from_slice(&word.to_le_bytes());
+        }
+    };
+
+    // A small string is stored inline, with its length in the last byte
+    memory[..5].copy_from_slice(b"hello");
+    memory[11] = 0x80 | 5;
+    assert_eq!(read_roc_str(&memory, 0), Ok("hello"));
+
+    // A heap string points to its bytes elsewhere
+    let text = b"this is too long to be small";
+    memory[32..32 + text.len()].copy_from_slice(text);
+    write_wrapper(&mut memory, 12, [32, text.len() as u32, text.len() as u32]);
+    assert_eq!(
+        read_roc_str(&memory, 12),
+        Ok("this is too long to be small")
+    );
+
+    // A seamless slice has the highest bit of its length set
+    write_wrapper(&mut memory, 12, [40, 3 | (1 << 31), 0]);
+    assert_eq!(read_roc_str(&memory, 12), Ok("too"));
+
+    write_wrapper(&mut memory, 12, [60, 20, 20]);
+    assert!(read_roc_str(&memory, 12).is_err());
+    assert!(read_roc_str(&memory, 60).is_err());
+
+    // A List of 3 U16s
+    memory[32..38].copy_from_slice(&[1, 0, 2, 0, 3, 0]);
+    write_wrapper(&mut memory, 12, [32, 3, 4]);
+    let elements = read_roc_list(&memory, 12, 2).unwrap();
+    assert_eq!(elements, [[1, 0], [2, 0], [3, 0]]);
+    assert_eq!(read_roc_list(&memory, 12, 0).unwrap().len(), 3);
+    assert!(read_roc_list(&memory, 12, 20).is_err());
+}
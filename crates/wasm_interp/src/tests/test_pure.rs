@@ -0,0 +1,109 @@
+use crate::{wasi, ClosureImportDispatcher, Instance, PureImportDispatcher, TrapReason};
+use bumpalo::{collections::Vec, Bump};
+use roc_wasm_module::{
+    opcodes::OpCode,
+    sections::{Import, ImportDesc},
+    Export, ExportType, Serialize, Signature, Value, ValueType, WasmModule,
+};
+
+/// Build a module that imports `env.double` and `proc_exit` from WASI,
+/// and an exported function that calls `double` and then, optionally, `proc_exit`.
+fn build_module(arena: &Bump, call_exit: bool) -> WasmModule<'_> {
+    let mut module = WasmModule::new(arena);
+
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in arena; ValueType::I32],
+        ret_type: Some(ValueType::I32),
+    });
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in arena; ValueType::I32],
+        ret_type: None,
+    });
+    module.import.imports.push(Import {
+        module: "env",
+        name: "double",
+        description: ImportDesc::Func { signature_index: 0 },
+    });
+    module.import.imports.push(Import {
+        module: wasi::MODULE_NAME,
+        name: "proc_exit",
+        description: ImportDesc::Func { signature_index: 1 },
+    });
+
+    module.code.function_count = 1;
+    let func_offset = module.code.bytes.len() as u32;
+    module.code.function_offsets.push(func_offset);
+    module.add_function_signature(Signature {
+        param_types: Vec::new_in(arena),
+        ret_type: Some(ValueType::I32),
+    });
+    module.export.append(Export {
+        name: "test",
+        ty: ExportType::Func,
+        index: 2,
+    });
+
+    let mut body = bumpalo::vec![in arena; 0, OpCode::I32CONST as u8, 21, OpCode::CALL as u8, 0];
+    if call_exit {
+        body.extend([OpCode::I32CONST as u8, 0, OpCode::CALL as u8, 1]);
+    }
+    body.push(OpCode::END as u8);
+    body.serialize(&mut module.code.bytes);
+
+    module
+}
+
+fn double() -> ClosureImportDispatcher<'static> {
+    ClosureImportDispatcher::default().with("env", "double", |args, _memory| {
+        Some(Value::I32(2 * args[0].expect_i32().unwrap()))
+    })
+}
+
+#[test]
+fn test_pure_allows_non_wasi_imports() {
+    let arena = Bump::new();
+    let module = build_module(&arena, false);
+
+    let dispatcher = PureImportDispatcher::new(double());
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    let result = inst.call_export("test", []);
+
+    assert_eq!(result, Ok(Some(Value::I32(42))));
+    assert_eq!(inst.import_dispatcher.trap(), None);
+}
+
+#[test]
+fn test_pure_traps_on_wasi() {
+    let arena = Bump::new();
+    let module = build_module(&arena, true);
+
+    let dispatcher = PureImportDispatcher::new(double());
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    let message = inst.call_export("test", []).unwrap_err();
+
+    assert!(message.contains("wasi_snapshot_preview1.proc_exit"));
+    assert_eq!(
+        inst.import_dispatcher.trap(),
+        Some(&TrapReason::EffectInPureContext {
+            module_name: wasi::MODULE_NAME.to_string(),
+            function_name: "proc_exit".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_pure_custom_effects() {
+    let arena = Bump::new();
+    let module = build_module(&arena, false);
+
+    let dispatcher = PureImportDispatcher::with_effectful_imports(double(), [("env", "double")]);
+    assert!(dispatcher.is_effectful("env", "double"));
+    assert!(!dispatcher.is_effectful(wasi::MODULE_NAME, "fd_write"));
+
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+    assert!(inst.call_export("test", []).is_err());
+    assert!(matches!(
+        inst.import_dispatcher.trap(),
+        Some(TrapReason::EffectInPureContext { function_name, .. }) if function_name == "double"
+    ));
+}
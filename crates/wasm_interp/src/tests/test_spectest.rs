@@ -0,0 +1,104 @@
+use crate::{DefaultImportDispatcher, Instance, SpectestDispatcher, SPECTEST_MODULE_NAME};
+use bumpalo::{collections::Vec, Bump};
+use roc_wasm_module::{
+    opcodes::OpCode,
+    sections::{Import, ImportDesc},
+    Export, ExportType, SerialBuffer, Serialize, Signature, Value, ValueType, WasmModule,
+};
+
+/// Build a module that imports `spectest.print_i32_f32` and `spectest.print`,
+/// with an exported function that calls both
+fn build_module<'a>(arena: &'a Bump, extra_import: Option<&'a str>) -> WasmModule<'a> {
+    let mut module = WasmModule::new(arena);
+
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in arena; ValueType::I32, ValueType::F32],
+        ret_type: None,
+    });
+    module.types.insert(Signature {
+        param_types: Vec::new_in(arena),
+        ret_type: None,
+    });
+    let imports = [("print_i32_f32", 0), ("print", 1)];
+    for (name, signature_index) in imports
+        .into_iter()
+        .chain(extra_import.map(|name| (name, 1)))
+    {
+        module.import.imports.push(Import {
+            module: SPECTEST_MODULE_NAME,
+            name,
+            description: ImportDesc::Func { signature_index },
+        });
+    }
+    let import_count = module.import.imports.len() as u32;
+
+    module.code.function_count = 1;
+    let func_offset = module.code.bytes.len() as u32;
+    module.code.function_offsets.push(func_offset);
+    module.add_function_signature(Signature {
+        param_types: Vec::new_in(arena),
+        ret_type: None,
+    });
+    module.export.append(Export {
+        name: "test",
+        ty: ExportType::Func,
+        index: import_count,
+    });
+
+    let mut body = bumpalo::vec![in arena; 0];
+    body.push(OpCode::I32CONST as u8);
+    body.encode_i32(42);
+    body.push(OpCode::F32CONST as u8);
+    body.encode_f32(1.5);
+    body.extend([OpCode::CALL as u8, 0, OpCode::CALL as u8, 1]);
+    if extra_import.is_some() {
+        body.extend([OpCode::CALL as u8, 2]);
+    }
+    body.push(OpCode::END as u8);
+    body.serialize(&mut module.code.bytes);
+
+    module
+}
+
+#[test]
+fn test_spectest_print() {
+    let arena = Bump::new();
+    let module = build_module(&arena, None);
+
+    let spectest = SpectestDispatcher::default();
+    let output = spectest.output();
+    let dispatcher = DefaultImportDispatcher::builder()
+        .module(SPECTEST_MODULE_NAME, spectest)
+        .build();
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+
+    assert_eq!(inst.call_export("test", []), Ok(None));
+    assert_eq!(*output.borrow(), "42 : i32\n1.5 : f32\n\n");
+}
+
+#[test]
+fn test_spectest_unknown_function() {
+    let arena = Bump::new();
+    let module = build_module(&arena, Some("print_i128"));
+
+    let dispatcher = DefaultImportDispatcher::builder()
+        .module(SPECTEST_MODULE_NAME, SpectestDispatcher::default())
+        .build();
+    let mut inst = Instance::for_module(&arena, &module, dispatcher, false).unwrap();
+
+    let message = inst.call_export("test", []).unwrap_err();
+    assert!(message.contains("spectest.print_i128"), "{message}");
+}
+
+#[test]
+fn test_spectest_globals() {
+    assert_eq!(
+        SpectestDispatcher::global("global_i32"),
+        Some(Value::I32(666))
+    );
+    assert_eq!(
+        SpectestDispatcher::global("global_f64"),
+        Some(Value::F64(666.6))
+    );
+    assert_eq!(SpectestDispatcher::global("memory"), None);
+}
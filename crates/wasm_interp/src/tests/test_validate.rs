@@ -0,0 +1,182 @@
+use super::{create_exported_function_no_locals, create_exported_function_with_locals};
+use crate::{
+    verify, verify_with_limits, DefaultImportDispatcher, Instance, ModuleLimit, ModuleLimits,
+    ValidationError,
+};
+use bumpalo::Bump;
+use roc_wasm_module::{
+    opcodes::OpCode,
+    sections::{Import, ImportDesc, MemorySection},
+    Export, ExportType, Signature, ValueType, WasmModule,
+};
+
+fn module_with_one_function(arena: &Bump) -> WasmModule<'_> {
+    let mut module = WasmModule::new(arena);
+    let signature = Signature {
+        param_types: bumpalo::vec![in arena],
+        ret_type: Some(ValueType::I32),
+    };
+    create_exported_function_no_locals(&mut module, "test", signature, |buf| {
+        buf.push(OpCode::I32CONST as u8);
+        buf.push(1);
+        buf.push(OpCode::END as u8);
+    });
+    module
+}
+
+#[test]
+fn test_verify_valid_module() {
+    let arena = Bump::new();
+    let module = module_with_one_function(&arena);
+    assert_eq!(verify(&module), Ok(()));
+}
+
+#[test]
+fn test_verify_export_out_of_bounds() {
+    let arena = Bump::new();
+    let mut module = module_with_one_function(&arena);
+    module.export.append(Export {
+        name: "missing",
+        ty: ExportType::Func,
+        index: 1,
+    });
+
+    assert_eq!(
+        verify(&module),
+        Err(ValidationError::ExportOutOfBounds {
+            name: "missing".into(),
+            ty: ExportType::Func,
+            index: 1,
+            count: 1,
+        })
+    );
+
+    let result = Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_verify_type_index_out_of_bounds() {
+    let arena = Bump::new();
+    let mut module = module_with_one_function(&arena);
+    module.import.imports.push(Import {
+        module: "env",
+        name: "foo",
+        description: ImportDesc::Func { signature_index: 5 },
+    });
+    // The import shifts the exported function along by one
+    module.export.exports[0].index = 1;
+
+    assert_eq!(
+        verify(&module),
+        Err(ValidationError::TypeIndexOutOfBounds {
+            fn_index: 0,
+            type_index: 5,
+            type_count: 1,
+        })
+    );
+}
+
+#[test]
+fn test_verify_function_count_mismatch() {
+    let arena = Bump::new();
+    let mut module = module_with_one_function(&arena);
+    module.function.add_sig(0);
+
+    assert_eq!(
+        verify(&module),
+        Err(ValidationError::FunctionCountMismatch {
+            signatures: 2,
+            bodies: 1,
+        })
+    );
+}
+
+#[test]
+fn test_verify_module_limits() {
+    let arena = Bump::new();
+    let mut module = WasmModule::new(&arena);
+    let signature = Signature {
+        param_types: bumpalo::vec![in &arena; ValueType::I32],
+        ret_type: None,
+    };
+    let locals = [(3, ValueType::I32), (2, ValueType::I64)];
+    create_exported_function_with_locals(&mut module, "test", signature, &locals, |buf| {
+        buf.push(OpCode::END as u8);
+    });
+    module.memory = MemorySection::new(&arena, 4 * MemorySection::PAGE_SIZE);
+
+    let exact = ModuleLimits {
+        max_functions: 1,
+        max_code_size: module.code.bytes.len(),
+        max_locals: 5,
+        max_memory_pages: 4,
+    };
+    assert_eq!(verify_with_limits(&module, &exact), Ok(()));
+    assert_eq!(verify(&module), Ok(()));
+
+    let too_large = |limits: ModuleLimits| verify_with_limits(&module, &limits).unwrap_err();
+    assert_eq!(
+        too_large(ModuleLimits {
+            max_functions: 0,
+            ..exact.clone()
+        }),
+        ValidationError::ModuleTooLarge {
+            limit: ModuleLimit::Functions,
+            actual: 1,
+            max: 0,
+        }
+    );
+    assert!(matches!(
+        too_large(ModuleLimits {
+            max_code_size: 4,
+            ..exact.clone()
+        }),
+        ValidationError::ModuleTooLarge {
+            limit: ModuleLimit::CodeSize,
+            max: 4,
+            ..
+        }
+    ));
+    // The argument isn't counted as a local
+    let locals_error = too_large(ModuleLimits {
+        max_locals: 4,
+        ..exact.clone()
+    });
+    assert_eq!(
+        locals_error,
+        ValidationError::ModuleTooLarge {
+            limit: ModuleLimit::Locals { fn_index: 0 },
+            actual: 5,
+            max: 4,
+        }
+    );
+    assert_eq!(
+        locals_error.to_string(),
+        "The module has 5 locals in function 0, but the limit is 4."
+    );
+    assert_eq!(
+        too_large(ModuleLimits {
+            max_memory_pages: 3,
+            ..exact.clone()
+        }),
+        ValidationError::ModuleTooLarge {
+            limit: ModuleLimit::MemoryPages,
+            actual: 4,
+            max: 3,
+        }
+    );
+
+    let strict = ModuleLimits {
+        max_locals: 1,
+        ..ModuleLimits::default()
+    };
+    let result = Instance::for_module_with_limits(
+        &arena,
+        &module,
+        &strict,
+        DefaultImportDispatcher::default(),
+        false,
+    );
+    assert!(result.is_err());
+}
@@ -0,0 +1,1178 @@
+use crate::tests::create_exported_function_no_locals;
+use crate::wasi::{
+    self, expand_args_files, WasiClockId, WasiDispatcher, WasiErrno, WasiFile, WasiFileSystem,
+};
+use crate::{DefaultImportDispatcher, Instance, RunOutcome, TrapReason};
+use bumpalo::Bump;
+use roc_wasm_module::{
+    opcodes::OpCode,
+    sections::{Import, ImportDesc},
+    Export, ExportType, SerialBuffer, Serialize, Signature, Value, ValueType, WasmModule,
+};
+use std::path::PathBuf;
+
+const FILESTAT_PTR: usize = 64;
+const PATH_PTR: usize = 256;
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn path_filestat_get(
+    wasi: &mut WasiDispatcher,
+    memory: &mut [u8],
+    fd: usize,
+    path: &str,
+    ptr_buf: usize,
+) -> Option<Value> {
+    memory[PATH_PTR..][..path.len()].copy_from_slice(path.as_bytes());
+    let args = [
+        Value::I32(fd as i32),
+        Value::I32(1), // follow symlinks
+        Value::I32(PATH_PTR as i32),
+        Value::I32(path.len() as i32),
+        Value::I32(ptr_buf as i32),
+    ];
+    wasi.dispatch("path_filestat_get", &args, memory)
+}
+
+fn read_u64(memory: &[u8], addr: usize) -> u64 {
+    u64::from_le_bytes(memory[addr..][..8].try_into().unwrap())
+}
+
+#[test]
+fn test_path_filestat_get() {
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), manifest_dir());
+    let mut memory = vec![0; 1024];
+
+    let result = path_filestat_get(&mut wasi, &mut memory, fd, "src/lib.rs", FILESTAT_PTR);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+
+    let expected_size = std::fs::metadata(manifest_dir().join("src/lib.rs"))
+        .unwrap()
+        .len();
+    assert_eq!(memory[FILESTAT_PTR + 16], 4); // regular file
+    assert_eq!(read_u64(&memory, FILESTAT_PTR + 32), expected_size);
+    assert_ne!(read_u64(&memory, FILESTAT_PTR + 48), 0); // mtim
+}
+
+#[test]
+fn test_path_filestat_get_errors() {
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), manifest_dir().join("src"));
+    let mut memory = vec![0; 1024];
+
+    let outside = path_filestat_get(&mut wasi, &mut memory, fd, "../Cargo.toml", FILESTAT_PTR);
+    assert_eq!(outside, Some(Value::I32(WasiErrno::Notcapable as i32)));
+
+    let absolute = path_filestat_get(&mut wasi, &mut memory, fd, "/etc", FILESTAT_PTR);
+    assert_eq!(absolute, Some(Value::I32(WasiErrno::Notcapable as i32)));
+
+    let missing = path_filestat_get(&mut wasi, &mut memory, fd, "nope.rs", FILESTAT_PTR);
+    assert_eq!(missing, Some(Value::I32(WasiErrno::Noent as i32)));
+
+    let bad_fd = path_filestat_get(&mut wasi, &mut memory, 99, "lib.rs", FILESTAT_PTR);
+    assert_eq!(bad_fd, Some(Value::I32(WasiErrno::Badf as i32)));
+
+    let out_of_bounds = path_filestat_get(&mut wasi, &mut memory, fd, "lib.rs", 1000);
+    assert_eq!(out_of_bounds, Some(Value::I32(WasiErrno::Fault as i32)));
+}
+
+#[test]
+fn test_fd_prestat() {
+    let mut wasi = WasiDispatcher::default();
+    wasi.files.push(WasiFile::ReadOnly(vec![1, 2, 3]));
+    let dir = wasi.preopen_dir("/data".into(), manifest_dir());
+    let mut memory = vec![0; 1024];
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+
+    let prestat = |wasi: &mut WasiDispatcher, memory: &mut [u8], fd: usize| {
+        let args = [Value::I32(fd as i32), Value::I32(FILESTAT_PTR as i32)];
+        wasi.dispatch("fd_prestat_get", &args, memory)
+    };
+    let dir_name = |wasi: &mut WasiDispatcher, memory: &mut [u8], fd: usize| {
+        let args = [
+            Value::I32(fd as i32),
+            Value::I32(PATH_PTR as i32),
+            Value::I32(5),
+        ];
+        wasi.dispatch("fd_prestat_dir_name", &args, memory)
+    };
+
+    assert_eq!(
+        prestat(&mut wasi, &mut memory, dir),
+        errno(WasiErrno::Success)
+    );
+    assert_eq!(memory[FILESTAT_PTR], 0); // directory
+    assert_eq!(read_u64(&memory, FILESTAT_PTR) >> 32, 5);
+    assert_eq!(
+        dir_name(&mut wasi, &mut memory, dir),
+        errno(WasiErrno::Success)
+    );
+    assert_eq!(&memory[PATH_PTR..][..5], b"/data");
+
+    // Open files that aren't preopened directories, like stdout or an in-memory file, have no prestat
+    for fd in [1, 3] {
+        assert_eq!(prestat(&mut wasi, &mut memory, fd), errno(WasiErrno::Badf));
+        assert_eq!(dir_name(&mut wasi, &mut memory, fd), errno(WasiErrno::Badf));
+    }
+}
+
+#[test]
+fn test_fd_filestat_get() {
+    let mut wasi = WasiDispatcher::default();
+    wasi.files.push(WasiFile::ReadOnly(vec![1, 2, 3]));
+    let mut memory = vec![0; 1024];
+
+    let args = [Value::I32(3), Value::I32(FILESTAT_PTR as i32)];
+    let result = wasi.dispatch("fd_filestat_get", &args, &mut memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    assert_eq!(memory[FILESTAT_PTR + 16], 4); // regular file
+    assert_eq!(read_u64(&memory, FILESTAT_PTR + 32), 3);
+
+    let bad_fd = [Value::I32(4), Value::I32(FILESTAT_PTR as i32)];
+    let result = wasi.dispatch("fd_filestat_get", &bad_fd, &mut memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Badf as i32)));
+}
+
+#[test]
+fn test_expand_args_files() {
+    let arena = bumpalo::Bump::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("args.txt");
+    std::fs::write(&path, "one\r\ntwo words\n@not-expanded\n").unwrap();
+
+    let at_path = format!("@{}", path.display());
+    let args: &[&[u8]] = &[b"@app.wasm", b"first", at_path.as_bytes(), b"last"];
+    let expanded = expand_args_files(&arena, args);
+
+    let expected: &[&[u8]] = &[
+        b"@app.wasm",
+        b"first",
+        b"one",
+        b"two words",
+        b"@not-expanded",
+        b"last",
+    ];
+    assert_eq!(expanded, Ok(expected));
+}
+
+#[test]
+fn test_expand_args_files_unreadable() {
+    let arena = bumpalo::Bump::new();
+    let args: &[&[u8]] = &[b"app.wasm", b"@/nonexistent/args.txt"];
+    let message = expand_args_files(&arena, args).unwrap_err();
+    assert!(message.starts_with("I couldn't read the arguments file /nonexistent/args.txt"));
+}
+
+fn path_op(wasi: &mut WasiDispatcher, name: &str, fd: usize, path: &str) -> Option<Value> {
+    let mut memory = vec![0; 1024];
+    memory[PATH_PTR..][..path.len()].copy_from_slice(path.as_bytes());
+    let args = [
+        Value::I32(fd as i32),
+        Value::I32(PATH_PTR as i32),
+        Value::I32(path.len() as i32),
+    ];
+    wasi.dispatch(name, &args, &mut memory)
+}
+
+#[test]
+fn test_path_mutations() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sandbox = temp_dir.path();
+
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), sandbox.to_path_buf());
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+
+    let create = path_op(&mut wasi, "path_create_directory", fd, "sub");
+    let create_again = path_op(&mut wasi, "path_create_directory", fd, "sub");
+    std::fs::write(sandbox.join("sub/file.txt"), "hi").unwrap();
+    let remove_full = path_op(&mut wasi, "path_remove_directory", fd, "sub");
+    let unlink_dir = path_op(&mut wasi, "path_unlink_file", fd, "sub");
+    let remove_file = path_op(&mut wasi, "path_remove_directory", fd, "sub/file.txt");
+    let unlink = path_op(&mut wasi, "path_unlink_file", fd, "sub/file.txt");
+    let unlink_again = path_op(&mut wasi, "path_unlink_file", fd, "sub/file.txt");
+    let escape = path_op(&mut wasi, "path_remove_directory", fd, "sub/../..");
+    let remove_preopen = path_op(&mut wasi, "path_remove_directory", fd, ".");
+    let remove = path_op(&mut wasi, "path_remove_directory", fd, "sub");
+
+    wasi.read_only = true;
+    let read_only = path_op(&mut wasi, "path_create_directory", fd, "other");
+
+    let leftover = std::fs::read_dir(sandbox).unwrap().count();
+
+    assert_eq!(create, errno(WasiErrno::Success));
+    assert_eq!(create_again, errno(WasiErrno::Exist));
+    assert_eq!(remove_full, errno(WasiErrno::Notempty));
+    assert_eq!(unlink_dir, errno(WasiErrno::Isdir));
+    assert_eq!(remove_file, errno(WasiErrno::Notdir));
+    assert_eq!(unlink, errno(WasiErrno::Success));
+    assert_eq!(unlink_again, errno(WasiErrno::Noent));
+    assert_eq!(escape, errno(WasiErrno::Notcapable));
+    assert_eq!(remove_preopen, errno(WasiErrno::Inval));
+    assert_eq!(remove, errno(WasiErrno::Success));
+    assert_eq!(read_only, errno(WasiErrno::Rofs));
+    assert_eq!(leftover, 0);
+}
+
+/// Write two paths into memory for `path_rename` or `path_symlink`, and return their (pointer, length) arguments
+fn two_paths(memory: &mut [u8], first: &str, second: &str) -> [[Value; 2]; 2] {
+    let second_ptr = PATH_PTR + first.len();
+    memory[PATH_PTR..][..first.len()].copy_from_slice(first.as_bytes());
+    memory[second_ptr..][..second.len()].copy_from_slice(second.as_bytes());
+    [
+        [Value::I32(PATH_PTR as i32), Value::I32(first.len() as i32)],
+        [
+            Value::I32(second_ptr as i32),
+            Value::I32(second.len() as i32),
+        ],
+    ]
+}
+
+fn rename_op(
+    wasi: &mut WasiDispatcher,
+    old_fd: usize,
+    old_path: &str,
+    new_fd: usize,
+    new_path: &str,
+) -> Option<Value> {
+    let mut memory = vec![0; 1024];
+    let [old, new] = two_paths(&mut memory, old_path, new_path);
+    let args = [
+        Value::I32(old_fd as i32),
+        old[0],
+        old[1],
+        Value::I32(new_fd as i32),
+        new[0],
+        new[1],
+    ];
+    wasi.dispatch("path_rename", &args, &mut memory)
+}
+
+fn symlink_op(
+    wasi: &mut WasiDispatcher,
+    target: &str,
+    fd: usize,
+    link_path: &str,
+) -> Option<Value> {
+    let mut memory = vec![0; 1024];
+    let [target, link] = two_paths(&mut memory, target, link_path);
+    let args = [
+        target[0],
+        target[1],
+        Value::I32(fd as i32),
+        link[0],
+        link[1],
+    ];
+    wasi.dispatch("path_symlink", &args, &mut memory)
+}
+
+#[test]
+fn test_path_rename() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sandbox = temp_dir.path();
+    std::fs::create_dir_all(sandbox.join("a/full")).unwrap();
+    std::fs::create_dir_all(sandbox.join("b/empty")).unwrap();
+    std::fs::write(sandbox.join("a/file.txt"), "hi").unwrap();
+    std::fs::write(sandbox.join("a/full/x"), "").unwrap();
+
+    let mut wasi = WasiDispatcher::default();
+    let a = wasi.preopen_dir("/a".into(), sandbox.join("a"));
+    let b = wasi.preopen_dir("/b".into(), sandbox.join("b"));
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+
+    // Files and directories can move between preopened directories
+    assert_eq!(
+        rename_op(&mut wasi, a, "file.txt", b, "moved.txt"),
+        errno(WasiErrno::Success)
+    );
+    assert_eq!(
+        std::fs::read_to_string(sandbox.join("b/moved.txt")).unwrap(),
+        "hi"
+    );
+    assert_eq!(
+        rename_op(&mut wasi, a, "file.txt", b, "again.txt"),
+        errno(WasiErrno::Noent)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, b, "moved.txt", b, "empty"),
+        errno(WasiErrno::Isdir)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, a, "full", b, "moved.txt"),
+        errno(WasiErrno::Notdir)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, b, "empty", a, "full"),
+        errno(WasiErrno::Notempty)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, a, "full", a, "full/inner"),
+        errno(WasiErrno::Inval)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, a, "full", b, "full"),
+        errno(WasiErrno::Success)
+    );
+    assert!(sandbox.join("b/full/x").is_file());
+
+    // Both paths have to stay inside their preopened directories
+    assert_eq!(
+        rename_op(&mut wasi, b, "full", a, "../c"),
+        errno(WasiErrno::Notcapable)
+    );
+    assert_eq!(
+        rename_op(&mut wasi, b, "../a", b, "c"),
+        errno(WasiErrno::Notcapable)
+    );
+    assert_eq!(rename_op(&mut wasi, b, ".", a, "b"), errno(WasiErrno::Busy));
+
+    wasi.read_only = true;
+    let read_only = rename_op(&mut wasi, b, "full", a, "full");
+
+    assert_eq!(read_only, errno(WasiErrno::Rofs));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_path_symlink() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sandbox = temp_dir.path();
+    std::fs::create_dir_all(sandbox.join("sub")).unwrap();
+    std::fs::write(sandbox.join("file.txt"), "hi").unwrap();
+
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), sandbox.to_path_buf());
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+
+    assert_eq!(
+        symlink_op(&mut wasi, "../file.txt", fd, "sub/link"),
+        errno(WasiErrno::Success)
+    );
+    let link = sandbox.join("sub/link");
+    assert_eq!(
+        std::fs::read_link(&link).unwrap(),
+        PathBuf::from("../file.txt")
+    );
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "hi");
+    assert_eq!(
+        symlink_op(&mut wasi, "file.txt", fd, "sub/link"),
+        errno(WasiErrno::Exist)
+    );
+
+    // Neither the link nor where it leads can be outside the preopened directory
+    assert_eq!(
+        symlink_op(&mut wasi, "file.txt", fd, "../link"),
+        errno(WasiErrno::Notcapable)
+    );
+    assert_eq!(
+        symlink_op(&mut wasi, "../../etc", fd, "sub/up"),
+        errno(WasiErrno::Notcapable)
+    );
+    assert_eq!(
+        symlink_op(&mut wasi, "/etc/passwd", fd, "abs"),
+        errno(WasiErrno::Notcapable)
+    );
+
+    wasi.allow_symlinks = false;
+    let disallowed = symlink_op(&mut wasi, "file.txt", fd, "other");
+
+    let leftover = std::fs::read_dir(sandbox).unwrap().count();
+    assert_eq!(disallowed, errno(WasiErrno::Perm));
+    assert_eq!(leftover, 2);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_path_symlink_through_a_link_to_parent() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sandbox = temp_dir.path();
+    std::fs::create_dir_all(sandbox.join("preopen/d")).unwrap();
+    std::fs::write(sandbox.join("secret.txt"), "outside").unwrap();
+
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), sandbox.join("preopen"));
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+
+    // `d/up` leads to the preopened directory itself, which is fine
+    let up = symlink_op(&mut wasi, "..", fd, "d/up");
+    // By its text, `d/up/..` is `d`, but on the host it's the directory above the preopened one
+    let escape = symlink_op(&mut wasi, "..", fd, "d/up/esc");
+    let escape_deeper = symlink_op(&mut wasi, "up/../..", fd, "d/esc");
+    let created_outside = std::fs::symlink_metadata(sandbox.join("preopen/esc")).is_ok()
+        || std::fs::symlink_metadata(sandbox.join("preopen/d/esc")).is_ok();
+
+    // Links that already lead outside can't be used as paths either
+    std::os::unix::fs::symlink("..", sandbox.join("preopen/d/up/planted")).unwrap();
+    let mut memory = vec![0; 1024];
+    let path = "planted/secret.txt";
+    memory[PATH_PTR..][..path.len()].copy_from_slice(path.as_bytes());
+    let args = [
+        Value::I32(fd as i32),
+        Value::I32(PATH_PTR as i32),
+        Value::I32(path.len() as i32),
+    ];
+    let unlink_outside = wasi.dispatch("path_unlink_file", &args, &mut memory);
+    let secret_kept = sandbox.join("secret.txt").is_file();
+
+    assert_eq!(up, errno(WasiErrno::Success));
+    assert_eq!(escape, errno(WasiErrno::Notcapable));
+    assert_eq!(escape_deeper, errno(WasiErrno::Notcapable));
+    assert!(!created_outside);
+    assert_eq!(unlink_outside, errno(WasiErrno::Notcapable));
+    assert!(secret_kept);
+}
+
+/// Call `path_readlink`, returning the errno and what it wrote to the buffer
+fn readlink_op(
+    wasi: &mut WasiDispatcher,
+    fd: usize,
+    link_path: &str,
+    buf_len: usize,
+) -> (Option<Value>, String) {
+    const BUF_PTR: usize = 512;
+    const NREAD_PTR: usize = 1020;
+    let mut memory = vec![0; 1024];
+    memory[PATH_PTR..][..link_path.len()].copy_from_slice(link_path.as_bytes());
+    let args = [
+        Value::I32(fd as i32),
+        Value::I32(PATH_PTR as i32),
+        Value::I32(link_path.len() as i32),
+        Value::I32(BUF_PTR as i32),
+        Value::I32(buf_len as i32),
+        Value::I32(NREAD_PTR as i32),
+    ];
+    let result = wasi.dispatch("path_readlink", &args, &mut memory);
+    let n_read = u32::from_le_bytes(memory[NREAD_PTR..][..4].try_into().unwrap()) as usize;
+    let contents = String::from_utf8(memory[BUF_PTR..][..n_read].to_vec()).unwrap();
+    (result, contents)
+}
+
+#[test]
+fn test_path_readlink() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sandbox = temp_dir.path();
+    std::fs::create_dir_all(sandbox.join("sub")).unwrap();
+    std::fs::write(sandbox.join("file.txt"), "hi").unwrap();
+
+    let mut wasi = WasiDispatcher::default();
+    let fd = wasi.preopen_dir(".".into(), sandbox.to_path_buf());
+    let errno = |e: WasiErrno| Some(Value::I32(e as i32));
+    symlink_op(&mut wasi, "../file.txt", fd, "sub/link");
+
+    let full = readlink_op(&mut wasi, fd, "sub/link", 100);
+    let truncated = readlink_op(&mut wasi, fd, "sub/link", 5);
+    let not_a_link = readlink_op(&mut wasi, fd, "file.txt", 100);
+    let missing = readlink_op(&mut wasi, fd, "sub/missing", 100);
+    let outside = readlink_op(&mut wasi, fd, "../link", 100);
+    let past_the_end = readlink_op(&mut wasi, fd, "sub/link", 1000);
+
+    assert_eq!(full, (errno(WasiErrno::Success), "../file.txt".into()));
+    assert_eq!(truncated, (errno(WasiErrno::Success), "../fi".into()));
+    assert_eq!(not_a_link.0, errno(WasiErrno::Inval));
+    assert_eq!(missing.0, errno(WasiErrno::Noent));
+    assert_eq!(outside.0, errno(WasiErrno::Notcapable));
+    assert_eq!(past_the_end.0, errno(WasiErrno::Fault));
+}
+
+#[test]
+fn test_sockets_not_supported() {
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    let args = [Value::I32(3), Value::I32(0), Value::I32(0)];
+    for name in ["sock_accept", "sock_recv", "sock_send", "sock_shutdown"] {
+        let result = wasi.dispatch(name, &args, &mut memory);
+        assert_eq!(result, Some(Value::I32(WasiErrno::Notsup as i32)), "{name}");
+    }
+}
+
+#[test]
+fn test_unimplemented_functions() {
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    for name in ["fd_seek", "poll_oneoff", "not_a_wasi_function"] {
+        let result = wasi.dispatch(name, &[], &mut memory);
+        assert_eq!(result, Some(Value::I32(WasiErrno::Nosys as i32)), "{name}");
+    }
+}
+
+/// Build a module whose `_start` calls `proc_exit(7)` and then traps if it keeps going
+fn build_exit_module(arena: &Bump) -> WasmModule<'_> {
+    let mut module = WasmModule::new(arena);
+
+    module.types.insert(Signature {
+        param_types: bumpalo::vec![in arena; ValueType::I32],
+        ret_type: None,
+    });
+    module.import.imports.push(Import {
+        module: wasi::MODULE_NAME,
+        name: "proc_exit",
+        description: ImportDesc::Func { signature_index: 0 },
+    });
+
+    module.code.function_count = 1;
+    let func_offset = module.code.bytes.len() as u32;
+    module.code.function_offsets.push(func_offset);
+    module.add_function_signature(Signature {
+        param_types: bumpalo::vec![in arena],
+        ret_type: None,
+    });
+    module.export.append(Export {
+        name: "_start",
+        ty: ExportType::Func,
+        index: 1,
+    });
+
+    let body = bumpalo::vec![in arena;
+        0, // no locals
+        OpCode::I32CONST as u8, 7,
+        OpCode::CALL as u8, 0,
+        OpCode::UNREACHABLE as u8,
+        OpCode::END as u8,
+    ];
+    body.serialize(&mut module.code.bytes);
+
+    module
+}
+
+#[test]
+fn test_proc_exit_stops_the_instance() {
+    let arena = Bump::new();
+    let module = build_exit_module(&arena);
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(inst.exit_code(), None);
+
+    assert_eq!(inst.call_export("_start", []), Ok(None));
+    assert_eq!(inst.exit_code(), Some(7));
+    assert_eq!(inst.value_stack_depth(), 0);
+}
+
+#[test]
+fn test_unknown_import_traps() {
+    let arena = Bump::new();
+    let mut module = build_exit_module(&arena);
+    module.import.imports[0].module = "env";
+
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    let message = inst.call_export("_start", []).unwrap_err();
+
+    assert!(message.contains("env.proc_exit"), "{message}");
+    assert_eq!(inst.exit_code(), None);
+}
+
+#[test]
+fn test_run_outcomes() {
+    let arena = Bump::new();
+    let build_module = |import_module| {
+        let mut module = build_exit_module(&arena);
+        module.import.imports[0].module = import_module;
+        let no_params = || Signature {
+            param_types: bumpalo::vec![in &arena],
+            ret_type: Some(ValueType::I32),
+        };
+        create_exported_function_no_locals(&mut module, "answer", no_params(), |buf| {
+            buf.push(OpCode::I32CONST as u8);
+            buf.encode_i32(42);
+            buf.push(OpCode::END as u8);
+        });
+        create_exported_function_no_locals(&mut module, "crash", no_params(), |buf| {
+            buf.push(OpCode::UNREACHABLE as u8);
+            buf.push(OpCode::END as u8);
+        });
+        module
+    };
+
+    let module = build_module(wasi::MODULE_NAME);
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(
+        inst.run("answer", []),
+        RunOutcome::Completed(vec![Value::I32(42)])
+    );
+    assert!(
+        matches!(inst.run("crash", []), RunOutcome::Failed(message) if message.contains("unreachable"))
+    );
+    assert_eq!(inst.run("_start", []), RunOutcome::Exited(7));
+
+    // The import is missing if it's in another module
+    let module = build_module("env");
+    let mut inst =
+        Instance::for_module(&arena, &module, DefaultImportDispatcher::default(), false).unwrap();
+    assert_eq!(
+        inst.run("_start", []),
+        RunOutcome::Trapped(TrapReason::UnknownImport {
+            module_name: "env".into(),
+            function_name: "proc_exit".into(),
+        })
+    );
+    // A later run that returns normally isn't mistaken for the earlier trap
+    assert_eq!(
+        inst.run("answer", []),
+        RunOutcome::Completed(vec![Value::I32(42)])
+    );
+}
+
+fn fd_write(wasi: &mut WasiDispatcher, fd: usize, text: &str) -> Option<Value> {
+    const IOV_PTR: usize = 0;
+    const NWRITTEN_PTR: usize = 8;
+    const TEXT_PTR: usize = 16;
+    let mut memory = vec![0; 64];
+    memory[IOV_PTR..][..4].copy_from_slice(&(TEXT_PTR as u32).to_le_bytes());
+    memory[IOV_PTR + 4..][..4].copy_from_slice(&(text.len() as u32).to_le_bytes());
+    memory[TEXT_PTR..][..text.len()].copy_from_slice(text.as_bytes());
+    let args = [
+        Value::I32(fd as i32),
+        Value::I32(IOV_PTR as i32),
+        Value::I32(1),
+        Value::I32(NWRITTEN_PTR as i32),
+    ];
+    wasi.dispatch("fd_write", &args, &mut memory)
+}
+
+#[test]
+fn test_fd_write_buffering() {
+    use crate::wasi::WriteBuffering;
+
+    let success = Some(Value::I32(WasiErrno::Success as i32));
+    let mut wasi = WasiDispatcher::default();
+
+    // stderr is unbuffered by default
+    assert_eq!(fd_write(&mut wasi, 2, ""), success);
+    assert_eq!(wasi.pending_output(2), b"");
+
+    // In line mode, a partial line is held back until there's a newline
+    wasi.set_write_buffering(2, WriteBuffering::Line);
+    assert_eq!(fd_write(&mut wasi, 2, "partial"), success);
+    assert_eq!(wasi.pending_output(2), b"partial");
+    assert_eq!(fd_write(&mut wasi, 2, " line\nnext"), success);
+    assert_eq!(wasi.pending_output(2), b"next");
+
+    wasi.flush().unwrap();
+    assert_eq!(wasi.pending_output(2), b"");
+}
+
+#[test]
+fn test_random_get() {
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    let args = [Value::I32(16), Value::I32(32)];
+    let result = wasi.dispatch("random_get", &args, &mut memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    assert!(memory[..16].iter().all(|b| *b == 0));
+    assert!(memory[48..].iter().all(|b| *b == 0));
+
+    let out_of_bounds = [Value::I32(48), Value::I32(32)];
+    let result = wasi.dispatch("random_get", &out_of_bounds, &mut memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Fault as i32)));
+}
+
+#[test]
+fn test_random_get_fallback() {
+    let os_unavailable = |_: &mut [u8]| Err(rand::Error::new("blocked by the sandbox"));
+
+    let mut wasi = WasiDispatcher::default();
+    let mut buf = [0; 32];
+    assert!(wasi.fill_random(&mut buf, os_unavailable) == WasiErrno::Success);
+    assert!(buf.iter().any(|b| *b != 0));
+
+    wasi.strict_random = true;
+    let mut buf = [0; 32];
+    assert!(wasi.fill_random(&mut buf, os_unavailable) == WasiErrno::Io);
+    assert_eq!(buf, [0; 32]);
+}
+
+#[test]
+fn test_fd_sync() {
+    use crate::wasi::WriteBuffering;
+
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    let file_fd = wasi.files.len();
+    wasi.files.push(WasiFile::WriteOnly(vec![]));
+    let dir_fd = wasi.preopen_dir(".".into(), manifest_dir());
+
+    for name in ["fd_sync", "fd_datasync"] {
+        let mut sync = |fd: usize| wasi.dispatch(name, &[Value::I32(fd as i32)], &mut memory);
+        assert_eq!(
+            sync(0),
+            Some(Value::I32(WasiErrno::Success as i32)),
+            "{name}"
+        );
+        assert_eq!(
+            sync(file_fd),
+            Some(Value::I32(WasiErrno::Success as i32)),
+            "{name}"
+        );
+        assert_eq!(
+            sync(dir_fd),
+            Some(Value::I32(WasiErrno::Badf as i32)),
+            "{name}"
+        );
+        assert_eq!(sync(99), Some(Value::I32(WasiErrno::Badf as i32)), "{name}");
+    }
+
+    // Syncing stderr writes out a partial line that is being held back
+    wasi.set_write_buffering(2, WriteBuffering::Line);
+    fd_write(&mut wasi, 2, "partial");
+    assert_eq!(wasi.pending_output(2), b"partial");
+    let result = wasi.dispatch("fd_sync", &[Value::I32(2)], &mut memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    assert_eq!(wasi.pending_output(2), b"");
+}
+
+#[test]
+fn test_fd_advise_and_allocate() {
+    let mut wasi = WasiDispatcher::default();
+    wasi.output_limit = 16;
+    let mut memory = vec![0; 64];
+    let read_fd = wasi.files.len();
+    wasi.files.push(WasiFile::ReadOnly(vec![1, 2, 3]));
+    let write_fd = wasi.files.len();
+    wasi.files.push(WasiFile::WriteOnly(vec![1, 2, 3]));
+    let dir_fd = wasi.preopen_dir(".".into(), manifest_dir());
+
+    let mut advise = |fd: usize, advice: i32| {
+        let args = [
+            Value::I32(fd as i32),
+            Value::I64(0),
+            Value::I64(3),
+            Value::I32(advice),
+        ];
+        wasi.dispatch("fd_advise", &args, &mut memory)
+    };
+    assert_eq!(
+        advise(read_fd, 2),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert_eq!(
+        advise(write_fd, 4),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert_eq!(
+        advise(read_fd, 6),
+        Some(Value::I32(WasiErrno::Inval as i32))
+    );
+    assert_eq!(advise(0, 0), Some(Value::I32(WasiErrno::Spipe as i32)));
+    assert_eq!(advise(dir_fd, 0), Some(Value::I32(WasiErrno::Badf as i32)));
+    assert_eq!(advise(99, 0), Some(Value::I32(WasiErrno::Badf as i32)));
+
+    let mut allocate = |wasi: &mut WasiDispatcher, fd: usize, offset: i64, len: i64| {
+        let args = [Value::I32(fd as i32), Value::I64(offset), Value::I64(len)];
+        wasi.dispatch("fd_allocate", &args, &mut memory)
+    };
+    // Files grow with zeros, but never shrink
+    assert_eq!(
+        allocate(&mut wasi, write_fd, 2, 4),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert!(
+        matches!(&wasi.files[write_fd], WasiFile::WriteOnly(content) if content == &[1, 2, 3, 0, 0, 0])
+    );
+    assert_eq!(
+        allocate(&mut wasi, write_fd, 0, 1),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert!(matches!(&wasi.files[write_fd], WasiFile::WriteOnly(content) if content.len() == 6));
+
+    assert_eq!(
+        allocate(&mut wasi, write_fd, 8, 9),
+        Some(Value::I32(WasiErrno::Fbig as i32))
+    );
+    assert_eq!(
+        allocate(&mut wasi, write_fd, -1, 1),
+        Some(Value::I32(WasiErrno::Fbig as i32))
+    );
+    for fd in [read_fd, 1, dir_fd, 99] {
+        assert_eq!(
+            allocate(&mut wasi, fd, 0, 1),
+            Some(Value::I32(WasiErrno::Badf as i32)),
+            "{fd}"
+        );
+    }
+}
+
+#[test]
+fn test_fd_renumber() {
+    use crate::wasi::WriteBuffering;
+
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    let file_fd = wasi.files.len();
+    wasi.files.push(WasiFile::WriteOnly(vec![]));
+    let mut renumber = |wasi: &mut WasiDispatcher, from: usize, to: usize| {
+        let args = [Value::I32(from as i32), Value::I32(to as i32)];
+        wasi.dispatch("fd_renumber", &args, &mut memory)
+    };
+
+    assert_eq!(
+        renumber(&mut wasi, 99, 1),
+        Some(Value::I32(WasiErrno::Badf as i32))
+    );
+    assert_eq!(
+        renumber(&mut wasi, 1, 99),
+        Some(Value::I32(WasiErrno::Badf as i32))
+    );
+
+    // Redirect stdout into the file. Output held back for the old stdout is written out.
+    wasi.set_write_buffering(1, WriteBuffering::Line);
+    fd_write(&mut wasi, 1, "partial");
+    let result = renumber(&mut wasi, file_fd, 1);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    assert_eq!(wasi.pending_output(1), b"");
+    assert_eq!(
+        fd_write(&mut wasi, 1, "hi"),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert!(matches!(&wasi.files[1], WasiFile::WriteOnly(content) if content == b"hi"));
+
+    // The source is closed afterwards
+    assert_eq!(
+        fd_write(&mut wasi, file_fd, "x"),
+        Some(Value::I32(WasiErrno::Badf as i32))
+    );
+    assert_eq!(
+        renumber(&mut wasi, file_fd, 1),
+        Some(Value::I32(WasiErrno::Badf as i32))
+    );
+
+    // The host's stderr still works from its new fd
+    let new_stderr = wasi.files.len();
+    wasi.files.push(WasiFile::ReadOnly(vec![]));
+    let result = renumber(&mut wasi, 2, new_stderr);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    wasi.set_write_buffering(2, WriteBuffering::Line);
+    fd_write(&mut wasi, new_stderr, "partial");
+    assert_eq!(wasi.pending_output(2), b"partial");
+    wasi.flush().unwrap();
+}
+
+#[test]
+fn test_output_limit() {
+    let mut wasi = WasiDispatcher::default();
+    wasi.output_limit = 4;
+    let fd = wasi.files.len();
+    wasi.files.push(WasiFile::WriteOnly(vec![]));
+
+    // Output that fits is written as usual
+    assert_eq!(
+        fd_write(&mut wasi, fd, "abc"),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert_eq!(wasi.take_trap(), None);
+
+    // Going over the limit keeps the output up to it, then traps
+    assert_eq!(
+        fd_write(&mut wasi, fd, "def"),
+        Some(Value::I32(WasiErrno::Fbig as i32))
+    );
+    assert!(matches!(&wasi.files[fd], WasiFile::WriteOnly(content) if content == b"abcd"));
+    assert_eq!(
+        wasi.take_trap(),
+        Some(TrapReason::OutputLimitExceeded { fd, limit: 4 })
+    );
+    assert_eq!(wasi.take_trap(), None);
+
+    // Or the excess can be dropped without the program noticing
+    wasi.drop_excess_output = true;
+    assert_eq!(
+        fd_write(&mut wasi, fd, "ghi"),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert!(matches!(&wasi.files[fd], WasiFile::WriteOnly(content) if content == b"abcd"));
+    assert_eq!(wasi.take_trap(), None);
+}
+
+#[test]
+fn test_file_system_is_shared_between_runs() {
+    let mut file_system = WasiFileSystem::default();
+    let data_fd = file_system.add_file(WasiFile::ReadWrite(vec![]));
+    let dir_fd = file_system.add_file(WasiFile::PreopenDir {
+        guest_path: "/data".into(),
+        host_path: manifest_dir(),
+    });
+
+    // The first run writes to the in-memory file
+    let mut first = WasiDispatcher::default().with_file_system(file_system);
+    assert_eq!(
+        fd_write(&mut first, data_fd, "from the first run"),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    let file_system = first.take_file_system();
+    assert_eq!(first.files.len(), 3);
+    assert_eq!(
+        file_system.file_contents(data_fd),
+        Some(&b"from the first run"[..])
+    );
+    assert_eq!(file_system.file_contents(dir_fd), None);
+
+    // The second run reads what the first one wrote, and has the same preopens
+    let mut second = WasiDispatcher::default().with_file_system(file_system);
+    const IOV_PTR: usize = 0;
+    const NREAD_PTR: usize = 8;
+    const BUF_PTR: usize = 16;
+    let mut memory = vec![0; 64];
+    memory[IOV_PTR..][..4].copy_from_slice(&(BUF_PTR as u32).to_le_bytes());
+    memory[IOV_PTR + 4..][..4].copy_from_slice(&32u32.to_le_bytes());
+    let args = [
+        Value::I32(data_fd as i32),
+        Value::I32(IOV_PTR as i32),
+        Value::I32(1),
+        Value::I32(NREAD_PTR as i32),
+    ];
+    assert_eq!(
+        second.dispatch("fd_read", &args, &mut memory),
+        Some(Value::I32(WasiErrno::Success as i32))
+    );
+    assert_eq!(memory[NREAD_PTR..][..4], 18u32.to_le_bytes());
+    assert_eq!(&memory[BUF_PTR..][..18], b"from the first run");
+    assert!(matches!(
+        &second.files[dir_fd],
+        WasiFile::PreopenDir { guest_path, .. } if guest_path == "/data"
+    ));
+}
+
+#[test]
+fn test_path_mapper() {
+    use std::path::Path;
+
+    let mut wasi = WasiDispatcher::default();
+    let data_fd = wasi.preopen_dir("/data".into(), manifest_dir().join("src/tests"));
+    wasi.preopen_dir("/scratch".into(), manifest_dir().join("src"));
+    let mut memory = vec![0; 1024];
+
+    // src/tests has no lib.rs
+    let unmapped = path_filestat_get(&mut wasi, &mut memory, data_fd, "lib.rs", FILESTAT_PTR);
+    assert_eq!(unmapped, Some(Value::I32(WasiErrno::Noent as i32)));
+
+    wasi.set_path_mapper(Box::new(|guest_path: &Path| {
+        if guest_path.ends_with("secret") {
+            return Err(WasiErrno::Access);
+        }
+        match guest_path.strip_prefix("/data") {
+            Ok(rest) => Ok(Path::new("/scratch").join(rest)),
+            Err(_) => Ok(guest_path.to_path_buf()),
+        }
+    }));
+
+    // Reads from /data are redirected to the directory preopened as /scratch
+    let mapped = path_filestat_get(&mut wasi, &mut memory, data_fd, "lib.rs", FILESTAT_PTR);
+    assert_eq!(mapped, Some(Value::I32(WasiErrno::Success as i32)));
+    let expected_size = std::fs::metadata(manifest_dir().join("src/lib.rs"))
+        .unwrap()
+        .len();
+    assert_eq!(read_u64(&memory, FILESTAT_PTR + 32), expected_size);
+
+    let denied = path_filestat_get(&mut wasi, &mut memory, data_fd, "secret", FILESTAT_PTR);
+    assert_eq!(denied, Some(Value::I32(WasiErrno::Access as i32)));
+
+    // The mapped path still can't escape the preopened directories
+    let outside = path_filestat_get(&mut wasi, &mut memory, data_fd, "../..", FILESTAT_PTR);
+    assert_eq!(outside, Some(Value::I32(WasiErrno::Notcapable as i32)));
+}
+
+/// Call `args_get` or `environ_get` after its `_sizes_get`, and return the strings it wrote
+fn get_strings(wasi: &mut WasiDispatcher, memory: &mut [u8], name: &str) -> Vec<String> {
+    let sizes = [Value::I32(0), Value::I32(4)];
+    let result = wasi.dispatch(&format!("{name}_sizes_get"), &sizes, memory);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    let count = u32::from_le_bytes(memory[0..4].try_into().unwrap()) as usize;
+    let buf_size = u32::from_le_bytes(memory[4..8].try_into().unwrap()) as usize;
+
+    // Fill the buffer so we can check that exactly `buf_size` bytes are written
+    let (ptrs, buf) = (16, 256);
+    memory[buf..][..buf_size + 1].fill(0xff);
+    let result = wasi.dispatch(
+        &format!("{name}_get"),
+        &[Value::I32(ptrs as i32), Value::I32(buf as i32)],
+        memory,
+    );
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+    assert_eq!(memory[buf + buf_size - 1], 0);
+    assert_eq!(memory[buf + buf_size], 0xff);
+
+    (0..count)
+        .map(|i| {
+            let ptr = u32::from_le_bytes(memory[ptrs + 4 * i..][..4].try_into().unwrap());
+            let bytes = &memory[ptr as usize..];
+            let len = bytes.iter().position(|b| *b == 0).unwrap();
+            String::from_utf8(bytes[..len].to_vec()).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_args_and_environ() {
+    let args: &[&[u8]] = &[b"app.wasm", b"", b"hello"];
+    let mut wasi = WasiDispatcher::new(args).with_env([("A", "1"), ("EMPTY", "")]);
+    let mut memory = vec![0; 1024];
+
+    assert_eq!(
+        get_strings(&mut wasi, &mut memory, "args"),
+        ["app.wasm", "", "hello"]
+    );
+    assert_eq!(
+        get_strings(&mut wasi, &mut memory, "environ"),
+        ["A=1", "EMPTY="]
+    );
+
+    let mut no_env = WasiDispatcher::default();
+    assert!(get_strings(&mut no_env, &mut memory, "environ").is_empty());
+}
+
+#[test]
+fn test_environ_is_a_snapshot() {
+    const VAR: &str = "ROC_WASM_INTERP_TEST_ENVIRON";
+    std::env::set_var(VAR, "before");
+    let mut wasi = WasiDispatcher::default().with_env(std::env::vars().filter(|(k, _)| k == VAR));
+    let mut memory = vec![0; 1024];
+
+    // Changing the host's environment mid-run doesn't change what the program sees
+    std::env::set_var(VAR, "after, which is longer");
+    assert_eq!(
+        get_strings(&mut wasi, &mut memory, "environ"),
+        [format!("{VAR}=before")]
+    );
+    std::env::remove_var(VAR);
+}
+
+#[test]
+fn test_dispatch_without_memory() {
+    let mut wasi = WasiDispatcher::default();
+
+    // Every pointer is out of bounds, so calls that take them fail cleanly
+    let args = [Value::I32(1), Value::I32(0), Value::I32(1), Value::I32(0)];
+    let result = wasi.dispatch("fd_write", &args, &mut []);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Fault as i32)));
+
+    let result = wasi.dispatch("fd_renumber", &[Value::I32(1), Value::I32(2)], &mut []);
+    assert_eq!(result, Some(Value::I32(WasiErrno::Success as i32)));
+}
+
+#[test]
+fn test_wasi_constants_match_the_spec() {
+    let clock_ids = [
+        (WasiClockId::Realtime, 0),
+        (WasiClockId::Monotonic, 1),
+        (WasiClockId::ProcessCputimeId, 2),
+        (WasiClockId::ThreadCputimeId, 3),
+    ];
+    for (clock_id, value) in clock_ids {
+        assert_eq!(i32::from(clock_id), value);
+        assert_eq!(WasiClockId::try_from(value), Ok(clock_id));
+    }
+    assert_eq!(WasiClockId::try_from(4), Err(WasiErrno::Inval));
+
+    let errnos = [
+        (WasiErrno::Success, 0),
+        (WasiErrno::Toobig, 1),
+        (WasiErrno::Access, 2),
+        (WasiErrno::Addrinuse, 3),
+        (WasiErrno::Addrnotavail, 4),
+        (WasiErrno::Afnosupport, 5),
+        (WasiErrno::Again, 6),
+        (WasiErrno::Already, 7),
+        (WasiErrno::Badf, 8),
+        (WasiErrno::Badmsg, 9),
+        (WasiErrno::Busy, 10),
+        (WasiErrno::Canceled, 11),
+        (WasiErrno::Child, 12),
+        (WasiErrno::Connaborted, 13),
+        (WasiErrno::Connrefused, 14),
+        (WasiErrno::Connreset, 15),
+        (WasiErrno::Deadlk, 16),
+        (WasiErrno::Destaddrreq, 17),
+        (WasiErrno::Dom, 18),
+        (WasiErrno::Dquot, 19),
+        (WasiErrno::Exist, 20),
+        (WasiErrno::Fault, 21),
+        (WasiErrno::Fbig, 22),
+        (WasiErrno::Hostunreach, 23),
+        (WasiErrno::Idrm, 24),
+        (WasiErrno::Ilseq, 25),
+        (WasiErrno::Inprogress, 26),
+        (WasiErrno::Intr, 27),
+        (WasiErrno::Inval, 28),
+        (WasiErrno::Io, 29),
+        (WasiErrno::Isconn, 30),
+        (WasiErrno::Isdir, 31),
+        (WasiErrno::Loop, 32),
+        (WasiErrno::Mfile, 33),
+        (WasiErrno::Mlink, 34),
+        (WasiErrno::Msgsize, 35),
+        (WasiErrno::Multihop, 36),
+        (WasiErrno::Nametoolong, 37),
+        (WasiErrno::Netdown, 38),
+        (WasiErrno::Netreset, 39),
+        (WasiErrno::Netunreach, 40),
+        (WasiErrno::Nfile, 41),
+        (WasiErrno::Nobufs, 42),
+        (WasiErrno::Nodev, 43),
+        (WasiErrno::Noent, 44),
+        (WasiErrno::Noexec, 45),
+        (WasiErrno::Nolck, 46),
+        (WasiErrno::Nolink, 47),
+        (WasiErrno::Nomem, 48),
+        (WasiErrno::Nomsg, 49),
+        (WasiErrno::Noprotoopt, 50),
+        (WasiErrno::Nospc, 51),
+        (WasiErrno::Nosys, 52),
+        (WasiErrno::Notconn, 53),
+        (WasiErrno::Notdir, 54),
+        (WasiErrno::Notempty, 55),
+        (WasiErrno::Notrecoverable, 56),
+        (WasiErrno::Notsock, 57),
+        (WasiErrno::Notsup, 58),
+        (WasiErrno::Notty, 59),
+        (WasiErrno::Nxio, 60),
+        (WasiErrno::Overflow, 61),
+        (WasiErrno::Ownerdead, 62),
+        (WasiErrno::Perm, 63),
+        (WasiErrno::Pipe, 64),
+        (WasiErrno::Proto, 65),
+        (WasiErrno::Protonosupport, 66),
+        (WasiErrno::Prototype, 67),
+        (WasiErrno::Range, 68),
+        (WasiErrno::Rofs, 69),
+        (WasiErrno::Spipe, 70),
+        (WasiErrno::Srch, 71),
+        (WasiErrno::Stale, 72),
+        (WasiErrno::Timedout, 73),
+        (WasiErrno::Txtbsy, 74),
+        (WasiErrno::Xdev, 75),
+        (WasiErrno::Notcapable, 76),
+    ];
+    for (errno, value) in errnos {
+        assert_eq!(i32::from(errno), value, "{errno:?}");
+        assert_eq!(Value::from(errno), Value::I32(value));
+    }
+}
+
+#[test]
+fn test_clock_time_get() {
+    let mut wasi = WasiDispatcher::default();
+    let mut memory = vec![0; 64];
+    let clock_time_get = |wasi: &mut WasiDispatcher, memory: &mut [u8], clock_id, ptr| {
+        let args = [Value::I32(clock_id), Value::I64(1), Value::I32(ptr)];
+        wasi.dispatch("clock_time_get", &args, memory)
+    };
+
+    let result = clock_time_get(&mut wasi, &mut memory, WasiClockId::Realtime.into(), 8);
+    assert_eq!(result, Some(WasiErrno::Success.into()));
+    // Some time after 2020
+    assert!(read_u64(&memory, 8) > 1_600_000_000 * 1_000_000_000);
+
+    let monotonic = WasiClockId::Monotonic.into();
+    clock_time_get(&mut wasi, &mut memory, monotonic, 16);
+    clock_time_get(&mut wasi, &mut memory, monotonic, 24);
+    assert!(read_u64(&memory, 16) <= read_u64(&memory, 24));
+
+    let bad_clock = clock_time_get(&mut wasi, &mut memory, 99, 8);
+    assert_eq!(bad_clock, Some(WasiErrno::Inval.into()));
+    let out_of_bounds = clock_time_get(&mut wasi, &mut memory, monotonic, 60);
+    assert_eq!(out_of_bounds, Some(WasiErrno::Fault.into()));
+}
@@ -0,0 +1,76 @@
+use roc_wasm_module::Value;
+use std::fmt;
+
+use crate::TrapReason;
+
+/// Something the program did that affects which path it takes, recorded by `Instance::record_trace`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    /// A Wasm function was entered, including by a tail call
+    Call(u32),
+    /// An import was called and returned this
+    Import {
+        fn_index: u32,
+        result: Option<Value>,
+    },
+}
+
+/// Written like `call 3` or `import 0 -> I32(5)`
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Call(fn_index) => write!(f, "call {fn_index}"),
+            Self::Import {
+                fn_index,
+                result: Some(value),
+            } => write!(f, "import {fn_index} -> {value:?}"),
+            Self::Import {
+                fn_index,
+                result: None,
+            } => write!(f, "import {fn_index}"),
+        }
+    }
+}
+
+/// The calls and import results of one or more runs, in the order they happened.
+/// Two runs of the same program with the same inputs should produce the same trace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecutionTrace {
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+pub(crate) enum TraceMode {
+    Record(ExecutionTrace),
+    Replay {
+        expected: ExecutionTrace,
+        position: usize,
+    },
+}
+
+impl TraceMode {
+    /// Record the event, or check that it's the next one in the trace being replayed
+    pub fn event(&mut self, event: TraceEvent) -> Result<(), TrapReason> {
+        match self {
+            Self::Record(trace) => {
+                trace.events.push(event);
+                Ok(())
+            }
+            Self::Replay { expected, position } => {
+                let diverged = |expected: String| TrapReason::TraceDivergence {
+                    position: *position,
+                    expected,
+                    actual: event.to_string(),
+                };
+                match expected.events.get(*position) {
+                    Some(expected_event) if *expected_event == event => {
+                        *position += 1;
+                        Ok(())
+                    }
+                    Some(expected_event) => Err(diverged(expected_event.to_string())),
+                    None => Err(diverged("the end of the trace".into())),
+                }
+            }
+        }
+    }
+}
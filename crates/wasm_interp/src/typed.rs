@@ -0,0 +1,132 @@
+use roc_wasm_module::{Value, ValueType};
+
+/// A Rust type that can be passed to or returned from WebAssembly as a single value.
+/// Unsigned integers are reinterpreted as the signed Wasm type of the same size.
+pub trait WasmType: Sized {
+    const VALUE_TYPE: ValueType;
+    fn into_value(self) -> Value;
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl WasmType for i32 {
+    const VALUE_TYPE: ValueType = ValueType::I32;
+    fn into_value(self) -> Value {
+        Value::I32(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_i32().ok()
+    }
+}
+
+impl WasmType for u32 {
+    const VALUE_TYPE: ValueType = ValueType::I32;
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_i32().ok().map(|x| x as u32)
+    }
+}
+
+impl WasmType for i64 {
+    const VALUE_TYPE: ValueType = ValueType::I64;
+    fn into_value(self) -> Value {
+        Value::I64(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_i64().ok()
+    }
+}
+
+impl WasmType for u64 {
+    const VALUE_TYPE: ValueType = ValueType::I64;
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_i64().ok().map(|x| x as u64)
+    }
+}
+
+impl WasmType for f32 {
+    const VALUE_TYPE: ValueType = ValueType::F32;
+    fn into_value(self) -> Value {
+        Value::F32(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_f32().ok()
+    }
+}
+
+impl WasmType for f64 {
+    const VALUE_TYPE: ValueType = ValueType::F64;
+    fn into_value(self) -> Value {
+        Value::F64(self)
+    }
+    fn from_value(value: Value) -> Option<Self> {
+        value.expect_f64().ok()
+    }
+}
+
+/// The arguments of `Instance::call_typed`, as a tuple of `WasmType`s like `(i32, f64)`
+pub trait WasmArgs {
+    fn param_types() -> Vec<ValueType>;
+    fn into_values(self) -> Vec<Value>;
+}
+
+macro_rules! impl_wasm_args {
+    ($($name:ident),*) => {
+        impl<$($name: WasmType),*> WasmArgs for ($($name,)*) {
+            fn param_types() -> Vec<ValueType> {
+                vec![$($name::VALUE_TYPE),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_values(self) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into_value()),*]
+            }
+        }
+    };
+}
+
+impl_wasm_args!();
+impl_wasm_args!(A);
+impl_wasm_args!(A, B);
+impl_wasm_args!(A, B, C);
+impl_wasm_args!(A, B, C, D);
+impl_wasm_args!(A, B, C, D, E);
+impl_wasm_args!(A, B, C, D, E, F);
+
+/// The return type of `Instance::call_typed`: a `WasmType`, or `()` for functions that don't return anything
+pub trait WasmResult: Sized {
+    fn result_type() -> Option<ValueType>;
+    fn from_result(value: Option<Value>) -> Option<Self>;
+}
+
+impl WasmResult for () {
+    fn result_type() -> Option<ValueType> {
+        None
+    }
+    fn from_result(_value: Option<Value>) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl<T: WasmType> WasmResult for T {
+    fn result_type() -> Option<ValueType> {
+        Some(T::VALUE_TYPE)
+    }
+    fn from_result(value: Option<Value>) -> Option<Self> {
+        value.and_then(T::from_value)
+    }
+}
+
+/// A signature written like `(I32, F64) -> I64`, for error messages
+pub(crate) fn signature_string(param_types: &[ValueType], ret_type: Option<ValueType>) -> String {
+    let params: Vec<String> = param_types.iter().map(|ty| format!("{ty:?}")).collect();
+    match ret_type {
+        Some(ty) => format!("({}) -> {ty:?}", params.join(", ")),
+        None => format!("({})", params.join(", ")),
+    }
+}
@@ -0,0 +1,34 @@
+//! Names for the Wasm proposals this interpreter doesn't implement yet, so that an
+//! unsupported instruction can say which feature to turn off in code gen.
+//!
+//! Pending features:
+//! - SIMD (`0xFD` prefix)
+//! - Reference types (`ref.*`, `table.get/set`, typed `select`, and table instructions under `0xFC`)
+//! - Exception handling (`try`, `catch`, `throw`, `rethrow`, `delegate`, `catch_all`)
+//! - Non-trapping float-to-int conversions (`trunc_sat`, under `0xFC`)
+//! - Bulk memory initialization (`memory.init`, `data.drop`, `table.init`, `elem.drop`, `table.copy`)
+
+use crate::Error;
+
+/// The error for an instruction we can't run, named after the proposal it comes from.
+/// `sub_opcode` is the immediate after a prefix byte like `0xFC`, if there is one.
+pub(crate) fn unsupported(opcode: u8, sub_opcode: Option<u32>) -> Error {
+    Error::UnsupportedFeature {
+        feature: feature_name(opcode, sub_opcode),
+        opcode,
+        sub_opcode,
+    }
+}
+
+fn feature_name(opcode: u8, sub_opcode: Option<u32>) -> &'static str {
+    match (opcode, sub_opcode) {
+        (0xFD, _) => "SIMD",
+        (0xFE, _) => "threads (atomics)",
+        (0xFC, Some(0..=7)) => "non-trapping float-to-int conversions",
+        (0xFC, Some(8..=9 | 12..=14)) => "bulk memory",
+        (0xFC, Some(15..=17)) => "reference types",
+        (0x1C | 0x25 | 0x26 | 0xD0..=0xD2, _) => "reference types",
+        (0x06..=0x0A | 0x18 | 0x19, _) => "exception handling",
+        _ => "an unknown proposal",
+    }
+}
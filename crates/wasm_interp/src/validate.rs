@@ -0,0 +1,354 @@
+use std::fmt;
+
+use roc_wasm_module::opcodes::OpCode;
+use roc_wasm_module::parse::{Parse, SkipBytes};
+use roc_wasm_module::sections::{ImportDesc, Limits};
+use roc_wasm_module::{ConstExpr, ExportType, GlobalType, WasmModule};
+
+/// Reasons a module can be rejected before running any of its code
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A function or import refers to a signature that doesn't exist
+    TypeIndexOutOfBounds {
+        fn_index: u32,
+        type_index: u32,
+        type_count: u32,
+    },
+    /// The Function and Code sections disagree on how many functions there are
+    FunctionCountMismatch { signatures: u32, bodies: u32 },
+    /// An export refers to something that doesn't exist
+    ExportOutOfBounds {
+        name: String,
+        ty: ExportType,
+        index: u32,
+        count: u32,
+    },
+    /// The function table refers to a function that doesn't exist
+    ElementOutOfBounds { fn_index: u32, fn_count: u32 },
+    /// A global's type or initializer can't be decoded
+    InvalidGlobal { index: u32, message: String },
+    /// The memory's limits can't be decoded, or the minimum is larger than the maximum
+    InvalidMemoryLimits(String),
+    /// The module is bigger than the `ModuleLimits` allow
+    ModuleTooLarge {
+        limit: ModuleLimit,
+        actual: u64,
+        max: u64,
+    },
+}
+
+/// Which of the `ModuleLimits` a module went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleLimit {
+    Functions,
+    CodeSize,
+    Locals { fn_index: u32 },
+    MemoryPages,
+}
+
+impl fmt::Display for ModuleLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Functions => write!(f, "functions"),
+            Self::CodeSize => write!(f, "bytes of code"),
+            Self::Locals { fn_index } => write!(f, "locals in function {fn_index}"),
+            Self::MemoryPages => write!(f, "pages of initial memory"),
+        }
+    }
+}
+
+/// Upper bounds on the size of a module, checked before instantiating it, so that a small but
+/// malicious module can't make the host allocate huge amounts of memory.
+/// The defaults are far above anything the Roc compiler produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleLimits {
+    /// Functions, including imports
+    pub max_functions: u32,
+    /// Total size of the Code section in bytes
+    pub max_code_size: usize,
+    /// Local variables declared in any one function, not counting its arguments
+    pub max_locals: u32,
+    /// Initial size of the memory, whether it's declared or imported
+    pub max_memory_pages: u32,
+}
+
+impl Default for ModuleLimits {
+    fn default() -> Self {
+        ModuleLimits {
+            max_functions: 1_000_000,
+            max_code_size: 256 * 1024 * 1024,
+            max_locals: 50_000,
+            max_memory_pages: 32 * 1024, // 2GiB
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TypeIndexOutOfBounds {
+                fn_index,
+                type_index,
+                type_count,
+            } => write!(
+                f,
+                "Function {fn_index} has type index {type_index}, but there are only {type_count} types."
+            ),
+            Self::FunctionCountMismatch { signatures, bodies } => write!(
+                f,
+                "The Function section declares {signatures} functions, but the Code section has {bodies}."
+            ),
+            Self::ExportOutOfBounds {
+                name,
+                ty,
+                index,
+                count,
+            } => write!(
+                f,
+                "Export `{name}` refers to {ty:?} {index}, but there are only {count}."
+            ),
+            Self::ElementOutOfBounds { fn_index, fn_count } => write!(
+                f,
+                "The function table refers to function {fn_index}, but there are only {fn_count} functions."
+            ),
+            Self::InvalidGlobal { index, message } => {
+                write!(f, "Global {index} is invalid: {message}")
+            }
+            Self::InvalidMemoryLimits(message) => {
+                write!(f, "The memory limits are invalid: {message}")
+            }
+            Self::ModuleTooLarge { limit, actual, max } => write!(
+                f,
+                "The module has {actual} {limit}, but the limit is {max}."
+            ),
+        }
+    }
+}
+
+/// Check that all the indices in a module's sections point at things that exist,
+/// so that a corrupt module is rejected up front rather than panicking mid-run.
+/// This doesn't type-check function bodies. Block results are checked as the blocks run instead.
+/// The module's size is checked against the default `ModuleLimits`.
+pub fn verify(module: &WasmModule) -> Result<(), ValidationError> {
+    verify_with_limits(module, &ModuleLimits::default())
+}
+
+/// Like `verify`, but with custom limits on the module's size
+pub fn verify_with_limits(
+    module: &WasmModule,
+    limits: &ModuleLimits,
+) -> Result<(), ValidationError> {
+    check_limits(module, limits)?;
+
+    let type_count = module.types.len() as u32;
+
+    let mut import_fn_count = 0;
+    let mut import_global_count = 0;
+    let mut import_mem_count = 0;
+    let mut import_table_count = 0;
+    for import in module.import.imports.iter() {
+        match import.description {
+            ImportDesc::Func { signature_index } => {
+                check_type_index(import_fn_count, signature_index, type_count)?;
+                import_fn_count += 1;
+            }
+            ImportDesc::Global { .. } => import_global_count += 1,
+            ImportDesc::Mem { .. } => import_mem_count += 1,
+            ImportDesc::Table { .. } => import_table_count += 1,
+        }
+    }
+
+    for (i, signature_index) in module.function.signatures.iter().enumerate() {
+        check_type_index(import_fn_count + i as u32, *signature_index, type_count)?;
+    }
+
+    let signatures = module.function.signatures.len() as u32;
+    // Dead imports are replaced with dummy functions, which get bodies when serialized
+    let bodies = module.code.dead_import_dummy_count + module.code.function_count;
+    if signatures != bodies {
+        return Err(ValidationError::FunctionCountMismatch { signatures, bodies });
+    }
+    let fn_count = import_fn_count + bodies;
+
+    verify_globals(module)?;
+    verify_memory(module)?;
+
+    let global_count = import_global_count + module.global.count;
+    let mem_count = import_mem_count + module.memory.count;
+    let table_count = import_table_count + 1;
+    for export in module.export.exports.iter() {
+        let count = match export.ty {
+            ExportType::Func => fn_count,
+            ExportType::Table => table_count,
+            ExportType::Mem => mem_count,
+            ExportType::Global => global_count,
+        };
+        if export.index >= count {
+            return Err(ValidationError::ExportOutOfBounds {
+                name: export.name.to_string(),
+                ty: export.ty,
+                index: export.index,
+                count,
+            });
+        }
+    }
+
+    for segment in module.element.segments.iter() {
+        if let Some(fn_index) = segment.fn_indices.iter().find(|i| **i >= fn_count) {
+            return Err(ValidationError::ElementOutOfBounds {
+                fn_index: *fn_index,
+                fn_count,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_limits(module: &WasmModule, limits: &ModuleLimits) -> Result<(), ValidationError> {
+    let check = |limit: ModuleLimit, actual: u64, max: u64| {
+        if actual > max {
+            Err(ValidationError::ModuleTooLarge { limit, actual, max })
+        } else {
+            Ok(())
+        }
+    };
+
+    let mut import_fn_count = 0;
+    for import in module.import.imports.iter() {
+        match &import.description {
+            ImportDesc::Func { .. } => import_fn_count += 1,
+            ImportDesc::Mem { limits: mem_limits } => check(
+                ModuleLimit::MemoryPages,
+                min_pages(mem_limits) as u64,
+                limits.max_memory_pages as u64,
+            )?,
+            _ => {}
+        }
+    }
+    check(
+        ModuleLimit::Functions,
+        import_fn_count + module.code.function_count as u64,
+        limits.max_functions as u64,
+    )?;
+    check(
+        ModuleLimit::CodeSize,
+        module.code.bytes.len() as u64,
+        limits.max_code_size as u64,
+    )?;
+
+    let bytes = &module.code.bytes;
+    for (i, offset) in module.code.function_offsets.iter().enumerate() {
+        // Only the counts matter here. A body that can't be decoded fails when it's called.
+        let mut cursor = *offset as usize;
+        let mut locals: u64 = 0;
+        if u32::parse((), bytes, &mut cursor).is_ok() {
+            if let Ok(group_count) = u32::parse((), bytes, &mut cursor) {
+                for _ in 0..group_count {
+                    let Ok(group_size) = u32::parse((), bytes, &mut cursor) else {
+                        break;
+                    };
+                    locals += group_size as u64;
+                    cursor += 1; // value type
+                }
+            }
+        }
+        check(
+            ModuleLimit::Locals {
+                fn_index: import_fn_count as u32 + i as u32,
+            },
+            locals,
+            limits.max_locals as u64,
+        )?;
+    }
+
+    if module.memory.count > 0 {
+        let mut cursor = 0;
+        // Bad limits are reported by verify_memory
+        if let Ok(mem_limits) = Limits::parse((), &module.memory.bytes, &mut cursor) {
+            check(
+                ModuleLimit::MemoryPages,
+                min_pages(&mem_limits) as u64,
+                limits.max_memory_pages as u64,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn min_pages(limits: &Limits) -> u32 {
+    match limits {
+        Limits::Min(min) | Limits::MinMax(min, _) => *min,
+    }
+}
+
+fn check_type_index(
+    fn_index: u32,
+    type_index: u32,
+    type_count: u32,
+) -> Result<(), ValidationError> {
+    if type_index < type_count {
+        Ok(())
+    } else {
+        Err(ValidationError::TypeIndexOutOfBounds {
+            fn_index,
+            type_index,
+            type_count,
+        })
+    }
+}
+
+fn verify_globals(module: &WasmModule) -> Result<(), ValidationError> {
+    let bytes = &module.global.bytes;
+    let mut cursor = 0;
+    for index in 0..module.global.count {
+        let invalid = |message: String| ValidationError::InvalidGlobal { index, message };
+
+        // type and mutability are one byte each, and at least two more for the initializer
+        if cursor + 4 > bytes.len() {
+            return Err(invalid("the Global section ends unexpectedly".into()));
+        }
+        if !matches!(bytes[cursor], 0x7c..=0x7f) {
+            return Err(invalid(format!(
+                "invalid value type {:#04x}",
+                bytes[cursor]
+            )));
+        }
+        GlobalType::skip_bytes(bytes, &mut cursor).map_err(|e| invalid(e.message))?;
+
+        // Check enough of the initializer that parsing it can't panic
+        let opcode = bytes[cursor];
+        let min_len = if opcode == OpCode::I32CONST as u8 || opcode == OpCode::I64CONST as u8 {
+            3
+        } else if opcode == OpCode::F32CONST as u8 {
+            6
+        } else if opcode == OpCode::F64CONST as u8 {
+            10
+        } else {
+            return Err(invalid(format!(
+                "unsupported initializer opcode {opcode:#04x}"
+            )));
+        };
+        if cursor + min_len > bytes.len() {
+            return Err(invalid("the Global section ends unexpectedly".into()));
+        }
+        ConstExpr::parse((), bytes, &mut cursor).map_err(|e| invalid(e.message))?;
+    }
+    Ok(())
+}
+
+fn verify_memory(module: &WasmModule) -> Result<(), ValidationError> {
+    if module.memory.count == 0 {
+        return Ok(());
+    }
+    let mut cursor = 0;
+    let limits = Limits::parse((), &module.memory.bytes, &mut cursor)
+        .map_err(|e| ValidationError::InvalidMemoryLimits(e.message))?;
+    match limits {
+        Limits::MinMax(min, max) if min > max => Err(ValidationError::InvalidMemoryLimits(
+            format!("the minimum ({min} pages) is larger than the maximum ({max} pages)"),
+        )),
+        _ => Ok(()),
+    }
+}
@@ -1,14 +1,101 @@
-use rand::prelude::*;
+use crate::TrapReason;
+use bumpalo::Bump;
+use rand::rngs::{OsRng, StdRng};
+use rand::{RngCore, SeedableRng};
 use roc_wasm_module::Value;
-use std::io::{self, Read, StderrLock, StdoutLock, Write};
-use std::process::exit;
+use std::fs::{self, Metadata};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, SystemTime};
 
 pub const MODULE_NAME: &str = "wasi_snapshot_preview1";
 
+/// Callback to rewrite or reject a path the program uses, before it's resolved against
+/// the preopened directories. It receives the path as the program sees it, joined onto the
+/// guest path of the directory it's relative to, e.g. "/data/input.txt".
+/// It returns the path to resolve instead, or an error code to deny access.
+pub type PathMapper<'a> = Box<dyn Fn(&Path) -> Result<PathBuf, WasiErrno> + 'a>;
+
+/// The default for `WasiDispatcher::output_limit`
+pub const DEFAULT_OUTPUT_LIMIT: usize = 256 * 1024 * 1024;
+
 pub struct WasiDispatcher<'a> {
-    pub args: &'a [&'a [u8]],
-    pub rng: ThreadRng,
+    argv: EncodedStrings,
+    environ: EncodedStrings,
     pub files: Vec<WasiFile>,
+    /// Reject calls that would modify the host file system, e.g. `path_create_directory`
+    pub read_only: bool,
+    /// Let the program create symbolic links with `path_symlink`.
+    /// A strict sandbox can turn this off, so that no new links appear in the preopened directories.
+    pub allow_symlinks: bool,
+    /// Make `random_get` fail if the OS can't provide entropy, rather than falling back to a PRNG
+    pub strict_random: bool,
+    /// The largest size, in bytes, that `fd_write` will grow an in-memory file to.
+    /// This stops a runaway program from using up the host's memory when its output is captured.
+    pub output_limit: usize,
+    /// What to do with output beyond `output_limit`: drop it silently if true, or trap if false
+    pub drop_excess_output: bool,
+    /// Seeded from the time, the first time the OS can't provide entropy
+    fallback_rng: Option<StdRng>,
+    /// Set when the program calls `proc_exit`
+    exit_code: Option<i32>,
+    /// Set when a call needs to stop the program, e.g. by going over `output_limit`
+    trap: Option<TrapReason>,
+    path_mapper: Option<PathMapper<'a>>,
+    /// Buffering mode for host output, indexed by fd. Only stdout and stderr are used.
+    write_buffering: [WriteBuffering; 3],
+    /// Host output that hasn't been written yet, indexed by fd
+    pending_output: [Vec<u8>; 3],
+    /// The start of the program's monotonic and CPU-time clocks
+    started: Instant,
+}
+
+/// C strings laid out the way `args_get` and `environ_get` write them.
+/// They're encoded once, so the sizes from `args_sizes_get` and `environ_sizes_get` always match.
+#[derive(Debug, Default)]
+struct EncodedStrings {
+    /// Where each string starts in `bytes`
+    offsets: Vec<u32>,
+    /// The strings, each followed by a zero byte
+    bytes: Vec<u8>,
+}
+
+impl EncodedStrings {
+    fn new<I, S>(strings: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        let mut encoded = Self::default();
+        for string in strings {
+            encoded.offsets.push(encoded.bytes.len() as u32);
+            encoded.bytes.extend_from_slice(string.as_ref());
+            encoded.bytes.push(0);
+        }
+        encoded
+    }
+
+    fn write_sizes(&self, memory: &mut [u8], ptr_count: usize, ptr_buf_size: usize) {
+        write_u32(memory, ptr_count, self.offsets.len() as u32);
+        write_u32(memory, ptr_buf_size, self.bytes.len() as u32);
+    }
+
+    fn write(&self, memory: &mut [u8], ptr_ptrs: usize, ptr_buf: usize) {
+        for (i, offset) in self.offsets.iter().enumerate() {
+            write_u32(memory, ptr_ptrs + 4 * i, ptr_buf as u32 + offset);
+        }
+        memory[ptr_buf..][..self.bytes.len()].copy_from_slice(&self.bytes);
+    }
+}
+
+/// How `fd_write` passes output through to the host's stdout or stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteBuffering {
+    /// Write and flush the output of every call straight away
+    Unbuffered,
+    /// Hold output back until there's a newline, then write and flush up to the last one.
+    /// This keeps the program's lines from being interleaved with the host's own output.
+    Line,
 }
 
 impl Default for WasiDispatcher<'_> {
@@ -17,16 +104,70 @@ impl Default for WasiDispatcher<'_> {
     }
 }
 
+impl Drop for WasiDispatcher<'_> {
+    fn drop(&mut self) {
+        // There's nowhere to report an error from here
+        let _ = self.flush();
+    }
+}
+
 pub enum WasiFile {
     ReadOnly(Vec<u8>),
     WriteOnly(Vec<u8>),
     ReadWrite(Vec<u8>),
-    HostSystemFile,
+    /// The host's stdin, stdout or stderr, identified by its fd on the host (0, 1 or 2).
+    /// This can differ from the program's fd for it, after `fd_renumber`.
+    HostSystemFile(usize),
+    /// A host directory that the program is allowed to access paths inside of
+    PreopenDir {
+        guest_path: String,
+        host_path: PathBuf,
+    },
+    /// A file descriptor that has been closed, e.g. the source of an `fd_renumber`
+    Closed,
+}
+
+/// The files a `WasiDispatcher` gives the program, indexed by fd: stdio, preopened directories
+/// and in-memory files. Moving it from one dispatcher to the next lets a sequence of runs share
+/// their files, e.g. so that one program reads an in-memory file that the previous one wrote.
+pub struct WasiFileSystem {
+    pub files: Vec<WasiFile>,
+}
+
+impl Default for WasiFileSystem {
+    /// Just the host's stdin, stdout and stderr
+    fn default() -> Self {
+        WasiFileSystem {
+            files: vec![
+                WasiFile::HostSystemFile(0),
+                WasiFile::HostSystemFile(1),
+                WasiFile::HostSystemFile(2),
+            ],
+        }
+    }
+}
+
+impl WasiFileSystem {
+    /// Add a file, returning the fd the program will see it as
+    pub fn add_file(&mut self, file: WasiFile) -> usize {
+        self.files.push(file);
+        self.files.len() - 1
+    }
+
+    /// The contents of an in-memory file
+    pub fn file_contents(&self, fd: usize) -> Option<&[u8]> {
+        match self.files.get(fd)? {
+            WasiFile::ReadOnly(content)
+            | WasiFile::WriteOnly(content)
+            | WasiFile::ReadWrite(content) => Some(content),
+            _ => None,
+        }
+    }
 }
 
 enum WriteLock<'a> {
-    StdOut(StdoutLock<'a>),
-    Stderr(StderrLock<'a>),
+    /// Output for the host's stdout or stderr, and which one it's for
+    Host(usize, &'a mut Vec<u8>),
     RegularFile(&'a mut Vec<u8>),
 }
 
@@ -37,38 +178,375 @@ enum WriteLock<'a> {
 impl<'a> WasiDispatcher<'a> {
     pub fn new(args: &'a [&'a [u8]]) -> Self {
         WasiDispatcher {
-            args,
-            rng: thread_rng(),
-            files: vec![
-                WasiFile::HostSystemFile,
-                WasiFile::HostSystemFile,
-                WasiFile::HostSystemFile,
+            argv: EncodedStrings::new(args),
+            environ: EncodedStrings::default(),
+            files: WasiFileSystem::default().files,
+            read_only: false,
+            allow_symlinks: true,
+            strict_random: false,
+            output_limit: DEFAULT_OUTPUT_LIMIT,
+            drop_excess_output: false,
+            fallback_rng: None,
+            exit_code: None,
+            trap: None,
+            path_mapper: None,
+            // Rust's stdout is line-buffered and its stderr isn't
+            write_buffering: [
+                WriteBuffering::Unbuffered,
+                WriteBuffering::Line,
+                WriteBuffering::Unbuffered,
             ],
+            pending_output: Default::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Give the program these environment variables, e.g. from `std::env::vars()`.
+    /// They're encoded here, so later changes to the host's environment aren't reflected.
+    pub fn with_env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.environ = EncodedStrings::new(vars.into_iter().map(|(key, value)| {
+            let mut var = key.as_ref().to_vec();
+            var.push(b'=');
+            var.extend_from_slice(value.as_ref());
+            var
+        }));
+        self
+    }
+
+    /// Start the program with these files instead of just stdio, e.g. the ones a previous
+    /// run left behind (see `take_file_system`)
+    pub fn with_file_system(mut self, file_system: WasiFileSystem) -> Self {
+        self.files = file_system.files;
+        self
+    }
+
+    /// Take the files as the program left them, including anything it wrote to in-memory files,
+    /// so that they can be given to the next run. This dispatcher is left with just stdio.
+    pub fn take_file_system(&mut self) -> WasiFileSystem {
+        WasiFileSystem {
+            files: std::mem::replace(&mut self.files, WasiFileSystem::default().files),
+        }
+    }
+
+    /// Set how `fd_write` passes output through to the host, for stdout (1) or stderr (2).
+    /// Other files are kept in memory, so buffering doesn't apply to them.
+    pub fn set_write_buffering(&mut self, fd: usize, buffering: WriteBuffering) {
+        assert!(
+            fd == 1 || fd == 2,
+            "Write buffering can only be set for stdout or stderr, not fd {fd}"
+        );
+        self.write_buffering[fd] = buffering;
+    }
+
+    /// Write out any host output that is still being held back, e.g. a line without a newline
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_host_output(1, true)?;
+        self.write_host_output(2, true)
+    }
+
+    /// Write out as much pending output for stdout or stderr as the buffering mode allows
+    fn write_host_output(&mut self, fd: usize, force: bool) -> io::Result<()> {
+        let pending = &mut self.pending_output[fd];
+        let end = match self.write_buffering[fd] {
+            _ if force => pending.len(),
+            WriteBuffering::Unbuffered => pending.len(),
+            WriteBuffering::Line => pending
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |i| i + 1),
+        };
+        if end == 0 {
+            return Ok(());
+        }
+
+        let result = if fd == 1 {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(&pending[..end])
+                .and_then(|_| stdout.flush())
+        } else {
+            let mut stderr = io::stderr().lock();
+            stderr
+                .write_all(&pending[..end])
+                .and_then(|_| stderr.flush())
+        };
+        pending.drain(..end);
+        result
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pending_output(&self, fd: usize) -> &[u8] {
+        &self.pending_output[fd]
+    }
+
+    /// The code the program passed to `proc_exit`, if it has exited.
+    /// Exiting never terminates the host process, it only stops the Instance.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// The reason the last call needs the program to stop, if there is one.
+    /// This resets it, so that a dispatcher can be reused for another run.
+    pub fn take_trap(&mut self) -> Option<TrapReason> {
+        self.trap.take()
+    }
+
+    /// The time on a clock in nanoseconds, for `clock_time_get`.
+    /// The interpreter runs the program on whichever thread calls it, so the CPU-time clocks
+    /// can't be measured separately. They count from when the dispatcher was created, like the
+    /// monotonic clock.
+    fn clock_time(&self, clock_id: WasiClockId) -> u64 {
+        match clock_id {
+            WasiClockId::Realtime => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |t| t.as_nanos() as u64),
+            WasiClockId::Monotonic
+            | WasiClockId::ProcessCputimeId
+            | WasiClockId::ThreadCputimeId => self.started.elapsed().as_nanos() as u64,
+        }
+    }
+
+    /// Fill a buffer for `random_get`, preferring real entropy from `os_fill`.
+    /// If that fails, fall back to a PRNG seeded from the time, unless `strict_random` is set.
+    pub(crate) fn fill_random(
+        &mut self,
+        buf: &mut [u8],
+        os_fill: impl FnOnce(&mut [u8]) -> Result<(), rand::Error>,
+    ) -> WasiErrno {
+        let os_error = match os_fill(buf) {
+            Ok(()) => return WasiErrno::Success,
+            Err(e) => e,
+        };
+        if self.strict_random {
+            return WasiErrno::Io;
+        }
+        let rng = self.fallback_rng.get_or_insert_with(|| {
+            eprintln!(
+                "WARNING: The OS couldn't provide random bytes for `random_get` ({os_error}). Falling back to a pseudo-random generator seeded from the time, which is not suitable for cryptography."
+            );
+            let seed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |t| t.as_nanos() as u64);
+            StdRng::seed_from_u64(seed)
+        });
+        rng.fill_bytes(buf);
+        WasiErrno::Success
+    }
+
+    /// Implementation of `fd_sync` and `fd_datasync`.
+    /// Files are held in memory rather than backed by host files, so the only data that
+    /// can be made more durable is output to stdout or stderr that hasn't been written yet.
+    fn sync_fd(&mut self, fd: usize) -> WasiErrno {
+        match self.files.get(fd) {
+            Some(WasiFile::HostSystemFile(host_fd)) => match *host_fd {
+                0 => WasiErrno::Success,
+                1 | 2 => match self.write_host_output(*host_fd, true) {
+                    Ok(()) => WasiErrno::Success,
+                    Err(_) => WasiErrno::Io,
+                },
+                _ => WasiErrno::Badf,
+            },
+            Some(WasiFile::ReadOnly(_) | WasiFile::WriteOnly(_) | WasiFile::ReadWrite(_)) => {
+                WasiErrno::Success
+            }
+            Some(WasiFile::PreopenDir { .. } | WasiFile::Closed) | None => WasiErrno::Badf,
+        }
+    }
+
+    /// Implementation of `fd_advise`. Files are held in memory, so there's nothing to do with
+    /// the advice, but it's still checked like the OS would check it.
+    fn advise_fd(&self, fd: usize, advice: i32) -> WasiErrno {
+        // normal, sequential, random, willneed, dontneed or noreuse
+        if !(0..=5).contains(&advice) {
+            return WasiErrno::Inval;
+        }
+        match self.files.get(fd) {
+            Some(WasiFile::ReadOnly(_) | WasiFile::WriteOnly(_) | WasiFile::ReadWrite(_)) => {
+                WasiErrno::Success
+            }
+            // Like posix_fadvise on a pipe or terminal
+            Some(WasiFile::HostSystemFile(_)) => WasiErrno::Spipe,
+            Some(WasiFile::PreopenDir { .. } | WasiFile::Closed) | None => WasiErrno::Badf,
         }
     }
 
+    /// Implementation of `fd_allocate`: make sure an in-memory file is at least `offset + len` bytes,
+    /// padding it with zeros. It's never truncated.
+    fn allocate_fd(&mut self, fd: usize, offset: u64, len: u64) -> WasiErrno {
+        let content = match self.files.get_mut(fd) {
+            Some(WasiFile::WriteOnly(content) | WasiFile::ReadWrite(content)) => content,
+            _ => return WasiErrno::Badf,
+        };
+        let new_len = match offset.checked_add(len) {
+            Some(end) if end <= self.output_limit as u64 => end as usize,
+            _ => return WasiErrno::Fbig,
+        };
+        if content.len() < new_len {
+            content.resize(new_len, 0);
+        }
+        WasiErrno::Success
+    }
+
+    /// Implementation of `fd_renumber`: move the file at `from` to `to`, closing whatever was
+    /// at `to` and leaving `from` closed. Like `dup2`, both descriptors must already be open.
+    fn renumber_fd(&mut self, from: usize, to: usize) -> WasiErrno {
+        let is_open = |file: Option<&WasiFile>| !matches!(file, None | Some(WasiFile::Closed));
+        if !is_open(self.files.get(from)) || !is_open(self.files.get(to)) {
+            return WasiErrno::Badf;
+        }
+        if from == to {
+            return WasiErrno::Success;
+        }
+
+        let file = std::mem::replace(&mut self.files[from], WasiFile::Closed);
+        let replaced = std::mem::replace(&mut self.files[to], file);
+
+        // Don't leave output from the closed file behind the output that follows it.
+        // The stream might not be referred to by any fd now, so nothing else would write it.
+        match replaced {
+            WasiFile::HostSystemFile(host_fd @ (1 | 2)) => {
+                match self.write_host_output(host_fd, true) {
+                    Ok(()) => WasiErrno::Success,
+                    Err(_) => WasiErrno::Io,
+                }
+            }
+            _ => WasiErrno::Success,
+        }
+    }
+
+    /// Give the program access to a directory on the host, returning its file descriptor.
+    /// `guest_path` is the name the program will see it under, e.g. "." or "/data".
+    pub fn preopen_dir(&mut self, guest_path: String, host_path: PathBuf) -> usize {
+        self.files.push(WasiFile::PreopenDir {
+            guest_path,
+            host_path,
+        });
+        self.files.len() - 1
+    }
+
+    /// Register a callback to rewrite or reject every path the program uses.
+    /// The rewritten path is resolved in whichever preopened directory it's inside of.
+    pub fn set_path_mapper(&mut self, mapper: PathMapper<'a>) {
+        self.path_mapper = Some(mapper);
+    }
+
+    /// Find the host path for a path argument that is relative to a preopened directory.
+    /// Returns the path along with the directory it's in.
+    fn resolve_path_arg(
+        &self,
+        memory: &[u8],
+        fd: usize,
+        ptr_path: usize,
+        path_len: usize,
+    ) -> Result<(&Path, PathBuf), WasiErrno> {
+        let (guest_dir, dir) = match self.files.get(fd) {
+            Some(WasiFile::PreopenDir {
+                guest_path,
+                host_path,
+            }) => (guest_path, host_path.as_path()),
+            Some(WasiFile::Closed) | None => return Err(WasiErrno::Badf),
+            Some(_) => return Err(WasiErrno::Notdir),
+        };
+
+        let path = read_path(memory, ptr_path, path_len)?;
+
+        let (dir, path) = match &self.path_mapper {
+            Some(mapper) => {
+                let mapped = mapper(&Path::new(guest_dir).join(path))?;
+                let (dir, relative_path) =
+                    self.find_preopen(&mapped).ok_or(WasiErrno::Notcapable)?;
+                (dir, relative_path.to_path_buf())
+            }
+            None => (dir, path.to_path_buf()),
+        };
+        let host_path = resolve_in_dir(dir, &path).ok_or(WasiErrno::Notcapable)?;
+        if !stays_in_dir(dir, &path) {
+            return Err(WasiErrno::Notcapable);
+        }
+
+        Ok((dir, host_path))
+    }
+
+    /// Find the preopened directory that a guest path is inside of, preferring the innermost one.
+    /// Returns the directory's host path, along with the rest of the path relative to it.
+    fn find_preopen<'p>(&self, guest_path: &'p Path) -> Option<(&Path, &'p Path)> {
+        self.files
+            .iter()
+            .filter_map(|file| match file {
+                WasiFile::PreopenDir {
+                    guest_path: dir_guest_path,
+                    host_path,
+                } => {
+                    let relative_path = guest_path.strip_prefix(dir_guest_path).ok()?;
+                    Some((host_path.as_path(), relative_path))
+                }
+                _ => None,
+            })
+            .min_by_key(|(_, relative_path)| relative_path.components().count())
+    }
+
+    /// Resolve the (fd, path, path_len) arguments of a call that modifies the host file system
+    fn resolve_mutable_path_arg(
+        &self,
+        arguments: &[Value],
+        memory: &[u8],
+    ) -> Result<(&Path, PathBuf), WasiErrno> {
+        if self.read_only {
+            return Err(WasiErrno::Rofs);
+        }
+        let fd = arguments[0].expect_i32().unwrap() as usize;
+        let ptr_path = arguments[1].expect_i32().unwrap() as usize;
+        let path_len = arguments[2].expect_i32().unwrap() as usize;
+        self.resolve_path_arg(memory, fd, ptr_path, path_len)
+    }
+
+    /// WASI functions whose arguments are all plain numbers rather than pointers into memory
+    fn takes_no_pointers(function_name: &str) -> bool {
+        matches!(
+            function_name,
+            "fd_advise"
+                | "fd_allocate"
+                | "fd_close"
+                | "fd_datasync"
+                | "fd_fdstat_set_flags"
+                | "fd_fdstat_set_rights"
+                | "fd_filestat_set_size"
+                | "fd_filestat_set_times"
+                | "fd_renumber"
+                | "fd_sync"
+                | "proc_exit"
+                | "proc_raise"
+                | "sched_yield"
+        )
+    }
+
     pub fn dispatch(
         &mut self,
         function_name: &str,
         arguments: &[Value],
         memory: &mut [u8],
     ) -> Option<Value> {
-        let success_code = Some(Value::I32(Errno::Success as i32));
+        let success_code = Some(Value::I32(WasiErrno::Success as i32));
+        let not_implemented = Some(Value::I32(WasiErrno::Nosys as i32));
+
+        // Any pointer is out of bounds in a module without a memory
+        if memory.is_empty() && !Self::takes_no_pointers(function_name) {
+            return Some(Value::I32(WasiErrno::Fault as i32));
+        }
+
         match function_name {
             "args_get" => {
                 // uint8_t ** argv,
-                let mut ptr_ptr_argv = arguments[0].expect_i32().unwrap() as usize;
+                let ptr_ptr_argv = arguments[0].expect_i32().unwrap() as usize;
                 // uint8_t * argv_buf
-                let mut ptr_argv_buf = arguments[1].expect_i32().unwrap() as usize;
-
-                for arg in self.args {
-                    write_u32(memory, ptr_ptr_argv, ptr_argv_buf as u32);
-                    let bytes_target = &mut memory[ptr_argv_buf..][..arg.len()];
-                    bytes_target.copy_from_slice(arg);
-                    memory[ptr_argv_buf + arg.len()] = 0; // C string zero termination
-                    ptr_argv_buf += arg.len() + 1;
-                    ptr_ptr_argv += 4;
-                }
+                let ptr_argv_buf = arguments[1].expect_i32().unwrap() as usize;
+
+                self.argv.write(memory, ptr_ptr_argv, ptr_argv_buf);
 
                 success_code
             }
@@ -80,17 +558,17 @@ impl<'a> WasiDispatcher<'a> {
                 // size of string arguments buffer
                 let ptr_argv_buf_size = arguments[1].expect_i32().unwrap() as usize;
 
-                let argc = self.args.len() as u32;
-                write_u32(memory, ptr_argc, argc);
-
-                let argv_buf_size: u32 = self.args.iter().map(|a| 1 + a.len() as u32).sum();
-                write_u32(memory, ptr_argv_buf_size, argv_buf_size);
+                self.argv.write_sizes(memory, ptr_argc, ptr_argv_buf_size);
 
                 success_code
             }
             "environ_get" => {
-                // `environ_sizes_get` always reports 0 environment variables
-                // so we don't have to do anything here.
+                // uint8_t ** environ,
+                let ptr_ptr_environ = arguments[0].expect_i32().unwrap() as usize;
+                // uint8_t * environ_buf
+                let ptr_environ_buf = arguments[1].expect_i32().unwrap() as usize;
+
+                self.environ.write(memory, ptr_ptr_environ, ptr_environ_buf);
 
                 success_code
             }
@@ -98,21 +576,48 @@ impl<'a> WasiDispatcher<'a> {
                 let num_env_ptr = arguments[0].expect_i32().unwrap() as usize;
                 let size_env_ptr = arguments[1].expect_i32().unwrap() as usize;
 
-                // Calculate the total size required for environment variables
-                let total_size = 0;
-                let count = 0;
-
-                write_u32(memory, num_env_ptr, count);
-                write_u32(memory, size_env_ptr, total_size as u32);
+                self.environ.write_sizes(memory, num_env_ptr, size_env_ptr);
 
                 success_code
             }
-            "clock_res_get" => success_code, // this dummy implementation seems to be good enough for some functions
-            "clock_time_get" => success_code,
-            "fd_advise" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_allocate" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_close" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_datasync" => todo!("WASI {}({:?})", function_name, arguments),
+            "clock_res_get" => {
+                let clock_id = arguments[0].expect_i32().unwrap();
+                let ptr_resolution = arguments[1].expect_i32().unwrap() as usize;
+                let errno = match WasiClockId::try_from(clock_id) {
+                    Ok(_) => write_u64(memory, ptr_resolution, 1),
+                    Err(errno) => errno,
+                };
+                Some(errno.into())
+            }
+            "clock_time_get" => {
+                let clock_id = arguments[0].expect_i32().unwrap();
+                // arguments[1] is the precision, which we can always meet
+                let ptr_time = arguments[2].expect_i32().unwrap() as usize;
+                let errno = match WasiClockId::try_from(clock_id) {
+                    Ok(clock_id) => write_u64(memory, ptr_time, self.clock_time(clock_id)),
+                    Err(errno) => errno,
+                };
+                Some(errno.into())
+            }
+            "fd_advise" => {
+                // file descriptor, then the offset and length of the region the advice is for
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                let advice = arguments[3].expect_i32().unwrap();
+                Some(Value::I32(self.advise_fd(fd, advice) as i32))
+            }
+            "fd_allocate" => {
+                // file descriptor, offset, length
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                let offset = arguments[1].expect_i64().unwrap() as u64;
+                let len = arguments[2].expect_i64().unwrap() as u64;
+                Some(Value::I32(self.allocate_fd(fd, offset, len) as i32))
+            }
+            "fd_close" => not_implemented,
+            "fd_datasync" => {
+                // file descriptor
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                Some(Value::I32(self.sync_fd(fd) as i32))
+            }
             "fd_fdstat_get" => {
                 // (i32, i32) -> i32
 
@@ -121,8 +626,8 @@ impl<'a> WasiDispatcher<'a> {
                 // ptr to a wasi_fdstat_t
                 let stat_mut_ptr = arguments[1].expect_i32().unwrap() as usize;
 
-                match fd {
-                    1 => {
+                match self.files.get(fd) {
+                    Some(WasiFile::HostSystemFile(1)) => {
                         // Tell WASI that stdout is a tty (no seek or tell)
                         // https://github.com/WebAssembly/wasi-libc/blob/659ff414560721b1660a19685110e484a081c3d4/libc-bottom-half/sources/isatty.c
                         // *Not* a tty if:
@@ -132,24 +637,58 @@ impl<'a> WasiDispatcher<'a> {
                         //     .fs_filetype = __WASI_FILETYPE_CHARACTER_DEVICE
                         //     .fs_rights_base = 0
 
-                        const WASI_FILETYPE_CHARACTER_DEVICE: u8 = 2;
-                        memory[stat_mut_ptr] = WASI_FILETYPE_CHARACTER_DEVICE;
+                        memory[stat_mut_ptr] = FILETYPE_CHARACTER_DEVICE;
 
                         for b in memory[stat_mut_ptr + 1..stat_mut_ptr + 24].iter_mut() {
                             *b = 0;
                         }
                     }
-                    _ => todo!("WASI {}({:?})", function_name, arguments),
+                    _ => return not_implemented,
                 }
 
                 success_code
             }
-            "fd_fdstat_set_flags" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_fdstat_set_rights" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_filestat_get" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_filestat_set_size" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_filestat_set_times" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_pread" => todo!("WASI {}({:?})", function_name, arguments),
+            "fd_fdstat_set_flags" => not_implemented,
+            "fd_fdstat_set_rights" => not_implemented,
+            "fd_filestat_get" => {
+                // (i32, i32) -> i32
+
+                // file descriptor
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                // ptr to a wasi_filestat_t
+                let ptr_buf = arguments[1].expect_i32().unwrap() as usize;
+
+                let filestat = match self.files.get(fd) {
+                    Some(WasiFile::ReadOnly(content))
+                    | Some(WasiFile::WriteOnly(content))
+                    | Some(WasiFile::ReadWrite(content)) => Filestat {
+                        filetype: FILETYPE_REGULAR_FILE,
+                        nlink: 1,
+                        size: content.len() as u64,
+                        ..Default::default()
+                    },
+                    Some(WasiFile::HostSystemFile(_)) => Filestat {
+                        filetype: FILETYPE_CHARACTER_DEVICE,
+                        nlink: 1,
+                        ..Default::default()
+                    },
+                    Some(WasiFile::PreopenDir { host_path, .. }) => match fs::metadata(host_path) {
+                        Ok(metadata) => Filestat::from_metadata(&metadata),
+                        Err(e) => return Some(Value::I32(WasiErrno::from(e) as i32)),
+                    },
+                    Some(WasiFile::Closed) | None => {
+                        return Some(Value::I32(WasiErrno::Badf as i32))
+                    }
+                };
+
+                match filestat.write(memory, ptr_buf) {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "fd_filestat_set_size" => not_implemented,
+            "fd_filestat_set_times" => not_implemented,
+            "fd_pread" => not_implemented,
             "fd_prestat_get" => {
                 // The preopened file descriptor to query
                 let fd = arguments[0].expect_i32().unwrap() as usize;
@@ -157,19 +696,34 @@ impl<'a> WasiDispatcher<'a> {
                 //  preopen type: 4 bytes, where 0=dir is the only one supported, it seems
                 //  preopen name length: 4 bytes
                 let ptr_buf = arguments[1].expect_i32().unwrap() as usize;
-                memory[ptr_buf..][..8].copy_from_slice(&0u64.to_le_bytes());
-                if fd < self.files.len() {
-                    success_code
-                } else {
-                    println!("WASI warning: file descriptor {fd} does not exist");
-                    Some(Value::I32(Errno::Badf as i32))
+                match self.files.get(fd) {
+                    Some(WasiFile::PreopenDir { guest_path, .. }) => {
+                        memory[ptr_buf..][..8].copy_from_slice(&0u64.to_le_bytes());
+                        write_u32(memory, ptr_buf + 4, guest_path.len() as u32);
+                        success_code
+                    }
+                    None | Some(WasiFile::Closed) => {
+                        println!("WASI warning: file descriptor {fd} does not exist");
+                        Some(Value::I32(WasiErrno::Badf as i32))
+                    }
+                    // Open, but not preopened. wasi-libc would register it as a directory otherwise.
+                    Some(_) => Some(Value::I32(WasiErrno::Badf as i32)),
                 }
             }
             "fd_prestat_dir_name" => {
-                // We're not giving names to any of our files so just return success
-                success_code
+                // Only preopened directories have names
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                let ptr_path = arguments[1].expect_i32().unwrap() as usize;
+                let path_len = arguments[2].expect_i32().unwrap() as usize;
+                if let Some(WasiFile::PreopenDir { guest_path, .. }) = self.files.get(fd) {
+                    let len = path_len.min(guest_path.len());
+                    memory[ptr_path..][..len].copy_from_slice(&guest_path.as_bytes()[..len]);
+                    success_code
+                } else {
+                    Some(Value::I32(WasiErrno::Badf as i32))
+                }
             }
-            "fd_pwrite" => todo!("WASI {}({:?})", function_name, arguments),
+            "fd_pwrite" => not_implemented,
             "fd_read" => {
                 use WasiFile::*;
 
@@ -203,7 +757,7 @@ impl<'a> WasiDispatcher<'a> {
                             n_read += len;
                         }
                     }
-                    Some(HostSystemFile) if fd == 0 => {
+                    Some(HostSystemFile(0)) => {
                         let mut stdin = io::stdin();
                         for _ in 0..iovs_len {
                             let iov_base = read_u32(memory, ptr_iovs) as usize;
@@ -218,17 +772,26 @@ impl<'a> WasiDispatcher<'a> {
                             }
                         }
                     }
-                    _ => return Some(Value::I32(Errno::Badf as i32)),
+                    _ => return Some(Value::I32(WasiErrno::Badf as i32)),
                 };
 
                 memory[ptr_nread..][..4].copy_from_slice(&(n_read as u32).to_le_bytes());
                 success_code
             }
-            "fd_readdir" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_renumber" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_seek" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_sync" => todo!("WASI {}({:?})", function_name, arguments),
-            "fd_tell" => todo!("WASI {}({:?})", function_name, arguments),
+            "fd_readdir" => not_implemented,
+            "fd_renumber" => {
+                // the file descriptor to move, and the one to move it to
+                let from = arguments[0].expect_i32().unwrap() as usize;
+                let to = arguments[1].expect_i32().unwrap() as usize;
+                Some(Value::I32(self.renumber_fd(from, to) as i32))
+            }
+            "fd_seek" => not_implemented,
+            "fd_sync" => {
+                // file descriptor
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                Some(Value::I32(self.sync_fd(fd) as i32))
+            }
+            "fd_tell" => not_implemented,
             "fd_write" => {
                 use WasiFile::*;
 
@@ -241,20 +804,20 @@ impl<'a> WasiDispatcher<'a> {
                 // Out param: number of bytes written
                 let ptr_nwritten = arguments[3].expect_i32().unwrap() as usize;
 
-                // Grab a lock for stdout/stderr before the loop rather than re-acquiring over and over.
-                // Not really necessary for other files, but it's easier to use the same structure.
+                // Output for stdout/stderr is collected first, and then written according to the buffering mode
                 let mut write_lock = match self.files.get_mut(fd) {
-                    Some(HostSystemFile) => match fd {
-                        1 => WriteLock::StdOut(io::stdout().lock()),
-                        2 => WriteLock::Stderr(io::stderr().lock()),
-                        _ => return Some(Value::I32(Errno::Inval as i32)),
+                    Some(HostSystemFile(host_fd)) => match *host_fd {
+                        1 | 2 => WriteLock::Host(*host_fd, &mut self.pending_output[*host_fd]),
+                        _ => return Some(Value::I32(WasiErrno::Inval as i32)),
                     },
                     Some(WriteOnly(content) | ReadWrite(content)) => {
                         WriteLock::RegularFile(content)
                     }
-                    _ => return Some(Value::I32(Errno::Badf as i32)),
+                    _ => return Some(Value::I32(WasiErrno::Badf as i32)),
                 };
 
+                let output_limit = self.output_limit;
+                let mut limit_exceeded = false;
                 let mut n_written: i32 = 0;
                 let mut negative_length_count = 0;
                 let mut write_result = Ok(());
@@ -277,16 +840,36 @@ impl<'a> WasiDispatcher<'a> {
                     let bytes = &memory[iov_base..][..iov_len as usize];
 
                     write_result = match &mut write_lock {
-                        WriteLock::StdOut(stdout) => stdout.write_all(bytes),
-                        WriteLock::Stderr(stderr) => stderr.write_all(bytes),
-                        WriteLock::RegularFile(content) => content.write_all(bytes),
+                        WriteLock::Host(_, pending) => pending.write_all(bytes),
+                        WriteLock::RegularFile(content) => {
+                            let room = output_limit.saturating_sub(content.len());
+                            if bytes.len() > room {
+                                limit_exceeded = true;
+                            }
+                            content.write_all(&bytes[..bytes.len().min(room)])
+                        }
                     };
-                    if write_result.is_err() {
+                    if write_result.is_err() || (limit_exceeded && !self.drop_excess_output) {
                         break;
                     }
                     n_written += bytes.len() as i32;
                 }
 
+                if limit_exceeded && !self.drop_excess_output {
+                    self.trap = Some(TrapReason::OutputLimitExceeded {
+                        fd,
+                        limit: output_limit,
+                    });
+                    return Some(Value::I32(WasiErrno::Fbig as i32));
+                }
+
+                match write_lock {
+                    WriteLock::Host(host_fd, _) if write_result.is_ok() => {
+                        write_result = self.write_host_output(host_fd, false);
+                    }
+                    _ => {}
+                }
+
                 write_i32(memory, ptr_nwritten, n_written);
                 if negative_length_count > 0 {
                     // Let's see if we ever get this message. If not, we can remove this negative-length stuff.
@@ -297,40 +880,205 @@ impl<'a> WasiDispatcher<'a> {
 
                 match write_result {
                     Ok(()) => success_code,
-                    Err(_) => Some(Value::I32(Errno::Io as i32)),
+                    Err(_) => Some(Value::I32(WasiErrno::Io as i32)),
+                }
+            }
+            "path_create_directory" => {
+                // (i32, i32, i32) -> i32
+                // directory fd, then the path as a non-terminated string
+                let result = self
+                    .resolve_mutable_path_arg(arguments, memory)
+                    .and_then(|(_, host_path)| fs::create_dir(host_path).map_err(WasiErrno::from));
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "path_filestat_get" => {
+                // (i32, i32, i32, i32, i32) -> i32
+
+                // file descriptor of the directory the path is relative to
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                // lookup flags
+                let flags = arguments[1].expect_i32().unwrap() as u32;
+                // the path, as a non-terminated string
+                let ptr_path = arguments[2].expect_i32().unwrap() as usize;
+                let path_len = arguments[3].expect_i32().unwrap() as usize;
+                // ptr to a wasi_filestat_t
+                let ptr_buf = arguments[4].expect_i32().unwrap() as usize;
+
+                let host_path = match self.resolve_path_arg(memory, fd, ptr_path, path_len) {
+                    Ok((_, host_path)) => host_path,
+                    Err(errno) => return Some(Value::I32(errno as i32)),
+                };
+
+                const LOOKUPFLAGS_SYMLINK_FOLLOW: u32 = 1;
+                let metadata_result = if flags & LOOKUPFLAGS_SYMLINK_FOLLOW != 0 {
+                    fs::metadata(host_path)
+                } else {
+                    fs::symlink_metadata(host_path)
+                };
+                let filestat = match metadata_result {
+                    Ok(metadata) => Filestat::from_metadata(&metadata),
+                    Err(e) => return Some(Value::I32(WasiErrno::from(e) as i32)),
+                };
+
+                match filestat.write(memory, ptr_buf) {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "path_filestat_set_times" => not_implemented,
+            "path_link" => not_implemented,
+            "path_open" => not_implemented,
+            "path_readlink" => {
+                // (i32, i32, i32, i32, i32, i32) -> i32
+                // directory fd and path of the link, then the buffer for its contents
+                // and where to write how much of the buffer was used
+                let fd = arguments[0].expect_i32().unwrap() as usize;
+                let ptr_path = arguments[1].expect_i32().unwrap() as usize;
+                let path_len = arguments[2].expect_i32().unwrap() as usize;
+                let ptr_buf = arguments[3].expect_i32().unwrap() as usize;
+                let buf_len = arguments[4].expect_i32().unwrap() as usize;
+                let ptr_nread = arguments[5].expect_i32().unwrap() as usize;
+
+                let result = self
+                    .resolve_path_arg(memory, fd, ptr_path, path_len)
+                    .and_then(|(_, link_path)| {
+                        if !fs::symlink_metadata(&link_path)?.is_symlink() {
+                            return Err(WasiErrno::Inval);
+                        }
+                        let target = fs::read_link(link_path)?;
+                        let target = target.to_str().ok_or(WasiErrno::Ilseq)?.to_owned();
+                        if memory.len() < ptr_nread.saturating_add(4) {
+                            return Err(WasiErrno::Fault);
+                        }
+                        let buf = memory
+                            .get_mut(ptr_buf..)
+                            .and_then(|m| m.get_mut(..buf_len))
+                            .ok_or(WasiErrno::Fault)?;
+                        // Like readlink in POSIX, a target that doesn't fit is truncated
+                        let n_read = target.len().min(buf_len);
+                        buf[..n_read].copy_from_slice(&target.as_bytes()[..n_read]);
+                        write_u32(memory, ptr_nread, n_read as u32);
+                        Ok(())
+                    });
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "path_remove_directory" => {
+                // (i32, i32, i32) -> i32
+                let result = self.resolve_mutable_path_arg(arguments, memory).and_then(
+                    |(dir, host_path)| {
+                        if host_path == dir {
+                            // Like rmdir("."), this would remove a directory we're still using
+                            return Err(WasiErrno::Inval);
+                        }
+                        if !fs::symlink_metadata(&host_path)?.is_dir() {
+                            return Err(WasiErrno::Notdir);
+                        }
+                        if fs::read_dir(&host_path)?.next().is_some() {
+                            return Err(WasiErrno::Notempty);
+                        }
+                        Ok(fs::remove_dir(host_path)?)
+                    },
+                );
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "path_rename" => {
+                // (i32, i32, i32, i32, i32, i32) -> i32
+                // the old directory fd and path, then the new directory fd and path
+                let result = self
+                    .resolve_mutable_path_arg(&arguments[..3], memory)
+                    .and_then(|(old_dir, old_path)| {
+                        let (new_dir, new_path) =
+                            self.resolve_mutable_path_arg(&arguments[3..], memory)?;
+                        if old_path == old_dir || new_path == new_dir {
+                            // We're still using the preopened directories
+                            return Err(WasiErrno::Busy);
+                        }
+                        rename(&old_path, &new_path)
+                    });
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "path_symlink" => {
+                // (i32, i32, i32, i32, i32) -> i32
+                // the contents of the link, then the directory fd and path of the link itself
+                let ptr_target = arguments[0].expect_i32().unwrap() as usize;
+                let target_len = arguments[1].expect_i32().unwrap() as usize;
+                let result = if self.allow_symlinks {
+                    self.resolve_mutable_path_arg(&arguments[2..], memory)
+                        .and_then(|(dir, link_path)| {
+                            let target = read_path(memory, ptr_target, target_len)?;
+                            if fs::symlink_metadata(&link_path).is_ok() {
+                                return Err(WasiErrno::Exist);
+                            }
+                            // Following the link mustn't lead outside the preopened directory
+                            let link_dir = link_path
+                                .parent()
+                                .and_then(|parent| parent.strip_prefix(dir).ok())
+                                .ok_or(WasiErrno::Notcapable)?;
+                            if !stays_in_dir(dir, &link_dir.join(target)) {
+                                return Err(WasiErrno::Notcapable);
+                            }
+                            symlink(target, &link_path)
+                        })
+                } else {
+                    Err(WasiErrno::Perm)
+                };
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
                 }
             }
-            "path_create_directory" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_filestat_get" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_filestat_set_times" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_link" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_open" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_readlink" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_remove_directory" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_rename" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_symlink" => todo!("WASI {}({:?})", function_name, arguments),
-            "path_unlink_file" => todo!("WASI {}({:?})", function_name, arguments),
-            "poll_oneoff" => todo!("WASI {}({:?})", function_name, arguments),
+            "path_unlink_file" => {
+                // (i32, i32, i32) -> i32
+                let result =
+                    self.resolve_mutable_path_arg(arguments, memory)
+                        .and_then(|(_, host_path)| {
+                            if fs::symlink_metadata(&host_path)?.is_dir() {
+                                return Err(WasiErrno::Isdir);
+                            }
+                            Ok(fs::remove_file(host_path)?)
+                        });
+                match result {
+                    Ok(()) => success_code,
+                    Err(errno) => Some(Value::I32(errno as i32)),
+                }
+            }
+            "poll_oneoff" => not_implemented,
             "proc_exit" => {
-                let exit_code = arguments[0].expect_i32().unwrap();
-                exit(exit_code);
+                self.exit_code = Some(arguments[0].expect_i32().unwrap());
+                None
             }
-            "proc_raise" => todo!("WASI {}({:?})", function_name, arguments),
-            "sched_yield" => todo!("WASI {}({:?})", function_name, arguments),
+            "proc_raise" => not_implemented,
+            "sched_yield" => not_implemented,
             "random_get" => {
                 // A pointer to a buffer where the random bytes will be written
                 let ptr_buf = arguments[0].expect_i32().unwrap() as usize;
                 // The number of bytes that will be written
                 let buf_len = arguments[1].expect_i32().unwrap() as usize;
-                for i in 0..buf_len {
-                    memory[ptr_buf + i] = self.rng.gen();
-                }
-                success_code
+                let errno = match memory.get_mut(ptr_buf..).and_then(|m| m.get_mut(..buf_len)) {
+                    Some(buf) => self.fill_random(buf, |b| OsRng.try_fill_bytes(b)),
+                    None => WasiErrno::Fault,
+                };
+                Some(Value::I32(errno as i32))
+            }
+            // Sockets aren't supported, but programs that import these functions without
+            // actually using them (or that can handle the error) should still be able to run.
+            "sock_accept" | "sock_recv" | "sock_send" | "sock_shutdown" => {
+                Some(Value::I32(WasiErrno::Notsup as i32))
             }
-            "sock_recv" => todo!("WASI {}({:?})", function_name, arguments),
-            "sock_send" => todo!("WASI {}({:?})", function_name, arguments),
-            "sock_shutdown" => todo!("WASI {}({:?})", function_name, arguments),
-            _ => panic!("Unknown WASI function {function_name}({arguments:?})"),
+            // Not part of WASI preview 1
+            _ => not_implemented,
         }
     }
 }
@@ -355,13 +1103,290 @@ fn write_i32(memory: &mut [u8], addr: usize, value: i32) {
     memory[addr..][..4].copy_from_slice(&value.to_le_bytes());
 }
 
+/// Write a u64 for the program, or fail with `Fault` if the address is out of bounds
+fn write_u64(memory: &mut [u8], addr: usize, value: u64) -> WasiErrno {
+    match memory.get_mut(addr..).and_then(|m| m.get_mut(..8)) {
+        Some(bytes) => {
+            bytes.copy_from_slice(&value.to_le_bytes());
+            WasiErrno::Success
+        }
+        None => WasiErrno::Fault,
+    }
+}
+
+/// Expand any `@file` arguments into the newline-separated arguments listed in that file,
+/// so that huge argument lists don't need to go on the command line.
+/// The first argument is the program name, and is never expanded.
+pub fn expand_args_files<'a>(arena: &'a Bump, args: &[&'a [u8]]) -> Result<&'a [&'a [u8]], String> {
+    let mut expanded = bumpalo::collections::Vec::with_capacity_in(args.len(), arena);
+    for (i, arg) in args.iter().enumerate() {
+        match arg.strip_prefix(b"@") {
+            Some(path_bytes) if i > 0 && !path_bytes.is_empty() => {
+                let path = String::from_utf8_lossy(path_bytes);
+                let contents = fs::read(path.as_ref())
+                    .map_err(|e| format!("I couldn't read the arguments file {path}: {e}"))?;
+                let contents: &'a [u8] = arena.alloc_slice_copy(&contents);
+                let contents = contents.strip_suffix(b"\n").unwrap_or(contents);
+
+                let lines = contents.split(|b| *b == b'\n');
+                expanded.extend(lines.map(|line| line.strip_suffix(b"\r").unwrap_or(line)));
+            }
+            _ => expanded.push(*arg),
+        }
+    }
+    Ok(expanded.into_bump_slice())
+}
+
+/// Read a path argument, a non-terminated string, from memory
+fn read_path(memory: &[u8], ptr_path: usize, path_len: usize) -> Result<&Path, WasiErrno> {
+    let path_bytes = memory
+        .get(ptr_path..)
+        .and_then(|p| p.get(..path_len))
+        .ok_or(WasiErrno::Fault)?;
+    let path = std::str::from_utf8(path_bytes).map_err(|_| WasiErrno::Ilseq)?;
+    Ok(Path::new(path))
+}
+
+/// Rename a file or directory with POSIX semantics, which `fs::rename` doesn't have on every host
+fn rename(old_path: &Path, new_path: &Path) -> Result<(), WasiErrno> {
+    let is_dir = fs::symlink_metadata(old_path)?.is_dir();
+    if is_dir && new_path.starts_with(old_path) && new_path != old_path {
+        // A directory can't be moved inside itself
+        return Err(WasiErrno::Inval);
+    }
+    if let Ok(existing) = fs::symlink_metadata(new_path) {
+        match (is_dir, existing.is_dir()) {
+            (true, false) => return Err(WasiErrno::Notdir),
+            (false, true) => return Err(WasiErrno::Isdir),
+            (true, true) if fs::read_dir(new_path)?.next().is_some() => {
+                return Err(WasiErrno::Notempty)
+            }
+            _ => {}
+        }
+    }
+    Ok(fs::rename(old_path, new_path)?)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link_path: &Path) -> Result<(), WasiErrno> {
+    Ok(std::os::unix::fs::symlink(target, link_path)?)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link_path: &Path) -> Result<(), WasiErrno> {
+    // Windows needs to know whether the link is to a directory, so it has to exist already
+    let resolved = link_path.parent().map(|dir| dir.join(target));
+    if resolved.is_some_and(|path| path.is_dir()) {
+        Ok(std::os::windows::fs::symlink_dir(target, link_path)?)
+    } else {
+        Ok(std::os::windows::fs::symlink_file(target, link_path)?)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _link_path: &Path) -> Result<(), WasiErrno> {
+    Err(WasiErrno::Nosys)
+}
+
+/// Join a guest path onto a preopened directory, without letting it escape.
+/// Returns None for absolute paths, or if `..` would go above the directory.
+/// This only looks at the text of the path, so it also needs checking with `stays_in_dir`.
+fn resolve_in_dir(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let mut resolved = dir.to_path_buf();
+    let mut depth = 0;
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => {
+                resolved.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return None;
+                }
+                resolved.pop();
+                depth -= 1;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// The most symlinks `stays_in_dir` follows, like the host's own limit before `ELOOP`
+const MAX_SYMLINK_HOPS: u32 = 40;
+
+/// Whether a path relative to a preopened directory really ends up inside it on the host.
+/// Unlike `resolve_in_dir`, this follows the symlinks along the way, the same as the host will,
+/// so that a link to `..` inside a subdirectory can't be used to climb out of the directory.
+/// Links with absolute targets are refused, since they can't be checked against the guest's view.
+fn stays_in_dir(dir: &Path, path: &Path) -> bool {
+    let Ok(root) = fs::canonicalize(dir) else {
+        return false;
+    };
+    let mut hops = 0;
+    follow_in_dir(&root, root.clone(), path, &mut hops).is_some()
+}
+
+/// Walk `path` from `start`, following symlinks, and return where it leads.
+/// `start` must be a real path inside `root`, i.e. one without any symlinks in it.
+fn follow_in_dir(root: &Path, start: PathBuf, path: &Path, hops: &mut u32) -> Option<PathBuf> {
+    let mut resolved = start;
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => {
+                let next = resolved.join(name);
+                match fs::read_link(&next) {
+                    Ok(target) => {
+                        *hops += 1;
+                        if *hops > MAX_SYMLINK_HOPS {
+                            return None;
+                        }
+                        // A relative link is relative to the directory it's in
+                        resolved = follow_in_dir(root, resolved, &target, hops)?;
+                    }
+                    // Not a link, or doesn't exist yet, e.g. a file that's about to be created
+                    Err(_) => resolved = next,
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if resolved == root {
+                    return None;
+                }
+                resolved.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(resolved)
+}
+
+const FILETYPE_UNKNOWN: u8 = 0;
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+const FILETYPE_SYMBOLIC_LINK: u8 = 7;
+
+/// WASI `filestat` struct. Timestamps are in nanoseconds since the Unix epoch.
+#[derive(Debug, Default)]
+struct Filestat {
+    dev: u64,
+    ino: u64,
+    filetype: u8,
+    nlink: u64,
+    size: u64,
+    atim: u64,
+    mtim: u64,
+    ctim: u64,
+}
+
+impl Filestat {
+    const SIZE: usize = 64;
+
+    fn from_metadata(metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+        let filetype = if file_type.is_symlink() {
+            FILETYPE_SYMBOLIC_LINK
+        } else if file_type.is_dir() {
+            FILETYPE_DIRECTORY
+        } else if file_type.is_file() {
+            FILETYPE_REGULAR_FILE
+        } else {
+            FILETYPE_UNKNOWN
+        };
+
+        let nanos = |time: io::Result<SystemTime>| {
+            time.ok()
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos() as u64)
+        };
+
+        #[cfg(unix)]
+        let (dev, ino, nlink, ctim) = {
+            use std::os::unix::fs::MetadataExt;
+            let ctim = (metadata.ctime() as u64)
+                .wrapping_mul(1_000_000_000)
+                .wrapping_add(metadata.ctime_nsec() as u64);
+            (metadata.dev(), metadata.ino(), metadata.nlink(), ctim)
+        };
+        #[cfg(not(unix))]
+        let (dev, ino, nlink, ctim) = (0, 0, 1, nanos(metadata.created()));
+
+        Filestat {
+            dev,
+            ino,
+            filetype,
+            nlink,
+            size: metadata.len(),
+            atim: nanos(metadata.accessed()),
+            mtim: nanos(metadata.modified()),
+            ctim,
+        }
+    }
+
+    fn write(&self, memory: &mut [u8], addr: usize) -> Result<(), WasiErrno> {
+        let buf = memory
+            .get_mut(addr..)
+            .and_then(|m| m.get_mut(..Self::SIZE))
+            .ok_or(WasiErrno::Fault)?;
+        buf.fill(0);
+        buf[0..8].copy_from_slice(&self.dev.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.ino.to_le_bytes());
+        buf[16] = self.filetype;
+        buf[24..32].copy_from_slice(&self.nlink.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.size.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.atim.to_le_bytes());
+        buf[48..56].copy_from_slice(&self.mtim.to_le_bytes());
+        buf[56..64].copy_from_slice(&self.ctim.to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Identifiers for clocks, for `clock_res_get` and `clock_time_get`
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasiClockId {
+    /// The clock measuring real time. Time value zero corresponds with 1970-01-01T00:00:00Z.
+    Realtime = 0,
+    /// The store-wide monotonic clock, which can't be adjusted and has an arbitrary starting point
+    Monotonic = 1,
+    /// The CPU-time clock associated with the current process
+    ProcessCputimeId = 2,
+    /// The CPU-time clock associated with the current thread
+    ThreadCputimeId = 3,
+}
+
+impl TryFrom<i32> for WasiClockId {
+    type Error = WasiErrno;
+
+    fn try_from(id: i32) -> Result<Self, WasiErrno> {
+        match id {
+            0 => Ok(Self::Realtime),
+            1 => Ok(Self::Monotonic),
+            2 => Ok(Self::ProcessCputimeId),
+            3 => Ok(Self::ThreadCputimeId),
+            _ => Err(WasiErrno::Inval),
+        }
+    }
+}
+
+impl From<WasiClockId> for i32 {
+    fn from(id: WasiClockId) -> Self {
+        id as i32
+    }
+}
+
 /// Error codes returned by functions.
 /// Not all of these error codes are returned by the functions provided by this
 /// API; some are used in higher-level library layers, and others are provided
 /// merely for alignment with POSIX.
+///
+/// Custom dispatchers can return these too, e.g. `Some(WasiErrno::Badf.into())`.
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum Errno {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasiErrno {
     /// No error occurred. System call completed successfully.
     Success,
     /// Argument list too long.
@@ -517,3 +1542,28 @@ pub enum Errno {
     /// Extension: Capabilities insufficient.
     Notcapable,
 }
+
+impl From<WasiErrno> for i32 {
+    fn from(errno: WasiErrno) -> Self {
+        errno as i32
+    }
+}
+
+impl From<WasiErrno> for Value {
+    fn from(errno: WasiErrno) -> Self {
+        Value::I32(errno as i32)
+    }
+}
+
+impl From<io::Error> for WasiErrno {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => WasiErrno::Noent,
+            io::ErrorKind::AlreadyExists => WasiErrno::Exist,
+            io::ErrorKind::PermissionDenied => WasiErrno::Access,
+            // There's no stable ErrorKind for this yet. It's EXDEV on both Linux and macOS.
+            _ if cfg!(unix) && e.raw_os_error() == Some(18) => WasiErrno::Xdev,
+            _ => WasiErrno::Io,
+        }
+    }
+}
@@ -45,6 +45,9 @@ pub struct WasmModule<'a> {
     pub reloc_code: RelocationSection<'a>,
     pub reloc_data: RelocationSection<'a>,
     pub names: NameSection<'a>,
+    /// Custom sections that aren't parsed into one of the fields above, by name.
+    /// These are only kept by `preload`, and `serialize` doesn't write them.
+    pub other_custom_sections: Vec<'a, (&'a str, &'a [u8])>,
 }
 
 impl<'a> WasmModule<'a> {
@@ -67,9 +70,19 @@ impl<'a> WasmModule<'a> {
             reloc_code: RelocationSection::new(arena, "reloc.CODE"),
             reloc_data: RelocationSection::new(arena, "reloc.DATA"),
             names: NameSection::new(arena),
+            other_custom_sections: Vec::new_in(arena),
         }
     }
 
+    /// The contents of a Custom section, after its name, e.g. metadata that Roc embeds in the
+    /// binary. Sections with their own field, like the `name` section, are only found there.
+    pub fn custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        self.other_custom_sections
+            .iter()
+            .find(|(section_name, _)| *section_name == name)
+            .map(|(_, contents)| *contents)
+    }
+
     /// Create entries in the Type and Function sections for a function signature
     pub fn add_function_signature(&mut self, signature: Signature<'a>) {
         let index = self.types.insert(signature);
@@ -150,9 +163,11 @@ impl<'a> WasmModule<'a> {
         let mut reloc_code = RelocationSection::new(arena, "reloc.CODE");
         let mut reloc_data = RelocationSection::new(arena, "reloc.DATA");
         let mut names = NameSection::new(arena);
+        let mut other_custom_sections = Vec::new_in(arena);
 
         // Consume all remaining Custom sections
-        while let Ok((section_name, section_end)) = Self::peek_custom_section(arena, bytes, cursor)
+        while let Ok((section_name, contents_start, section_end)) =
+            Self::peek_custom_section(arena, bytes, cursor)
         {
             match section_name {
                 "linking" => {
@@ -170,6 +185,15 @@ impl<'a> WasmModule<'a> {
                     names = NameSection::parse(arena, bytes, &mut cursor)?;
                 }
                 _ => {
+                    let contents = bytes.get(contents_start..section_end).ok_or_else(|| {
+                        ParseError {
+                            offset: contents_start,
+                            message: format!(
+                                "Custom section \"{section_name}\" goes past the end of the file"
+                            ),
+                        }
+                    })?;
+                    other_custom_sections.push((section_name, &*arena.alloc_slice_copy(contents)));
                     cursor = section_end;
                 }
             }
@@ -230,6 +254,7 @@ impl<'a> WasmModule<'a> {
             reloc_code,
             reloc_data,
             names,
+            other_custom_sections,
         })
     }
 
@@ -237,7 +262,7 @@ impl<'a> WasmModule<'a> {
         arena: &'a Bump,
         module_bytes: &[u8],
         immutable_cursor: usize,
-    ) -> Result<(&'a str, usize), ParseError> {
+    ) -> Result<(&'a str, usize, usize), ParseError> {
         let mut cursor = immutable_cursor;
 
         if cursor >= module_bytes.len() {
@@ -261,7 +286,7 @@ impl<'a> WasmModule<'a> {
         let section_end = cursor + section_size as usize;
         let section_name = <&'a str>::parse(arena, module_bytes, &mut cursor)?;
 
-        Ok((section_name, section_end))
+        Ok((section_name, cursor, section_end))
     }
 
     pub fn eliminate_dead_code(&mut self, arena: &'a Bump, called_fns: BitVec<usize>) {
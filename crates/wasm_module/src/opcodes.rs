@@ -19,6 +19,8 @@ pub enum OpCode {
     RETURN = 0x0f,
     CALL = 0x10,
     CALLINDIRECT = 0x11,
+    RETURNCALL = 0x12,
+    RETURNCALLINDIRECT = 0x13,
     DROP = 0x1a,
     SELECT = 0x1b,
     GETLOCAL = 0x20,
@@ -52,6 +54,7 @@ pub enum OpCode {
     CURRENTMEMORY = 0x3f,
     GROWMEMORY = 0x40,
     MEMORY = 0xFC,
+    ATOMIC = 0xFE,
     I32CONST = 0x41,
     I64CONST = 0x42,
     F32CONST = 0x43,
@@ -211,6 +214,8 @@ pub const LOOKUP_TABLE: [Option<OpCode>; 256] = {
     result[0x0f] = Some(RETURN);
     result[0x10] = Some(CALL);
     result[0x11] = Some(CALLINDIRECT);
+    result[0x12] = Some(RETURNCALL);
+    result[0x13] = Some(RETURNCALLINDIRECT);
     result[0x1a] = Some(DROP);
     result[0x1b] = Some(SELECT);
     result[0x20] = Some(GETLOCAL);
@@ -244,6 +249,7 @@ pub const LOOKUP_TABLE: [Option<OpCode>; 256] = {
     result[0x3f] = Some(CURRENTMEMORY);
     result[0x40] = Some(GROWMEMORY);
     result[0xfc] = Some(MEMORY);
+    result[0xfe] = Some(ATOMIC);
     result[0x41] = Some(I32CONST);
     result[0x42] = Some(I64CONST);
     result[0x43] = Some(F32CONST);
@@ -421,6 +427,107 @@ impl TryFrom<u8> for MemoryInstruction {
     }
 }
 
+/// The value type and memory access size of an atomic load, store, or read-modify-write.
+/// Narrow accesses are zero-extended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicWidth {
+    I32,
+    I64,
+    I32From8,
+    I32From16,
+    I64From8,
+    I64From16,
+    I64From32,
+}
+
+impl AtomicWidth {
+    fn from_index(index: u32) -> Self {
+        match index {
+            0 => Self::I32,
+            1 => Self::I64,
+            2 => Self::I32From8,
+            3 => Self::I32From16,
+            4 => Self::I64From8,
+            5 => Self::I64From16,
+            6 => Self::I64From32,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::I32From8 | Self::I64From8 => 1,
+            Self::I32From16 | Self::I64From16 => 2,
+            Self::I32 | Self::I64From32 => 4,
+            Self::I64 => 8,
+        }
+    }
+
+    pub fn is_64_bit(&self) -> bool {
+        matches!(
+            self,
+            Self::I64 | Self::I64From8 | Self::I64From16 | Self::I64From32
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicRmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+    Cmpxchg,
+}
+
+/// Instructions from the threads proposal, which all start with the 0xFE prefix byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtomicInstruction {
+    Notify,
+    Wait32,
+    Wait64,
+    Fence,
+    Load(AtomicWidth),
+    Store(AtomicWidth),
+    Rmw(AtomicRmwOp, AtomicWidth),
+}
+
+impl TryFrom<u32> for AtomicInstruction {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        use AtomicRmwOp::*;
+
+        // Loads, stores, and each kind of read-modify-write come in groups of 7 widths
+        let width = |first: u32| AtomicWidth::from_index(value - first);
+        let rmw_op = |group: u32| match group {
+            0 => Add,
+            1 => Sub,
+            2 => And,
+            3 => Or,
+            4 => Xor,
+            5 => Xchg,
+            _ => Cmpxchg,
+        };
+
+        match value {
+            0x00 => Ok(Self::Notify),
+            0x01 => Ok(Self::Wait32),
+            0x02 => Ok(Self::Wait64),
+            0x03 => Ok(Self::Fence),
+            0x10..=0x16 => Ok(Self::Load(width(0x10))),
+            0x17..=0x1d => Ok(Self::Store(width(0x17))),
+            0x1e..=0x4e => {
+                let group = (value - 0x1e) / 7;
+                Ok(Self::Rmw(rmw_op(group), width(0x1e + group * 7)))
+            }
+            _ => Err(value),
+        }
+    }
+}
+
 /// The format of the *immediate* operands of an operator
 /// Immediates appear directly in the byte stream after the opcode,
 /// rather than being popped off the value stack. These are the possible forms.
@@ -435,6 +542,7 @@ enum OpImmediates {
     Leb32x2,
     BrTable,
     Memory,
+    Atomic,
 }
 
 fn immediates_for(op: OpCode) -> Result<OpImmediates, String> {
@@ -450,8 +558,8 @@ fn immediates_for(op: OpCode) -> Result<OpImmediates, String> {
         BR | BRIF => Leb32x1,
         BRTABLE => BrTable,
         RETURN => NoImmediate,
-        CALL => Leb32x1,
-        CALLINDIRECT => Leb32x2,
+        CALL | RETURNCALL => Leb32x1,
+        CALLINDIRECT | RETURNCALLINDIRECT => Leb32x2,
         DROP => NoImmediate,
         SELECT => NoImmediate,
         GETLOCAL | SETLOCAL | TEELOCAL => Leb32x1,
@@ -464,6 +572,7 @@ fn immediates_for(op: OpCode) -> Result<OpImmediates, String> {
 
         CURRENTMEMORY | GROWMEMORY => Byte1,
         MEMORY => Memory,
+        ATOMIC => Atomic,
 
         I32CONST => Leb32x1,
         I64CONST => Leb64x1,
@@ -534,7 +643,8 @@ impl SkipBytes for OpCode {
             Leb32x2 => {
                 *cursor += 1;
                 u32::skip_bytes(bytes, cursor)?;
-                u32::skip_bytes(bytes, cursor)?;
+                // A memarg offset can be 64 bits wide in a 64-bit memory
+                u64::skip_bytes(bytes, cursor)?;
             }
             BrTable => {
                 *cursor += 1;
@@ -566,6 +676,27 @@ impl SkipBytes for OpCode {
                     Err(other) => unreachable!("invalid memory instruction {other:?}"),
                 }
             }
+            Atomic => {
+                *cursor += 1;
+                let sub_opcode = u32::parse((), bytes, cursor)?;
+                match AtomicInstruction::try_from(sub_opcode) {
+                    Ok(AtomicInstruction::Fence) => {
+                        // a single reserved zero byte
+                        *cursor += 1;
+                    }
+                    Ok(_) => {
+                        // alignment and offset
+                        u32::skip_bytes(bytes, cursor)?;
+                        u64::skip_bytes(bytes, cursor)?;
+                    }
+                    Err(other) => {
+                        return Err(ParseError {
+                            message: format!("Unknown atomic instruction 0xfe 0x{other:02x}"),
+                            offset: *cursor,
+                        })
+                    }
+                }
+            }
         }
         Ok(())
     }
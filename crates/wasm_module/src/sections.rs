@@ -299,6 +299,11 @@ impl<'a> TypeSection<'a> {
         self.bytes.is_empty()
     }
 
+    /// Number of signatures in the section
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
     pub fn look_up(&'a self, sig_index: u32) -> (SignatureParamsIter<'a>, Option<ValueType>) {
         let mut offset = self.offsets[sig_index as usize];
         offset += 1; // separator
@@ -764,6 +769,14 @@ enum LimitsId {
     MinMax = 1,
 }
 
+/// Flag bit set in the limits of a 64-bit memory (memory64 proposal)
+const LIMITS_FLAG_MEMORY64: u8 = 0x04;
+
+/// Whether the limits flag byte says there's a maximum. The memory64 flag may also be set.
+fn limits_has_max(variant_id: u8) -> bool {
+    variant_id & LimitsId::MinMax as u8 != 0
+}
+
 impl Serialize for Limits {
     fn serialize<T: SerialBuffer>(&self, buffer: &mut T) {
         match self {
@@ -785,7 +798,7 @@ impl SkipBytes for Limits {
         let variant_id = bytes[*cursor];
         u8::skip_bytes(bytes, cursor)?; // advance past the variant byte
         u32::skip_bytes(bytes, cursor)?; // skip "min"
-        if variant_id == LimitsId::MinMax as u8 {
+        if limits_has_max(variant_id) {
             u32::skip_bytes(bytes, cursor)?; // skip "max"
         }
         Ok(())
@@ -800,9 +813,9 @@ impl Parse<()> for Limits {
         let variant_id = bytes[*cursor];
         *cursor += 1;
 
-        let min = u32::parse((), bytes, cursor).unwrap();
-        if variant_id == LimitsId::MinMax as u8 {
-            let max = u32::parse((), bytes, cursor).unwrap();
+        let min = u32::parse((), bytes, cursor)?;
+        if limits_has_max(variant_id) {
+            let max = u32::parse((), bytes, cursor)?;
             Ok(Limits::MinMax(min, max))
         } else {
             Ok(Limits::Min(min))
@@ -836,24 +849,29 @@ impl<'a> MemorySection<'a> {
         }
     }
 
-    pub fn min_bytes(&self) -> Result<u32, ParseError> {
+    pub fn min_bytes(&self) -> Result<u64, ParseError> {
         let mut cursor = 0;
         let memory_limits = Limits::parse((), &self.bytes, &mut cursor)?;
         let min_pages = match memory_limits {
             Limits::Min(pages) | Limits::MinMax(pages, _) => pages,
         };
-        Ok(min_pages * MemorySection::PAGE_SIZE)
+        Ok(min_pages as u64 * MemorySection::PAGE_SIZE as u64)
     }
 
-    pub fn max_bytes(&self) -> Result<Option<u32>, ParseError> {
+    pub fn max_bytes(&self) -> Result<Option<u64>, ParseError> {
         let mut cursor = 0;
         let memory_limits = Limits::parse((), &self.bytes, &mut cursor)?;
         let bytes = match memory_limits {
             Limits::Min(_) => None,
-            Limits::MinMax(_, pages) => Some(pages * MemorySection::PAGE_SIZE),
+            Limits::MinMax(_, pages) => Some(pages as u64 * MemorySection::PAGE_SIZE as u64),
         };
         Ok(bytes)
     }
+
+    /// Whether the memory is indexed with 64-bit addresses (memory64 proposal)
+    pub fn is_memory64(&self) -> bool {
+        self.count > 0 && self.bytes[0] & LIMITS_FLAG_MEMORY64 != 0
+    }
 }
 
 section_impl!(MemorySection, SectionId::Memory);
@@ -965,11 +983,13 @@ impl Parse<()> for ConstExpr {
             OpCode::F32CONST => {
                 let mut b = [0; 4];
                 b.copy_from_slice(&bytes[*cursor..][..4]);
+                *cursor += 4;
                 Ok(ConstExpr::F32(f32::from_le_bytes(b)))
             }
             OpCode::F64CONST => {
                 let mut b = [0; 8];
                 b.copy_from_slice(&bytes[*cursor..][..8]);
+                *cursor += 8;
                 Ok(ConstExpr::F64(f64::from_le_bytes(b)))
             }
             _ => Err(ParseError {
@@ -1579,6 +1599,9 @@ impl<'a> DataSection<'a> {
             let start = match mode {
                 DataMode::Active {
                     offset: ConstExpr::I32(addr),
+                } => addr as u32 as usize,
+                DataMode::Active {
+                    offset: ConstExpr::I64(addr),
                 } => addr as usize,
                 _ => {
                     continue;
@@ -1712,6 +1735,8 @@ enum NameSubSections {
 }
 
 pub struct NameSection<'a> {
+    /// The name of the module itself, if the producer gave it one
+    pub module_name: Option<&'a str>,
     pub function_names: Vec<'a, (u32, &'a str)>,
 }
 
@@ -1720,10 +1745,23 @@ impl<'a> NameSection<'a> {
     const NAME: &'static str = "name";
 
     pub fn size(&self) -> usize {
-        self.function_names
+        let module_name_size = self
+            .module_name
+            .map_or(0, |s| 1 + 2 * MAX_SIZE_ENCODED_U32 + s.len());
+        let function_names_size: usize = self
+            .function_names
             .iter()
             .map(|(_, s)| MAX_SIZE_ENCODED_U32 + s.len())
-            .sum()
+            .sum();
+        module_name_size + function_names_size
+    }
+
+    /// Look up the name of a function, if the section has one for it
+    pub fn function_name(&self, fn_index: u32) -> Option<&'a str> {
+        self.function_names
+            .iter()
+            .find(|(index, _)| *index == fn_index)
+            .map(|(_, name)| *name)
     }
 
     pub fn append_function(&mut self, index: u32, name: &'a str) {
@@ -1732,6 +1770,7 @@ impl<'a> NameSection<'a> {
 
     pub fn new(arena: &'a Bump) -> Self {
         NameSection {
+            module_name: None,
             function_names: bumpalo::vec![in arena],
         }
     }
@@ -1758,7 +1797,10 @@ impl<'a> NameSection<'a> {
         let mut function_names = Vec::from_iter_in(names, arena);
         function_names.sort_by_key(|(idx, _name)| *idx);
 
-        NameSection { function_names }
+        NameSection {
+            module_name: None,
+            function_names,
+        }
     }
 }
 
@@ -1789,7 +1831,8 @@ impl<'a> Parse<&'a Bump> for NameSection<'a> {
             return Ok(Self::new(arena));
         }
 
-        // Find function names subsection
+        // Find function names subsection. The module name comes before it, if there is one.
+        let mut module_name = None;
         let mut found_function_names = false;
         for _possible_subsection_id in 0..2 {
             let subsection_id = module_bytes[*cursor];
@@ -1799,7 +1842,11 @@ impl<'a> Parse<&'a Bump> for NameSection<'a> {
                 found_function_names = true;
                 break;
             }
-            *cursor += subsection_size as usize;
+            let subsection_start = *cursor;
+            if subsection_id == NameSubSections::ModuleName as u8 {
+                module_name = Some(<&'a str>::parse(arena, module_bytes, cursor)?);
+            }
+            *cursor = subsection_start + subsection_size as usize;
             if *cursor >= section_end {
                 return Err(ParseError {
                     message: "Failed to parse Name section".into(),
@@ -1816,6 +1863,7 @@ impl<'a> Parse<&'a Bump> for NameSection<'a> {
 
         let count = u32::parse((), module_bytes, cursor)?;
         let mut section = NameSection {
+            module_name,
             function_names: Vec::with_capacity_in(count as usize, arena),
         };
 
@@ -1837,6 +1885,18 @@ impl<'a> Serialize for NameSection<'a> {
         if !self.function_names.is_empty() {
             let header_indices = write_custom_section_header(buffer, Self::NAME);
 
+            if let Some(module_name) = self.module_name {
+                let subsection_id = NameSubSections::ModuleName as u8;
+                subsection_id.serialize(buffer);
+                let subsection_size_index = buffer.encode_padded_u32(0);
+                let subsection_start = buffer.size();
+                module_name.serialize(buffer);
+                buffer.overwrite_padded_u32(
+                    subsection_size_index,
+                    (buffer.size() - subsection_start) as u32,
+                );
+            }
+
             let subsection_id = NameSubSections::FunctionNames as u8;
             subsection_id.serialize(buffer);
 
@@ -1859,6 +1919,10 @@ impl<'a> Debug for NameSection<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "NameSection")?;
 
+        if let Some(module_name) = self.module_name {
+            writeln!(f, "  module: {module_name}")?;
+        }
+
         for (index, name) in self.function_names.iter() {
             writeln!(f, "  {index:4}: {name}")?;
         }
@@ -1921,4 +1985,21 @@ mod tests {
         }
         test_assert_types_preload(arena, &section);
     }
+
+    #[test]
+    fn test_name_section_module_name() {
+        let arena = &Bump::new();
+        let mut section = NameSection::new(arena);
+        section.module_name = Some("platform");
+        section.append_function(0, "main");
+
+        let mut serialized = Vec::with_capacity_in(section.size(), arena);
+        section.serialize(&mut serialized);
+        let mut cursor = 0;
+        let preloaded = NameSection::parse(arena, &serialized, &mut cursor).unwrap();
+
+        assert_eq!(preloaded.module_name, Some("platform"));
+        assert_eq!(preloaded.function_name(0), Some("main"));
+        assert_eq!(preloaded.function_name(1), None);
+    }
 }